@@ -17,6 +17,11 @@ pub enum CommerceError {
     CategoryNotFound(String),
     /// Category already exists.
     CategoryAlreadyExists(String),
+    /// Changes were dropped from a full change feed before being drained.
+    ChangeFeedGap {
+        /// Number of changes dropped.
+        dropped: u64,
+    },
     /// Cart not found.
     CartNotFound(String),
     /// Cart is empty.
@@ -31,6 +36,13 @@ pub enum CommerceError {
     InvalidQuantity,
     /// Product not available for purchase.
     ProductNotAvailable(String),
+    /// Product is listed but cannot be bought until its availability date.
+    NotYetAvailable {
+        /// Product ID.
+        product_id:     String,
+        /// When the product becomes available.
+        available_from: u64,
+    },
     /// Insufficient inventory.
     InsufficientInventory {
         /// Product ID.
@@ -40,6 +52,24 @@ pub enum CommerceError {
         /// Requested quantity.
         requested:  u32,
     },
+    /// A reference already holds a reservation of a different quantity.
+    ReservationConflict {
+        /// Reservation reference (e.g. order ID).
+        reference: String,
+        /// Quantity already reserved under the reference.
+        reserved:  u32,
+        /// Quantity requested.
+        requested: u32,
+    },
+    /// Requested quantity exceeds the per-order limit.
+    QuantityLimitExceeded {
+        /// Product ID.
+        product_id: String,
+        /// Maximum quantity allowed per order.
+        limit:      u32,
+    },
+    /// Currency code is not supported.
+    UnsupportedCurrency(String),
     /// Currency mismatch.
     CurrencyMismatch {
         /// Expected currency.
@@ -51,12 +81,18 @@ pub enum CommerceError {
     DiscountAlreadyApplied(String),
     /// Discount not found.
     DiscountNotFound(String),
+    /// Cart does not meet the discount's conditions.
+    DiscountNotEligible(String),
     /// Shipping address required.
     ShippingAddressRequired,
     /// Order not found.
     OrderNotFound(String),
     /// Order cannot be cancelled.
     OrderNotCancellable(String),
+    /// Order is on hold and cannot be fulfilled.
+    OrderOnHold(String),
+    /// Order has an outstanding balance and cannot be fulfilled yet.
+    PaymentIncomplete(String),
     /// Location not found.
     LocationNotFound(String),
     /// Location already exists.
@@ -67,6 +103,13 @@ pub enum CommerceError {
     TransferNotFound(String),
     /// Invalid transfer status.
     InvalidTransferStatus,
+    /// Status transition not allowed from the current status.
+    InvalidStatusTransition {
+        /// Current status.
+        from: String,
+        /// Requested status.
+        to:   String,
+    },
     /// Validation error.
     ValidationError(String),
     /// Internal error.
@@ -92,6 +135,13 @@ impl fmt::Display for CommerceError {
             Self::SkuAlreadyExists(sku) => write!(f, "SKU already exists: {}", sku),
             Self::CategoryNotFound(id) => write!(f, "Category not found: {}", id),
             Self::CategoryAlreadyExists(id) => write!(f, "Category already exists: {}", id),
+            Self::ChangeFeedGap { dropped } => {
+                write!(
+                    f,
+                    "Change feed dropped {} changes, resync required",
+                    dropped
+                )
+            },
             Self::CartNotFound(id) => write!(f, "Cart not found: {}", id),
             Self::CartEmpty => write!(f, "Cart is empty"),
             Self::CartNotActive => write!(f, "Cart is not active"),
@@ -99,6 +149,13 @@ impl fmt::Display for CommerceError {
             Self::ItemNotInCart(id) => write!(f, "Item not in cart: {}", id),
             Self::InvalidQuantity => write!(f, "Invalid quantity"),
             Self::ProductNotAvailable(id) => write!(f, "Product not available: {}", id),
+            Self::NotYetAvailable { product_id, available_from } => {
+                write!(
+                    f,
+                    "Product {} not available until {}",
+                    product_id, available_from
+                )
+            },
             Self::InsufficientInventory { product_id, available, requested } => {
                 write!(
                     f,
@@ -106,19 +163,40 @@ impl fmt::Display for CommerceError {
                     product_id, available, requested
                 )
             },
+            Self::ReservationConflict { reference, reserved, requested } => {
+                write!(
+                    f,
+                    "Reservation {} already holds {}, requested {}",
+                    reference, reserved, requested
+                )
+            },
+            Self::QuantityLimitExceeded { product_id, limit } => {
+                write!(
+                    f,
+                    "Quantity limit exceeded for {}: at most {} per order",
+                    product_id, limit
+                )
+            },
+            Self::UnsupportedCurrency(code) => write!(f, "Unsupported currency: {}", code),
             Self::CurrencyMismatch { expected, got } => {
                 write!(f, "Currency mismatch: expected {}, got {}", expected, got)
             },
             Self::DiscountAlreadyApplied(code) => write!(f, "Discount already applied: {}", code),
             Self::DiscountNotFound(code) => write!(f, "Discount not found: {}", code),
+            Self::DiscountNotEligible(reason) => write!(f, "Discount not eligible: {}", reason),
             Self::ShippingAddressRequired => write!(f, "Shipping address required"),
             Self::OrderNotFound(id) => write!(f, "Order not found: {}", id),
             Self::OrderNotCancellable(id) => write!(f, "Order cannot be cancelled: {}", id),
+            Self::OrderOnHold(id) => write!(f, "Order is on hold: {}", id),
+            Self::PaymentIncomplete(id) => write!(f, "Order is not fully paid: {}", id),
             Self::LocationNotFound(id) => write!(f, "Location not found: {}", id),
             Self::LocationAlreadyExists(id) => write!(f, "Location already exists: {}", id),
             Self::InventoryNotFound(id) => write!(f, "Inventory record not found: {}", id),
             Self::TransferNotFound(id) => write!(f, "Transfer not found: {}", id),
             Self::InvalidTransferStatus => write!(f, "Invalid transfer status"),
+            Self::InvalidStatusTransition { from, to } => {
+                write!(f, "Invalid status transition from {} to {}", from, to)
+            },
             Self::ValidationError(msg) => write!(f, "Validation error: {}", msg),
             Self::InternalError(msg) => write!(f, "Internal error: {}", msg),
             Self::PaymentPluginNotConfigured => write!(f, "Payment plugin not configured"),
@@ -141,20 +219,49 @@ impl From<CommerceError> for essentia_api::PluginError {
 /// Marketplace-specific errors.
 #[derive(Debug, Clone)]
 pub enum MarketplaceError {
+    /// Lock acquisition failed
+    LockError,
     /// Listing not found
     ListingNotFound,
     /// Listing not active
     ListingNotActive,
     /// Seller not found
     SellerNotFound,
+    /// Seller profile already registered
+    SellerExists,
     /// Invalid listing data
     InvalidListing,
+    /// Invalid seller profile data
+    InvalidSellerProfile,
+    /// Review rating outside the 1-5 range
+    InvalidRating,
     /// Payment amount required
     AmountRequired,
+    /// Amount larger than the available balance
+    AmountExceedsAvailable {
+        /// Amount requested
+        requested: u64,
+        /// Balance available
+        available: u64,
+    },
     /// Payment amount below minimum
     BelowMinimum,
+    /// Payment amount differs from a fixed price
+    AmountMismatch {
+        /// Listing price
+        expected: u64,
+        /// Amount paid
+        got:      u64,
+    },
     /// Order not found
     OrderNotFound,
+    /// Order status does not allow the requested transition
+    InvalidOrderTransition {
+        /// Current status
+        from: String,
+        /// Requested status
+        to:   String,
+    },
     /// Insufficient funds
     InsufficientFunds,
     /// Payment failed
@@ -173,6 +280,8 @@ pub enum MarketplaceError {
     TokenExpired,
     /// Download limit reached
     DownloadLimitReached,
+    /// Every license under the listing's cap has been sold
+    LicenseCapReached,
     /// No content providers available
     NoProviders,
     /// Content not found
@@ -187,18 +296,43 @@ pub enum MarketplaceError {
     InvalidEscrowState,
     /// Release conditions not met
     ReleaseConditionsNotMet,
+    /// Caller is not the arbitrator assigned to the escrow
+    UnauthorizedArbitrator,
+    /// Moderation status does not allow the requested action
+    InvalidModerationState,
 }
 
 impl fmt::Display for MarketplaceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::LockError => write!(f, "Failed to acquire lock"),
             Self::ListingNotFound => write!(f, "Listing not found"),
             Self::ListingNotActive => write!(f, "Listing not active"),
             Self::SellerNotFound => write!(f, "Seller not found"),
+            Self::SellerExists => write!(f, "Seller already exists"),
             Self::InvalidListing => write!(f, "Invalid listing data"),
+            Self::InvalidSellerProfile => write!(f, "Invalid seller profile data"),
+            Self::InvalidRating => write!(f, "Rating must be between 1 and 5"),
             Self::AmountRequired => write!(f, "Payment amount required"),
+            Self::AmountExceedsAvailable { requested, available } => {
+                write!(
+                    f,
+                    "Amount {} exceeds available balance {}",
+                    requested, available
+                )
+            },
             Self::BelowMinimum => write!(f, "Payment amount below minimum"),
+            Self::AmountMismatch { expected, got } => {
+                write!(
+                    f,
+                    "Payment amount mismatch: expected {}, got {}",
+                    expected, got
+                )
+            },
             Self::OrderNotFound => write!(f, "Order not found"),
+            Self::InvalidOrderTransition { from, to } => {
+                write!(f, "Invalid order transition from {} to {}", from, to)
+            },
             Self::InsufficientFunds => write!(f, "Insufficient funds"),
             Self::PaymentFailed => write!(f, "Payment failed"),
             Self::EscrowError(msg) => write!(f, "Escrow error: {}", msg),
@@ -208,6 +342,7 @@ impl fmt::Display for MarketplaceError {
             Self::InvalidToken => write!(f, "Invalid access token"),
             Self::TokenExpired => write!(f, "Token expired"),
             Self::DownloadLimitReached => write!(f, "Download limit reached"),
+            Self::LicenseCapReached => write!(f, "License cap reached"),
             Self::NoProviders => write!(f, "No content providers available"),
             Self::ContentNotFound => write!(f, "Content not found"),
             Self::InsufficientFundsForEscrow => write!(f, "Insufficient funds for escrow"),
@@ -215,6 +350,10 @@ impl fmt::Display for MarketplaceError {
             Self::EscrowNotFound => write!(f, "Escrow not found"),
             Self::InvalidEscrowState => write!(f, "Invalid escrow state for operation"),
             Self::ReleaseConditionsNotMet => write!(f, "Release conditions not met"),
+            Self::UnauthorizedArbitrator => write!(f, "Not the assigned arbitrator"),
+            Self::InvalidModerationState => {
+                write!(f, "Invalid moderation state for operation")
+            },
         }
     }
 }
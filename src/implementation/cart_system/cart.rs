@@ -1,26 +1,56 @@
 //! Shopping cart and totals
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, sync::OnceLock};
 
 use crate::{
     errors::CommerceError,
-    types::product_catalog::{Currency, Product, ProductId},
+    types::{
+        inventory_sync::{FulfillmentType, InventoryKey, InventoryService, LocationId},
+        product_catalog::{Currency, Product, ProductId},
+    },
 };
 
 use super::item::CartItem;
 use super::shipping::{ShippingAddress, ShippingMethod};
-use super::types::{AppliedDiscount, CartId, CartStatus, CustomerId, DiscountType};
+use super::types::{
+    AppliedDiscount, CartId, CartStatus, CouponCode, CustomerId, DiscountType, MarkdownStacking,
+    TaxMode, TaxRounding,
+};
+
+/// Per-product (and variant) allocation of quantities to fulfillment
+/// locations.
+pub type FulfillmentPlan = Vec<(ProductId, Option<ProductId>, Vec<(LocationId, u32)>)>;
+
+/// Cart-wide settings that [`CartTotals::calculate`] prices with.
+#[derive(Debug, Clone)]
+pub struct TotalsSettings {
+    /// Tax rate percentage for lines without their own rate.
+    pub tax_rate:           f64,
+    /// Tax rounding strategy.
+    pub tax_rounding:       TaxRounding,
+    /// Whether prices include tax.
+    pub tax_mode:           TaxMode,
+    /// Automatic storewide markdown percentage.
+    pub automatic_markdown: Option<u8>,
+    /// Currency of the totals.
+    pub currency:           Currency,
+}
 
 /// Cart price totals.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct CartTotals {
     /// Subtotal (sum of line totals before discounts).
     pub subtotal:       u64,
     /// Total discounts applied.
     pub discount_total: u64,
-    /// Shipping cost.
+    /// Shipping cost (base shipping plus surcharges).
     pub shipping_total: u64,
-    /// Tax amount.
+    /// Base shipping cost, waived by free shipping.
+    pub base_shipping:  u64,
+    /// Oversize handling surcharge, charged even under free shipping.
+    pub surcharge:      u64,
+    /// Tax amount, included in the discounted subtotal when prices are
+    /// tax-inclusive.
     pub tax_total:      u64,
     /// Grand total.
     pub grand_total:    u64,
@@ -32,50 +62,157 @@ pub struct CartTotals {
     pub currency:       Currency,
 }
 
+impl Default for CartTotals {
+    fn default() -> Self {
+        Self::empty(Currency::ess())
+    }
+}
+
 impl CartTotals {
+    /// Zero totals in the given currency.
+    #[must_use]
+    pub fn empty(currency: Currency) -> Self {
+        Self {
+            subtotal: 0,
+            discount_total: 0,
+            shipping_total: 0,
+            base_shipping: 0,
+            surcharge: 0,
+            tax_total: 0,
+            grand_total: 0,
+            total_savings: 0,
+            item_count: 0,
+            currency,
+        }
+    }
+
     /// Calculates totals for a cart.
+    ///
+    /// An automatic markdown is applied to the subtotal after item discounts
+    /// and before cart-level coupons, which then see the marked-down amount.
+    /// Percentage coupons only see the items they are eligible for; a
+    /// fixed amount off the items never exceeds their total, while one off
+    /// the cart applies in full.
+    ///
+    /// Free shipping, from a coupon or the method's threshold, waives only
+    /// the base shipping cost. Oversize surcharges are handling fees and are
+    /// always charged.
+    ///
+    /// Tax is charged per line, at the item's own rate when it has one and
+    /// the settings' `tax_rate` otherwise, on the line's share of the
+    /// discounted subtotal. Lines sharing a rate are rounded together.
+    ///
+    /// Under [`TaxMode::Inclusive`] the tax is backed out of the discounted
+    /// subtotal rather than added, so the grand total is that subtotal plus
+    /// shipping.
     #[must_use]
     pub fn calculate(
         items: &[CartItem], cart_discounts: &[AppliedDiscount], shipping: Option<&ShippingMethod>,
-        tax_rate: f64, currency: Currency,
+        settings: &TotalsSettings,
     ) -> Self {
+        let TotalsSettings { tax_rate, tax_rounding, tax_mode, automatic_markdown, .. } = *settings;
+
         let subtotal: u64 = items.iter().map(|i| i.subtotal()).sum();
         let item_discounts: u64 = items.iter().map(|i| i.total_discount()).sum();
         let sale_savings: u64 = items.iter().map(|i| i.sale_savings()).sum();
 
+        let markdown =
+            markdown_savings(subtotal.saturating_sub(item_discounts), automatic_markdown);
+
+        // Each line's share of the discounted subtotal, for per-line tax rates
+        let mut line_amounts: Vec<u64> = items.iter().map(CartItem::total).collect();
+        let weights = line_amounts.clone();
+        spread_discount(&mut line_amounts, &weights, markdown);
+
         // Calculate cart-level discounts
         let mut cart_discount_total: u64 = 0;
         for discount in cart_discounts {
-            match discount.discount_type {
+            let savings = match discount.discount_type {
                 DiscountType::Percentage => {
-                    cart_discount_total += (subtotal * discount.value) / 100;
+                    let base = coupon_base(items, discount, automatic_markdown);
+                    (base * discount.value) / 100
+                },
+                DiscountType::FixedAmount => discount.value,
+                DiscountType::FixedAmountOnItems => {
+                    let base = coupon_base(items, discount, automatic_markdown);
+                    discount.value.min(base)
                 },
-                DiscountType::FixedAmount => {
-                    cart_discount_total += discount.value;
+                DiscountType::BuyXGetY => {
+                    // The free units' own lines carry the savings
+                    let free = buy_x_get_y_savings(items, discount);
+                    for (amount, free) in line_amounts.iter_mut().zip(free) {
+                        let taken = free.min(*amount);
+                        *amount -= taken;
+                        cart_discount_total += taken;
+                    }
+                    continue;
                 },
-                DiscountType::FreeShipping | DiscountType::BuyXGetY => {
+                DiscountType::FreeShipping => {
                     // Handled separately
+                    continue;
                 },
-            }
+            };
+            cart_discount_total += savings;
+
+            // A per-cart fixed amount is shared by every line
+            let per_cart = discount.discount_type == DiscountType::FixedAmount;
+            let weights: Vec<u64> = items
+                .iter()
+                .zip(&line_amounts)
+                .map(|(item, &amount)| {
+                    if per_cart || discount.applies_to(item) {
+                        amount
+                    } else {
+                        0
+                    }
+                })
+                .collect();
+            spread_discount(&mut line_amounts, &weights, savings);
         }
 
-        let discount_total = item_discounts + cart_discount_total;
+        let discount_total = item_discounts + markdown + cart_discount_total;
         let subtotal_after_discount = subtotal.saturating_sub(discount_total);
 
-        // Check for free shipping discount
+        // Free-shipping thresholds are applied by the method's rate
         let has_free_shipping =
             cart_discounts.iter().any(|d| d.discount_type == DiscountType::FreeShipping);
 
-        let shipping_total = if has_free_shipping {
+        let base_shipping = if has_free_shipping {
             0
         } else {
-            shipping.map(|s| s.cost.amount).unwrap_or(0)
+            shipping.map_or(0, |s| {
+                s.rate_for_items(items, subtotal_after_discount).amount
+            })
         };
 
-        // Calculate tax
-        let tax_total = ((subtotal_after_discount as f64) * tax_rate / 100.0) as u64;
-
-        let grand_total = subtotal_after_discount + shipping_total + tax_total;
+        let oversize_units: u64 =
+            items.iter().filter(|i| i.oversize).map(|i| u64::from(i.quantity)).sum();
+        let surcharge = shipping.map_or(0, |s| s.oversize_surcharge * oversize_units);
+        let shipping_total = base_shipping + surcharge;
+
+        // Calculate tax once per rate. Lines at the cart rate take whatever
+        // the overriding lines leave, so with no overrides the tax is exactly
+        // that of the discounted subtotal.
+        let mut rated: Vec<(f64, u64)> = Vec::new();
+        for (item, &amount) in items.iter().zip(&line_amounts) {
+            if let Some(rate) = item.tax_rate {
+                match rated.iter_mut().find(|(r, _)| *r == rate) {
+                    Some((_, total)) => *total += amount,
+                    None => rated.push((rate, amount)),
+                }
+            }
+        }
+        let overridden: u64 = rated.iter().map(|(_, amount)| amount).sum();
+        rated.push((tax_rate, subtotal_after_discount.saturating_sub(overridden)));
+        let tax_total = rated
+            .iter()
+            .map(|&(rate, amount)| tax_mode.tax_on(amount, rate, tax_rounding))
+            .sum();
+
+        let grand_total = match tax_mode {
+            TaxMode::Exclusive => subtotal_after_discount + shipping_total + tax_total,
+            TaxMode::Inclusive => subtotal_after_discount + shipping_total,
+        };
         let total_savings = sale_savings + discount_total;
 
         let item_count: u32 = items.iter().map(|i| i.quantity).sum();
@@ -84,13 +221,94 @@ impl CartTotals {
             subtotal,
             discount_total,
             shipping_total,
+            base_shipping,
+            surcharge,
             tax_total,
             grand_total,
             total_savings,
             item_count,
-            currency,
+            currency: settings.currency.clone(),
+        }
+    }
+}
+
+/// Savings from a storewide percentage markdown.
+fn markdown_savings(amount: u64, percent: Option<u8>) -> u64 {
+    percent.map_or(0, |p| amount * u64::from(p.min(100)) / 100)
+}
+
+/// Takes `discount` off `amounts` in proportion to `weights`, giving the
+/// units lost to rounding to the largest remainders.
+fn spread_discount(amounts: &mut [u64], weights: &[u64], discount: u64) {
+    let weight_total: u128 = weights.iter().map(|&w| u128::from(w)).sum();
+
+    let mut shares: Vec<(usize, u64, u128)> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, &weight)| {
+            let scaled = u128::from(discount) * u128::from(weight);
+            let share = scaled.checked_div(weight_total).unwrap_or(0);
+            (
+                i,
+                share as u64,
+                scaled.checked_rem(weight_total).unwrap_or(0),
+            )
+        })
+        .collect();
+
+    let allocated: u64 = shares.iter().map(|(_, share, _)| share).sum();
+    let mut leftover = if weight_total == 0 {
+        0
+    } else {
+        discount - allocated
+    };
+    shares.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+    for (_, share, _) in shares.iter_mut() {
+        if leftover == 0 {
+            break;
         }
+        *share += 1;
+        leftover -= 1;
+    }
+
+    for (i, share, _) in shares {
+        amounts[i] = amounts[i].saturating_sub(share);
+    }
+}
+
+/// Per-line savings from a buy-X-get-Y coupon: each full group of
+/// `buy_quantity + get_quantity` eligible units makes `get_quantity` units
+/// free, taken from the cheapest.
+fn buy_x_get_y_savings(items: &[CartItem], discount: &AppliedDiscount) -> Vec<u64> {
+    let group = u64::from(discount.buy_quantity) + u64::from(discount.get_quantity);
+    let mut eligible: Vec<usize> =
+        (0..items.len()).filter(|&i| discount.applies_to(&items[i])).collect();
+    let units: u64 = eligible.iter().map(|&i| u64::from(items[i].quantity)).sum();
+    let mut free = units.checked_div(group).unwrap_or(0) * u64::from(discount.get_quantity);
+
+    eligible.sort_by_key(|&i| items[i].unit_price.amount);
+    let mut savings = vec![0; items.len()];
+    for i in eligible {
+        let taken = free.min(u64::from(items[i].quantity));
+        savings[i] = items[i].unit_price.amount * taken;
+        free -= taken;
     }
+    savings
+}
+
+/// Amount a coupon applies to: the subtotal of its eligible items less their
+/// share of the automatic markdown.
+fn coupon_base(
+    items: &[CartItem], discount: &AppliedDiscount, automatic_markdown: Option<u8>,
+) -> u64 {
+    let eligible = items.iter().filter(|i| discount.applies_to(i));
+    let (subtotal, item_discounts) = eligible.fold((0_u64, 0_u64), |(s, d), i| {
+        (s + i.subtotal(), d + i.total_discount())
+    });
+    subtotal.saturating_sub(markdown_savings(
+        subtotal.saturating_sub(item_discounts),
+        automatic_markdown,
+    ))
 }
 
 /// Shopping cart.
@@ -100,22 +318,34 @@ pub struct Cart {
     pub id:               CartId,
     /// Customer ID.
     pub customer_id:      CustomerId,
+    /// Customer pricing group (e.g. "wholesale").
+    pub customer_group:   Option<String>,
     /// Cart status.
     pub status:           CartStatus,
     /// Items in cart.
-    pub items:            Vec<CartItem>,
+    items:                Vec<CartItem>,
     /// Applied coupon codes.
-    pub discounts:        Vec<AppliedDiscount>,
+    discounts:            Vec<AppliedDiscount>,
     /// Shipping address.
     pub shipping_address: Option<ShippingAddress>,
     /// Billing address.
     pub billing_address:  Option<ShippingAddress>,
     /// Selected shipping method.
-    pub shipping_method:  Option<ShippingMethod>,
+    shipping_method:      Option<ShippingMethod>,
     /// Default currency.
-    pub currency:         Currency,
+    currency:             Currency,
     /// Tax rate percentage.
-    pub tax_rate:         f64,
+    tax_rate:             f64,
+    /// Tax rate percentages by product tax class, overriding `tax_rate`.
+    tax_class_rates:      HashMap<String, f64>,
+    /// Tax rounding strategy.
+    tax_rounding:         TaxRounding,
+    /// Whether prices include tax.
+    tax_mode:             TaxMode,
+    /// Automatic storewide markdown percentage (no code required).
+    automatic_markdown:   Option<u8>,
+    /// How the automatic markdown combines with coupons.
+    markdown_stacking:    MarkdownStacking,
     /// Cart notes.
     pub notes:            Option<Cow<'static, str>>,
     /// Creation timestamp.
@@ -126,12 +356,20 @@ pub struct Cart {
     pub last_activity_at: u64,
     /// Cart expiration timestamp.
     pub expires_at:       Option<u64>,
+    /// Memoized totals, cleared whenever the cart is mutated.
+    totals_cache:         OnceLock<CartTotals>,
 }
 
 impl Cart {
     /// Creates a new cart.
     #[must_use]
     pub fn new(customer_id: CustomerId) -> Self {
+        Self::with_currency(customer_id, Currency::usd())
+    }
+
+    /// Creates a new cart priced in the given currency.
+    #[must_use]
+    pub fn with_currency(customer_id: CustomerId, currency: Currency) -> Self {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs())
@@ -140,19 +378,26 @@ impl Cart {
         Self {
             id: CartId::generate(),
             customer_id,
+            customer_group: None,
             status: CartStatus::Active,
             items: Vec::new(),
             discounts: Vec::new(),
             shipping_address: None,
             billing_address: None,
             shipping_method: None,
-            currency: Currency::usd(),
+            currency,
             tax_rate: 0.0,
+            tax_class_rates: HashMap::new(),
+            tax_rounding: TaxRounding::default(),
+            tax_mode: TaxMode::default(),
+            automatic_markdown: None,
+            markdown_stacking: MarkdownStacking::default(),
             notes: None,
             created_at: now,
             updated_at: now,
             last_activity_at: now,
             expires_at: Some(now + 7 * 24 * 60 * 60), // 7 days default
+            totals_cache: OnceLock::new(),
         }
     }
 
@@ -180,73 +425,274 @@ impl Cart {
         self.items.iter().map(|i| i.quantity).sum()
     }
 
-    /// Updates the last activity timestamp.
-    fn touch(&mut self) {
+    /// Items in the cart.
+    #[must_use]
+    pub fn items(&self) -> &[CartItem] {
+        &self.items
+    }
+
+    /// Mutable access to the items for the cart service.
+    ///
+    /// Clears the cached totals, since the caller may change any line.
+    pub(crate) fn items_mut(&mut self) -> &mut Vec<CartItem> {
+        self.totals_cache = OnceLock::new();
+        &mut self.items
+    }
+
+    /// Applied coupon codes.
+    #[must_use]
+    pub fn discounts(&self) -> &[AppliedDiscount] {
+        &self.discounts
+    }
+
+    /// Selected shipping method.
+    #[must_use]
+    pub fn shipping_method(&self) -> Option<&ShippingMethod> {
+        self.shipping_method.as_ref()
+    }
+
+    /// Cart currency.
+    #[must_use]
+    pub fn currency(&self) -> &Currency {
+        &self.currency
+    }
+
+    /// Tax rate percentage.
+    #[must_use]
+    pub fn tax_rate(&self) -> f64 {
+        self.tax_rate
+    }
+
+    /// Whether prices include tax.
+    #[must_use]
+    pub fn tax_mode(&self) -> TaxMode {
+        self.tax_mode
+    }
+
+    /// Updates the last activity timestamp, invalidates cached totals and
+    /// refreshes discount savings.
+    pub(crate) fn touch(&mut self) {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
         self.last_activity_at = now;
         self.updated_at = now;
+        self.totals_cache = OnceLock::new();
+        self.refresh_discount_savings();
+    }
+
+    /// Recomputes the `savings` of each applied cart-level discount.
+    fn refresh_discount_savings(&mut self) {
+        let breakdown = self.applied_discount_breakdown();
+        for (discount, (_, savings)) in self.discounts.iter_mut().zip(breakdown) {
+            discount.savings = savings;
+        }
+    }
+
+    /// Whether a coupon takes effect given the automatic markdown policy
+    /// and its minimum subtotal, which items removed since it was applied
+    /// may no longer reach.
+    fn coupon_applies(&self, discount: &AppliedDiscount) -> bool {
+        let stacks = self.automatic_markdown.is_none()
+            || self.markdown_stacking == MarkdownStacking::Stack
+            || !matches!(
+                discount.discount_type,
+                DiscountType::Percentage
+                    | DiscountType::FixedAmount
+                    | DiscountType::FixedAmountOnItems
+            );
+        stacks
+            && discount.minimum_subtotal.is_none_or(|minimum| {
+                self.items.iter().map(|i| i.subtotal()).sum::<u64>() >= minimum
+            })
+    }
+
+    /// Computes the individual savings of each applied cart-level discount.
+    ///
+    /// Discounts are evaluated in the order they were applied, after any
+    /// automatic markdown; a discount can never save more than what remains
+    /// of the subtotal after earlier ones.
+    #[must_use]
+    pub fn applied_discount_breakdown(&self) -> Vec<(CouponCode, u64)> {
+        let subtotal: u64 = self.items.iter().map(|i| i.subtotal()).sum();
+        let item_discounts: u64 = self.items.iter().map(|i| i.total_discount()).sum();
+        let markdown = markdown_savings(
+            subtotal.saturating_sub(item_discounts),
+            self.automatic_markdown,
+        );
+        let mut remaining = subtotal.saturating_sub(item_discounts).saturating_sub(markdown);
+
+        self.discounts
+            .iter()
+            .map(|discount| {
+                if !self.coupon_applies(discount) {
+                    return (discount.code.clone(), 0);
+                }
+                let savings = match discount.discount_type {
+                    DiscountType::Percentage => {
+                        let base = coupon_base(&self.items, discount, self.automatic_markdown);
+                        ((base * discount.value) / 100).min(remaining)
+                    },
+                    DiscountType::FixedAmount => discount.value.min(remaining),
+                    DiscountType::FixedAmountOnItems => {
+                        let base = coupon_base(&self.items, discount, self.automatic_markdown);
+                        discount.value.min(base).min(remaining)
+                    },
+                    DiscountType::FreeShipping => {
+                        self.shipping_method.as_ref().map_or(0, |s| s.rate_for(self).amount)
+                    },
+                    DiscountType::BuyXGetY => {
+                        let base = coupon_base(&self.items, discount, self.automatic_markdown);
+                        let free: u64 = buy_x_get_y_savings(&self.items, discount).iter().sum();
+                        free.min(base).min(remaining)
+                    },
+                };
+                if discount.discount_type != DiscountType::FreeShipping {
+                    remaining -= savings;
+                }
+                (discount.code.clone(), savings)
+            })
+            .collect()
     }
 
     /// Adds an item to the cart.
     ///
-    /// If product already exists, increases quantity.
+    /// If the product already has a line without a variant, increases its
+    /// quantity. The product must be priced in the cart currency.
     pub fn add_item(&mut self, product: &Product, quantity: u32) -> Result<(), CommerceError> {
+        self.add_variant_item(product, None, quantity)
+    }
+
+    /// Adds a variant of a product to the cart.
+    ///
+    /// If the cart already has a line for the product and variant, increases
+    /// its quantity.
+    pub fn add_variant_item(
+        &mut self, product: &Product, variant_id: Option<&ProductId>, quantity: u32,
+    ) -> Result<(), CommerceError> {
         if quantity == 0 {
             return Err(CommerceError::InvalidQuantity);
         }
 
-        if !product.status.is_purchasable() {
-            return Err(CommerceError::ProductNotAvailable(product.id.0.to_string()));
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if !product.is_buyable(now) {
+            if !product.status.is_purchasable() {
+                return Err(CommerceError::ProductNotAvailable(product.id.0.to_string()));
+            }
+            if let Some(available_from) = product.available_from
+                && now < available_from
+            {
+                return Err(CommerceError::NotYetAvailable {
+                    product_id: product.id.0.to_string(),
+                    available_from,
+                });
+            }
+            // Otherwise out of stock, reported with quantities below
         }
 
-        // Check if product already in cart
-        if let Some(item) = self.items.iter_mut().find(|i| i.product_id == product.id) {
-            let new_qty = item.quantity.saturating_add(quantity);
+        let price = product.price_for_group(self.customer_group.as_deref());
+        if price.currency != self.currency {
+            return Err(CommerceError::CurrencyMismatch {
+                expected: self.currency.0.to_string(),
+                got:      price.currency.0.to_string(),
+            });
+        }
 
-            // Check inventory
-            if !product.backorders_allowed && (new_qty as i64) > product.inventory_quantity {
-                return Err(CommerceError::InsufficientInventory {
+        let existing = self
+            .items
+            .iter()
+            .find(|i| i.is_line_for(&product.id, variant_id))
+            .map_or(0, |i| i.quantity);
+        // The per-order cap covers every variant of the product
+        let product_in_cart: u32 = self
+            .items
+            .iter()
+            .filter(|i| i.product_id == product.id)
+            .fold(0, |sum, i| sum.saturating_add(i.quantity));
+        if quantity > product.max_purchasable(product_in_cart) {
+            let requested = product_in_cart.saturating_add(quantity);
+            if let Some(limit) = product.max_quantity_per_order
+                && requested > limit
+            {
+                return Err(CommerceError::QuantityLimitExceeded {
                     product_id: product.id.0.to_string(),
-                    available:  product.inventory_quantity as u32,
-                    requested:  new_qty,
+                    limit,
                 });
             }
-
-            item.set_quantity(new_qty);
-        } else {
-            // Check inventory for new item
-            if !product.backorders_allowed && (quantity as i64) > product.inventory_quantity {
+            return Err(CommerceError::InsufficientInventory {
+                product_id: product.id.0.to_string(),
+                available: u32::try_from(product.inventory_quantity.max(0)).unwrap_or(u32::MAX),
+                requested,
+            });
+        }
+        // A variant with its own stock is also checked against that stock
+        if let Some(variant) =
+            variant_id.and_then(|id| product.variants.iter().find(|v| &v.id == id))
+            && !product.backorders_allowed
+        {
+            let variant_in_cart: u32 = self
+                .items
+                .iter()
+                .filter(|i| i.is_line_for(&product.id, variant_id))
+                .fold(0, |sum, i| sum.saturating_add(i.quantity));
+            let available = u32::try_from(variant.inventory_count.max(0)).unwrap_or(u32::MAX);
+            let requested = variant_in_cart.saturating_add(quantity);
+            if requested > available {
                 return Err(CommerceError::InsufficientInventory {
-                    product_id: product.id.0.to_string(),
-                    available:  product.inventory_quantity as u32,
-                    requested:  quantity,
+                    product_id: variant.id.0.to_string(),
+                    available,
+                    requested,
                 });
             }
+        }
 
-            self.items.push(CartItem::from_product(product, quantity));
+        if let Some(item) = self.items.iter_mut().find(|i| i.is_line_for(&product.id, variant_id)) {
+            item.set_quantity(existing + quantity);
+        } else {
+            let mut item =
+                CartItem::from_product_for_group(product, quantity, self.customer_group.as_deref());
+            item.variant_id = variant_id.cloned();
+            if item.tax_rate.is_none() {
+                item.tax_rate = product
+                    .tax_class
+                    .as_ref()
+                    .and_then(|class| self.tax_class_rates.get(class))
+                    .copied();
+            }
+            self.items.push(item);
         }
 
         self.touch();
         Ok(())
     }
 
-    /// Updates item quantity.
+    /// Updates the quantity of the product's line without a variant.
     ///
     /// Removes item if quantity is 0.
     pub fn update_item_quantity(
         &mut self, product_id: &ProductId, quantity: u32,
+    ) -> Result<(), CommerceError> {
+        self.update_variant_quantity(product_id, None, quantity)
+    }
+
+    /// Updates the quantity of the line matching both product and variant.
+    ///
+    /// Removes item if quantity is 0.
+    pub fn update_variant_quantity(
+        &mut self, product_id: &ProductId, variant_id: Option<&ProductId>, quantity: u32,
     ) -> Result<(), CommerceError> {
         if quantity == 0 {
-            return self.remove_item(product_id);
+            return self.remove_variant_item(product_id, variant_id);
         }
 
         let item = self
             .items
             .iter_mut()
-            .find(|i| &i.product_id == product_id)
+            .find(|i| i.is_line_for(product_id, variant_id))
             .ok_or_else(|| CommerceError::ItemNotInCart(product_id.0.to_string()))?;
 
         item.set_quantity(quantity);
@@ -254,10 +700,17 @@ impl Cart {
         Ok(())
     }
 
-    /// Removes an item from the cart.
+    /// Removes the product's line without a variant from the cart.
     pub fn remove_item(&mut self, product_id: &ProductId) -> Result<(), CommerceError> {
+        self.remove_variant_item(product_id, None)
+    }
+
+    /// Removes the line matching both product and variant from the cart.
+    pub fn remove_variant_item(
+        &mut self, product_id: &ProductId, variant_id: Option<&ProductId>,
+    ) -> Result<(), CommerceError> {
         let initial_len = self.items.len();
-        self.items.retain(|i| &i.product_id != product_id);
+        self.items.retain(|i| !i.is_line_for(product_id, variant_id));
 
         if self.items.len() == initial_len {
             return Err(CommerceError::ItemNotInCart(product_id.0.to_string()));
@@ -275,23 +728,37 @@ impl Cart {
     }
 
     /// Applies a discount code.
+    ///
+    /// A code already applied as many times as its `max_uses_per_cart`
+    /// allows is rejected, as is a coupon whose minimum subtotal the current
+    /// items do not reach before discounts.
     pub fn apply_discount(&mut self, discount: AppliedDiscount) -> Result<(), CommerceError> {
-        // Check if already applied
-        if self.discounts.iter().any(|d| d.code.0 == discount.code.0) {
+        let uses = self.discounts.iter().filter(|d| d.code.matches(&discount.code.0)).count();
+        if uses >= discount.max_uses_per_cart.unwrap_or(1) as usize {
             return Err(CommerceError::DiscountAlreadyApplied(
                 discount.code.0.to_string(),
             ));
         }
 
+        if let Some(minimum) = discount.minimum_subtotal {
+            let subtotal: u64 = self.items.iter().map(|i| i.subtotal()).sum();
+            if subtotal < minimum {
+                return Err(CommerceError::DiscountNotEligible(format!(
+                    "{} requires a subtotal of at least {}, cart has {}",
+                    discount.code.0, minimum, subtotal
+                )));
+            }
+        }
+
         self.discounts.push(discount);
         self.touch();
         Ok(())
     }
 
-    /// Removes a discount code.
+    /// Removes a discount code, matched as [`CouponCode::matches`] does.
     pub fn remove_discount(&mut self, code: &str) -> Result<(), CommerceError> {
         let initial_len = self.discounts.len();
-        self.discounts.retain(|d| d.code.0 != code);
+        self.discounts.retain(|d| !d.code.matches(code));
 
         if self.discounts.len() == initial_len {
             return Err(CommerceError::DiscountNotFound(code.to_string()));
@@ -314,20 +781,92 @@ impl Cart {
     }
 
     /// Sets shipping method.
-    pub fn set_shipping_method(&mut self, method: ShippingMethod) {
+    ///
+    /// # Errors
+    /// Returns `CurrencyMismatch` if the method charges in another currency
+    /// than the cart's.
+    pub fn set_shipping_method(&mut self, method: ShippingMethod) -> Result<(), CommerceError> {
+        method.validate_currency(&self.currency)?;
         self.shipping_method = Some(method);
         self.touch();
+        Ok(())
+    }
+
+    /// Shipping methods that deliver to the cart's shipping address.
+    ///
+    /// Every method is offered until an address has been set.
+    #[must_use]
+    pub fn available_shipping_methods<'a>(
+        &self, methods: &'a [ShippingMethod],
+    ) -> Vec<&'a ShippingMethod> {
+        match &self.shipping_address {
+            Some(address) => methods.iter().filter(|m| m.ships_to(&address.country_code)).collect(),
+            None => methods.iter().collect(),
+        }
+    }
+
+    /// Sets the customer pricing group used for items added afterwards.
+    pub fn set_customer_group(&mut self, group: Option<String>) {
+        self.customer_group = group;
+        self.touch();
+    }
+
+    /// Sets the tax rate percentage.
+    pub fn set_tax_rate(&mut self, tax_rate: f64) {
+        self.tax_rate = tax_rate;
+        self.touch();
+    }
+
+    /// Sets the tax rate percentage of a product tax class, used for items
+    /// added afterwards.
+    pub fn set_tax_class_rate(&mut self, tax_class: impl Into<String>, tax_rate: f64) {
+        self.tax_class_rates.insert(tax_class.into(), tax_rate);
+        self.touch();
+    }
+
+    /// Sets the tax rounding strategy.
+    pub fn set_tax_rounding(&mut self, tax_rounding: TaxRounding) {
+        self.tax_rounding = tax_rounding;
+        self.touch();
+    }
+
+    /// Sets whether prices include tax.
+    pub fn set_tax_mode(&mut self, tax_mode: TaxMode) {
+        self.tax_mode = tax_mode;
+        self.touch();
+    }
+
+    /// Sets the automatic storewide markdown and how it stacks with coupons.
+    pub fn set_automatic_markdown(&mut self, percent: Option<u8>, stacking: MarkdownStacking) {
+        self.automatic_markdown = percent;
+        self.markdown_stacking = stacking;
+        self.touch();
     }
 
     /// Calculates cart totals.
+    ///
+    /// The result is cached until the cart is next mutated.
     #[must_use]
     pub fn calculate_totals(&self) -> CartTotals {
+        self.totals_cache.get_or_init(|| self.compute_totals()).clone()
+    }
+
+    /// Computes totals from scratch.
+    fn compute_totals(&self) -> CartTotals {
+        let discounts: Vec<AppliedDiscount> =
+            self.discounts.iter().filter(|d| self.coupon_applies(d)).cloned().collect();
+
         CartTotals::calculate(
             &self.items,
-            &self.discounts,
+            &discounts,
             self.shipping_method.as_ref(),
-            self.tax_rate,
-            self.currency.clone(),
+            &TotalsSettings {
+                tax_rate:           self.tax_rate,
+                tax_rounding:       self.tax_rounding,
+                tax_mode:           self.tax_mode,
+                automatic_markdown: self.automatic_markdown,
+                currency:           self.currency.clone(),
+            },
         )
     }
 
@@ -345,6 +884,78 @@ impl Cart {
         }
     }
 
+    /// Exports the cart's items, discount codes, and addresses as an opaque
+    /// token
+    /// for [`CartService::import_cart`](super::CartService::import_cart).
+    #[must_use]
+    pub fn export_token(&self) -> String {
+        super::token::encode(self)
+    }
+
+    /// Plans which locations would ship each product in the cart, without
+    /// reserving stock.
+    ///
+    /// Lines for the same product and variant are combined and allocated
+    /// with [`InventoryService::split_fulfillment`]; products keep the order
+    /// in which they first appear in the cart.
+    ///
+    /// # Errors
+    /// Returns `InsufficientInventory` if any product cannot be fully
+    /// allocated.
+    pub fn fulfillment_plan(
+        &self, inventory: &InventoryService,
+    ) -> Result<FulfillmentPlan, CommerceError> {
+        let mut quantities: Vec<(&ProductId, Option<&ProductId>, u32)> = Vec::new();
+        for item in &self.items {
+            match quantities
+                .iter_mut()
+                .find(|(id, variant_id, _)| item.is_line_for(id, *variant_id))
+            {
+                Some((_, _, quantity)) => *quantity = quantity.saturating_add(item.quantity),
+                None => {
+                    quantities.push((&item.product_id, item.variant_id.as_ref(), item.quantity))
+                },
+            }
+        }
+
+        quantities
+            .into_iter()
+            .map(|(product_id, variant_id, quantity)| {
+                let sources = inventory.split_fulfillment(
+                    product_id,
+                    variant_id,
+                    quantity,
+                    &FulfillmentType::Ship,
+                )?;
+                Ok((product_id.clone(), variant_id.cloned(), sources))
+            })
+            .collect()
+    }
+
+    /// Reserves stock for every line at `location_id` under `reference`.
+    ///
+    /// Bundle lines reserve each of their components. Either the whole cart
+    /// is reserved or nothing is; see
+    /// [`InventoryService::reserve_stock_batch`].
+    pub fn reserve_stock(
+        &self, inventory: &InventoryService, location_id: &LocationId, reference: &str,
+    ) -> Result<(), CommerceError> {
+        let requested: Vec<(InventoryKey, u32)> = self
+            .items
+            .iter()
+            .flat_map(|item| {
+                InventoryKey::for_line(
+                    &item.product_id,
+                    item.variant_id.as_ref(),
+                    &item.bundle_items,
+                    location_id,
+                    item.quantity,
+                )
+            })
+            .collect();
+        inventory.reserve_stock_batch(&requested, reference)
+    }
+
     /// Validates cart is ready for checkout.
     pub fn validate_for_checkout(&self) -> Result<(), CommerceError> {
         if self.is_empty() {
@@ -2,7 +2,7 @@
 
 use std::{borrow::Cow, collections::HashMap};
 
-use crate::types::product_catalog::{Price, Product, ProductId};
+use crate::types::product_catalog::{BundleComponent, CategoryId, Price, Product, ProductId};
 
 use super::types::AppliedDiscount;
 
@@ -19,16 +19,33 @@ pub struct CartItem {
     pub product_sku:    Cow<'static, str>,
     /// Product image URL (cached).
     pub image_url:      Option<Cow<'static, str>>,
+    /// Product categories (cached for scoped discounts).
+    pub categories:     Vec<CategoryId>,
     /// Quantity.
     pub quantity:       u32,
-    /// Unit price at time of adding.
+    /// Unit price at time of adding, for the current quantity.
     pub unit_price:     Price,
+    /// Unit price at time of adding before volume tiers (the sale or
+    /// customer group price).
+    pub catalog_price:  Price,
+    /// Volume tier prices by minimum quantity, used to reprice the item when
+    /// its quantity changes. Empty when a customer group price applies.
+    pub volume_prices:  Vec<(u32, Price)>,
     /// Original price (before any sale).
     pub original_price: Price,
     /// Applied item-level discounts.
     pub discounts:      Vec<AppliedDiscount>,
     /// Custom options selected.
     pub custom_options: HashMap<Cow<'static, str>, Cow<'static, str>>,
+    /// Whether the product ships as an oversize package.
+    pub oversize:       bool,
+    /// Unit weight in grams (cached for shipping rates).
+    pub weight_grams:   u32,
+    /// Components per unit (cached for bundles).
+    pub bundle_items:   Vec<BundleComponent>,
+    /// Tax rate percentage overriding the cart's rate for this line; zero
+    /// for products that are not taxable.
+    pub tax_rate:       Option<f64>,
     /// When item was added.
     pub added_at:       u64,
     /// When item was last updated.
@@ -39,25 +56,50 @@ impl CartItem {
     /// Creates a new cart item from a product.
     #[must_use]
     pub fn from_product(product: &Product, quantity: u32) -> Self {
+        Self::from_product_for_group(product, quantity, None)
+    }
+
+    /// Creates a new cart item priced for a customer group.
+    ///
+    /// Volume tiers apply unless the group has its own price.
+    #[must_use]
+    pub fn from_product_for_group(product: &Product, quantity: u32, group: Option<&str>) -> Self {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
 
-        Self {
+        let has_group_price = group.is_some_and(|g| product.tier_prices.contains_key(g));
+        let volume_prices = if has_group_price {
+            Vec::new()
+        } else {
+            product.quantity_tiers.clone()
+        };
+        let catalog_price = product.price_for_group(group).clone();
+
+        let mut item = Self {
             product_id: product.id.clone(),
             variant_id: None,
             product_name: Cow::Owned(product.name.clone()),
             product_sku: Cow::Owned(product.sku.0.to_string()),
             image_url: product.primary_image().map(|img| Cow::Owned(img.url.clone())),
+            categories: product.categories.clone(),
             quantity,
-            unit_price: product.effective_price().clone(),
+            unit_price: catalog_price.clone(),
+            catalog_price,
+            volume_prices,
             original_price: product.price.clone(),
             discounts: Vec::new(),
             custom_options: HashMap::new(),
+            oversize: product.dimensions.as_ref().is_some_and(|d| d.is_oversize()),
+            weight_grams: product.dimensions.as_ref().map_or(0, |d| d.weight_grams),
+            bundle_items: product.bundle_items.clone(),
+            tax_rate: (!product.is_taxable).then_some(0.0),
             added_at: now,
             updated_at: now,
-        }
+        };
+        item.reprice();
+        item
     }
 
     /// Calculates line total before discounts.
@@ -78,28 +120,67 @@ impl CartItem {
         self.subtotal().saturating_sub(self.total_discount())
     }
 
-    /// Whether item is on sale.
+    /// Whether item is on sale. Volume tier prices are not sales.
     #[must_use]
     pub fn is_on_sale(&self) -> bool {
-        self.unit_price.amount < self.original_price.amount
+        self.catalog_price.amount < self.original_price.amount
     }
 
     /// Calculates savings from sale price.
     #[must_use]
     pub fn sale_savings(&self) -> u64 {
         if self.is_on_sale() {
-            (self.original_price.amount - self.unit_price.amount) * u64::from(self.quantity)
+            (self.original_price.amount - self.catalog_price.amount) * u64::from(self.quantity)
         } else {
             0
         }
     }
 
+    /// Whether this is the line for the product and variant.
+    #[must_use]
+    pub(crate) fn is_line_for(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>,
+    ) -> bool {
+        &self.product_id == product_id && self.variant_id.as_ref() == variant_id
+    }
+
+    /// Folds another line for the same product into this one.
+    ///
+    /// Quantities are summed. The price and any conflicting custom options
+    /// come from whichever line was updated more recently, with ties going
+    /// to `self`; options set on only one line are kept.
+    pub fn merge_from(&mut self, other: CartItem) {
+        self.quantity = self.quantity.saturating_add(other.quantity);
+
+        if other.updated_at > self.updated_at {
+            self.unit_price = other.unit_price;
+            self.catalog_price = other.catalog_price;
+            self.volume_prices = other.volume_prices;
+            self.original_price = other.original_price;
+            self.updated_at = other.updated_at;
+            self.custom_options.extend(other.custom_options);
+        } else {
+            for (key, value) in other.custom_options {
+                self.custom_options.entry(key).or_insert(value);
+            }
+        }
+        self.reprice();
+    }
+
     /// Updates quantity.
     pub fn set_quantity(&mut self, quantity: u32) {
         self.quantity = quantity;
+        self.reprice();
         self.updated_at = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
     }
+
+    /// Sets the unit price from the volume tier matching the quantity,
+    /// unless the catalog price is cheaper.
+    fn reprice(&mut self) {
+        self.unit_price =
+            Product::volume_price(&self.catalog_price, &self.volume_prices, self.quantity).clone();
+    }
 }
@@ -6,18 +6,32 @@ mod cart;
 mod item;
 mod service;
 mod shipping;
+mod token;
 mod types;
 
-pub use cart::{Cart, CartTotals};
+pub use cart::{Cart, CartTotals, FulfillmentPlan, TotalsSettings};
 pub use item::CartItem;
 pub use service::CartService;
-pub use shipping::{ShippingAddress, ShippingMethod};
-pub use types::{AppliedDiscount, CartId, CartStatus, CouponCode, CustomerId, DiscountType};
+pub use shipping::{ShippingAddress, ShippingMethod, ShippingRate};
+pub use types::{
+    AppliedDiscount, CartId, CartStatus, Coupon, CouponCode, CustomerId, DiscountScope,
+    DiscountType, MarkdownStacking, TaxMode, TaxRounding,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::product_catalog::{Currency, Price, Product, ProductId, ProductStatus, Sku};
+    use crate::{
+        errors::CommerceError,
+        implementation::product_catalog::service::ProductCatalog,
+        types::{
+            inventory_sync::{InventoryLocation, InventoryService, LocationId},
+            product_catalog::{
+                CategoryId, Currency, Price, Product, ProductDimensions, ProductId, ProductStatus,
+                ProductVariant, Sku,
+            },
+        },
+    };
 
     fn create_test_product(id: &str, price: u64) -> Product {
         let mut product = Product::new(
@@ -64,6 +78,82 @@ mod tests {
         assert_eq!(cart.total_quantity(), 5);
     }
 
+    #[test]
+    fn test_add_item_respects_per_order_limit() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let mut product = create_test_product("001", 1000);
+        product.max_quantity_per_order = Some(3);
+
+        cart.add_item(&product, 2).expect("add within limit");
+        let err = cart.add_item(&product, 2).expect_err("should exceed limit");
+
+        assert!(matches!(err, CommerceError::QuantityLimitExceeded {
+            limit: 3,
+            ..
+        }));
+        assert_eq!(cart.total_quantity(), 2);
+
+        // Other variants of the product count toward the same cap
+        let large = ProductId::new("001-L");
+        cart.add_variant_item(&product, Some(&large), 1)
+            .expect("add variant within limit");
+        let err = cart
+            .add_variant_item(&product, Some(&large), 1)
+            .expect_err("variants share the limit");
+        assert!(matches!(err, CommerceError::QuantityLimitExceeded {
+            limit: 3,
+            ..
+        }));
+        assert_eq!(cart.total_quantity(), 3);
+    }
+
+    #[test]
+    fn test_add_variant_item_checks_stock_across_lines() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let mut product = create_test_product("001", 1000);
+        product.inventory_quantity = 5;
+        let small = ProductId::new("001-S");
+        let mut variant = ProductVariant::new(small.clone(), product.id.clone(), Sku::new("S"));
+        variant.inventory_count = 3;
+        product.variants.push(variant);
+
+        // The variant's own stock
+        cart.add_variant_item(&product, Some(&small), 2)
+            .expect("add within variant stock");
+        let err = cart
+            .add_variant_item(&product, Some(&small), 2)
+            .expect_err("variant stock exceeded");
+        assert!(matches!(err, CommerceError::InsufficientInventory {
+            available: 3,
+            requested: 4,
+            ..
+        }));
+
+        // The product's stock is shared by all of its lines
+        cart.add_item(&product, 2).expect("add within product stock");
+        let large = ProductId::new("001-L");
+        let err = cart
+            .add_variant_item(&product, Some(&large), 2)
+            .expect_err("product stock exceeded");
+        assert!(matches!(err, CommerceError::InsufficientInventory {
+            available: 5,
+            requested: 6,
+            ..
+        }));
+        assert_eq!(cart.total_quantity(), 4);
+    }
+
+    #[test]
+    fn test_default_totals_have_currency() {
+        let totals = CartTotals::default();
+        assert_eq!(totals.currency, Currency::ess());
+        assert_eq!(totals.grand_total, 0);
+
+        let totals = CartTotals::empty(Currency::usd());
+        assert_eq!(totals.currency.0, "USD");
+        assert_eq!(totals.item_count, 0);
+    }
+
     #[test]
     fn test_remove_item() {
         let mut cart = Cart::new(CustomerId::new("customer-1"));
@@ -75,6 +165,33 @@ mod tests {
         assert!(cart.is_empty());
     }
 
+    #[test]
+    fn test_remove_and_update_target_single_variant() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let product = create_test_product("001", 1000);
+        let small = ProductId::new("001-S");
+        let large = ProductId::new("001-L");
+        for (variant, quantity) in [(&small, 1), (&large, 2)] {
+            let mut item = CartItem::from_product(&product, quantity);
+            item.variant_id = Some(variant.clone());
+            cart.items_mut().push(item);
+        }
+
+        // Variant lines are not the product's plain line
+        assert!(matches!(
+            cart.remove_item(&product.id),
+            Err(CommerceError::ItemNotInCart(_))
+        ));
+
+        cart.update_variant_quantity(&product.id, Some(&large), 4)
+            .expect("update large");
+        cart.remove_variant_item(&product.id, Some(&small)).expect("remove small");
+
+        assert_eq!(cart.unique_item_count(), 1);
+        assert_eq!(cart.items()[0].variant_id.as_ref(), Some(&large));
+        assert_eq!(cart.items()[0].quantity, 4);
+    }
+
     #[test]
     fn test_update_quantity() {
         let mut cart = Cart::new(CustomerId::new("customer-1"));
@@ -89,7 +206,7 @@ mod tests {
     #[test]
     fn test_calculate_totals() {
         let mut cart = Cart::new(CustomerId::new("customer-1"));
-        cart.tax_rate = 10.0;
+        cart.set_tax_rate(10.0);
 
         let product1 = create_test_product("001", 1000);
         let product2 = create_test_product("002", 2000);
@@ -104,13 +221,146 @@ mod tests {
         assert_eq!(totals.item_count, 3);
     }
 
+    #[test]
+    fn test_tax_rounding_modes() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.set_tax_rate(10.0);
+        cart.add_item(&create_test_product("001", 105), 1).expect("add");
+
+        // 10% of 105 is exactly 10.5
+        assert_eq!(cart.calculate_totals().tax_total, 10);
+
+        cart.set_tax_rounding(TaxRounding::HalfUp);
+        assert_eq!(cart.calculate_totals().tax_total, 11);
+
+        cart.set_tax_rounding(TaxRounding::Bankers);
+        assert_eq!(cart.calculate_totals().tax_total, 10);
+
+        // 10% of 115 is exactly 11.5, which rounds to the even 12
+        cart.add_item(&create_test_product("002", 10), 1).expect("add");
+        assert_eq!(cart.calculate_totals().tax_total, 12);
+    }
+
+    #[test]
+    fn test_item_tax_rates_override_cart_rate() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.set_tax_rate(8.25);
+        let mut groceries = create_test_product("groceries", 1000);
+        groceries.is_taxable = false;
+        cart.add_item(&groceries, 1).expect("add");
+        cart.add_item(&create_test_product("electronics", 2000), 1).expect("add");
+        assert_eq!(cart.items()[0].tax_rate, Some(0.0));
+        assert_eq!(cart.items()[1].tax_rate, None);
+
+        // Only the electronics are taxed: 8.25% of 2000
+        assert_eq!(cart.calculate_totals().tax_total, 165);
+
+        // A cart-wide coupon is shared by both lines: 8.25% of 1800
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10% off",
+        ))
+        .expect("apply discount");
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.tax_total, 148);
+        assert_eq!(totals.grand_total, 2700 + 148);
+    }
+
+    #[test]
+    fn test_tax_class_rate_applies_to_added_items() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.set_tax_rate(20.0);
+        cart.set_tax_class_rate("reduced", 5.0);
+        let mut book = create_test_product("book", 2000);
+        book.tax_class = Some("reduced".to_string());
+        cart.add_item(&book, 1).expect("add");
+        cart.add_item(&create_test_product("lamp", 1000), 1).expect("add");
+
+        assert_eq!(cart.items()[0].tax_rate, Some(5.0));
+        // 5% of 2000 plus 20% of 1000
+        assert_eq!(cart.calculate_totals().tax_total, 100 + 200);
+    }
+
+    #[test]
+    fn test_tax_inclusive_mode_backs_out_tax() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.set_tax_rate(20.0);
+        cart.set_tax_mode(TaxMode::Inclusive);
+        cart.add_item(&create_test_product("001", 6000), 2).expect("add");
+
+        // 12000 gross at 20% is 10000 net plus 2000 VAT
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.subtotal, 12000);
+        assert_eq!(totals.tax_total, 2000);
+        assert_eq!(totals.grand_total, 12000);
+
+        // Rounding leaves the remainder as net, so nothing drifts
+        cart.add_item(&create_test_product("002", 1), 1).expect("add");
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.tax_total, 2000); // 12001 / 6 truncated
+        assert_eq!(totals.grand_total, 12001);
+
+        cart.set_tax_mode(TaxMode::Exclusive);
+        assert_eq!(cart.calculate_totals().grand_total, 12001 + 2400);
+    }
+
+    #[test]
+    fn test_minimum_subtotal_coupon_requires_enough_items() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let product = create_test_product("001", 500);
+        let coupon = AppliedDiscount::fixed_amount(CouponCode::new("TENOFF"), 1000, "$10 off $100")
+            .with_minimum_subtotal(10000);
+
+        cart.add_item(&product, 1).expect("add");
+        assert!(matches!(
+            cart.apply_discount(coupon.clone()),
+            Err(CommerceError::DiscountNotEligible(_))
+        ));
+        assert!(cart.discounts().is_empty());
+
+        cart.update_item_quantity(&product.id, 20).expect("update");
+        cart.apply_discount(coupon).expect("apply once eligible");
+        assert_eq!(cart.calculate_totals().discount_total, 1000);
+
+        // Dropping below the minimum later stops the coupon applying
+        cart.update_item_quantity(&product.id, 19).expect("update");
+        assert_eq!(cart.calculate_totals().discount_total, 0);
+    }
+
+    #[test]
+    fn test_buy_two_get_one_frees_cheapest_unit() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("socks", 1000), 3).expect("add");
+        cart.add_item(&create_test_product("boots", 5000), 1).expect("add");
+        cart.set_tax_rate(10.0);
+        cart.items_mut()[0].tax_rate = Some(0.0);
+        cart.apply_discount(AppliedDiscount::buy_x_get_y(
+            CouponCode::new("B2G1"),
+            2,
+            1,
+            "Buy 2 get 1 free",
+        ))
+        .expect("apply discount");
+
+        // Four units make one full group of three; the cheapest unit is free
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.discount_total, 1000);
+        assert_eq!(totals.total_savings, 1000);
+        assert_eq!(cart.discounts()[0].savings, 1000);
+
+        // The free sock is tax exempt, so the boots keep their full tax
+        assert_eq!(totals.tax_total, 500);
+        assert_eq!(totals.grand_total, 8000 - 1000 + 500);
+    }
+
     #[test]
     fn test_apply_discount() {
         let mut cart = Cart::new(CustomerId::new("customer-1"));
         let discount = AppliedDiscount::percentage(CouponCode::new("SAVE10"), 10, "10% off");
 
         cart.apply_discount(discount).expect("apply discount");
-        assert_eq!(cart.discounts.len(), 1);
+        assert_eq!(cart.discounts().len(), 1);
     }
 
     #[test]
@@ -160,4 +410,978 @@ mod tests {
         ));
         assert!(cart.validate_for_checkout().is_ok());
     }
+
+    #[test]
+    fn test_applied_discount_breakdown() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let product = create_test_product("001", 5000);
+        cart.add_item(&product, 2).expect("add");
+
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10% off",
+        ))
+        .expect("apply percentage");
+        cart.apply_discount(AppliedDiscount::fixed_amount(
+            CouponCode::new("FIVEOFF"),
+            500,
+            "5.00 off",
+        ))
+        .expect("apply fixed");
+
+        let breakdown = cart.applied_discount_breakdown();
+        assert_eq!(breakdown.len(), 2);
+        assert_eq!(breakdown[0].0.0, "SAVE10");
+        assert_eq!(breakdown[0].1, 1000); // 10% of 10000
+        assert_eq!(breakdown[1].0.0, "FIVEOFF");
+        assert_eq!(breakdown[1].1, 500);
+
+        // Savings are back-filled onto the applied discounts
+        assert_eq!(cart.discounts()[0].savings, 1000);
+        assert_eq!(cart.discounts()[1].savings, 500);
+        assert_eq!(cart.calculate_totals().discount_total, 1500);
+    }
+
+    #[test]
+    fn test_expire_stale_carts() {
+        let service = CartService::new();
+        let mut cart = service.create_cart(CustomerId::new("customer-1")).expect("create");
+        service.create_cart(CustomerId::new("customer-2")).expect("create");
+
+        cart.expires_at = Some(0);
+        service.update_cart(cart.clone()).expect("update");
+
+        assert_eq!(service.expire_stale_carts().expect("sweep"), 1);
+        assert_eq!(
+            service.get_cart(&cart.id).expect("get").status,
+            CartStatus::Expired
+        );
+        assert_eq!(service.expire_stale_carts().expect("sweep again"), 0);
+    }
+
+    #[test]
+    fn test_generate_recovery_coupon() {
+        let service = CartService::new();
+        let customer = CustomerId::new("customer-1");
+        let mut cart = service.create_cart(customer.clone()).expect("create");
+        cart.status = CartStatus::Abandoned;
+        service.update_cart(cart.clone()).expect("update");
+
+        let coupon = service.generate_recovery_coupon(&cart.id, 15, 3600).expect("generate");
+        assert_eq!(coupon.discount.value, 15);
+        assert_eq!(coupon.cart_id.as_ref(), Some(&cart.id));
+        let recorded = service.get_coupon(&coupon.code().0.to_lowercase()).expect("recorded");
+        assert_eq!(recorded.expires_at, coupon.expires_at);
+
+        let issued_at = coupon.expires_at - 3600;
+        assert!(coupon.validate_for(&customer, issued_at).is_ok());
+        assert!(matches!(
+            coupon.validate_for(&CustomerId::new("customer-2"), issued_at),
+            Err(CommerceError::DiscountNotEligible(_))
+        ));
+        assert!(coupon.validate_for(&customer, coupon.expires_at - 1).is_ok());
+        assert!(matches!(
+            coupon.validate_for(&customer, coupon.expires_at),
+            Err(CommerceError::DiscountNotEligible(_))
+        ));
+
+        assert!(matches!(
+            service.generate_recovery_coupon(&cart.id, 0, 3600),
+            Err(CommerceError::ValidationError(_))
+        ));
+        service.mark_as_converted(&cart.id).expect("convert");
+        assert!(service.generate_recovery_coupon(&cart.id, 15, 3600).is_err());
+    }
+
+    #[test]
+    fn test_recovery_coupon_codes_are_unguessable_and_replaced() {
+        let service = CartService::new();
+        let cart = service.create_cart(CustomerId::new("customer-1")).expect("create");
+
+        let first = service.generate_recovery_coupon(&cart.id, 15, 3600).expect("generate");
+        let second = service.generate_recovery_coupon(&cart.id, 15, 3600).expect("generate");
+        assert!(!first.code().0.contains(cart.id.0.as_ref()));
+        assert_ne!(first.code().0, second.code().0);
+        assert!(matches!(
+            service.get_coupon(&first.code().0),
+            Err(CommerceError::DiscountNotFound(_))
+        ));
+        assert!(service.get_coupon(&second.code().0).is_ok());
+    }
+
+    #[test]
+    fn test_redeem_recovery_coupon_once() {
+        let service = CartService::new();
+        let customer = CustomerId::new("customer-1");
+        let cart = service.create_cart(customer.clone()).expect("create");
+        let coupon = service.generate_recovery_coupon(&cart.id, 15, 3600).expect("generate");
+
+        let other = service.create_cart(CustomerId::new("customer-2")).expect("create");
+        assert!(matches!(
+            service.redeem_coupon(&other.id, &coupon.code().0),
+            Err(CommerceError::DiscountNotEligible(_))
+        ));
+        assert!(matches!(
+            service.redeem_coupon(&cart.id, "RECOVER-GUESS"),
+            Err(CommerceError::DiscountNotFound(_))
+        ));
+
+        let redeemed = service.redeem_coupon(&cart.id, &coupon.code().0).expect("redeem");
+        assert_eq!(redeemed.discounts().len(), 1);
+        assert_eq!(
+            service.get_cart(&cart.id).expect("cart").discounts().len(),
+            1
+        );
+        assert_eq!(
+            service.get_coupon(&coupon.code().0).expect("coupon").uses_remaining,
+            0
+        );
+
+        // A fresh cart of the same customer cannot reuse it
+        let next = service.create_cart(customer).expect("create");
+        assert!(matches!(
+            service.redeem_coupon(&next.id, &coupon.code().0),
+            Err(CommerceError::DiscountNotEligible(_))
+        ));
+    }
+
+    #[test]
+    fn test_automatic_markdown_alone() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 10000), 1).expect("add");
+        cart.set_automatic_markdown(Some(20), MarkdownStacking::Stack);
+
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.discount_total, 2000);
+        assert_eq!(totals.total_savings, 2000);
+        assert_eq!(totals.grand_total, 8000);
+    }
+
+    #[test]
+    fn test_automatic_markdown_stacking_with_coupon() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 10000), 1).expect("add");
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10% off",
+        ))
+        .expect("apply coupon");
+        cart.set_automatic_markdown(Some(20), MarkdownStacking::Stack);
+
+        // Markdown first (2000), then the coupon on the marked-down 8000 (800)
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.discount_total, 2800);
+        assert_eq!(totals.grand_total, 7200);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 800);
+
+        cart.set_automatic_markdown(Some(20), MarkdownStacking::Exclusive);
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.discount_total, 2000);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 0);
+    }
+
+    #[test]
+    fn test_totals_cache_is_invalidated_by_mutations() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let product = create_test_product("001", 1000);
+        cart.add_item(&product, 1).expect("add");
+
+        let first = cart.calculate_totals();
+        let second = cart.calculate_totals();
+        assert_eq!(first.grand_total, second.grand_total);
+        assert_eq!(first.item_count, second.item_count);
+
+        cart.add_item(&product, 2).expect("add more");
+        let updated = cart.calculate_totals();
+        assert_eq!(updated.subtotal, 3000);
+        assert_eq!(updated.item_count, 3);
+
+        // Setters and mutable item access both clear the cache
+        cart.set_tax_rate(10.0);
+        assert_eq!(cart.calculate_totals().tax_total, 300);
+        cart.items_mut()[0].quantity = 1;
+        assert_eq!(cart.calculate_totals().subtotal, 1000);
+    }
+
+    #[test]
+    fn test_free_shipping_threshold_keeps_oversize_surcharge() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let regular = create_test_product("001", 6000);
+        let mut bulky = create_test_product("002", 5000);
+        bulky.dimensions = Some(ProductDimensions::new(180.0, 60.0, 40.0, 12_000));
+        cart.add_item(&regular, 1).expect("add regular");
+        cart.add_item(&bulky, 2).expect("add bulky");
+
+        let mut method =
+            ShippingMethod::new("std", "Standard", Price::new(800, Currency::usd(), 2));
+        method.free_threshold = Some(10_000);
+        method.oversize_surcharge = 1500;
+        cart.set_shipping_method(method).expect("set shipping");
+
+        // Subtotal 16000 is over the threshold: base is waived, 2 oversize units are
+        // not
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.base_shipping, 0);
+        assert_eq!(totals.surcharge, 3000);
+        assert_eq!(totals.shipping_total, 3000);
+        assert_eq!(totals.grand_total, 19_000);
+
+        cart.remove_item(&regular.id).expect("remove regular");
+        cart.update_item_quantity(&bulky.id, 1).expect("update bulky");
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.base_shipping, 800);
+        assert_eq!(totals.surcharge, 1500);
+    }
+
+    #[test]
+    fn test_free_over_rate_crossing_threshold() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let product = create_test_product("001", 3000);
+        cart.add_item(&product, 1).expect("add");
+
+        let method = ShippingMethod::new("std", "Standard", Price::new(800, Currency::usd(), 2))
+            .with_rate(ShippingRate::FreeOver {
+                threshold: 5000,
+                fallback:  Price::new(600, Currency::usd(), 2),
+            });
+        cart.set_shipping_method(method.clone()).expect("set shipping");
+
+        // 3000 is under the threshold: the fallback applies, not the static cost
+        assert_eq!(method.rate_for(&cart).amount, 600);
+        assert_eq!(cart.calculate_totals().shipping_total, 600);
+
+        cart.update_item_quantity(&product.id, 2).expect("update");
+        assert_eq!(method.rate_for(&cart).amount, 0);
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.shipping_total, 0);
+        assert_eq!(totals.grand_total, 6000);
+    }
+
+    #[test]
+    fn test_free_threshold_applies_to_rate_for() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 3000), 2).expect("add");
+
+        // The lower of the two thresholds wins
+        let mut method =
+            ShippingMethod::new("std", "Standard", Price::new(800, Currency::usd(), 2)).with_rate(
+                ShippingRate::FreeOver {
+                    threshold: 10_000,
+                    fallback:  Price::new(600, Currency::usd(), 2),
+                },
+            );
+        method.free_threshold = Some(5000);
+        assert_eq!(method.free_shipping_threshold(), Some(5000));
+        assert_eq!(method.rate_for(&cart).amount, 0);
+        cart.set_shipping_method(method).expect("set shipping");
+        assert_eq!(cart.calculate_totals().base_shipping, 0);
+    }
+
+    #[test]
+    fn test_shipping_method_must_match_cart_currency() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let method = ShippingMethod::new("std", "Standard", Price::new(800, Currency::usd(), 2))
+            .with_rate(ShippingRate::Flat(Price::new(700, Currency::new("EUR"), 2)));
+
+        assert!(matches!(
+            cart.set_shipping_method(method),
+            Err(CommerceError::CurrencyMismatch { .. })
+        ));
+        assert!(cart.shipping_method().is_none());
+    }
+
+    #[test]
+    fn test_per_kg_rate_rounds_weight_up() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let mut product = create_test_product("001", 1000);
+        product.dimensions = Some(ProductDimensions::new(10.0, 10.0, 10.0, 700));
+        cart.add_item(&product, 3).expect("add");
+
+        cart.set_shipping_method(
+            ShippingMethod::new("std", "Standard", Price::new(800, Currency::usd(), 2)).with_rate(
+                ShippingRate::PerKg { base: Price::new(300, Currency::usd(), 2), per_kg: 150 },
+            ),
+        )
+        .expect("set shipping");
+
+        // 2100 g bills as 3 kg
+        assert_eq!(cart.calculate_totals().base_shipping, 300 + 3 * 150);
+    }
+
+    #[test]
+    fn test_category_scoped_percentage_coupon() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let mut shirt = create_test_product("001", 4000);
+        shirt.categories.push(CategoryId::new("apparel"));
+        let mut mug = create_test_product("002", 1500);
+        mug.categories.push(CategoryId::new("kitchen"));
+        cart.add_item(&shirt, 1).expect("add shirt");
+        cart.add_item(&mug, 2).expect("add mug");
+
+        cart.apply_discount(
+            AppliedDiscount::percentage(CouponCode::new("APPAREL20"), 20, "20% off apparel")
+                .with_scope(DiscountScope::Categories(vec![CategoryId::new("apparel")])),
+        )
+        .expect("apply coupon");
+
+        // Only the 4000 apparel line is discounted
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.subtotal, 7000);
+        assert_eq!(totals.discount_total, 800);
+        assert_eq!(totals.grand_total, 6200);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 800);
+    }
+
+    #[test]
+    fn test_customer_group_uses_tier_price() {
+        let mut product = create_test_product("001", 1000);
+        product
+            .tier_prices
+            .insert("wholesale".to_string(), Price::new(700, Currency::usd(), 2));
+
+        let mut wholesale = Cart::new(CustomerId::new("customer-1"));
+        wholesale.set_customer_group(Some("wholesale".to_string()));
+        wholesale.add_item(&product, 2).expect("add wholesale");
+        assert_eq!(wholesale.calculate_totals().subtotal, 1400);
+
+        let mut retail = Cart::new(CustomerId::new("customer-2"));
+        retail.set_customer_group(Some("retail".to_string()));
+        retail.add_item(&product, 2).expect("add retail");
+        assert_eq!(retail.calculate_totals().subtotal, 2000);
+    }
+
+    #[test]
+    fn test_coupon_excluding_sale_items() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let mut on_sale = create_test_product("001", 5000);
+        on_sale.sale_price = Some(Price::new(4000, Currency::usd(), 2));
+        let full_price = create_test_product("002", 3000);
+        cart.add_item(&on_sale, 1).expect("add sale item");
+        cart.add_item(&full_price, 1).expect("add full-price item");
+
+        let percentage = CouponCode::new("TENOFF");
+        cart.apply_discount(
+            AppliedDiscount::percentage(percentage.clone(), 10, "10% off").excluding_sale_items(),
+        )
+        .expect("apply percentage");
+
+        // Only the 3000 full-price line is discounted
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.subtotal, 7000);
+        assert_eq!(totals.discount_total, 300);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 300);
+
+        // A fixed amount off the items is capped at the eligible lines
+        cart.remove_discount(&percentage.0).expect("remove percentage");
+        let on_items = CouponCode::new("BIG");
+        cart.apply_discount(
+            AppliedDiscount::fixed_amount_on_items(on_items.clone(), 5000, "50 off")
+                .excluding_sale_items(),
+        )
+        .expect("apply fixed on items");
+        assert_eq!(cart.calculate_totals().discount_total, 3000);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 3000);
+
+        // A fixed amount off the cart still applies in full
+        cart.remove_discount(&on_items.0).expect("remove fixed on items");
+        cart.apply_discount(
+            AppliedDiscount::fixed_amount(CouponCode::new("CART50"), 5000, "50 off")
+                .excluding_sale_items(),
+        )
+        .expect("apply fixed");
+        assert_eq!(cart.calculate_totals().discount_total, 5000);
+        assert_eq!(cart.applied_discount_breakdown()[0].1, 5000);
+    }
+
+    #[test]
+    fn test_add_item_rejects_other_currency() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 1000), 1).expect("usd product");
+
+        let mut ess_product = create_test_product("002", 1000);
+        ess_product.price = Price::ess(1000);
+        assert!(matches!(
+            cart.add_item(&ess_product, 1),
+            Err(CommerceError::CurrencyMismatch { .. })
+        ));
+        assert_eq!(cart.unique_item_count(), 1);
+
+        let mut ess_cart = Cart::with_currency(CustomerId::new("customer-2"), Currency::ess());
+        ess_cart.add_item(&ess_product, 2).expect("ess product");
+        let totals = ess_cart.calculate_totals();
+        assert_eq!(totals.currency, Currency::ess());
+        assert_eq!(totals.subtotal, 2000);
+    }
+
+    #[test]
+    fn test_available_shipping_methods_filter_by_country() {
+        let mut domestic =
+            ShippingMethod::new("domestic", "Domestic", Price::new(500, Currency::usd(), 2));
+        domestic.allowed_countries = Some(["US".to_string()].into_iter().collect());
+        let worldwide = ShippingMethod::new(
+            "worldwide",
+            "Worldwide",
+            Price::new(2500, Currency::usd(), 2),
+        );
+        let methods = vec![domestic, worldwide];
+
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        assert_eq!(cart.available_shipping_methods(&methods).len(), 2);
+
+        cart.set_shipping_address(ShippingAddress::new(
+            "Jane", "Doe", "1 Rue", "Paris", "IDF", "75001", "FR",
+        ));
+        let available = cart.available_shipping_methods(&methods);
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].id, "worldwide");
+
+        cart.set_shipping_address(ShippingAddress::new(
+            "John",
+            "Doe",
+            "123 Main St",
+            "City",
+            "State",
+            "12345",
+            "us",
+        ));
+        assert_eq!(cart.available_shipping_methods(&methods).len(), 2);
+
+        let mut no_fr = methods[1].clone();
+        no_fr.excluded_countries = Some(["FR".to_string()].into_iter().collect());
+        assert!(no_fr.ships_to("US"));
+        assert!(!no_fr.ships_to("fr"));
+    }
+
+    #[test]
+    fn test_export_token_round_trip() {
+        let catalog = ProductCatalog::new();
+        let mut product = create_test_product("001", 1500);
+        product.categories.push(CategoryId::new("books"));
+        catalog.add_product(product.clone()).expect("add product");
+
+        let mut cart = Cart::new(CustomerId::new("mobile"));
+        cart.add_item(&product, 2).expect("add");
+        cart.items_mut()[0]
+            .custom_options
+            .insert("gift wrap".into(), "yes, please\tnow".into());
+        let books10 = AppliedDiscount::percentage(CouponCode::new("BOOKS10"), 10, "10% off, books")
+            .with_scope(DiscountScope::Categories(vec![CategoryId::new("books")]))
+            .excluding_sale_items();
+        cart.apply_discount(books10.clone()).expect("apply");
+        let mut address =
+            ShippingAddress::new("Jane", "Doe", "1 Main St", "City", "State", "12345", "US");
+        address.phone = Some("555-0100".into());
+        cart.set_shipping_address(address);
+
+        let service = CartService::new();
+        let token = cart.export_token();
+        let imported = service
+            .import_cart(&token, CustomerId::new("desktop"), &catalog, |code| {
+                (code == "BOOKS10").then(|| books10.clone())
+            })
+            .expect("import");
+
+        assert_ne!(imported.id, cart.id);
+        assert_eq!(imported.customer_id, CustomerId::new("desktop"));
+        assert_eq!(imported.total_quantity(), 2);
+        assert_eq!(
+            imported.items()[0].custom_options.get("gift wrap").map(|v| v.as_ref()),
+            Some("yes, please\tnow")
+        );
+        assert_eq!(imported.discounts().len(), 1);
+        assert_eq!(imported.discounts()[0].description, "10% off, books");
+        assert!(!imported.discounts()[0].apply_to_sale_items);
+        assert_eq!(imported.discounts()[0].scope, cart.discounts()[0].scope);
+        let shipping = imported.shipping_address.as_ref().expect("address");
+        assert_eq!(shipping.phone.as_deref(), Some("555-0100"));
+        assert!(shipping.company.is_none());
+        assert_eq!(
+            imported.calculate_totals().grand_total,
+            cart.calculate_totals().grand_total
+        );
+        assert_eq!(
+            service
+                .get_customer_cart(&CustomerId::new("desktop"))
+                .expect("lookup")
+                .map(|c| c.id),
+            Some(imported.id)
+        );
+
+        assert!(matches!(
+            service.import_cart("not a token!", CustomerId::new("desktop"), &catalog, |_| {
+                None
+            }),
+            Err(CommerceError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_cart_drops_out_of_stock_items() {
+        let catalog = ProductCatalog::new();
+        let in_stock = create_test_product("001", 1000);
+        let mut selling_out = create_test_product("002", 2000);
+        catalog.add_product(in_stock.clone()).expect("add");
+        catalog.add_product(selling_out.clone()).expect("add");
+
+        let mut cart = Cart::new(CustomerId::new("mobile"));
+        cart.add_item(&in_stock, 1).expect("add");
+        cart.add_item(&selling_out, 3).expect("add");
+        let token = cart.export_token();
+
+        selling_out.inventory_quantity = 0;
+        catalog.update_product(selling_out).expect("update");
+
+        let imported = CartService::new()
+            .import_cart(&token, CustomerId::new("desktop"), &catalog, |_| None)
+            .expect("import");
+        assert_eq!(imported.unique_item_count(), 1);
+        assert_eq!(imported.items()[0].product_id, ProductId::new("001"));
+    }
+
+    #[test]
+    fn test_import_cart_resolves_discounts_by_code() {
+        let catalog = ProductCatalog::new();
+        let product = create_test_product("001", 1000);
+        catalog.add_product(product.clone()).expect("add");
+
+        let mut cart = Cart::new(CustomerId::new("mobile"));
+        cart.add_item(&product, 2).expect("add");
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10%",
+        ))
+        .expect("apply");
+        // A discount forged on the exporting side must not survive the import
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("FREE"),
+            100,
+            "Free",
+        ))
+        .expect("apply");
+        cart.apply_discount(AppliedDiscount::fixed_amount(
+            CouponCode::new("BIG5"),
+            500,
+            "$5",
+        ))
+        .expect("apply");
+        let token = cart.export_token();
+
+        // The server-side terms differ from what the exporting cart applied
+        let imported = CartService::new()
+            .import_cart(
+                &token,
+                CustomerId::new("desktop"),
+                &catalog,
+                |code| match code {
+                    "SAVE10" => Some(AppliedDiscount::percentage(CouponCode::new(code), 5, "5%")),
+                    "BIG5" => Some(
+                        AppliedDiscount::fixed_amount(CouponCode::new(code), 500, "$5")
+                            .with_minimum_subtotal(5000),
+                    ),
+                    _ => None,
+                },
+            )
+            .expect("import");
+        assert_eq!(imported.discounts().len(), 1);
+        assert_eq!(imported.discounts()[0].value, 5);
+        assert_eq!(imported.calculate_totals().discount_total, 100);
+    }
+
+    #[test]
+    fn test_import_cart_enforces_current_use_limits() {
+        let catalog = ProductCatalog::new();
+        let product = create_test_product("001", 1000);
+        catalog.add_product(product.clone()).expect("add");
+
+        let mut stacking = AppliedDiscount::fixed_amount(CouponCode::new("STACK"), 100, "$1");
+        stacking.max_uses_per_cart = Some(2);
+        let mut cart = Cart::new(CustomerId::new("mobile"));
+        cart.add_item(&product, 1).expect("add");
+        cart.apply_discount(stacking.clone()).expect("first use");
+        cart.apply_discount(stacking.clone()).expect("second use");
+        let token = cart.export_token();
+
+        // The limit was lowered after the cart was exported
+        stacking.max_uses_per_cart = Some(1);
+        let imported = CartService::new()
+            .import_cart(&token, CustomerId::new("desktop"), &catalog, |code| {
+                (code == "STACK").then(|| stacking.clone())
+            })
+            .expect("import");
+        assert_eq!(imported.discounts().len(), 1);
+        assert_eq!(imported.discounts()[0].max_uses_per_cart, Some(1));
+    }
+
+    #[test]
+    fn test_import_cart_keeps_variant_lines_apart() {
+        let catalog = ProductCatalog::new();
+        let product = create_test_product("001", 1000);
+        catalog.add_product(product.clone()).expect("add");
+        let large = ProductId::new("001-large");
+
+        let mut cart = Cart::new(CustomerId::new("mobile"));
+        cart.add_item(&product, 1).expect("add");
+        cart.add_variant_item(&product, Some(&large), 2).expect("add variant");
+        cart.items_mut()[1]
+            .custom_options
+            .insert("engraving".into(), "für Zoë ✓".into());
+        let token = cart.export_token();
+
+        let imported = CartService::new()
+            .import_cart(&token, CustomerId::new("desktop"), &catalog, |_| None)
+            .expect("import");
+        assert_eq!(imported.unique_item_count(), 2);
+        assert!(imported.items()[0].is_line_for(&ProductId::new("001"), None));
+        assert_eq!(imported.items()[0].quantity, 1);
+        assert!(imported.items()[1].is_line_for(&ProductId::new("001"), Some(&large)));
+        assert_eq!(imported.items()[1].quantity, 2);
+        assert_eq!(
+            imported.items()[1].custom_options.get("engraving").map(|v| v.as_ref()),
+            Some("für Zoë ✓")
+        );
+    }
+
+    #[test]
+    fn test_concurrent_merges_share_one_customer_cart() {
+        use std::{sync::Arc, thread};
+
+        let service = Arc::new(CartService::new());
+        let customer_id = CustomerId::new("customer-1");
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let service = Arc::clone(&service);
+                let customer_id = customer_id.clone();
+                thread::spawn(move || {
+                    let mut guest = service
+                        .create_cart(CustomerId::new(format!("guest-{i}")))
+                        .expect("guest cart");
+                    guest.add_item(&create_test_product(&format!("{i:03}"), 100), 1).expect("add");
+                    service.update_cart(guest.clone()).expect("update");
+                    service.get_or_create_cart(customer_id.clone()).expect("customer cart");
+                    service.merge_carts(&guest.id, &customer_id).expect("merge")
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().expect("merge thread");
+        }
+
+        let cart = service.get_customer_cart(&customer_id).expect("lookup").expect("cart");
+        assert_eq!(cart.unique_item_count(), 8);
+    }
+
+    #[test]
+    fn test_merge_keeps_newer_price_and_merges_options() {
+        use std::borrow::Cow;
+
+        let service = CartService::new();
+        let customer_id = CustomerId::new("customer-1");
+
+        let mut customer = service.get_or_create_cart(customer_id.clone()).expect("customer cart");
+        customer.add_item(&create_test_product("001", 1000), 1).expect("add");
+        let item = &mut customer.items_mut()[0];
+        item.updated_at = 100;
+        item.custom_options.insert(Cow::Borrowed("color"), Cow::Borrowed("red"));
+        item.custom_options.insert(Cow::Borrowed("gift_wrap"), Cow::Borrowed("yes"));
+        service.update_cart(customer).expect("update");
+
+        let mut guest = service.create_cart(CustomerId::new("guest")).expect("guest cart");
+        guest.add_item(&create_test_product("001", 800), 2).expect("add");
+        let item = &mut guest.items_mut()[0];
+        item.updated_at = 200;
+        item.custom_options.insert(Cow::Borrowed("color"), Cow::Borrowed("blue"));
+        item.custom_options.insert(Cow::Borrowed("engraving"), Cow::Borrowed("AB"));
+        service.update_cart(guest.clone()).expect("update");
+
+        let merged = service.merge_carts(&guest.id, &customer_id).expect("merge");
+
+        let item = &merged.items()[0];
+        assert_eq!(item.quantity, 3);
+        assert_eq!(item.unit_price.amount, 800);
+        assert_eq!(item.updated_at, 200);
+        assert_eq!(item.custom_options["color"], "blue");
+        assert_eq!(item.custom_options["gift_wrap"], "yes");
+        assert_eq!(item.custom_options["engraving"], "AB");
+    }
+
+    #[test]
+    fn test_merge_keeps_variant_lines_apart() {
+        let service = CartService::new();
+        let customer_id = CustomerId::new("customer-1");
+        let product = create_test_product("001", 1000);
+        let variant_line = |variant: &str, quantity: u32| {
+            let mut item = CartItem::from_product(&product, quantity);
+            item.variant_id = Some(ProductId::new(variant));
+            item
+        };
+
+        let mut customer = service.get_or_create_cart(customer_id.clone()).expect("customer cart");
+        customer.items_mut().push(variant_line("001-S", 1));
+        service.update_cart(customer).expect("update");
+
+        let mut guest = service.create_cart(CustomerId::new("guest")).expect("guest cart");
+        guest.items_mut().push(variant_line("001-L", 2));
+        guest.items_mut().push(variant_line("001-S", 3));
+        service.update_cart(guest.clone()).expect("update");
+
+        let merged = service.merge_carts(&guest.id, &customer_id).expect("merge");
+        let quantity = |variant: &str| {
+            merged
+                .items()
+                .iter()
+                .find(|i| i.is_line_for(&product.id, Some(&ProductId::new(variant))))
+                .map(|i| i.quantity)
+        };
+        assert_eq!(merged.unique_item_count(), 2);
+        assert_eq!(quantity("001-S"), Some(4));
+        assert_eq!(quantity("001-L"), Some(2));
+    }
+
+    #[test]
+    fn test_merge_refreshes_discount_savings() {
+        let service = CartService::new();
+        let customer_id = CustomerId::new("customer-1");
+        let product = create_test_product("001", 1000);
+
+        let mut customer = service.get_or_create_cart(customer_id.clone()).expect("customer cart");
+        customer.add_item(&product, 1).expect("add");
+        customer
+            .apply_discount(AppliedDiscount::percentage(
+                CouponCode::new("SAVE10"),
+                10,
+                "10% off",
+            ))
+            .expect("apply");
+        customer.last_activity_at = 0;
+        service.update_cart(customer).expect("update");
+
+        let mut guest = service.create_cart(CustomerId::new("guest")).expect("guest cart");
+        guest.add_item(&product, 2).expect("add");
+        service.update_cart(guest.clone()).expect("update");
+
+        let merged = service.merge_carts(&guest.id, &customer_id).expect("merge");
+        assert_eq!(merged.discounts()[0].savings, 300);
+        assert!(merged.last_activity_at > 0);
+        assert_eq!(merged.calculate_totals().discount_total, 300);
+    }
+
+    #[test]
+    fn test_merge_rejects_currency_mismatch() {
+        let service = CartService::new();
+        let customer_id = CustomerId::new("customer-1");
+        let mut customer = service.get_or_create_cart(customer_id.clone()).expect("customer cart");
+        customer.add_item(&create_test_product("001", 1000), 1).expect("add");
+        service.update_cart(customer.clone()).expect("update");
+
+        let token =
+            Cart::with_currency(CustomerId::new("guest"), Currency::new("EUR")).export_token();
+        let guest = service
+            .import_cart(
+                &token,
+                CustomerId::new("guest"),
+                &ProductCatalog::new(),
+                |_| None,
+            )
+            .expect("guest cart");
+
+        let result = service.merge_carts(&guest.id, &customer_id);
+        assert!(matches!(
+            result,
+            Err(CommerceError::CurrencyMismatch { ref expected, ref got })
+                if expected == "USD" && got == "EUR"
+        ));
+        assert_eq!(
+            service.get_cart(&guest.id).expect("guest").status,
+            CartStatus::Active
+        );
+        assert_eq!(
+            service.get_cart(&customer.id).expect("customer").total_quantity(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_existing_item_when_guest_is_older() {
+        use std::borrow::Cow;
+
+        let mut existing = CartItem::from_product(&create_test_product("001", 1000), 1);
+        existing.updated_at = 200;
+        existing.custom_options.insert(Cow::Borrowed("color"), Cow::Borrowed("red"));
+
+        let mut older = CartItem::from_product(&create_test_product("001", 800), 1);
+        older.updated_at = 100;
+        older.custom_options.insert(Cow::Borrowed("color"), Cow::Borrowed("blue"));
+        older.custom_options.insert(Cow::Borrowed("size"), Cow::Borrowed("L"));
+
+        existing.merge_from(older);
+
+        assert_eq!(existing.quantity, 2);
+        assert_eq!(existing.unit_price.amount, 1000);
+        assert_eq!(existing.custom_options["color"], "red");
+        assert_eq!(existing.custom_options["size"], "L");
+    }
+
+    fn two_warehouse_inventory(main_stock: i64, east_stock: i64) -> InventoryService {
+        let inventory = InventoryService::new();
+        let east = LocationId::new("warehouse-east");
+        let mut location = InventoryLocation::warehouse(east.clone(), "East");
+        location.fulfillment_priority = 2;
+        inventory.add_location(location).expect("add location");
+        for (location_id, stock) in [
+            (LocationId::default_warehouse(), main_stock),
+            (east, east_stock),
+        ] {
+            inventory
+                .set_inventory(ProductId::new("001"), None, location_id, stock, "Initial")
+                .expect("set");
+        }
+        inventory
+    }
+
+    #[test]
+    fn test_fulfillment_plan_single_location() {
+        let inventory = two_warehouse_inventory(10, 10);
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 1000), 4).expect("add");
+
+        let plan = cart.fulfillment_plan(&inventory).expect("plan");
+
+        assert_eq!(plan, vec![(ProductId::new("001"), None, vec![(
+            LocationId::default_warehouse(),
+            4
+        )])]);
+        // Planning reserves nothing
+        let level = inventory
+            .get_inventory(
+                &ProductId::new("001"),
+                None,
+                &LocationId::default_warehouse(),
+            )
+            .expect("level");
+        assert_eq!(level.available, 10);
+    }
+
+    #[test]
+    fn test_fulfillment_plan_splits_across_locations() {
+        let inventory = two_warehouse_inventory(3, 10);
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 1000), 7).expect("add");
+
+        let plan = cart.fulfillment_plan(&inventory).expect("plan");
+        assert_eq!(plan, vec![(ProductId::new("001"), None, vec![
+            (LocationId::default_warehouse(), 3),
+            (LocationId::new("warehouse-east"), 4),
+        ])]);
+
+        cart.update_item_quantity(&ProductId::new("001"), 20).expect("update");
+        assert!(matches!(
+            cart.fulfillment_plan(&inventory),
+            Err(CommerceError::InsufficientInventory { available: 13, requested: 20, .. })
+        ));
+    }
+
+    #[test]
+    fn test_cart_item_reprices_across_volume_tiers() {
+        let mut product = create_test_product("001", 1000);
+        product.quantity_tiers = vec![
+            (1, Price::new(1000, Currency::usd(), 2)),
+            (10, Price::new(900, Currency::usd(), 2)),
+            (100, Price::new(750, Currency::usd(), 2)),
+        ];
+        product.inventory_quantity = 1000;
+
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&product, 5).expect("add");
+        assert_eq!(cart.items()[0].unit_price.amount, 1000);
+
+        cart.add_item(&product, 5).expect("add more");
+        assert_eq!(cart.items()[0].unit_price.amount, 900);
+        assert_eq!(cart.items()[0].subtotal(), 9000);
+
+        cart.update_item_quantity(&ProductId::new("001"), 150).expect("update");
+        assert_eq!(cart.items()[0].subtotal(), 150 * 750);
+
+        cart.update_item_quantity(&ProductId::new("001"), 2).expect("update");
+        assert_eq!(cart.items()[0].unit_price.amount, 1000);
+    }
+
+    #[test]
+    fn test_volume_tier_price_is_not_a_sale() {
+        let mut product = create_test_product("001", 1000);
+        product.quantity_tiers = vec![(10, Price::new(900, Currency::usd(), 2))];
+        product.inventory_quantity = 1000;
+
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&product, 10).expect("add");
+        assert!(!cart.items()[0].is_on_sale());
+        cart.apply_discount(
+            AppliedDiscount::percentage(CouponCode::new("FULLPRICE10"), 10, "10% off")
+                .excluding_sale_items(),
+        )
+        .expect("apply");
+        let totals = cart.calculate_totals();
+        assert_eq!(totals.discount_total, 900);
+        assert_eq!(totals.total_savings, 900);
+
+        // A sale below the top tier keeps its price
+        product.sale_price = Some(Price::new(850, Currency::usd(), 2));
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&product, 10).expect("add");
+        assert_eq!(cart.items()[0].unit_price.amount, 850);
+        assert_eq!(cart.items()[0].sale_savings(), 1500);
+    }
+
+    #[test]
+    fn test_discount_codes_match_case_insensitively() {
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&create_test_product("001", 1000), 1).expect("add");
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10% off",
+        ))
+        .expect("apply");
+
+        // Statically created codes are stored as written
+        let lowercase = AppliedDiscount::percentage(CouponCode::from_static("save10"), 10, "Again");
+        assert!(matches!(
+            cart.apply_discount(lowercase),
+            Err(CommerceError::DiscountAlreadyApplied(_))
+        ));
+
+        cart.remove_discount(" save10 ").expect("remove");
+        assert!(cart.discounts().is_empty());
+        assert!(matches!(
+            cart.remove_discount("save10"),
+            Err(CommerceError::DiscountNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_pre_order_product_before_and_after_availability() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut product = create_test_product("001", 1000);
+        product.available_from = Some(now + 3600);
+        assert!(product.status.is_visible());
+        assert!(!product.is_available_at(now));
+        assert!(product.is_available_at(now + 3600));
+
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        let result = cart.add_item(&product, 1);
+        assert!(matches!(
+            result,
+            Err(CommerceError::NotYetAvailable { available_from, .. }) if available_from > now
+        ));
+
+        product.available_from = Some(now.saturating_sub(60));
+        cart.add_item(&product, 1).expect("available now");
+        assert_eq!(cart.unique_item_count(), 1);
+    }
 }
@@ -1,22 +1,28 @@
 //! Cart management service
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 
-use crate::errors::CommerceError;
+use crate::{errors::CommerceError, implementation::product_catalog::service::ProductCatalog};
 
 use super::cart::Cart;
-use super::types::{CartId, CartStatus, CustomerId};
+use super::types::{AppliedDiscount, CartId, CartStatus, Coupon, CouponCode, CustomerId};
 
 /// Cart management service.
+///
+/// Methods that hold several maps lock `carts` before `carts_by_customer`
+/// before `coupons`, and never call back into the service while holding any.
 #[derive(Debug)]
 pub struct CartService {
     /// Carts indexed by ID.
     carts:             Arc<Mutex<HashMap<CartId, Cart>>>,
     /// Carts indexed by customer ID.
     carts_by_customer: Arc<Mutex<HashMap<CustomerId, Vec<CartId>>>>,
+    /// Issued coupons indexed by normalized code.
+    coupons:           Arc<Mutex<HashMap<String, Coupon>>>,
 }
 
 impl CartService {
@@ -26,6 +32,7 @@ impl CartService {
         Self {
             carts:             Arc::new(Mutex::new(HashMap::new())),
             carts_by_customer: Arc::new(Mutex::new(HashMap::new())),
+            coupons:           Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -60,25 +67,84 @@ impl CartService {
         let carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
         let by_customer = self.carts_by_customer.lock().map_err(|_| CommerceError::LockError)?;
 
-        let cart_ids = by_customer.get(customer_id).cloned().unwrap_or_default();
-
-        // Return most recent active cart
-        let active_cart = cart_ids
-            .iter()
-            .filter_map(|id| carts.get(id))
-            .filter(|c| c.status == CartStatus::Active && !c.is_expired())
-            .max_by_key(|c| c.last_activity_at)
-            .cloned();
-
-        Ok(active_cart)
+        Ok(active_cart_id(&carts, &by_customer, customer_id)
+            .and_then(|id| carts.get(&id))
+            .cloned())
     }
 
     /// Gets or creates a cart for a customer.
     pub fn get_or_create_cart(&self, customer_id: CustomerId) -> Result<Cart, CommerceError> {
-        if let Some(cart) = self.get_customer_cart(&customer_id)? {
-            return Ok(cart);
+        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+        let mut by_customer =
+            self.carts_by_customer.lock().map_err(|_| CommerceError::LockError)?;
+
+        if let Some(id) = active_cart_id(&carts, &by_customer, &customer_id) {
+            return Ok(carts[&id].clone());
+        }
+
+        let cart = Cart::new(customer_id.clone());
+        carts.insert(cart.id.clone(), cart.clone());
+        by_customer.entry(customer_id).or_default().push(cart.id.clone());
+        Ok(cart)
+    }
+
+    /// Rebuilds a cart exported with [`Cart::export_token`] for a customer.
+    ///
+    /// Items are re-added from the catalog at current prices; items that are
+    /// no longer available or in stock are dropped. The token only carries
+    /// discount codes, so each is looked up again with `resolve_discount`;
+    /// codes it does not know, or that no longer apply to the cart, are
+    /// dropped.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` if the token is malformed.
+    pub fn import_cart(
+        &self, token: &str, customer_id: CustomerId, catalog: &ProductCatalog,
+        resolve_discount: impl Fn(&str) -> Option<AppliedDiscount>,
+    ) -> Result<Cart, CommerceError> {
+        let snapshot = super::token::decode(token)?;
+
+        let mut cart = Cart::with_currency(customer_id.clone(), snapshot.currency);
+        cart.set_customer_group(snapshot.customer_group);
+
+        for item in snapshot.items {
+            let Ok(product) = catalog.get_product(&item.product_id) else {
+                continue;
+            };
+            let variant_id = item.variant_id.as_ref();
+            if cart.add_variant_item(&product, variant_id, item.quantity).is_err() {
+                continue;
+            }
+            if let Some(added) = cart
+                .items_mut()
+                .iter_mut()
+                .find(|i| i.is_line_for(&item.product_id, variant_id))
+            {
+                added.custom_options.extend(
+                    item.custom_options.into_iter().map(|(k, v)| (Cow::Owned(k), Cow::Owned(v))),
+                );
+            }
+        }
+        for code in snapshot.discounts {
+            if let Some(discount) = resolve_discount(&code) {
+                let _ = cart.apply_discount(discount);
+            }
+        }
+        if let Some(address) = snapshot.shipping_address {
+            cart.set_shipping_address(address);
+        }
+        if let Some(address) = snapshot.billing_address {
+            cart.set_billing_address(address);
         }
-        self.create_cart(customer_id)
+
+        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+        let mut by_customer =
+            self.carts_by_customer.lock().map_err(|_| CommerceError::LockError)?;
+
+        carts.insert(cart.id.clone(), cart.clone());
+        by_customer.entry(customer_id).or_default().push(cart.id.clone());
+
+        Ok(cart)
     }
 
     /// Updates a cart.
@@ -94,10 +160,22 @@ impl CartService {
     }
 
     /// Merges a guest cart into a customer cart.
+    ///
+    /// Lines for the same product and variant are combined with
+    /// [`CartItem::merge_from`](super::item::CartItem::merge_from). A customer
+    /// without an active cart gets one in the guest cart's currency.
+    ///
+    /// # Errors
+    /// Returns `CurrencyMismatch` if the customer's cart uses a different
+    /// currency than the guest cart; neither cart is changed.
     pub fn merge_carts(
         &self, guest_cart_id: &CartId, customer_id: &CustomerId,
     ) -> Result<Cart, CommerceError> {
-        let carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+        // Both maps stay locked throughout so a concurrent merge for the same
+        // customer cannot create a second active cart.
+        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+        let mut by_customer =
+            self.carts_by_customer.lock().map_err(|_| CommerceError::LockError)?;
 
         let guest_cart = carts
             .get(guest_cart_id)
@@ -105,22 +183,39 @@ impl CartService {
             .clone();
 
         // Get or create customer cart
-        drop(carts);
-        let mut customer_cart = self.get_or_create_cart(customer_id.clone())?;
+        let mut customer_cart = match active_cart_id(&carts, &by_customer, customer_id) {
+            Some(id) => {
+                let cart = carts[&id].clone();
+                if cart.currency() != guest_cart.currency() {
+                    return Err(CommerceError::CurrencyMismatch {
+                        expected: cart.currency().0.to_string(),
+                        got:      guest_cart.currency().0.to_string(),
+                    });
+                }
+                cart
+            },
+            None => {
+                let cart = Cart::with_currency(customer_id.clone(), guest_cart.currency().clone());
+                by_customer.entry(customer_id.clone()).or_default().push(cart.id.clone());
+                cart
+            },
+        };
 
         // Merge items
-        for item in guest_cart.items {
-            if let Some(existing) =
-                customer_cart.items.iter_mut().find(|i| i.product_id == item.product_id)
+        for item in guest_cart.items().iter().cloned() {
+            if let Some(existing) = customer_cart
+                .items_mut()
+                .iter_mut()
+                .find(|i| i.is_line_for(&item.product_id, item.variant_id.as_ref()))
             {
-                existing.quantity = existing.quantity.saturating_add(item.quantity);
+                existing.merge_from(item);
             } else {
-                customer_cart.items.push(item);
+                customer_cart.items_mut().push(item);
             }
         }
+        customer_cart.touch();
 
         // Update guest cart status
-        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
         if let Some(guest) = carts.get_mut(guest_cart_id) {
             guest.status = CartStatus::Merged;
         }
@@ -141,6 +236,128 @@ impl CartService {
         Ok(())
     }
 
+    /// Marks active carts past their expiry as expired.
+    ///
+    /// Unlike [`Self::cleanup_carts`], carts are kept. Returns the number of
+    /// carts changed.
+    pub fn expire_stale_carts(&self) -> Result<usize, CommerceError> {
+        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut expired = 0;
+        for cart in carts.values_mut() {
+            if cart.status == CartStatus::Active && cart.is_expired() {
+                cart.status = CartStatus::Expired;
+                expired += 1;
+            }
+        }
+
+        Ok(expired)
+    }
+
+    /// Issues a single-use percentage coupon to win back a cart's customer.
+    ///
+    /// The coupon has a random code and is valid only for the cart's customer
+    /// and for `ttl_secs` from now. Issuing again for the same cart replaces
+    /// the earlier coupon.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` if `percent` is not between 1 and 100 or the
+    /// cart was already converted or merged, and `CartNotFound` for an
+    /// unknown cart.
+    pub fn generate_recovery_coupon(
+        &self, cart_id: &CartId, percent: u8, ttl_secs: u64,
+    ) -> Result<Coupon, CommerceError> {
+        if !(1..=100).contains(&percent) {
+            return Err(CommerceError::ValidationError(format!(
+                "recovery discount must be 1-100%, got {}",
+                percent
+            )));
+        }
+
+        let customer_id = {
+            let carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+            let cart = carts
+                .get(cart_id)
+                .ok_or_else(|| CommerceError::CartNotFound(cart_id.0.to_string()))?;
+            if matches!(cart.status, CartStatus::Converted | CartStatus::Merged) {
+                return Err(CommerceError::ValidationError(format!(
+                    "cart {} is no longer recoverable",
+                    cart_id.0
+                )));
+            }
+            cart.customer_id.clone()
+        };
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let code = CouponCode::new(format!("RECOVER-{}", essentia_uuid::Uuid::new_v4()));
+        let mut discount = AppliedDiscount::percentage(
+            code,
+            u64::from(percent),
+            format!("{}% off to complete your order", percent),
+        );
+        discount.max_uses_per_cart = Some(1);
+        let coupon = Coupon {
+            discount,
+            customer_id,
+            cart_id: Some(cart_id.clone()),
+            expires_at: now.saturating_add(ttl_secs),
+            uses_remaining: 1,
+        };
+
+        let mut coupons = self.coupons.lock().map_err(|_| CommerceError::LockError)?;
+        coupons.retain(|_, issued| issued.cart_id.as_ref() != Some(cart_id));
+        coupons.insert(coupon.code().0.to_string(), coupon.clone());
+        Ok(coupon)
+    }
+
+    /// Applies an issued coupon to a cart and uses up one redemption.
+    ///
+    /// The coupon is checked, applied and decremented under one lock, so
+    /// concurrent redemptions cannot exceed its uses.
+    ///
+    /// # Errors
+    /// Returns `CartNotFound` for an unknown cart, `DiscountNotFound` if no
+    /// coupon was issued under `code`, `DiscountNotEligible` if the cart's
+    /// customer may not use it, and any error from
+    /// [`Cart::apply_discount`].
+    pub fn redeem_coupon(&self, cart_id: &CartId, code: &str) -> Result<Cart, CommerceError> {
+        let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
+        let mut coupons = self.coupons.lock().map_err(|_| CommerceError::LockError)?;
+
+        let cart = carts
+            .get_mut(cart_id)
+            .ok_or_else(|| CommerceError::CartNotFound(cart_id.0.to_string()))?;
+        let coupon = coupons
+            .get_mut(&CouponCode::normalize(code))
+            .ok_or_else(|| CommerceError::DiscountNotFound(code.to_string()))?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        coupon.validate_for(&cart.customer_id, now)?;
+        cart.apply_discount(coupon.discount.clone())?;
+        coupon.uses_remaining -= 1;
+
+        Ok(cart.clone())
+    }
+
+    /// Gets an issued coupon by code, ignoring case and surrounding
+    /// whitespace.
+    ///
+    /// # Errors
+    /// Returns `DiscountNotFound` if no coupon was issued under `code`.
+    pub fn get_coupon(&self, code: &str) -> Result<Coupon, CommerceError> {
+        let coupons = self.coupons.lock().map_err(|_| CommerceError::LockError)?;
+        coupons
+            .get(&CouponCode::normalize(code))
+            .cloned()
+            .ok_or_else(|| CommerceError::DiscountNotFound(code.to_string()))
+    }
+
     /// Deletes expired and abandoned carts.
     pub fn cleanup_carts(&self, max_age_days: u64) -> Result<usize, CommerceError> {
         let mut carts = self.carts.lock().map_err(|_| CommerceError::LockError)?;
@@ -167,6 +384,20 @@ impl CartService {
     }
 }
 
+/// Most recently active, unexpired cart of a customer.
+fn active_cart_id(
+    carts: &HashMap<CartId, Cart>, by_customer: &HashMap<CustomerId, Vec<CartId>>,
+    customer_id: &CustomerId,
+) -> Option<CartId> {
+    by_customer
+        .get(customer_id)?
+        .iter()
+        .filter_map(|id| carts.get(id))
+        .filter(|c| c.status == CartStatus::Active && !c.is_expired())
+        .max_by_key(|c| c.last_activity_at)
+        .map(|c| c.id.clone())
+}
+
 impl Default for CartService {
     fn default() -> Self {
         Self::new()
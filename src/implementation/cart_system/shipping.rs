@@ -1,8 +1,14 @@
 //! Shipping address and method types
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet};
 
-use crate::types::product_catalog::{Currency, Price};
+use crate::{
+    errors::CommerceError,
+    types::product_catalog::{Currency, Price},
+};
+
+use super::cart::Cart;
+use super::item::CartItem;
 
 /// Shipping address.
 #[derive(Debug, Clone, Default)]
@@ -58,6 +64,28 @@ impl ShippingAddress {
     }
 }
 
+/// How a shipping method prices a cart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShippingRate {
+    /// The same price for every cart.
+    Flat(Price),
+    /// A base price plus a charge per started kilogram of item weight.
+    PerKg {
+        /// Price charged whatever the weight.
+        base:   Price,
+        /// Charge per kilogram, in the base price's currency units.
+        per_kg: u64,
+    },
+    /// Free once the discounted subtotal reaches a threshold, like setting
+    /// [`ShippingMethod::free_threshold`].
+    FreeOver {
+        /// Discounted subtotal at which shipping becomes free.
+        threshold: u64,
+        /// Price below the threshold.
+        fallback:  Price,
+    },
+}
+
 /// Shipping method.
 #[derive(Debug, Clone)]
 pub struct ShippingMethod {
@@ -75,6 +103,17 @@ pub struct ShippingMethod {
     pub estimated_days_max: u32,
     /// Whether tracking is available.
     pub has_tracking:       bool,
+    /// Discounted subtotal at which base shipping becomes free.
+    pub free_threshold:     Option<u64>,
+    /// Handling surcharge per oversize unit, charged even when shipping is
+    /// free.
+    pub oversize_surcharge: u64,
+    /// Country codes this method ships to; `None` ships everywhere.
+    pub allowed_countries:  Option<HashSet<String>>,
+    /// Country codes this method never ships to.
+    pub excluded_countries: Option<HashSet<String>>,
+    /// How the cost depends on the cart; `None` charges `cost`.
+    pub rate:               Option<ShippingRate>,
 }
 
 impl ShippingMethod {
@@ -89,6 +128,11 @@ impl ShippingMethod {
             estimated_days_min: 3,
             estimated_days_max: 7,
             has_tracking: true,
+            free_threshold: None,
+            oversize_surcharge: 0,
+            allowed_countries: None,
+            excluded_countries: None,
+            rate: None,
         }
     }
 
@@ -103,6 +147,11 @@ impl ShippingMethod {
             estimated_days_min: 3,
             estimated_days_max: 7,
             has_tracking: true,
+            free_threshold: None,
+            oversize_surcharge: 0,
+            allowed_countries: None,
+            excluded_countries: None,
+            rate: None,
         }
     }
 
@@ -117,7 +166,96 @@ impl ShippingMethod {
             estimated_days_min: 5,
             estimated_days_max: 10,
             has_tracking:       false,
+            free_threshold:     None,
+            oversize_surcharge: 0,
+            allowed_countries:  None,
+            excluded_countries: None,
+            rate:               None,
+        }
+    }
+
+    /// Prices the method with a cart-dependent rate.
+    #[must_use]
+    pub fn with_rate(mut self, rate: ShippingRate) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Base shipping cost for a cart, after any free-shipping threshold and
+    /// before free-shipping discounts and oversize surcharges.
+    #[must_use]
+    pub fn rate_for(&self, cart: &Cart) -> Price {
+        let totals = cart.calculate_totals();
+        self.rate_for_items(
+            cart.items(),
+            totals.subtotal.saturating_sub(totals.discount_total),
+        )
+    }
+
+    /// Base shipping cost for items with the given discounted subtotal.
+    pub(crate) fn rate_for_items(&self, items: &[CartItem], discounted_subtotal: u64) -> Price {
+        let mut price = self.base_price().clone();
+        if let Some(ShippingRate::PerKg { per_kg, .. }) = &self.rate {
+            let grams: u64 =
+                items.iter().map(|i| u64::from(i.weight_grams) * u64::from(i.quantity)).sum();
+            price.amount += per_kg * grams.div_ceil(1000);
+        }
+        if self
+            .free_shipping_threshold()
+            .is_some_and(|threshold| discounted_subtotal >= threshold)
+        {
+            price.amount = 0;
         }
+        price
+    }
+
+    /// Discounted subtotal at which base shipping becomes free: the lower of
+    /// `free_threshold` and a [`ShippingRate::FreeOver`] threshold.
+    #[must_use]
+    pub fn free_shipping_threshold(&self) -> Option<u64> {
+        let rate_threshold = match &self.rate {
+            Some(ShippingRate::FreeOver { threshold, .. }) => Some(*threshold),
+            _ => None,
+        };
+        match (self.free_threshold, rate_threshold) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Checks that the method charges in `currency`.
+    ///
+    /// # Errors
+    /// Returns `CurrencyMismatch` if its price is in another currency.
+    pub fn validate_currency(&self, currency: &Currency) -> Result<(), CommerceError> {
+        let price = self.base_price();
+        if &price.currency != currency {
+            return Err(CommerceError::CurrencyMismatch {
+                expected: currency.0.to_string(),
+                got:      price.currency.0.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Price charged before weight and thresholds.
+    fn base_price(&self) -> &Price {
+        match &self.rate {
+            None => &self.cost,
+            Some(ShippingRate::Flat(price)) => price,
+            Some(ShippingRate::PerKg { base, .. }) => base,
+            Some(ShippingRate::FreeOver { fallback, .. }) => fallback,
+        }
+    }
+
+    /// Whether this method delivers to a country (ISO 3166-1 alpha-2, case
+    /// insensitive).
+    #[must_use]
+    pub fn ships_to(&self, country_code: &str) -> bool {
+        let listed =
+            |set: &HashSet<String>| set.iter().any(|c| c.eq_ignore_ascii_case(country_code));
+        self.allowed_countries.as_ref().is_none_or(listed)
+            && !self.excluded_countries.as_ref().is_some_and(listed)
     }
 
     /// Estimated delivery range string.
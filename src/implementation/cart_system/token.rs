@@ -0,0 +1,261 @@
+//! Portable cart tokens
+//!
+//! A token is the URL-safe base64 of a line-based record format holding the
+//! cart's items, discount codes, and addresses. Prices and discount terms are
+//! not carried: importing a token re-adds items from the catalog and resolves
+//! discount codes again.
+
+use std::borrow::Cow;
+
+use crate::{
+    errors::CommerceError,
+    types::product_catalog::{Currency, ProductId},
+};
+
+use super::cart::Cart;
+use super::shipping::ShippingAddress;
+
+/// First line of every token, bumped if the record format changes.
+const HEADER: &str = "cart2";
+
+/// URL-safe base64 alphabet.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Cart contents decoded from a token.
+#[derive(Debug)]
+pub(crate) struct CartSnapshot {
+    /// Cart currency.
+    pub(crate) currency:         Currency,
+    /// Customer pricing group.
+    pub(crate) customer_group:   Option<String>,
+    /// Items to re-add from the catalog.
+    pub(crate) items:            Vec<SnapshotItem>,
+    /// Codes of the applied coupons.
+    pub(crate) discounts:        Vec<String>,
+    /// Shipping address.
+    pub(crate) shipping_address: Option<ShippingAddress>,
+    /// Billing address.
+    pub(crate) billing_address:  Option<ShippingAddress>,
+}
+
+/// Cart item as carried by a token.
+#[derive(Debug)]
+pub(crate) struct SnapshotItem {
+    /// Product ID.
+    pub(crate) product_id:     ProductId,
+    /// Variant ID (if applicable).
+    pub(crate) variant_id:     Option<ProductId>,
+    /// Quantity.
+    pub(crate) quantity:       u32,
+    /// Custom options selected.
+    pub(crate) custom_options: Vec<(String, String)>,
+}
+
+/// Encodes a cart's contents as a token.
+pub(crate) fn encode(cart: &Cart) -> String {
+    let mut lines = vec![
+        HEADER.to_string(),
+        record(&["currency", &cart.currency().0]),
+    ];
+
+    if let Some(group) = &cart.customer_group {
+        lines.push(record(&["group", group]));
+    }
+
+    for item in cart.items() {
+        let variant_id = item.variant_id.as_ref().map_or("", |v| v.0.as_ref());
+        lines.push(record(&[
+            "item",
+            &item.product_id.0,
+            variant_id,
+            &item.quantity.to_string(),
+        ]));
+        for (key, value) in &item.custom_options {
+            lines.push(record(&["option", key, value]));
+        }
+    }
+
+    for discount in cart.discounts() {
+        lines.push(record(&["discount", &discount.code.0]));
+    }
+
+    let addresses = [
+        ("shipping", &cart.shipping_address),
+        ("billing", &cart.billing_address),
+    ];
+    for (kind, address) in addresses {
+        if let Some(address) = address {
+            lines.push(record(&[
+                kind,
+                &address.first_name,
+                &address.last_name,
+                address.company.as_deref().unwrap_or(""),
+                &address.address_line1,
+                address.address_line2.as_deref().unwrap_or(""),
+                &address.city,
+                &address.state,
+                &address.postal_code,
+                &address.country_code,
+                address.phone.as_deref().unwrap_or(""),
+            ]));
+        }
+    }
+
+    base64_encode(lines.join("\n").as_bytes())
+}
+
+/// Decodes a token produced by [`encode`].
+///
+/// # Errors
+/// Returns `ValidationError` if the token is malformed.
+pub(crate) fn decode(token: &str) -> Result<CartSnapshot, CommerceError> {
+    let invalid = || CommerceError::ValidationError("invalid cart token".to_string());
+
+    let bytes = base64_decode(token.trim()).ok_or_else(invalid)?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+    let mut lines = text.split('\n');
+    if lines.next() != Some(HEADER) {
+        return Err(invalid());
+    }
+
+    let mut currency = None;
+    let mut snapshot = CartSnapshot {
+        currency:         Currency::usd(),
+        customer_group:   None,
+        items:            Vec::new(),
+        discounts:        Vec::new(),
+        shipping_address: None,
+        billing_address:  None,
+    };
+
+    for line in lines {
+        let fields: Vec<String> =
+            line.split('\t').map(unescape).collect::<Option<_>>().ok_or_else(invalid)?;
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+
+        match fields.as_slice() {
+            ["currency", code] => currency = Some(Currency::new(*code)),
+            ["group", group] => snapshot.customer_group = Some((*group).to_string()),
+            ["item", product_id, variant_id, quantity] => snapshot.items.push(SnapshotItem {
+                product_id:     ProductId::new(*product_id),
+                variant_id:     non_empty(variant_id).map(ProductId::new),
+                quantity:       quantity.parse().map_err(|_| invalid())?,
+                custom_options: Vec::new(),
+            }),
+            ["option", key, value] => {
+                let item = snapshot.items.last_mut().ok_or_else(invalid)?;
+                item.custom_options.push(((*key).to_string(), (*value).to_string()));
+            },
+            ["discount", code] => snapshot.discounts.push((*code).to_string()),
+            [
+                kind @ ("shipping" | "billing"),
+                first_name,
+                last_name,
+                company,
+                line1,
+                line2,
+                city,
+                state,
+                postal_code,
+                country_code,
+                phone,
+            ] => {
+                let mut address = ShippingAddress::new(
+                    *first_name,
+                    *last_name,
+                    *line1,
+                    *city,
+                    *state,
+                    *postal_code,
+                    *country_code,
+                );
+                address.company = non_empty(company).map(|c| Cow::Owned(c.to_string()));
+                address.address_line2 = non_empty(line2).map(|l| Cow::Owned(l.to_string()));
+                address.phone = non_empty(phone).map(|p| Cow::Owned(p.to_string()));
+                if *kind == "shipping" {
+                    snapshot.shipping_address = Some(address);
+                } else {
+                    snapshot.billing_address = Some(address);
+                }
+            },
+            _ => return Err(invalid()),
+        }
+    }
+
+    snapshot.currency = currency.ok_or_else(invalid)?;
+    Ok(snapshot)
+}
+
+/// `None` for an empty field.
+fn non_empty(field: &str) -> Option<&str> {
+    (!field.is_empty()).then_some(field)
+}
+
+/// Joins escaped fields with tabs.
+fn record(fields: &[&str]) -> String {
+    fields.iter().map(|f| escape(f)).collect::<Vec<_>>().join("\t")
+}
+
+/// Percent-escapes the characters used as separators.
+fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            '\t' => out.push_str("%09"),
+            '\n' => out.push_str("%0A"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverses [`escape`].
+///
+/// Escapes decode to bytes, so the result is checked to be UTF-8 again.
+fn unescape(field: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('%') {
+        out.extend_from_slice(&rest.as_bytes()[..pos]);
+        let code = rest.get(pos + 1..pos + 3)?;
+        out.push(u8::from_str_radix(code, 16).ok()?);
+        rest = &rest[pos + 3..];
+    }
+    out.extend_from_slice(rest.as_bytes());
+    String::from_utf8(out).ok()
+}
+
+/// Encodes bytes as unpadded URL-safe base64.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            out.push(char::from(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize]));
+        }
+    }
+    out
+}
+
+/// Decodes unpadded URL-safe base64.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3 + 2);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0_u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
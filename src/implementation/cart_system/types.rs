@@ -2,6 +2,13 @@
 
 use std::borrow::Cow;
 
+use crate::{
+    errors::CommerceError,
+    types::product_catalog::{CategoryId, ProductId},
+};
+
+use super::item::CartItem;
+
 /// Unique cart identifier.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CartId(pub Cow<'static, str>);
@@ -76,6 +83,69 @@ pub enum CartStatus {
     Merged,
 }
 
+/// Rounding applied to fractional tax amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TaxRounding {
+    /// Drop the fractional part.
+    #[default]
+    Truncate,
+    /// Round halves up.
+    HalfUp,
+    /// Round halves to the nearest even unit.
+    Bankers,
+}
+
+impl TaxRounding {
+    /// Rounds a non-negative tax amount to whole units.
+    #[must_use]
+    pub fn apply(self, amount: f64) -> u64 {
+        match self {
+            Self::Truncate => amount as u64,
+            Self::HalfUp => amount.round() as u64,
+            Self::Bankers => amount.round_ties_even() as u64,
+        }
+    }
+}
+
+/// Whether prices include tax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TaxMode {
+    /// Tax is added on top of prices.
+    #[default]
+    Exclusive,
+    /// Prices already include tax (e.g. VAT), which is backed out of them.
+    Inclusive,
+}
+
+impl TaxMode {
+    /// Tax owed on a discounted amount at `tax_rate` percent, rounded with
+    /// `rounding`.
+    ///
+    /// An inclusive amount keeps the exact remainder as its net price, so
+    /// net plus tax always equals the amount.
+    #[must_use]
+    pub fn tax_on(self, amount: u64, tax_rate: f64, rounding: TaxRounding) -> u64 {
+        match self {
+            Self::Exclusive => rounding.apply(amount as f64 * tax_rate / 100.0),
+            Self::Inclusive if tax_rate > 0.0 => {
+                rounding.apply(amount as f64 * tax_rate / (100.0 + tax_rate)).min(amount)
+            },
+            Self::Inclusive => 0,
+        }
+    }
+}
+
+/// How an automatic markdown combines with coupon discounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownStacking {
+    /// Coupons apply on top of the marked-down subtotal.
+    #[default]
+    Stack,
+    /// Percentage and fixed-amount coupons are ignored while a markdown is
+    /// active. Free-shipping coupons still apply.
+    Exclusive,
+}
+
 /// Coupon/discount code.
 #[derive(Debug, Clone)]
 pub struct CouponCode(pub Cow<'static, str>);
@@ -84,7 +154,20 @@ impl CouponCode {
     /// Creates a new coupon code.
     #[must_use]
     pub fn new(code: impl Into<String>) -> Self {
-        Self(Cow::Owned(code.into().to_uppercase()))
+        Self(Cow::Owned(Self::normalize(&code.into())))
+    }
+
+    /// Canonical form of a code as typed: trimmed and uppercased.
+    #[must_use]
+    pub fn normalize(code: &str) -> String {
+        code.trim().to_uppercase()
+    }
+
+    /// Whether `code` names this coupon, ignoring case and surrounding
+    /// whitespace.
+    #[must_use]
+    pub fn matches(&self, code: &str) -> bool {
+        Self::normalize(&self.0) == Self::normalize(code)
     }
 
     /// Creates a coupon code from a static string slice (zero-copy).
@@ -99,27 +182,65 @@ impl CouponCode {
 pub enum DiscountType {
     /// Percentage discount.
     Percentage,
-    /// Fixed amount discount.
+    /// Fixed amount off the cart.
     FixedAmount,
+    /// Fixed amount off the eligible items, capped at their total.
+    FixedAmountOnItems,
     /// Free shipping.
     FreeShipping,
     /// Buy X get Y free.
     BuyXGetY,
 }
 
+/// Which cart items a discount applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DiscountScope {
+    /// All items in the cart.
+    #[default]
+    EntireCart,
+    /// Items in any of these categories.
+    Categories(Vec<CategoryId>),
+    /// These products only.
+    Products(Vec<ProductId>),
+}
+
+impl DiscountScope {
+    /// Whether an item is eligible under this scope.
+    #[must_use]
+    pub fn includes(&self, item: &CartItem) -> bool {
+        match self {
+            Self::EntireCart => true,
+            Self::Categories(categories) => item.categories.iter().any(|c| categories.contains(c)),
+            Self::Products(products) => products.contains(&item.product_id),
+        }
+    }
+}
+
 /// Applied discount on cart.
 #[derive(Debug, Clone)]
 pub struct AppliedDiscount {
     /// Discount code used.
-    pub code:          CouponCode,
+    pub code:                CouponCode,
     /// Type of discount.
-    pub discount_type: DiscountType,
+    pub discount_type:       DiscountType,
     /// Discount value (percentage or amount).
-    pub value:         u64,
+    pub value:               u64,
     /// Description of the discount.
-    pub description:   String,
+    pub description:         String,
     /// Amount saved by this discount.
-    pub savings:       u64,
+    pub savings:             u64,
+    /// Items the discount applies to.
+    pub scope:               DiscountScope,
+    /// Whether items already on sale are eligible.
+    pub apply_to_sale_items: bool,
+    /// Cart subtotal, before discounts, required for the discount to apply.
+    pub minimum_subtotal:    Option<u64>,
+    /// Times the code may be applied to one cart (once if unset).
+    pub max_uses_per_cart:   Option<u32>,
+    /// Units to buy for a buy-X-get-Y discount.
+    pub buy_quantity:        u32,
+    /// Units free per group bought for a buy-X-get-Y discount.
+    pub get_quantity:        u32,
 }
 
 impl AppliedDiscount {
@@ -132,6 +253,12 @@ impl AppliedDiscount {
             value: percent,
             description: description.into(),
             savings: 0,
+            scope: DiscountScope::EntireCart,
+            apply_to_sale_items: true,
+            minimum_subtotal: None,
+            max_uses_per_cart: None,
+            buy_quantity: 0,
+            get_quantity: 0,
         }
     }
 
@@ -144,6 +271,123 @@ impl AppliedDiscount {
             value: amount,
             description: description.into(),
             savings: 0,
+            scope: DiscountScope::EntireCart,
+            apply_to_sale_items: true,
+            minimum_subtotal: None,
+            max_uses_per_cart: None,
+            buy_quantity: 0,
+            get_quantity: 0,
+        }
+    }
+
+    /// Creates a fixed amount discount off the eligible items only.
+    #[must_use]
+    pub fn fixed_amount_on_items(
+        code: CouponCode, amount: u64, description: impl Into<String>,
+    ) -> Self {
+        Self {
+            discount_type: DiscountType::FixedAmountOnItems,
+            ..Self::fixed_amount(code, amount, description)
+        }
+    }
+
+    /// Creates a buy-X-get-Y discount: in every group of `buy + get`
+    /// eligible units, the `get` cheapest are free.
+    #[must_use]
+    pub fn buy_x_get_y(
+        code: CouponCode, buy: u32, get: u32, description: impl Into<String>,
+    ) -> Self {
+        Self {
+            code,
+            discount_type: DiscountType::BuyXGetY,
+            value: 0,
+            description: description.into(),
+            savings: 0,
+            scope: DiscountScope::EntireCart,
+            apply_to_sale_items: true,
+            minimum_subtotal: None,
+            max_uses_per_cart: None,
+            buy_quantity: buy,
+            get_quantity: get,
+        }
+    }
+
+    /// Restricts the discount to the given scope.
+    #[must_use]
+    pub fn with_scope(mut self, scope: DiscountScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Requires a cart subtotal of at least `amount` before discounts.
+    #[must_use]
+    pub fn with_minimum_subtotal(mut self, amount: u64) -> Self {
+        self.minimum_subtotal = Some(amount);
+        self
+    }
+
+    /// Excludes items that are already on sale.
+    #[must_use]
+    pub fn excluding_sale_items(mut self) -> Self {
+        self.apply_to_sale_items = false;
+        self
+    }
+
+    /// Whether an item is eligible for this discount.
+    #[must_use]
+    pub fn applies_to(&self, item: &CartItem) -> bool {
+        self.scope.includes(item) && (self.apply_to_sale_items || !item.is_on_sale())
+    }
+}
+
+/// Coupon issued to a single customer, such as an abandoned-cart recovery
+/// offer.
+#[derive(Debug, Clone)]
+pub struct Coupon {
+    /// Discount granted when the coupon is applied.
+    pub discount:       AppliedDiscount,
+    /// Only customer allowed to use the coupon.
+    pub customer_id:    CustomerId,
+    /// Cart the coupon was issued for.
+    pub cart_id:        Option<CartId>,
+    /// Expiration timestamp.
+    pub expires_at:     u64,
+    /// Redemptions left.
+    pub uses_remaining: u32,
+}
+
+impl Coupon {
+    /// Coupon code.
+    #[must_use]
+    pub fn code(&self) -> &CouponCode {
+        &self.discount.code
+    }
+
+    /// Checks that `customer_id` may use the coupon at `now`.
+    ///
+    /// # Errors
+    /// Returns `DiscountNotEligible` for another customer, once the coupon
+    /// has expired, or once it has been used up.
+    pub fn validate_for(&self, customer_id: &CustomerId, now: u64) -> Result<(), CommerceError> {
+        let code = &self.code().0;
+        if &self.customer_id != customer_id {
+            return Err(CommerceError::DiscountNotEligible(format!(
+                "{} was issued to another customer",
+                code
+            )));
+        }
+        if now >= self.expires_at {
+            return Err(CommerceError::DiscountNotEligible(format!(
+                "{} has expired",
+                code
+            )));
+        }
+        if self.uses_remaining == 0 {
+            return Err(CommerceError::DiscountNotEligible(format!(
+                "{} was already used",
+                code
+            )));
         }
+        Ok(())
     }
 }
@@ -3,7 +3,7 @@
 //! Implementation of the InventoryService for managing inventory operations.
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{Arc, Mutex},
     time::SystemTime,
 };
@@ -12,7 +12,10 @@ use essentia_time::Instant;
 
 use crate::{
     errors::CommerceError,
-    types::{inventory_sync::*, product_catalog::ProductId},
+    types::{
+        inventory_sync::*,
+        product_catalog::{BundleComponent, ProductId},
+    },
 };
 
 impl InventoryService {
@@ -20,11 +23,16 @@ impl InventoryService {
     #[must_use]
     pub fn new() -> Self {
         let service = Self {
-            levels:      Arc::new(Mutex::new(HashMap::new())),
-            locations:   Arc::new(Mutex::new(HashMap::new())),
-            adjustments: Arc::new(Mutex::new(Vec::new())),
-            transfers:   Arc::new(Mutex::new(HashMap::new())),
-            sources:     Arc::new(Mutex::new(HashMap::new())),
+            levels:                     Arc::new(Mutex::new(HashMap::new())),
+            locations:                  Arc::new(Mutex::new(HashMap::new())),
+            adjustments:                Arc::new(Mutex::new(Vec::new())),
+            reserved_by_reference:      Arc::new(Mutex::new(HashMap::new())),
+            transfers:                  Arc::new(Mutex::new(HashMap::new())),
+            sources:                    Arc::new(Mutex::new(HashMap::new())),
+            stale_reservation_secs:     DEFAULT_STALE_RESERVATION_SECS,
+            sync_discrepancy_threshold: DEFAULT_SYNC_DISCREPANCY_THRESHOLD,
+            sync_known_locations_only:  false,
+            sync_skip_stale_changes:    false,
         };
 
         // Add default location
@@ -36,6 +44,38 @@ impl InventoryService {
         service
     }
 
+    /// Sets the age after which reservations count as stale in
+    /// [`Self::reserved_summary`].
+    #[must_use]
+    pub fn with_stale_reservation_age(mut self, secs: u64) -> Self {
+        self.stale_reservation_secs = secs;
+        self
+    }
+
+    /// Sets the on-hand change above which a synced update is reported in
+    /// [`SyncResult::discrepancies`].
+    #[must_use]
+    pub fn with_sync_discrepancy_threshold(mut self, threshold: u64) -> Self {
+        self.sync_discrepancy_threshold = threshold;
+        self
+    }
+
+    /// Fails synced changes for locations that were never added, instead of
+    /// creating a level there.
+    #[must_use]
+    pub fn with_sync_known_locations_only(mut self) -> Self {
+        self.sync_known_locations_only = true;
+        self
+    }
+
+    /// Skips synced changes whose source timestamp is older than the local
+    /// level's last update, instead of applying them.
+    #[must_use]
+    pub fn with_sync_skip_stale_changes(mut self) -> Self {
+        self.sync_skip_stale_changes = true;
+        self
+    }
+
     // ========================================================================
     // LOCATION MANAGEMENT
     // ========================================================================
@@ -73,14 +113,15 @@ impl InventoryService {
     // INVENTORY LEVEL MANAGEMENT
     // ========================================================================
 
-    /// Sets inventory level for a product at a location.
+    /// Sets inventory level for a product, or one of its variants, at a
+    /// location.
     pub fn set_inventory(
-        &self, product_id: ProductId, location_id: LocationId, on_hand: i64,
-        reason: impl Into<String>,
+        &self, product_id: ProductId, variant_id: Option<ProductId>, location_id: LocationId,
+        on_hand: i64, reason: impl Into<String>,
     ) -> Result<(), CommerceError> {
         let key = InventoryKey {
             product_id:  product_id.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.clone(),
             location_id: location_id.clone(),
         };
 
@@ -88,9 +129,10 @@ impl InventoryService {
 
         let previous_quantity = levels.get(&key).map(|l| l.on_hand).unwrap_or(0);
 
-        let level = levels
-            .entry(key)
-            .or_insert_with(|| InventoryLevel::new(product_id.clone(), location_id.clone()));
+        let level = levels.entry(key).or_insert_with(|| {
+            InventoryLevel::new(product_id.clone(), location_id.clone())
+                .with_variant(variant_id.clone())
+        });
 
         level.on_hand = on_hand;
         level.recalculate_available();
@@ -103,7 +145,8 @@ impl InventoryService {
             on_hand - previous_quantity,
             previous_quantity,
             reason,
-        );
+        )
+        .with_variant(variant_id);
 
         drop(levels);
         self.record_adjustment(adjustment)?;
@@ -111,13 +154,43 @@ impl InventoryService {
         Ok(())
     }
 
-    /// Gets inventory level for a product at a location.
+    /// Configures the stock thresholds for a product, or one of its variants,
+    /// at a location, which drive low-stock and reorder reporting.
+    ///
+    /// # Errors
+    /// Returns `InventoryNotFound` if the product is not stocked there.
+    pub fn set_thresholds(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        thresholds: StockThresholds,
+    ) -> Result<(), CommerceError> {
+        let key = InventoryKey {
+            product_id:  product_id.clone(),
+            variant_id:  variant_id.cloned(),
+            location_id: location_id.clone(),
+        };
+
+        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let level = levels
+            .get_mut(&key)
+            .ok_or_else(|| CommerceError::InventoryNotFound(product_id.0.to_string()))?;
+
+        level.low_stock_threshold = thresholds.low_stock_threshold;
+        level.reorder_point = thresholds.reorder_point;
+        level.reorder_quantity = thresholds.reorder_quantity;
+        level.safety_stock = thresholds.safety_stock;
+        level.recalculate_available();
+
+        Ok(())
+    }
+
+    /// Gets inventory level for a product, or one of its variants, at a
+    /// location.
     pub fn get_inventory(
-        &self, product_id: &ProductId, location_id: &LocationId,
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
     ) -> Result<InventoryLevel, CommerceError> {
         let key = InventoryKey {
             product_id:  product_id.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.cloned(),
             location_id: location_id.clone(),
         };
 
@@ -129,18 +202,61 @@ impl InventoryService {
     }
 
     /// Gets total available quantity across all locations.
-    pub fn get_total_available(&self, product_id: &ProductId) -> Result<i64, CommerceError> {
+    ///
+    /// With a `variant_id`, only that variant's stock is counted; otherwise
+    /// the product's own stock and all of its variants' are.
+    pub fn get_total_available(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>,
+    ) -> Result<i64, CommerceError> {
         let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
 
         let total: i64 = levels
             .iter()
-            .filter(|(k, _)| &k.product_id == product_id)
+            .filter(|(k, _)| {
+                &k.product_id == product_id
+                    && variant_id.is_none_or(|v| k.variant_id.as_ref() == Some(v))
+            })
             .map(|(_, v)| v.available)
             .sum();
 
         Ok(total)
     }
 
+    /// Gets how many bundles can be assembled from component stock across all
+    /// locations, limited by the scarcest component.
+    ///
+    /// A component listed more than once counts towards the same stock.
+    pub fn bundle_available(&self, components: &[BundleComponent]) -> Result<i64, CommerceError> {
+        let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut per_bundle: Vec<(&ProductId, Option<&ProductId>, u32)> = Vec::new();
+        for c in components {
+            let variant_id = c.variant_id.as_ref();
+            match per_bundle.iter_mut().find(|(p, v, _)| *p == &c.product_id && *v == variant_id) {
+                Some((_, _, quantity)) => *quantity = quantity.saturating_add(c.quantity),
+                None => per_bundle.push((&c.product_id, variant_id, c.quantity)),
+            }
+        }
+
+        let available = per_bundle
+            .into_iter()
+            .filter(|(_, _, quantity)| *quantity > 0)
+            .map(|(product_id, variant_id, quantity)| {
+                let total: i64 = levels
+                    .iter()
+                    .filter(|(k, _)| {
+                        &k.product_id == product_id && k.variant_id.as_ref() == variant_id
+                    })
+                    .map(|(_, v)| v.available)
+                    .sum();
+                total.max(0) / i64::from(quantity)
+            })
+            .min()
+            .unwrap_or(0);
+
+        Ok(available)
+    }
+
     /// Gets inventory levels across all locations.
     pub fn get_all_inventory_for_product(
         &self, product_id: &ProductId,
@@ -154,65 +270,323 @@ impl InventoryService {
             .collect())
     }
 
+    /// Picks the shipping location closest to the destination that can
+    /// fulfill `quantity` of a product, or one of its variants, on its own.
+    ///
+    /// Geocoded locations are ranked by distance ahead of locations without
+    /// coordinates, which fall back to fulfillment priority.
+    pub fn nearest_shippable_location(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, quantity: u32,
+        dest_lat: f64, dest_lng: f64,
+    ) -> Result<LocationId, CommerceError> {
+        let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let locations = self.locations.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut best_available: i64 = 0;
+        let mut candidates: Vec<(&InventoryLocation, Option<f64>)> = Vec::new();
+        let stocked = levels
+            .values()
+            .filter(|l| &l.product_id == product_id && l.variant_id.as_ref() == variant_id);
+        for level in stocked {
+            let Some(location) = locations.get(&level.location_id) else {
+                continue;
+            };
+            if !location.is_active || !location.can_ship {
+                continue;
+            }
+            best_available = best_available.max(level.available);
+            if level.available >= i64::from(quantity) {
+                candidates.push((location, location.distance_km_to(dest_lat, dest_lng)));
+            }
+        }
+
+        candidates.sort_by(|(a, a_dist), (b, b_dist)| {
+            let by_distance = match (a_dist, b_dist) {
+                (Some(a), Some(b)) => a.total_cmp(b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            };
+            by_distance
+                .then(a.fulfillment_priority.cmp(&b.fulfillment_priority))
+                .then_with(|| a.id.0.cmp(&b.id.0))
+        });
+
+        candidates.first().map(|(location, _)| location.id.clone()).ok_or_else(|| {
+            CommerceError::InsufficientInventory {
+                product_id: product_id.0.to_string(),
+                available:  best_available.max(0) as u32,
+                requested:  quantity,
+            }
+        })
+    }
+
+    /// Picks the location to fulfill every item from.
+    ///
+    /// Items are `(product, variant, quantity)`. Only locations serving
+    /// `fulfillment` (see [`FulfillmentType::accepts`]) and holding all of
+    /// `items` are considered. Normal orders go to the
+    /// lowest `fulfillment_priority`; rush orders go to the shortest
+    /// estimated delivery, with unestimated locations last.
+    ///
+    /// # Errors
+    /// Returns `InsufficientInventory` if no location has enough of an item,
+    /// or `ValidationError` if no single location has enough of all of them.
+    pub fn select_fulfillment_location(
+        &self, items: &[(ProductId, Option<ProductId>, u32)], rush: bool,
+        fulfillment: &FulfillmentType,
+    ) -> Result<LocationId, CommerceError> {
+        let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let locations = self.locations.lock().map_err(|_| CommerceError::LockError)?;
+
+        let available =
+            |product_id: &ProductId, variant_id: &Option<ProductId>, location_id: &LocationId| {
+                let key = InventoryKey {
+                    product_id:  product_id.clone(),
+                    variant_id:  variant_id.clone(),
+                    location_id: location_id.clone(),
+                };
+                levels.get(&key).map_or(0, |l| l.available)
+            };
+
+        let eligible: Vec<&InventoryLocation> =
+            locations.values().filter(|l| fulfillment.accepts(l)).collect();
+        let mut candidates: Vec<&InventoryLocation> = eligible
+            .iter()
+            .copied()
+            .filter(|l| items.iter().all(|(p, v, q)| available(p, v, &l.id) >= i64::from(*q)))
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            let by_speed = if rush {
+                match (a.estimated_days, b.estimated_days) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            } else {
+                std::cmp::Ordering::Equal
+            };
+            by_speed
+                .then(a.fulfillment_priority.cmp(&b.fulfillment_priority))
+                .then_with(|| a.id.0.cmp(&b.id.0))
+        });
+
+        if let Some(location) = candidates.first() {
+            return Ok(location.id.clone());
+        }
+
+        for (product_id, variant_id, quantity) in items {
+            let best = eligible
+                .iter()
+                .map(|l| available(product_id, variant_id, &l.id))
+                .max()
+                .unwrap_or(0);
+            if best < i64::from(*quantity) {
+                return Err(CommerceError::InsufficientInventory {
+                    product_id: product_id.0.to_string(),
+                    available:  best.max(0) as u32,
+                    requested:  *quantity,
+                });
+            }
+        }
+        Err(CommerceError::ValidationError(
+            "no single location can fulfill every item".to_string(),
+        ))
+    }
+
+    /// Allocates `quantity` of a product, or one of its variants, across the
+    /// locations serving `fulfillment` without reserving anything.
+    ///
+    /// Locations are drawn from in fulfillment-priority order, each giving
+    /// up to its available stock, so the fewest preferred locations are
+    /// used.
+    ///
+    /// # Errors
+    /// Returns `InsufficientInventory` if those locations together cannot
+    /// cover the quantity.
+    pub fn split_fulfillment(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, quantity: u32,
+        fulfillment: &FulfillmentType,
+    ) -> Result<Vec<(LocationId, u32)>, CommerceError> {
+        let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let locations = self.locations.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut sources: Vec<(&InventoryLocation, i64)> = levels
+            .values()
+            .filter(|l| {
+                &l.product_id == product_id
+                    && l.variant_id.as_ref() == variant_id
+                    && l.available > 0
+            })
+            .filter_map(|l| {
+                let location = locations.get(&l.location_id)?;
+                fulfillment.accepts(location).then_some((location, l.available))
+            })
+            .collect();
+        sources.sort_by(|(a, _), (b, _)| {
+            a.fulfillment_priority
+                .cmp(&b.fulfillment_priority)
+                .then_with(|| a.id.0.cmp(&b.id.0))
+        });
+
+        let mut remaining = quantity;
+        let mut allocation = Vec::new();
+        for (location, available) in &sources {
+            if remaining == 0 {
+                break;
+            }
+            let take = remaining.min(u32::try_from(*available).unwrap_or(u32::MAX));
+            allocation.push((location.id.clone(), take));
+            remaining -= take;
+        }
+
+        if remaining > 0 {
+            return Err(CommerceError::InsufficientInventory {
+                product_id: product_id.0.to_string(),
+                available:  quantity - remaining,
+                requested:  quantity,
+            });
+        }
+        Ok(allocation)
+    }
+
     // ========================================================================
     // STOCK OPERATIONS
     // ========================================================================
 
     /// Reserves stock for an order.
+    ///
+    /// Reservations are idempotent on `reference`: repeating one that is
+    /// still held with the same quantity (e.g. a retried request) does
+    /// nothing.
+    ///
+    /// # Errors
+    /// Returns `ReservationConflict` if the reference already holds a
+    /// different quantity, or `InsufficientInventory` if stock is short.
     pub fn reserve_stock(
-        &self, product_id: &ProductId, location_id: &LocationId, quantity: u32,
-        reference: impl Into<String>,
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        quantity: u32, reference: impl Into<String>,
     ) -> Result<(), CommerceError> {
         let key = InventoryKey {
             product_id:  product_id.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.cloned(),
             location_id: location_id.clone(),
         };
+        self.reserve_all(
+            &[(key, quantity)],
+            &reference.into(),
+            "Stock reserved for order",
+        )
+    }
 
-        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
-
-        let level = levels
-            .get_mut(&key)
-            .ok_or_else(|| CommerceError::InventoryNotFound(product_id.0.to_string()))?;
+    /// Reserves the components of `quantity` bundles at a location.
+    ///
+    /// Either every component is reserved or, if any is short, none is.
+    /// Idempotent on `reference` like [`Self::reserve_stock`].
+    pub fn reserve_bundle(
+        &self, components: &[BundleComponent], location_id: &LocationId, quantity: u32,
+        reference: impl Into<String>,
+    ) -> Result<(), CommerceError> {
+        let requested = InventoryKey::for_components(components, location_id, quantity);
+        self.reserve_all(&requested, &reference.into(), "Stock reserved for bundle")
+    }
 
-        if level.available < i64::from(quantity) {
-            return Err(CommerceError::InsufficientInventory {
-                product_id: product_id.0.to_string(),
-                available:  level.available.max(0) as u32,
-                requested:  quantity,
-            });
-        }
+    /// Reserves several products or variants, possibly at different
+    /// locations, under one reference.
+    ///
+    /// Either every line is reserved or, if any is short, none is. Lines for
+    /// the same key draw on the same stock. Idempotent on `reference` like
+    /// [`Self::reserve_stock`].
+    ///
+    /// # Errors
+    /// Returns `InventoryNotFound`, `ReservationConflict` or
+    /// `InsufficientInventory` for the first line that cannot be satisfied,
+    /// with nothing reserved.
+    pub fn reserve_stock_batch(
+        &self, reservations: &[(InventoryKey, u32)], reference: &str,
+    ) -> Result<(), CommerceError> {
+        self.reserve_all(reservations, reference, "Stock reserved for order")
+    }
 
-        let previous = level.committed;
-        level.committed = level.committed.saturating_add(i64::from(quantity));
-        level.recalculate_available();
+    /// Reserves every requested quantity under `reference`, or nothing.
+    ///
+    /// Quantities for the same key are combined. A key the reference already
+    /// holds with the combined quantity is left as is, so retries are no-ops.
+    fn reserve_all(
+        &self, requested: &[(InventoryKey, u32)], reference: &str, reason: &str,
+    ) -> Result<(), CommerceError> {
+        let combined = combine_quantities(requested);
 
-        // Clone product_id and location_id for the adjustment since we still need
-        // references for error handling
-        let adjustment = InventoryAdjustment::new(
-            product_id.clone(),
-            location_id.clone(),
-            AdjustmentType::Reserved,
-            i64::from(quantity),
-            previous,
-            "Stock reserved for order",
-        )
-        .with_reference(reference);
+        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let mut adjustments = self.adjustments.lock().map_err(|_| CommerceError::LockError)?;
+        let mut reserved_by_reference =
+            self.reserved_by_reference.lock().map_err(|_| CommerceError::LockError)?;
+
+        // Check every key before reserving any
+        let mut pending = Vec::with_capacity(combined.len());
+        for (key, quantity) in combined {
+            let level = levels
+                .get(key)
+                .ok_or_else(|| CommerceError::InventoryNotFound(key.product_id.0.to_string()))?;
+
+            let reserved = reserved_in_ledger(&reserved_by_reference, key, reference);
+            if reserved == quantity {
+                continue;
+            }
+            if reserved > 0 {
+                return Err(CommerceError::ReservationConflict {
+                    reference: reference.to_string(),
+                    reserved,
+                    requested: quantity,
+                });
+            }
+            if level.available < i64::from(quantity) {
+                return Err(CommerceError::InsufficientInventory {
+                    product_id: key.product_id.0.to_string(),
+                    available:  level.available.max(0) as u32,
+                    requested:  quantity,
+                });
+            }
+            pending.push((key, quantity));
+        }
 
-        drop(levels);
-        self.record_adjustment(adjustment)?;
+        for (key, quantity) in pending {
+            let Some(level) = levels.get_mut(key) else {
+                continue;
+            };
+            let previous = level.committed;
+            level.committed = level.committed.saturating_add(i64::from(quantity));
+            level.recalculate_available();
+
+            append_to_ledger(
+                &mut adjustments,
+                &mut reserved_by_reference,
+                InventoryAdjustment::new(
+                    key.product_id.clone(),
+                    key.location_id.clone(),
+                    AdjustmentType::Reserved,
+                    i64::from(quantity),
+                    previous,
+                    reason,
+                )
+                .with_variant(key.variant_id.clone())
+                .with_reference(reference),
+            );
+        }
 
         Ok(())
     }
 
     /// Releases reserved stock (e.g., order cancelled).
     pub fn release_stock(
-        &self, product_id: &ProductId, location_id: &LocationId, quantity: u32,
-        reference: impl Into<String>,
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        quantity: u32, reference: impl Into<String>,
     ) -> Result<(), CommerceError> {
         let key = InventoryKey {
             product_id:  product_id.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.cloned(),
             location_id: location_id.clone(),
         };
 
@@ -234,6 +608,7 @@ impl InventoryService {
             previous,
             "Stock released",
         )
+        .with_variant(variant_id.cloned())
         .with_reference(reference);
 
         drop(levels);
@@ -242,14 +617,180 @@ impl InventoryService {
         Ok(())
     }
 
+    /// Quantity still reserved at a location under a reference, according to
+    /// the adjustment ledger (reserved minus released and shipped).
+    pub fn reserved_for_reference(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        reference: &str,
+    ) -> Result<u32, CommerceError> {
+        let key = InventoryKey {
+            product_id:  product_id.clone(),
+            variant_id:  variant_id.cloned(),
+            location_id: location_id.clone(),
+        };
+        let reserved_by_reference =
+            self.reserved_by_reference.lock().map_err(|_| CommerceError::LockError)?;
+        Ok(reserved_in_ledger(&reserved_by_reference, &key, reference))
+    }
+
+    /// Summarizes outstanding reservations across all products and locations.
+    ///
+    /// Releases and shipments under a reference consume its oldest
+    /// reservations first; what remains counts as stale once older than
+    /// `stale_reservation_secs`.
+    pub fn reserved_summary(&self) -> Result<ReservedSummary, CommerceError> {
+        let adjustments = self.adjustments.lock().map_err(|_| CommerceError::LockError)?;
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let cutoff = now.saturating_sub(self.stale_reservation_secs);
+
+        // Outstanding (created_at, quantity) reservations, oldest first
+        type Key<'a> = (
+            &'a ProductId,
+            Option<&'a ProductId>,
+            &'a LocationId,
+            &'a str,
+        );
+        let mut open: HashMap<Key<'_>, VecDeque<(u64, i64)>> = HashMap::new();
+        for adjustment in adjustments.iter() {
+            let Some(reference) = adjustment.reference.as_deref() else {
+                continue;
+            };
+            let key = (
+                &adjustment.product_id,
+                adjustment.variant_id.as_ref(),
+                &adjustment.location_id,
+                reference,
+            );
+            match adjustment.adjustment_type {
+                AdjustmentType::Reserved => {
+                    open.entry(key)
+                        .or_default()
+                        .push_back((adjustment.created_at, adjustment.quantity));
+                },
+                AdjustmentType::Unreserved | AdjustmentType::Shipped => {
+                    let Some(queue) = open.get_mut(&key) else {
+                        continue;
+                    };
+                    let mut remaining = -adjustment.quantity;
+                    while remaining > 0 {
+                        let Some(front) = queue.front_mut() else {
+                            break;
+                        };
+                        let taken = front.1.min(remaining);
+                        front.1 -= taken;
+                        remaining -= taken;
+                        if front.1 == 0 {
+                            queue.pop_front();
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let mut summary = ReservedSummary::default();
+        for ((_, _, _, reference), queue) in open {
+            for (created_at, quantity) in queue {
+                summary.total_reserved += quantity;
+                if created_at < cutoff {
+                    summary.stale_reserved += quantity;
+                }
+                *summary.by_reference.entry(reference.to_string()).or_default() += quantity;
+            }
+        }
+        summary.by_reference.retain(|_, quantity| *quantity > 0);
+
+        Ok(summary)
+    }
+
+    /// Releases part of the stock reserved under a reference, e.g. when some
+    /// lines of an order are cancelled.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` if more than is still reserved for the
+    /// reference would be released.
+    pub fn release_for_reference_partial(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        quantity: u32, reference: impl Into<String>,
+    ) -> Result<(), CommerceError> {
+        let key = InventoryKey::new(product_id.clone(), variant_id.cloned(), location_id.clone());
+        self.release_for_reference_batch(&[(key, quantity)], &reference.into())
+    }
+
+    /// Releases stock reserved under a reference for several keys at once.
+    ///
+    /// Either everything is released or, if any key has less than requested
+    /// still reserved under the reference, nothing is. Quantities for the
+    /// same key are combined.
+    ///
+    /// # Errors
+    /// Returns `InventoryNotFound` for an unstocked key, or `ValidationError`
+    /// if more than is still reserved for the reference would be released.
+    pub fn release_for_reference_batch(
+        &self, releases: &[(InventoryKey, u32)], reference: &str,
+    ) -> Result<(), CommerceError> {
+        let combined = combine_quantities(releases);
+
+        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let mut adjustments = self.adjustments.lock().map_err(|_| CommerceError::LockError)?;
+        let mut reserved_by_reference =
+            self.reserved_by_reference.lock().map_err(|_| CommerceError::LockError)?;
+
+        // Check every key before releasing any
+        for (key, quantity) in &combined {
+            if !levels.contains_key(*key) {
+                return Err(CommerceError::InventoryNotFound(
+                    key.product_id.0.to_string(),
+                ));
+            }
+            let reserved = reserved_in_ledger(&reserved_by_reference, key, reference);
+            if *quantity > reserved {
+                return Err(CommerceError::ValidationError(format!(
+                    "cannot release {quantity} of {}: only {reserved} reserved for {reference}",
+                    key.product_id.0
+                )));
+            }
+        }
+
+        for (key, quantity) in combined {
+            let Some(level) = levels.get_mut(key) else {
+                continue;
+            };
+            let previous = level.committed;
+            level.committed = level.committed.saturating_sub(i64::from(quantity));
+            level.recalculate_available();
+
+            append_to_ledger(
+                &mut adjustments,
+                &mut reserved_by_reference,
+                InventoryAdjustment::new(
+                    key.product_id.clone(),
+                    key.location_id.clone(),
+                    AdjustmentType::Unreserved,
+                    -(i64::from(quantity)),
+                    previous,
+                    "Stock released",
+                )
+                .with_variant(key.variant_id.clone())
+                .with_reference(reference),
+            );
+        }
+
+        Ok(())
+    }
+
     /// Commits stock (deduct from on-hand for shipped order).
     pub fn commit_stock(
-        &self, product_id: &ProductId, location_id: &LocationId, quantity: u32,
-        reference: impl Into<String>,
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        quantity: u32, reference: impl Into<String>,
     ) -> Result<(), CommerceError> {
         let key = InventoryKey {
             product_id:  product_id.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.cloned(),
             location_id: location_id.clone(),
         };
 
@@ -272,6 +813,7 @@ impl InventoryService {
             previous,
             "Stock shipped",
         )
+        .with_variant(variant_id.cloned())
         .with_reference(reference);
 
         drop(levels);
@@ -282,8 +824,8 @@ impl InventoryService {
 
     /// Receives stock (add to on-hand).
     pub fn receive_stock(
-        &self, product_id: &ProductId, location_id: &LocationId, quantity: u32,
-        reference: impl Into<String>,
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        quantity: u32, reference: impl Into<String>,
     ) -> Result<(), CommerceError> {
         // Clone for key - required since we need owned values in the key
         let product_id_owned = product_id.clone();
@@ -291,7 +833,7 @@ impl InventoryService {
 
         let key = InventoryKey {
             product_id:  product_id_owned.clone(),
-            variant_id:  None,
+            variant_id:  variant_id.cloned(),
             location_id: location_id_owned.clone(),
         };
 
@@ -299,6 +841,7 @@ impl InventoryService {
 
         let level = levels.entry(key).or_insert_with(|| {
             InventoryLevel::new(product_id_owned.clone(), location_id_owned.clone())
+                .with_variant(variant_id.cloned())
         });
 
         let previous = level.on_hand;
@@ -313,6 +856,7 @@ impl InventoryService {
             previous,
             "Stock received",
         )
+        .with_variant(variant_id.cloned())
         .with_reference(reference);
 
         drop(levels);
@@ -351,9 +895,35 @@ impl InventoryService {
             .ok_or_else(|| CommerceError::TransferNotFound(id.to_string()))
     }
 
-    /// Completes a transfer.
-    pub fn complete_transfer(&self, transfer_id: &str) -> Result<(), CommerceError> {
-        // First, get the transfer data and validate status
+    /// Adds a product, or one of its variants, to a pending transfer.
+    ///
+    /// # Errors
+    /// Returns `InvalidTransferStatus` once the transfer has been dispatched.
+    pub fn add_transfer_item(
+        &self, transfer_id: &str, product_id: ProductId, variant_id: Option<ProductId>,
+        quantity: u32,
+    ) -> Result<(), CommerceError> {
+        let mut transfers = self.transfers.lock().map_err(|_| CommerceError::LockError)?;
+        let transfer = transfers
+            .get_mut(transfer_id)
+            .ok_or_else(|| CommerceError::TransferNotFound(transfer_id.to_string()))?;
+
+        if transfer.status != TransferStatus::Pending {
+            return Err(CommerceError::InvalidTransferStatus);
+        }
+        transfer.add_variant_item(product_id, variant_id, quantity);
+        Ok(())
+    }
+
+    /// Dispatches a pending transfer.
+    ///
+    /// Stock leaves the source location and is counted as in transit at the
+    /// destination until received.
+    ///
+    /// # Errors
+    /// Returns `InsufficientInventory` if the source cannot cover an item;
+    /// nothing is moved in that case.
+    pub fn dispatch_transfer(&self, transfer_id: &str) -> Result<(), CommerceError> {
         let (items, from_location, to_location) = {
             let transfers = self.transfers.lock().map_err(|_| CommerceError::LockError)?;
 
@@ -361,13 +931,10 @@ impl InventoryService {
                 .get(transfer_id)
                 .ok_or_else(|| CommerceError::TransferNotFound(transfer_id.to_string()))?;
 
-            if transfer.status != TransferStatus::Pending
-                && transfer.status != TransferStatus::InProgress
-            {
+            if transfer.status != TransferStatus::Pending {
                 return Err(CommerceError::InvalidTransferStatus);
             }
 
-            // Clone the data we need
             (
                 transfer.items.clone(),
                 transfer.from_location.clone(),
@@ -375,31 +942,224 @@ impl InventoryService {
             )
         };
 
-        // Move stock for each item (lock is released)
+        let reference = format!("Transfer {}", transfer_id);
+        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+
+        // Check every item before moving anything
         for item in &items {
-            let reference = format!("Transfer {}", transfer_id);
+            let requested: u32 = items
+                .iter()
+                .filter(|other| {
+                    other.product_id == item.product_id && other.variant_id == item.variant_id
+                })
+                .map(|other| other.quantity)
+                .sum();
+            let key = InventoryKey {
+                product_id:  item.product_id.clone(),
+                variant_id:  item.variant_id.clone(),
+                location_id: from_location.clone(),
+            };
+            let available = levels.get(&key).map_or(0, |level| level.available);
+            if available < i64::from(requested) {
+                return Err(CommerceError::InsufficientInventory {
+                    product_id: item.product_id.0.to_string(),
+                    available: available.max(0) as u32,
+                    requested,
+                });
+            }
+        }
 
-            // Deduct from source
-            self.commit_stock(&item.product_id, &from_location, item.quantity, &reference)?;
+        let mut adjustments = Vec::with_capacity(items.len());
+        for item in &items {
+            let quantity = i64::from(item.quantity);
+            let source_key = InventoryKey {
+                product_id:  item.product_id.clone(),
+                variant_id:  item.variant_id.clone(),
+                location_id: from_location.clone(),
+            };
+            if let Some(level) = levels.get_mut(&source_key) {
+                let previous = level.on_hand;
+                level.on_hand -= quantity;
+                level.recalculate_available();
+                adjustments.push(
+                    InventoryAdjustment::new(
+                        item.product_id.clone(),
+                        from_location.clone(),
+                        AdjustmentType::Transfer,
+                        -quantity,
+                        previous,
+                        "Stock dispatched",
+                    )
+                    .with_variant(item.variant_id.clone())
+                    .with_reference(reference.clone()),
+                );
+            }
 
-            // Add to destination
-            self.receive_stock(&item.product_id, &to_location, item.quantity, &reference)?;
+            let destination_key = InventoryKey {
+                product_id:  item.product_id.clone(),
+                variant_id:  item.variant_id.clone(),
+                location_id: to_location.clone(),
+            };
+            let level = levels.entry(destination_key).or_insert_with(|| {
+                InventoryLevel::new(item.product_id.clone(), to_location.clone())
+                    .with_variant(item.variant_id.clone())
+            });
+            level.in_transit += quantity;
+        }
+        drop(levels);
+
+        for adjustment in adjustments {
+            self.record_adjustment(adjustment)?;
         }
 
-        // Update transfer status
         let mut transfers = self.transfers.lock().map_err(|_| CommerceError::LockError)?;
         let transfer = transfers
             .get_mut(transfer_id)
             .ok_or_else(|| CommerceError::TransferNotFound(transfer_id.to_string()))?;
+        transfer.status = TransferStatus::InProgress;
+        transfer.updated_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
 
-        transfer.status = TransferStatus::Completed;
-        transfer.arrived_at = Some(
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
-        );
+        Ok(())
+    }
+
+    /// Receives part of a dispatched transfer of a product, or one of its
+    /// variants, at its destination.
+    ///
+    /// The quantity moves from in transit to on hand. The transfer completes
+    /// once every item is fully received.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` if the product is not on the transfer or the
+    /// quantity exceeds what is still in transit.
+    pub fn receive_transfer(
+        &self, transfer_id: &str, product_id: &ProductId, variant_id: Option<&ProductId>,
+        quantity: u32,
+    ) -> Result<(), CommerceError> {
+        let is_item = |item: &TransferItem| {
+            &item.product_id == product_id && item.variant_id.as_ref() == variant_id
+        };
+        // Held until the receipt is recorded so concurrent receipts cannot
+        // both pass the outstanding check
+        let mut transfers = self.transfers.lock().map_err(|_| CommerceError::LockError)?;
+
+        let transfer = transfers
+            .get_mut(transfer_id)
+            .ok_or_else(|| CommerceError::TransferNotFound(transfer_id.to_string()))?;
+
+        if transfer.status != TransferStatus::InProgress {
+            return Err(CommerceError::InvalidTransferStatus);
+        }
+
+        let outstanding: u32 = transfer
+            .items
+            .iter()
+            .filter(|item| is_item(item))
+            .map(|item| item.quantity - item.quantity_received)
+            .sum();
+        if quantity == 0 || quantity > outstanding {
+            return Err(CommerceError::ValidationError(format!(
+                "cannot receive {} of {} on transfer {} ({} in transit)",
+                quantity, product_id, transfer_id, outstanding
+            )));
+        }
+
+        let reference = format!("Transfer {}", transfer_id);
+        let to_location = transfer.to_location.clone();
+        self.adjust_in_transit(product_id, variant_id, &to_location, -i64::from(quantity))?;
+        self.receive_stock(product_id, variant_id, &to_location, quantity, &reference)?;
+
+        let mut remaining = quantity;
+        for item in transfer.items.iter_mut().filter(|item| is_item(item)) {
+            let received = remaining.min(item.quantity - item.quantity_received);
+            item.quantity_received += received;
+            remaining -= received;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        transfer.updated_at = now;
+        if transfer.items.iter().all(|item| item.quantity_received >= item.quantity) {
+            transfer.status = TransferStatus::Completed;
+            transfer.arrived_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    /// Completes a transfer, dispatching it first if still pending and
+    /// receiving everything still in transit.
+    pub fn complete_transfer(&self, transfer_id: &str) -> Result<(), CommerceError> {
+        if self.get_transfer(transfer_id)?.status == TransferStatus::Pending {
+            self.dispatch_transfer(transfer_id)?;
+        }
+
+        let transfer = self.get_transfer(transfer_id)?;
+        if transfer.status != TransferStatus::InProgress {
+            return Err(CommerceError::InvalidTransferStatus);
+        }
+
+        let mut outstanding: Vec<(&ProductId, Option<&ProductId>, u32)> = Vec::new();
+        for item in &transfer.items {
+            let quantity = item.quantity - item.quantity_received;
+            let variant_id = item.variant_id.as_ref();
+            match outstanding
+                .iter_mut()
+                .find(|(id, variant, _)| *id == &item.product_id && *variant == variant_id)
+            {
+                Some((_, _, total)) => *total += quantity,
+                None => outstanding.push((&item.product_id, variant_id, quantity)),
+            }
+        }
+
+        for (product_id, variant_id, quantity) in outstanding.into_iter().filter(|(_, _, q)| *q > 0)
+        {
+            self.receive_transfer(transfer_id, product_id, variant_id, quantity)?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets the quantity of a product in transit to any location.
+    ///
+    /// With a `variant_id`, only that variant's transfers are counted;
+    /// otherwise the product's own and all of its variants' are.
+    pub fn get_total_in_transit(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>,
+    ) -> Result<i64, CommerceError> {
+        let levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+
+        Ok(levels
+            .iter()
+            .filter(|(k, _)| {
+                &k.product_id == product_id
+                    && variant_id.is_none_or(|v| k.variant_id.as_ref() == Some(v))
+            })
+            .map(|(_, v)| v.in_transit)
+            .sum())
+    }
 
+    /// Changes the in-transit quantity of a product or variant at a location.
+    fn adjust_in_transit(
+        &self, product_id: &ProductId, variant_id: Option<&ProductId>, location_id: &LocationId,
+        delta: i64,
+    ) -> Result<(), CommerceError> {
+        let key = InventoryKey {
+            product_id:  product_id.clone(),
+            variant_id:  variant_id.cloned(),
+            location_id: location_id.clone(),
+        };
+
+        let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
+        let level = levels.entry(key).or_insert_with(|| {
+            InventoryLevel::new(product_id.clone(), location_id.clone())
+                .with_variant(variant_id.cloned())
+        });
+        level.in_transit = level.in_transit.saturating_add(delta).max(0);
         Ok(())
     }
 
@@ -423,19 +1183,37 @@ impl InventoryService {
         let mut updated = 0u32;
         let mut failed = 0u32;
         let mut errors = Vec::new();
+        let mut details = Vec::with_capacity(changes.len());
+        let mut discrepancies = Vec::new();
 
         for change in changes {
             processed += 1;
 
             // Attempt to apply change
-            let result = self.apply_single_change(&change, source_id);
-            match result {
-                Ok(()) => updated += 1,
-                Err(e) => {
+            let outcome = match self.apply_single_change(&change, source_id) {
+                Ok(outcome) => outcome,
+                Err(e) => SyncChangeOutcome::Failed(e.to_string()),
+            };
+            match &outcome {
+                SyncChangeOutcome::Updated { from, to } => {
+                    updated += 1;
+                    // Still applied, but a jump this large may be a data error
+                    if from.abs_diff(*to) > self.sync_discrepancy_threshold {
+                        discrepancies.push((ProductId::new(&change.product_id), *from, *to));
+                    }
+                },
+                SyncChangeOutcome::Skipped(_) => {},
+                SyncChangeOutcome::Failed(reason) => {
                     failed += 1;
-                    errors.push(format!("Product {}: {}", change.product_id, e));
+                    errors.push(format!("Product {}: {}", change.product_id, reason));
                 },
             }
+
+            details.push(SyncChangeResult {
+                product_id: change.product_id,
+                location_id: change.location_id,
+                outcome,
+            });
         }
 
         let now = SystemTime::now()
@@ -458,18 +1236,34 @@ impl InventoryService {
             items_updated: updated,
             items_failed: failed,
             errors,
+            details,
+            discrepancies,
             synced_at: now,
             duration_ms: start.elapsed().as_millis() as u64,
         })
     }
 
     /// Applies a single inventory change.
+    ///
+    /// See [`Self::with_sync_known_locations_only`] and
+    /// [`Self::with_sync_skip_stale_changes`] for changes that are not
+    /// applied.
     fn apply_single_change(
         &self, change: &InventoryChange, source_id: &str,
-    ) -> Result<(), CommerceError> {
+    ) -> Result<SyncChangeOutcome, CommerceError> {
         let product_id = ProductId::new(&change.product_id);
         let location_id = LocationId::new(&change.location_id);
 
+        if self.sync_known_locations_only
+            && !self
+                .locations
+                .lock()
+                .map_err(|_| CommerceError::LockError)?
+                .contains_key(&location_id)
+        {
+            return Err(CommerceError::LocationNotFound(change.location_id.clone()));
+        }
+
         // Clone once for key, reuse for or_insert_with
         let key = InventoryKey {
             product_id:  product_id.clone(),
@@ -479,6 +1273,17 @@ impl InventoryService {
 
         let mut levels = self.levels.lock().map_err(|_| CommerceError::LockError)?;
 
+        if self.sync_skip_stale_changes
+            && let (Some(existing), Some(source_timestamp)) =
+                (levels.get(&key), change.source_timestamp)
+            && source_timestamp < existing.updated_at
+        {
+            return Ok(SyncChangeOutcome::Skipped(format!(
+                "change at {} is older than local update at {}",
+                source_timestamp, existing.updated_at
+            )));
+        }
+
         // Use key's cloned values for or_insert_with to avoid additional clones
         let key_product_id = product_id.clone();
         let key_location_id = location_id.clone();
@@ -486,6 +1291,7 @@ impl InventoryService {
             .entry(key)
             .or_insert_with(|| InventoryLevel::new(key_product_id, key_location_id));
 
+        let from = level.on_hand;
         match change.change_type {
             InventoryChangeType::Set => {
                 level.on_hand = change.quantity;
@@ -497,6 +1303,7 @@ impl InventoryService {
                 level.on_hand = level.on_hand.saturating_sub(change.quantity);
             },
         }
+        let to = level.on_hand;
 
         level.recalculate_available();
 
@@ -505,15 +1312,15 @@ impl InventoryService {
             product_id,
             location_id,
             AdjustmentType::Adjustment,
-            change.quantity,
-            level.on_hand - change.quantity,
+            to - from,
+            from,
             format!("Sync from {}", source_id),
         );
 
         drop(levels);
         self.record_adjustment(adjustment)?;
 
-        Ok(())
+        Ok(SyncChangeOutcome::Updated { from, to })
     }
 
     // ========================================================================
@@ -548,7 +1355,9 @@ impl InventoryService {
     /// Records an adjustment.
     fn record_adjustment(&self, adjustment: InventoryAdjustment) -> Result<(), CommerceError> {
         let mut adjustments = self.adjustments.lock().map_err(|_| CommerceError::LockError)?;
-        adjustments.push(adjustment);
+        let mut reserved_by_reference =
+            self.reserved_by_reference.lock().map_err(|_| CommerceError::LockError)?;
+        append_to_ledger(&mut adjustments, &mut reserved_by_reference, adjustment);
         Ok(())
     }
 
@@ -572,6 +1381,62 @@ impl InventoryService {
     }
 }
 
+/// Default age after which a reservation counts as stale (one day).
+const DEFAULT_STALE_RESERVATION_SECS: u64 = 24 * 60 * 60;
+
+/// Default on-hand change above which a synced update is a discrepancy.
+const DEFAULT_SYNC_DISCREPANCY_THRESHOLD: u64 = 1_000;
+
+/// Sums the quantities requested for each key, keeping the order in which
+/// keys first appear.
+fn combine_quantities(requested: &[(InventoryKey, u32)]) -> Vec<(&InventoryKey, u32)> {
+    let mut combined: Vec<(&InventoryKey, u32)> = Vec::with_capacity(requested.len());
+    for (key, quantity) in requested {
+        match combined.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, total)) => *total = total.saturating_add(*quantity),
+            None => combined.push((key, *quantity)),
+        }
+    }
+    combined
+}
+
+/// Appends an adjustment to the ledger, keeping the per-reference
+/// reservation index in step.
+fn append_to_ledger(
+    adjustments: &mut Vec<InventoryAdjustment>,
+    reserved_by_reference: &mut HashMap<String, HashMap<InventoryKey, i64>>,
+    adjustment: InventoryAdjustment,
+) {
+    if let Some(reference) = &adjustment.reference
+        && matches!(
+            adjustment.adjustment_type,
+            AdjustmentType::Reserved | AdjustmentType::Unreserved | AdjustmentType::Shipped
+        )
+    {
+        let key = InventoryKey {
+            product_id:  adjustment.product_id.clone(),
+            variant_id:  adjustment.variant_id.clone(),
+            location_id: adjustment.location_id.clone(),
+        };
+        *reserved_by_reference
+            .entry(reference.clone())
+            .or_default()
+            .entry(key)
+            .or_default() += adjustment.quantity;
+    }
+    adjustments.push(adjustment);
+}
+
+/// Net quantity reserved under a reference: reservations less releases and
+/// shipments recorded against it.
+fn reserved_in_ledger(
+    reserved_by_reference: &HashMap<String, HashMap<InventoryKey, i64>>, key: &InventoryKey,
+    reference: &str,
+) -> u32 {
+    let net = reserved_by_reference.get(reference).and_then(|keys| keys.get(key)).copied();
+    u32::try_from(net.unwrap_or(0).max(0)).unwrap_or(u32::MAX)
+}
+
 impl Default for InventoryService {
     fn default() -> Self {
         Self::new()
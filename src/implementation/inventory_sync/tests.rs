@@ -4,9 +4,16 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::types::{
-        inventory_sync::{InventoryLocation, InventoryService, LocationId},
-        product_catalog::ProductId,
+    use crate::{
+        errors::CommerceError,
+        types::{
+            inventory_sync::{
+                FulfillmentType, InventoryChange, InventoryChangeType, InventoryKey,
+                InventoryLocation, InventoryService, LocationId, StockThresholds,
+                SyncChangeOutcome, SyncStatus, TransferStatus,
+            },
+            product_catalog::{BundleComponent, ProductId},
+        },
     };
 
     #[test]
@@ -27,13 +34,14 @@ mod tests {
         service
             .set_inventory(
                 product_id.clone(),
+                None,
                 location_id.clone(),
                 100,
                 "Initial stock",
             )
             .expect("set inventory");
 
-        let level = service.get_inventory(&product_id, &location_id).expect("get");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
 
         assert_eq!(level.on_hand, 100);
         assert_eq!(level.available, 100);
@@ -46,14 +54,20 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 100, "Initial")
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                100,
+                "Initial",
+            )
             .expect("set");
 
         service
-            .reserve_stock(&product_id, &location_id, 30, "ORD-001")
+            .reserve_stock(&product_id, None, &location_id, 30, "ORD-001")
             .expect("reserve");
 
-        let level = service.get_inventory(&product_id, &location_id).expect("get");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
         assert_eq!(level.on_hand, 100);
         assert_eq!(level.committed, 30);
         assert_eq!(level.available, 70);
@@ -66,10 +80,16 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 10, "Low stock")
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                10,
+                "Low stock",
+            )
             .expect("set");
 
-        let result = service.reserve_stock(&product_id, &location_id, 50, "ORD-001");
+        let result = service.reserve_stock(&product_id, None, &location_id, 50, "ORD-001");
         assert!(result.is_err());
     }
 
@@ -80,15 +100,23 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 100, "Initial")
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                100,
+                "Initial",
+            )
             .expect("set");
 
         service
-            .reserve_stock(&product_id, &location_id, 30, "ORD-001")
+            .reserve_stock(&product_id, None, &location_id, 30, "ORD-001")
             .expect("reserve");
-        service.commit_stock(&product_id, &location_id, 30, "ORD-001").expect("commit");
+        service
+            .commit_stock(&product_id, None, &location_id, 30, "ORD-001")
+            .expect("commit");
 
-        let level = service.get_inventory(&product_id, &location_id).expect("get");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
         assert_eq!(level.on_hand, 70);
         assert_eq!(level.committed, 0);
         assert_eq!(level.available, 70);
@@ -101,14 +129,14 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 50, "Initial")
+            .set_inventory(product_id.clone(), None, location_id.clone(), 50, "Initial")
             .expect("set");
 
         service
-            .receive_stock(&product_id, &location_id, 100, "PO-001")
+            .receive_stock(&product_id, None, &location_id, 100, "PO-001")
             .expect("receive");
 
-        let level = service.get_inventory(&product_id, &location_id).expect("get");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
         assert_eq!(level.on_hand, 150);
         assert_eq!(level.available, 150);
     }
@@ -120,16 +148,69 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 5, "Low stock")
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                5,
+                "Low stock",
+            )
             .expect("set");
 
-        let level = service.get_inventory(&product_id, &location_id).expect("get");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
         assert!(level.is_low_stock());
 
         let low_stock = service.get_low_stock_products().expect("get low");
         assert_eq!(low_stock.len(), 1);
     }
 
+    #[test]
+    fn test_custom_thresholds_drive_low_stock_and_reorder() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let small = ProductId::new("prod-001-s");
+        let location_id = LocationId::default_warehouse();
+
+        for variant_id in [None, Some(small.clone())] {
+            service
+                .set_inventory(
+                    product_id.clone(),
+                    variant_id,
+                    location_id.clone(),
+                    40,
+                    "Initial",
+                )
+                .expect("set");
+        }
+        // Above the default threshold of 10 and reorder point of 20
+        assert!(service.get_low_stock_products().expect("get low").is_empty());
+        assert!(service.get_reorder_needed().expect("get reorder").is_empty());
+
+        let thresholds = StockThresholds {
+            low_stock_threshold: 50,
+            reorder_point:       60,
+            reorder_quantity:    100,
+            safety_stock:        10,
+        };
+        service
+            .set_thresholds(&product_id, Some(&small), &location_id, thresholds)
+            .expect("thresholds");
+        let low = service.get_low_stock_products().expect("get low");
+        assert_eq!(low.len(), 1);
+        assert_eq!(low[0].variant_id, Some(small));
+        let reorder = service.get_reorder_needed().expect("get reorder");
+        assert_eq!(reorder.len(), 1);
+        assert_eq!(reorder[0].reorder_quantity, 100);
+
+        let missing = service.set_thresholds(
+            &ProductId::new("prod-unknown"),
+            None,
+            &location_id,
+            StockThresholds::default(),
+        );
+        assert!(matches!(missing, Err(CommerceError::InventoryNotFound(_))));
+    }
+
     #[test]
     fn test_total_available_across_locations() {
         let service = InventoryService::new();
@@ -146,16 +227,136 @@ mod tests {
             .expect("add location");
 
         service
-            .set_inventory(product_id.clone(), location1, 100, "Stock 1")
+            .set_inventory(product_id.clone(), None, location1, 100, "Stock 1")
             .expect("set 1");
         service
-            .set_inventory(product_id.clone(), location2, 50, "Stock 2")
+            .set_inventory(product_id.clone(), None, location2, 50, "Stock 2")
             .expect("set 2");
 
-        let total = service.get_total_available(&product_id).expect("total");
+        let total = service.get_total_available(&product_id, None).expect("total");
         assert_eq!(total, 150);
     }
 
+    #[test]
+    fn test_variant_reservations_do_not_interfere() {
+        let service = InventoryService::new();
+        let shirt = ProductId::new("shirt");
+        let small = ProductId::new("shirt-s");
+        let large = ProductId::new("shirt-l");
+        let location_id = LocationId::default_warehouse();
+
+        for (variant, stock) in [(&small, 5), (&large, 2)] {
+            service
+                .set_inventory(
+                    shirt.clone(),
+                    Some(variant.clone()),
+                    location_id.clone(),
+                    stock,
+                    "",
+                )
+                .expect("set inventory");
+        }
+
+        service
+            .reserve_stock(&shirt, Some(&small), &location_id, 3, "order-1")
+            .expect("reserve S");
+        service
+            .reserve_stock(&shirt, Some(&large), &location_id, 2, "order-1")
+            .expect("reserve L");
+        assert!(matches!(
+            service.reserve_stock(&shirt, Some(&large), &location_id, 1, "order-2"),
+            Err(CommerceError::InsufficientInventory { available: 0, .. })
+        ));
+
+        let small_level = service.get_inventory(&shirt, Some(&small), &location_id).expect("S");
+        let large_level = service.get_inventory(&shirt, Some(&large), &location_id).expect("L");
+        assert_eq!((small_level.committed, small_level.available), (3, 2));
+        assert_eq!((large_level.committed, large_level.available), (2, 0));
+        assert_eq!(small_level.variant_id, Some(small.clone()));
+        assert!(service.get_inventory(&shirt, None, &location_id).is_err());
+
+        assert_eq!(
+            service.get_total_available(&shirt, Some(&small)).expect("total"),
+            2
+        );
+        assert_eq!(service.get_total_available(&shirt, None).expect("total"), 2);
+    }
+
+    #[test]
+    fn test_variant_reservations_release_and_allocate_per_variant() {
+        let service = InventoryService::new();
+        let shirt = ProductId::new("shirt");
+        let small = ProductId::new("shirt-s");
+        let location_id = LocationId::default_warehouse();
+        let store = LocationId::new("store-1");
+        service
+            .add_location(InventoryLocation::store(store.clone(), "Store"))
+            .expect("add store");
+        service
+            .set_inventory(shirt.clone(), None, location_id.clone(), 10, "")
+            .expect("set shirt");
+        service
+            .set_inventory(
+                shirt.clone(),
+                Some(small.clone()),
+                location_id.clone(),
+                5,
+                "",
+            )
+            .expect("set S");
+
+        service
+            .reserve_stock(&shirt, None, &location_id, 2, "order-1")
+            .expect("reserve shirt");
+        service
+            .reserve_stock(&shirt, Some(&small), &location_id, 3, "order-1")
+            .expect("reserve S");
+        let reserved = |variant_id: Option<&ProductId>| {
+            service
+                .reserved_for_reference(&shirt, variant_id, &location_id, "order-1")
+                .expect("ledger")
+        };
+        assert_eq!((reserved(None), reserved(Some(&small))), (2, 3));
+
+        service
+            .release_for_reference_partial(&shirt, Some(&small), &location_id, 2, "order-1")
+            .expect("release S");
+        assert_eq!((reserved(None), reserved(Some(&small))), (2, 1));
+        let summary = service.reserved_summary().expect("summary");
+        assert_eq!(summary.by_reference["order-1"], 3);
+
+        // Only the variant's own stock is allocated, from the one location
+        assert_eq!(
+            service
+                .split_fulfillment(&shirt, Some(&small), 4, &FulfillmentType::Ship)
+                .expect("split"),
+            vec![(location_id.clone(), 4)]
+        );
+        assert!(
+            service
+                .split_fulfillment(&shirt, Some(&small), 5, &FulfillmentType::Ship)
+                .is_err()
+        );
+
+        // Transfers move the variant's stock, not the parent's
+        let transfer = service.create_transfer(location_id.clone(), store.clone()).expect("create");
+        service
+            .add_transfer_item(&transfer.id, shirt.clone(), Some(small.clone()), 4)
+            .expect("add item");
+        service.dispatch_transfer(&transfer.id).expect("dispatch");
+        let in_transit = |variant_id: Option<&ProductId>| {
+            service.get_total_in_transit(&shirt, variant_id).expect("in transit")
+        };
+        let large = ProductId::new("shirt-l");
+        assert_eq!((in_transit(Some(&small)), in_transit(Some(&large))), (4, 0));
+        assert_eq!(in_transit(None), 4);
+        service.complete_transfer(&transfer.id).expect("complete");
+        let at_store = service.get_inventory(&shirt, Some(&small), &store).expect("store S");
+        assert_eq!(at_store.on_hand, 4);
+        let parent = service.get_inventory(&shirt, None, &location_id).expect("shirt");
+        assert_eq!(parent.on_hand, 10);
+    }
+
     #[test]
     fn test_adjustment_history() {
         let service = InventoryService::new();
@@ -163,14 +364,570 @@ mod tests {
         let location_id = LocationId::default_warehouse();
 
         service
-            .set_inventory(product_id.clone(), location_id.clone(), 100, "Initial")
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                100,
+                "Initial",
+            )
             .expect("set");
-        service.receive_stock(&product_id, &location_id, 50, "PO-001").expect("receive");
         service
-            .reserve_stock(&product_id, &location_id, 30, "ORD-001")
+            .receive_stock(&product_id, None, &location_id, 50, "PO-001")
+            .expect("receive");
+        service
+            .reserve_stock(&product_id, None, &location_id, 30, "ORD-001")
             .expect("reserve");
 
         let history = service.get_adjustment_history(&product_id, None).expect("history");
         assert_eq!(history.len(), 3);
     }
+
+    #[test]
+    fn test_nearest_shippable_location() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let new_york = LocationId::new("warehouse-nyc");
+        let los_angeles = LocationId::new("warehouse-la");
+
+        service
+            .add_location(
+                InventoryLocation::warehouse(new_york.clone(), "New York")
+                    .with_coordinates(40.71, -74.01),
+            )
+            .expect("add location");
+        let mut la = InventoryLocation::warehouse(los_angeles.clone(), "Los Angeles")
+            .with_coordinates(34.05, -118.24);
+        la.fulfillment_priority = 0;
+        service.add_location(la).expect("add location");
+
+        service
+            .set_inventory(product_id.clone(), None, new_york.clone(), 10, "Stock")
+            .expect("set nyc");
+        service
+            .set_inventory(product_id.clone(), None, los_angeles.clone(), 50, "Stock")
+            .expect("set la");
+
+        // Boston is nearer New York despite LA's better priority
+        let chosen = service
+            .nearest_shippable_location(&product_id, None, 5, 42.36, -71.06)
+            .expect("nearest");
+        assert_eq!(chosen, new_york);
+
+        // New York cannot cover the quantity on its own
+        let chosen = service
+            .nearest_shippable_location(&product_id, None, 20, 42.36, -71.06)
+            .expect("nearest");
+        assert_eq!(chosen, los_angeles);
+
+        assert!(
+            service
+                .nearest_shippable_location(&product_id, None, 100, 42.36, -71.06)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sync_changes_report_per_item_details() {
+        let service = InventoryService::new().with_sync_known_locations_only();
+        let product_id = ProductId::new("prod-001");
+        service
+            .set_inventory(
+                product_id,
+                None,
+                LocationId::default_warehouse(),
+                10,
+                "Initial",
+            )
+            .expect("set");
+
+        let change = |location: &str| InventoryChange {
+            product_id:       "prod-001".to_string(),
+            sku:              None,
+            location_id:      location.to_string(),
+            quantity:         25,
+            change_type:      InventoryChangeType::Set,
+            source_timestamp: None,
+        };
+
+        let result = service
+            .apply_sync_changes("erp", vec![
+                change("warehouse-main"),
+                change("warehouse-unknown"),
+            ])
+            .expect("sync");
+
+        assert_eq!(result.status, SyncStatus::Partial);
+        assert_eq!(result.items_updated, 1);
+        assert_eq!(result.items_failed, 1);
+        assert_eq!(result.details.len(), 2);
+        assert_eq!(result.details[0].outcome, SyncChangeOutcome::Updated {
+            from: 10,
+            to:   25,
+        });
+        assert_eq!(result.details[1].location_id, "warehouse-unknown");
+        assert!(matches!(
+            result.details[1].outcome,
+            SyncChangeOutcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_sync_unknown_locations_and_stale_changes_are_opt_in() {
+        let change = |location: &str, source_timestamp: Option<u64>| InventoryChange {
+            product_id: "prod-001".to_string(),
+            sku: None,
+            location_id: location.to_string(),
+            quantity: 25,
+            change_type: InventoryChangeType::Set,
+            source_timestamp,
+        };
+
+        // By default an unknown location gets a level and stale changes apply
+        let service = InventoryService::new();
+        let result = service
+            .apply_sync_changes("erp", vec![
+                change("warehouse-new", None),
+                change("warehouse-new", Some(0)),
+            ])
+            .expect("sync");
+        assert_eq!(result.status, SyncStatus::Success);
+        assert_eq!(result.items_updated, 2);
+        let level = service
+            .get_inventory(
+                &ProductId::new("prod-001"),
+                None,
+                &LocationId::new("warehouse-new"),
+            )
+            .expect("level");
+        assert_eq!(level.on_hand, 25);
+
+        let service = InventoryService::new().with_sync_skip_stale_changes();
+        let result = service
+            .apply_sync_changes("erp", vec![
+                change("warehouse-main", None),
+                change("warehouse-main", Some(0)),
+            ])
+            .expect("sync");
+        assert_eq!(result.items_updated, 1);
+        assert!(matches!(
+            result.details[1].outcome,
+            SyncChangeOutcome::Skipped(_)
+        ));
+    }
+
+    #[test]
+    fn test_sync_reports_jumps_beyond_discrepancy_threshold() {
+        let service = InventoryService::new().with_sync_discrepancy_threshold(50);
+        let location_id = LocationId::default_warehouse();
+        for product in ["prod-001", "prod-002"] {
+            service
+                .set_inventory(
+                    ProductId::new(product),
+                    None,
+                    location_id.clone(),
+                    100,
+                    "Initial",
+                )
+                .expect("set");
+        }
+
+        let change = |product: &str, quantity: i64| InventoryChange {
+            product_id: product.to_string(),
+            sku: None,
+            location_id: location_id.0.to_string(),
+            quantity,
+            change_type: InventoryChangeType::Set,
+            source_timestamp: None,
+        };
+        let result = service
+            .apply_sync_changes("erp", vec![change("prod-001", 140), change("prod-002", 5)])
+            .expect("sync");
+
+        // Both changes are applied; only the jump of 95 is flagged
+        assert_eq!(result.items_updated, 2);
+        assert_eq!(result.discrepancies, vec![(
+            ProductId::new("prod-002"),
+            100,
+            5
+        )]);
+        let level = service
+            .get_inventory(&ProductId::new("prod-002"), None, &location_id)
+            .expect("level");
+        assert_eq!(level.on_hand, 5);
+    }
+
+    #[test]
+    fn test_bundle_availability_and_reservation() {
+        let service = InventoryService::new();
+        let location_id = LocationId::default_warehouse();
+        let frame = ProductId::new("frame");
+        let wheel = ProductId::new("wheel");
+        service
+            .set_inventory(
+                frame.clone(),
+                None,
+                location_id.clone(),
+                10,
+                "Initial stock",
+            )
+            .expect("set frames");
+        service
+            .set_inventory(wheel.clone(), None, location_id.clone(), 9, "Initial stock")
+            .expect("set wheels");
+
+        // Wheels are the limiting component: 9 / 2 = 4 bundles, whether the
+        // wheels are listed together or one at a time
+        let bundle = vec![
+            BundleComponent::new(frame.clone(), 1),
+            BundleComponent::new(wheel.clone(), 2),
+        ];
+        let listed_twice = vec![
+            BundleComponent::new(frame.clone(), 1),
+            BundleComponent::new(wheel.clone(), 1),
+            BundleComponent::new(wheel.clone(), 1),
+        ];
+        assert_eq!(
+            service.bundle_available(&listed_twice).expect("available"),
+            4
+        );
+        assert_eq!(service.bundle_available(&bundle).expect("available"), 4);
+
+        service.reserve_bundle(&bundle, &location_id, 3, "order-1").expect("reserve");
+        let available = |product_id: &ProductId| {
+            service.get_inventory(product_id, None, &location_id).expect("level").available
+        };
+        assert_eq!(available(&frame), 7);
+        assert_eq!(available(&wheel), 3);
+        assert_eq!(service.bundle_available(&bundle).expect("available"), 1);
+
+        // Retrying the same reservation is a no-op; a different size conflicts
+        service.reserve_bundle(&bundle, &location_id, 3, "order-1").expect("retry");
+        assert_eq!(available(&wheel), 3);
+        assert!(matches!(
+            service.reserve_bundle(&bundle, &location_id, 1, "order-1"),
+            Err(CommerceError::ReservationConflict { reserved: 3, requested: 1, .. })
+        ));
+
+        // A short component leaves every component untouched
+        assert!(service.reserve_bundle(&bundle, &location_id, 2, "order-2").is_err());
+        assert_eq!(available(&frame), 7);
+        assert_eq!(available(&wheel), 3);
+    }
+
+    #[test]
+    fn test_reserve_stock_batch_is_all_or_nothing() {
+        let service = InventoryService::new();
+        let warehouse = LocationId::default_warehouse();
+        let store = LocationId::new("store-1");
+        service
+            .add_location(InventoryLocation::store(store.clone(), "Store"))
+            .expect("add store");
+
+        let shirt = ProductId::new("prod-001");
+        let large = Some(ProductId::new("prod-001-l"));
+        let lines = [
+            (
+                InventoryKey::new(shirt.clone(), large.clone(), warehouse.clone()),
+                4,
+            ),
+            (
+                InventoryKey::new(ProductId::new("prod-002"), None, store.clone()),
+                2,
+            ),
+            (
+                InventoryKey::new(ProductId::new("prod-003"), None, warehouse.clone()),
+                5,
+            ),
+        ];
+        for ((key, _), stock) in lines.iter().zip([10, 10, 3]) {
+            service
+                .set_inventory(
+                    key.product_id.clone(),
+                    key.variant_id.clone(),
+                    key.location_id.clone(),
+                    stock,
+                    "Initial",
+                )
+                .expect("set inventory");
+        }
+        service
+            .set_inventory(shirt.clone(), None, warehouse.clone(), 10, "Initial")
+            .expect("set inventory");
+
+        assert!(matches!(
+            service.reserve_stock_batch(&lines, "order-1"),
+            Err(CommerceError::InsufficientInventory { available: 3, requested: 5, .. })
+        ));
+        for (key, _) in &lines {
+            let level = service
+                .get_inventory(&key.product_id, key.variant_id.as_ref(), &key.location_id)
+                .expect("get");
+            assert_eq!(level.committed, 0);
+        }
+
+        service.reserve_stock_batch(&lines[..2], "order-1").expect("reserve batch");
+        service.reserve_stock_batch(&lines[..2], "order-1").expect("retry batch");
+        let level = service.get_inventory(&shirt, large.as_ref(), &warehouse).expect("get");
+        assert_eq!(level.committed, 4);
+        // The variant's reservation leaves the parent product's stock alone
+        let level = service.get_inventory(&shirt, None, &warehouse).expect("get");
+        assert_eq!(level.committed, 0);
+    }
+
+    #[test]
+    fn test_partial_release_is_limited_to_reference() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let location_id = LocationId::default_warehouse();
+        service
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                100,
+                "Initial",
+            )
+            .expect("set");
+        service
+            .reserve_stock(&product_id, None, &location_id, 5, "ORD-001")
+            .expect("reserve");
+        service
+            .reserve_stock(&product_id, None, &location_id, 7, "ORD-002")
+            .expect("reserve");
+
+        service
+            .release_for_reference_partial(&product_id, None, &location_id, 3, "ORD-001")
+            .expect("release");
+        let reserved = |reference: &str| {
+            service
+                .reserved_for_reference(&product_id, None, &location_id, reference)
+                .expect("ledger")
+        };
+        assert_eq!(reserved("ORD-001"), 2);
+        assert_eq!(reserved("ORD-002"), 7);
+        assert_eq!(
+            service.get_inventory(&product_id, None, &location_id).expect("get").committed,
+            9
+        );
+
+        // ORD-001 has only 2 left even though 9 are committed overall
+        let over =
+            service.release_for_reference_partial(&product_id, None, &location_id, 3, "ORD-001");
+        assert!(matches!(over, Err(CommerceError::ValidationError(_))));
+        assert_eq!(
+            service.get_inventory(&product_id, None, &location_id).expect("get").committed,
+            9
+        );
+    }
+
+    #[test]
+    fn test_reserved_summary_counts_stale_reservations() {
+        let service = InventoryService::new().with_stale_reservation_age(3600);
+        let product_id = ProductId::new("prod-001");
+        let other_id = ProductId::new("prod-002");
+        let location_id = LocationId::default_warehouse();
+        for id in [&product_id, &other_id] {
+            service
+                .set_inventory(id.clone(), None, location_id.clone(), 100, "Initial")
+                .expect("set");
+        }
+        service
+            .reserve_stock(&product_id, None, &location_id, 4, "ORD-OLD")
+            .expect("reserve");
+        service
+            .reserve_stock(&other_id, None, &location_id, 6, "ORD-OLD")
+            .expect("reserve");
+        service
+            .reserve_stock(&product_id, None, &location_id, 5, "ORD-NEW")
+            .expect("reserve");
+        service
+            .reserve_stock(&product_id, None, &location_id, 2, "ORD-GONE")
+            .expect("reserve");
+        service
+            .release_stock(&product_id, None, &location_id, 2, "ORD-GONE")
+            .expect("release");
+
+        // Age the first ORD-OLD reservation past the stale cutoff
+        let mut adjustments = service.adjustments.lock().expect("lock");
+        let first = adjustments
+            .iter_mut()
+            .find(|a| a.reference.as_deref() == Some("ORD-OLD"))
+            .expect("reservation");
+        first.created_at -= 7200;
+        drop(adjustments);
+        // Releasing consumes the oldest reservation first
+        service
+            .release_for_reference_partial(&product_id, None, &location_id, 1, "ORD-OLD")
+            .expect("release");
+
+        let summary = service.reserved_summary().expect("summary");
+        assert_eq!(summary.total_reserved, 14);
+        assert_eq!(summary.stale_reserved, 3);
+        assert_eq!(summary.by_reference.len(), 2);
+        assert_eq!(summary.by_reference["ORD-OLD"], 9);
+        assert_eq!(summary.by_reference["ORD-NEW"], 5);
+    }
+
+    #[test]
+    fn test_pickup_only_store_serves_pickup_orders_only() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let warehouse = LocationId::default_warehouse();
+        let store = LocationId::new("store-downtown");
+        let mut location = InventoryLocation::store(store.clone(), "Downtown");
+        location.can_ship = false;
+        location.fulfillment_priority = 0;
+        service.add_location(location).expect("add store");
+        service
+            .set_inventory(product_id.clone(), None, warehouse.clone(), 5, "Stock")
+            .expect("set");
+        service
+            .set_inventory(product_id.clone(), None, store.clone(), 20, "Stock")
+            .expect("set");
+        let items = [(product_id.clone(), None, 8)];
+
+        // The store has more stock and better priority but cannot ship
+        let ship = FulfillmentType::Ship;
+        assert!(matches!(
+            service.select_fulfillment_location(&items, false, &ship),
+            Err(CommerceError::InsufficientInventory { available: 5, .. })
+        ));
+        assert_eq!(
+            service.split_fulfillment(&product_id, None, 4, &ship).expect("split"),
+            vec![(warehouse.clone(), 4)]
+        );
+
+        let pickup = FulfillmentType::Pickup(store.clone());
+        assert_eq!(
+            service.select_fulfillment_location(&items, false, &pickup).expect("select"),
+            store
+        );
+        assert_eq!(
+            service.split_fulfillment(&product_id, None, 8, &pickup).expect("split"),
+            vec![(store, 8)]
+        );
+
+        // Pickup is only offered at locations that allow it
+        let warehouse_pickup = FulfillmentType::Pickup(warehouse);
+        assert!(service.split_fulfillment(&product_id, None, 1, &warehouse_pickup).is_err());
+    }
+
+    #[test]
+    fn test_transfer_in_transit_until_received() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let source = LocationId::default_warehouse();
+        let destination = LocationId::new("store-downtown");
+        service
+            .add_location(InventoryLocation::warehouse(
+                destination.clone(),
+                "Downtown",
+            ))
+            .expect("add location");
+        service
+            .set_inventory(product_id.clone(), None, source.clone(), 100, "Initial")
+            .expect("set");
+
+        let transfer =
+            service.create_transfer(source.clone(), destination.clone()).expect("transfer");
+        service
+            .add_transfer_item(&transfer.id, product_id.clone(), None, 30)
+            .expect("add item");
+
+        service.dispatch_transfer(&transfer.id).expect("dispatch");
+        assert!(matches!(
+            service.add_transfer_item(&transfer.id, product_id.clone(), None, 5),
+            Err(CommerceError::InvalidTransferStatus)
+        ));
+        assert_eq!(
+            service.get_total_in_transit(&product_id, None).expect("in transit"),
+            30
+        );
+        assert_eq!(
+            service.get_total_available(&product_id, None).expect("available"),
+            70
+        );
+        let inbound = service.get_inventory(&product_id, None, &destination).expect("destination");
+        assert_eq!(inbound.available, 0);
+        assert_eq!(inbound.available_to_promise(), 30);
+
+        service
+            .receive_transfer(&transfer.id, &product_id, None, 10)
+            .expect("partial receipt");
+        assert_eq!(
+            service.get_total_in_transit(&product_id, None).expect("in transit"),
+            20
+        );
+        assert_eq!(
+            service.get_total_available(&product_id, None).expect("available"),
+            80
+        );
+        assert!(matches!(
+            service.receive_transfer(&transfer.id, &product_id, None, 21),
+            Err(CommerceError::ValidationError(_))
+        ));
+
+        service.complete_transfer(&transfer.id).expect("complete");
+        assert_eq!(
+            service.get_total_in_transit(&product_id, None).expect("in transit"),
+            0
+        );
+        assert_eq!(
+            service.get_total_available(&product_id, None).expect("available"),
+            100
+        );
+        let transfer = service.get_transfer(&transfer.id).expect("transfer");
+        assert_eq!(transfer.status, TransferStatus::Completed);
+        assert!(transfer.arrived_at.is_some());
+    }
+
+    #[test]
+    fn test_reserve_stock_is_idempotent_per_reference() {
+        let service = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        let location_id = LocationId::default_warehouse();
+        service
+            .set_inventory(
+                product_id.clone(),
+                None,
+                location_id.clone(),
+                100,
+                "Initial",
+            )
+            .expect("set");
+
+        service
+            .reserve_stock(&product_id, None, &location_id, 30, "ORD-001")
+            .expect("reserve");
+        // A retried request reserves nothing more
+        service
+            .reserve_stock(&product_id, None, &location_id, 30, "ORD-001")
+            .expect("retry");
+        let level = service.get_inventory(&product_id, None, &location_id).expect("get");
+        assert_eq!(level.committed, 30);
+        assert_eq!(level.available, 70);
+
+        let result = service.reserve_stock(&product_id, None, &location_id, 40, "ORD-001");
+        assert!(matches!(
+            result,
+            Err(CommerceError::ReservationConflict { reserved: 30, requested: 40, .. })
+        ));
+        assert_eq!(
+            service.get_inventory(&product_id, None, &location_id).expect("get").committed,
+            30
+        );
+
+        // Once released, the reference can reserve again
+        service
+            .release_stock(&product_id, None, &location_id, 30, "ORD-001")
+            .expect("release");
+        service
+            .reserve_stock(&product_id, None, &location_id, 40, "ORD-001")
+            .expect("re-reserve");
+        assert_eq!(
+            service.get_inventory(&product_id, None, &location_id).expect("get").committed,
+            40
+        );
+    }
 }
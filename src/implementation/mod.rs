@@ -14,6 +14,8 @@ use essentia_traits::plugin_contracts::flexforge_integration::{
     ConfigField, ConfigSchema, FlexForgeIntegration, FlexForgePanelCategory, UiConfigurable,
 };
 
+use crate::types::{ConflictResolution, DEFAULT_SYNC_BATCH_SIZE};
+
 /// `FlexForge` integration for the Commerce plugin
 #[derive(Debug)]
 pub struct CommerceFlexForgeIntegration {
@@ -89,6 +91,21 @@ impl UiConfigurable for CommerceFlexForgeIntegration {
                 10.0,
             ))
             .with_field(ConfigField::toggle("genesis_sync", "Genesis Sync", true))
+            .with_field(ConfigField::number(
+                "genesis_sync_batch_size",
+                "Genesis Sync Batch Size",
+                DEFAULT_SYNC_BATCH_SIZE as f64,
+                1.0,
+                10_000.0,
+            ))
+            .with_field(ConfigField::select(
+                "genesis_conflict_policy",
+                "Genesis Conflict Policy",
+                ConflictResolution::ALL
+                    .iter()
+                    .map(|policy| policy.as_str().to_string())
+                    .collect(),
+            ))
             .with_field(ConfigField::toggle("auto_verify", "Auto-Verify", false))
     }
 
@@ -102,6 +119,14 @@ impl UiConfigurable for CommerceFlexForgeIntegration {
                 config.fee_percentage = value.parse().map_err(|_| "Invalid number")?;
             },
             "genesis_sync" => config.genesis_sync = value == "true",
+            "genesis_sync_batch_size" => {
+                config.genesis_sync_batch_size =
+                    value.parse().ok().filter(|size| *size > 0).ok_or("Invalid batch size")?;
+            },
+            "genesis_conflict_policy" => {
+                config.genesis_conflict_policy =
+                    ConflictResolution::from_name(value).ok_or("Invalid conflict policy")?;
+            },
             "auto_verify" => config.auto_verify = value == "true",
             _ => return Err(format!("Unknown key: {}", key)),
         }
@@ -133,6 +158,14 @@ impl UiConfigurable for CommerceFlexForgeIntegration {
                 config.fee_percentage.to_string(),
             ),
             ("genesis_sync".to_string(), config.genesis_sync.to_string()),
+            (
+                "genesis_sync_batch_size".to_string(),
+                config.genesis_sync_batch_size.to_string(),
+            ),
+            (
+                "genesis_conflict_policy".to_string(),
+                config.genesis_conflict_policy.as_str().to_string(),
+            ),
             ("auto_verify".to_string(), config.auto_verify.to_string()),
         ]
     }
@@ -153,4 +186,72 @@ mod tests {
         assert!(config.marketplace_enabled);
         assert_eq!(config.currency, "ESS");
     }
+
+    #[test]
+    fn test_schema_currencies_map_to_typed_currency() {
+        let mut config = crate::types::CommerceConfig::default();
+        for (code, decimals) in [("ESS", 18), ("BTC", 8), ("ETH", 18), ("USDT", 6)] {
+            config.currency = code.to_string();
+            let currency = config.currency_typed().expect("schema currency");
+            assert_eq!(currency.0, code);
+            assert_eq!(currency.decimals(), Some(decimals));
+        }
+    }
+
+    #[test]
+    fn test_unknown_currency_rejected() {
+        let config =
+            crate::types::CommerceConfig { currency: "DOGE".to_string(), ..Default::default() };
+        assert!(matches!(
+            config.currency_typed(),
+            Err(crate::errors::CommerceError::UnsupportedCurrency(code)) if code == "DOGE"
+        ));
+    }
+
+    #[test]
+    fn test_genesis_directory_coherence_queries() {
+        use essentia_api::implementation::commerce::BusinessEntity;
+
+        let mut directory = crate::types::GenesisDirectory::new();
+        assert_eq!(directory.average_coherence(), None);
+        for (name, coherence_score) in [("low", 0.99), ("high", 1.0), ("mid", 0.995)] {
+            directory
+                .register_business(BusinessEntity { name: name.to_string(), coherence_score })
+                .expect("register");
+        }
+
+        let names = |entities: Vec<&BusinessEntity>| -> Vec<String> {
+            entities.iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(directory.query_by_min_coherence(0.995).len(), 2);
+        assert_eq!(names(directory.top_by_coherence(2)), vec!["high", "mid"]);
+        assert_eq!(directory.top_by_coherence(10).len(), 3);
+        let average = directory.average_coherence().expect("average");
+        assert!((average - 0.995).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_genesis_sync_settings_round_trip() {
+        let mut integration = CommerceFlexForgeIntegration::new();
+        integration
+            .on_config_changed("genesis_sync_batch_size", "25")
+            .expect("batch size");
+        integration
+            .on_config_changed("genesis_conflict_policy", "keep_local")
+            .expect("policy");
+        assert!(integration.on_config_changed("genesis_sync_batch_size", "0").is_err());
+        assert!(integration.on_config_changed("genesis_conflict_policy", "newest").is_err());
+
+        let config = integration.config();
+        assert_eq!(config.genesis_sync_batch_size, 25);
+        assert_eq!(
+            config.genesis_conflict_policy,
+            ConflictResolution::KeepLocal
+        );
+        let current = integration.get_current_config();
+        assert!(current.contains(&(
+            "genesis_conflict_policy".to_string(),
+            "keep_local".to_string()
+        )));
+    }
 }
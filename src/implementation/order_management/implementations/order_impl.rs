@@ -6,14 +6,21 @@ use super::super::types::{
     basic_types::{FulfillmentStatus, OrderId, OrderStatus, PaymentStatus},
     main_order_types::{Order, OrderSource, OrderTotals},
     order_types::{
-        OrderEventType, OrderHistoryEvent, OrderLineItem, OrderNote, PaymentTransaction, Shipment,
-        TransactionStatus, TransactionType,
+        LedgerAccount, LedgerEntry, OrderEventType, OrderHistoryEvent, OrderLineItem, OrderNote,
+        PaymentTransaction, RefundDestination, Shipment, StoreCredit, TransactionStatus,
+        TransactionType,
     },
 };
-use crate::implementation::cart_system::{Cart, ShippingMethod};
+use crate::{
+    errors::CommerceError,
+    implementation::cart_system::{Cart, ShippingMethod, TaxMode},
+    types::inventory_sync::{FulfillmentType, LocationId},
+};
 
 impl Order {
     /// Creates an order from a cart.
+    ///
+    /// The order number is left empty; `OrderService` assigns it.
     #[must_use]
     pub fn from_cart(cart: &Cart, customer_email: impl Into<String>) -> Self {
         let now = std::time::SystemTime::now()
@@ -22,29 +29,48 @@ impl Order {
             .unwrap_or(0);
 
         let order_id = OrderId::generate();
-        let order_number = format!("#{}", &order_id.0[4..]);
 
         let cart_totals = cart.calculate_totals();
 
         // Convert cart items to order line items
-        let line_items: Vec<OrderLineItem> = cart
-            .items
+        let mut line_items: Vec<OrderLineItem> = cart
+            .items()
             .iter()
             .enumerate()
             .map(|(i, item)| {
-                OrderLineItem::from_cart_item(item, format!("line-{}", i + 1), cart.tax_rate)
+                OrderLineItem::from_cart_item(item, format!("line-{}", i + 1), cart.tax_rate())
             })
             .collect();
 
-        let totals = OrderTotals::from_cart_totals(&cart_totals);
+        // Line taxes are rounded one by one; spread the cart's tax total
+        // over them instead so they add up to what the customer was shown.
+        let rates: Vec<f64> = cart
+            .items()
+            .iter()
+            .map(|item| item.tax_rate.unwrap_or(cart.tax_rate()))
+            .collect();
+        allocate_tax(&mut line_items, &rates, cart_totals.tax_total);
+
+        let mut totals = OrderTotals::from_cart_totals(&cart_totals);
+        // Orders keep tax on top of net prices
+        if cart.tax_mode() == TaxMode::Inclusive {
+            for line in &mut line_items {
+                line.subtotal -= line.tax;
+                line.total = line.subtotal.saturating_sub(line.discount) + line.tax;
+            }
+            totals.subtotal -= totals.tax_total;
+        }
+        // Cart-level coupons are not attributed to any line
+        let line_discounts: u64 = line_items.iter().map(|li| li.discount).sum();
+        totals.order_discount = totals.discount_total.saturating_sub(line_discounts);
 
         let shipping_address = cart.shipping_address.clone().unwrap_or_default();
         let shipping_method =
-            cart.shipping_method.clone().unwrap_or_else(ShippingMethod::free_shipping);
+            cart.shipping_method().cloned().unwrap_or_else(ShippingMethod::free_shipping);
 
         let mut order = Self {
             id: order_id,
-            order_number,
+            order_number: String::new(),
             customer_id: cart.customer_id.clone().into(),
             customer_email: customer_email.into(),
             customer_phone: None,
@@ -56,28 +82,85 @@ impl Order {
             billing_address: cart.billing_address.clone(),
             shipping_method,
             totals,
-            currency: cart.currency.clone(),
+            currency: cart.currency().clone(),
             transactions: Vec::new(),
+            store_credits: Vec::new(),
             payment_invoice_id: None,
             blockchain_tx_id: None,
             shipments: Vec::new(),
             notes: Vec::new(),
             history: Vec::new(),
             customer_note: cart.notes.as_ref().map(|n| n.to_string()),
+            gift_message: None,
             ip_address: None,
             user_agent: None,
             source: OrderSource::Web,
             tags: Vec::new(),
+            is_rush: false,
+            fulfillment_type: FulfillmentType::Ship,
+            ship_on_deposit: false,
+            hold_reason: None,
+            status_before_hold: None,
             created_at: now,
             updated_at: now,
         };
 
         // Add creation event
         order.add_history_event(OrderEventType::Created, "Order created", None);
+        debug_assert!(order.assert_totals_consistent().is_ok());
 
         order
     }
 
+    /// Checks that the order totals agree with the line items.
+    ///
+    /// Line totals plus shipping, less the order-level discount (which no
+    /// line carries), must equal the grand total; the discount and tax
+    /// totals must equal the line discounts plus the order discount, and
+    /// the line taxes.
+    ///
+    /// # Errors
+    /// Returns `InternalError` describing the first mismatch.
+    pub fn assert_totals_consistent(&self) -> Result<(), CommerceError> {
+        let line_total: u64 = self.line_items.iter().map(|li| li.total).sum();
+        let line_discounts: u64 = self.line_items.iter().map(|li| li.discount).sum();
+        let line_tax: u64 = self.line_items.iter().map(|li| li.tax).sum();
+
+        let expected_grand_total =
+            (line_total + self.totals.shipping_total).saturating_sub(self.totals.order_discount);
+        let checks = [
+            ("grand total", self.totals.grand_total, expected_grand_total),
+            (
+                "discount total",
+                self.totals.discount_total,
+                line_discounts + self.totals.order_discount,
+            ),
+            ("tax total", self.totals.tax_total, line_tax),
+        ];
+
+        match checks.into_iter().find(|(_, actual, expected)| actual != expected) {
+            Some((name, actual, expected)) => Err(CommerceError::InternalError(format!(
+                "order {} {} is {} but line items give {}",
+                self.id.0, name, actual, expected
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that every line item is priced in the order currency.
+    ///
+    /// # Errors
+    /// Returns `CurrencyMismatch` for the first line item in another currency.
+    pub fn validate_currency(&self) -> Result<(), CommerceError> {
+        match self.line_items.iter().find(|li| li.unit_price.currency != self.currency) {
+            Some(mismatched) => Err(CommerceError::CurrencyMismatch {
+                expected: self.currency.0.to_string(),
+                got:      mismatched.unit_price.currency.0.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Adds a history event.
     pub fn add_history_event(
         &mut self, event_type: OrderEventType, description: impl Into<String>, user: Option<String>,
@@ -99,9 +182,15 @@ impl Order {
     }
 
     /// Updates order status.
+    ///
+    /// Moving the order out of `OnHold` clears its hold.
     pub fn update_status(&mut self, new_status: OrderStatus, user: Option<String>) {
         let previous_status = self.status;
         self.status = new_status;
+        if new_status != OrderStatus::OnHold {
+            self.hold_reason = None;
+            self.status_before_hold = None;
+        }
         self.touch();
 
         self.history.push(OrderHistoryEvent {
@@ -130,20 +219,7 @@ impl Order {
                     self.totals.amount_refunded.saturating_add(transaction.amount);
             }
 
-            self.totals.amount_due = self
-                .totals
-                .grand_total
-                .saturating_sub(self.totals.amount_paid)
-                .saturating_add(self.totals.amount_refunded);
-
-            // Update payment status
-            if self.totals.amount_refunded >= self.totals.grand_total {
-                self.payment_status = PaymentStatus::Refunded;
-            } else if self.totals.amount_refunded > 0 {
-                self.payment_status = PaymentStatus::PartiallyRefunded;
-            } else if self.totals.amount_paid >= self.totals.grand_total {
-                self.payment_status = PaymentStatus::Captured;
-            }
+            self.refresh_payment_state();
         }
 
         let event_type = match (transaction.transaction_type, transaction.status) {
@@ -168,8 +244,505 @@ impl Order {
         self.touch();
     }
 
+    /// Recomputes paid, refunded, and due amounts by replaying successful
+    /// transactions, correcting totals left inconsistent by out-of-order
+    /// events.
+    pub fn reconcile_payments(&mut self) {
+        let (paid, refunded) = self.replay_transactions();
+        self.totals.amount_paid = paid;
+        self.totals.amount_refunded = refunded;
+        self.refresh_payment_state();
+        self.touch();
+    }
+
+    /// Whether recorded payment totals match the transaction log.
+    #[must_use]
+    pub fn is_balanced(&self) -> bool {
+        let (paid, refunded) = self.replay_transactions();
+        self.totals.amount_paid == paid
+            && self.totals.amount_refunded == refunded
+            && self.totals.amount_due == self.expected_amount_due()
+    }
+
+    /// Records a deposit toward the order total, e.g. for a pre-order.
+    ///
+    /// The deposit is captured like any payment, leaving the rest as
+    /// `amount_due`. An order awaiting payment moves on to processing.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` for a zero deposit or one above the amount
+    /// due.
+    pub fn record_deposit(&mut self, amount: u64) -> Result<(), CommerceError> {
+        if amount == 0 || amount > self.totals.amount_due {
+            return Err(CommerceError::ValidationError(format!(
+                "deposit of {} invalid for order {} (due: {})",
+                amount, self.id.0, self.totals.amount_due
+            )));
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.record_payment(PaymentTransaction {
+            id: format!("{}-deposit-{}", self.id.0, self.transactions.len() + 1),
+            external_id: None,
+            transaction_type: TransactionType::Capture,
+            amount,
+            currency: self.currency.clone(),
+            status: TransactionStatus::Success,
+            gateway: String::new(),
+            payment_method: None,
+            error_message: None,
+            created_at: now,
+        });
+
+        if self.status == OrderStatus::PendingPayment {
+            self.update_status(OrderStatus::Processing, None);
+        }
+        Ok(())
+    }
+
+    /// Refunds part of the order's payment.
+    ///
+    /// `OriginalMethod` records a refund transaction through the gateway of
+    /// the most recent successful capture; `StoreCredit` issues credit to the
+    /// customer instead and touches no gateway.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` if the order is not refundable or the amount
+    /// exceeds [`Self::max_refund_amount`].
+    pub fn refund(
+        &mut self, amount: u64, destination: RefundDestination,
+    ) -> Result<(), CommerceError> {
+        if !self.can_refund() || amount == 0 || amount > self.max_refund_amount() {
+            return Err(CommerceError::ValidationError(format!(
+                "cannot refund {} of order {} (refundable: {})",
+                amount,
+                self.id.0,
+                self.max_refund_amount()
+            )));
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        match destination {
+            RefundDestination::OriginalMethod => {
+                let capture = self.transactions.iter().rev().find(|t| {
+                    t.transaction_type == TransactionType::Capture
+                        && t.status == TransactionStatus::Success
+                });
+                let transaction = PaymentTransaction {
+                    id: format!("{}-refund-{}", self.id.0, self.transactions.len() + 1),
+                    external_id: None,
+                    transaction_type: TransactionType::Refund,
+                    amount,
+                    currency: self.currency.clone(),
+                    status: TransactionStatus::Success,
+                    gateway: capture.map(|t| t.gateway.clone()).unwrap_or_default(),
+                    payment_method: capture.and_then(|t| t.payment_method.clone()),
+                    error_message: None,
+                    created_at: now,
+                };
+                self.record_payment(transaction);
+            },
+            RefundDestination::StoreCredit => {
+                let credit = StoreCredit {
+                    code: format!("{}-CREDIT-{}", self.id.0, self.store_credits.len() + 1),
+                    amount,
+                    currency: self.currency.clone(),
+                    issued_at: now,
+                };
+                self.add_history_event(
+                    OrderEventType::Refunded,
+                    format!("Store credit {} issued for {}", credit.code, amount),
+                    None,
+                );
+                self.store_credits.push(credit);
+                self.totals.amount_refunded = self.totals.amount_refunded.saturating_add(amount);
+                self.refresh_payment_state();
+                self.touch();
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Refunds `quantity` units of a line item at its per-unit total.
+    ///
+    /// # Errors
+    /// Returns `ValidationError` for an unknown line, a quantity above what
+    /// is left neither cancelled nor refunded on the line, or if
+    /// [`Self::refund`] fails.
+    pub fn refund_line_item(
+        &mut self, line_item_id: &str, quantity: u32, destination: RefundDestination,
+    ) -> Result<(), CommerceError> {
+        let line = self.line_items.iter().find(|l| l.id == line_item_id).ok_or_else(|| {
+            CommerceError::ValidationError(format!("unknown line {line_item_id}"))
+        })?;
+        // `total` only covers the units left after cancellations
+        let kept = line.quantity.saturating_sub(line.quantity_cancelled);
+        let open = kept.saturating_sub(line.quantity_refunded);
+        if quantity == 0 || quantity > open {
+            return Err(CommerceError::ValidationError(format!(
+                "cannot refund {} of line {}",
+                quantity, line.id
+            )));
+        }
+        let total = u128::from(line.total);
+        let unrefunded = total - total * u128::from(line.quantity_refunded) / u128::from(kept);
+        let amount = (unrefunded * u128::from(quantity) / u128::from(open)) as u64;
+
+        self.refund(amount, destination)?;
+        if let Some(line) = self.line_items.iter_mut().find(|l| l.id == line_item_id) {
+            line.quantity_refunded += quantity;
+        }
+        Ok(())
+    }
+
+    /// Total store credit issued as refunds.
+    #[must_use]
+    pub fn store_credit_issued(&self) -> u64 {
+        self.store_credits.iter().map(|c| c.amount).sum()
+    }
+
+    /// Flattens the order into balanced debit/credit rows for accounting.
+    ///
+    /// Revenue is credited per line, with line and order discounts debited
+    /// as contra-revenue, tax credited as a liability and shipping as
+    /// income. Cash is debited with the amount paid; any unpaid balance goes
+    /// to accounts receivable. Refunds are debited as contra-revenue against
+    /// cash or, for store credit, a credit liability. Zero rows are omitted.
+    #[must_use]
+    pub fn to_ledger_entries(&self) -> Vec<LedgerEntry> {
+        let mut entries = Vec::new();
+        let mut push = |entry: LedgerEntry| {
+            if entry.debit > 0 || entry.credit > 0 {
+                entries.push(entry);
+            }
+        };
+
+        push(LedgerEntry::debit(
+            LedgerAccount::Cash,
+            self.totals.amount_paid,
+            "Payment received",
+        ));
+        let paid = self.totals.amount_paid;
+        let grand_total = self.totals.grand_total;
+        if paid < grand_total {
+            push(LedgerEntry::debit(
+                LedgerAccount::AccountsReceivable,
+                grand_total - paid,
+                "Balance due",
+            ));
+        } else {
+            push(LedgerEntry::credit(
+                LedgerAccount::AccountsReceivable,
+                paid - grand_total,
+                "Overpayment",
+            ));
+        }
+
+        for line in &self.line_items {
+            push(LedgerEntry::credit(
+                LedgerAccount::Revenue,
+                line.subtotal,
+                &line.sku,
+            ));
+            push(LedgerEntry::debit(
+                LedgerAccount::Discounts,
+                line.discount,
+                &line.sku,
+            ));
+        }
+        push(LedgerEntry::debit(
+            LedgerAccount::Discounts,
+            self.totals.order_discount,
+            "Order discount",
+        ));
+        push(LedgerEntry::credit(
+            LedgerAccount::TaxPayable,
+            self.totals.tax_total,
+            "Tax collected",
+        ));
+        push(LedgerEntry::credit(
+            LedgerAccount::ShippingIncome,
+            self.totals.shipping_total,
+            "Shipping",
+        ));
+
+        let store_credit = self.store_credit_issued().min(self.totals.amount_refunded);
+        let cash_refunded = self.totals.amount_refunded - store_credit;
+        push(LedgerEntry::debit(
+            LedgerAccount::Refunds,
+            cash_refunded,
+            "Refund",
+        ));
+        push(LedgerEntry::credit(
+            LedgerAccount::Cash,
+            cash_refunded,
+            "Refund",
+        ));
+        push(LedgerEntry::debit(
+            LedgerAccount::Refunds,
+            store_credit,
+            "Store credit",
+        ));
+        push(LedgerEntry::credit(
+            LedgerAccount::StoreCreditLiability,
+            store_credit,
+            "Store credit",
+        ));
+
+        entries
+    }
+
+    /// Sums successful captures and refunds, counting issued store credit as
+    /// refunded.
+    fn replay_transactions(&self) -> (u64, u64) {
+        self.transactions
+            .iter()
+            .filter(|t| t.status == TransactionStatus::Success)
+            .fold(
+                (0u64, self.store_credit_issued()),
+                |(paid, refunded), t| match t.transaction_type {
+                    TransactionType::Capture => (paid.saturating_add(t.amount), refunded),
+                    TransactionType::Refund => (paid, refunded.saturating_add(t.amount)),
+                    TransactionType::Authorization | TransactionType::Void => (paid, refunded),
+                },
+            )
+    }
+
+    /// Amount due implied by the current paid and refunded totals.
+    fn expected_amount_due(&self) -> u64 {
+        self.totals
+            .grand_total
+            .saturating_sub(self.totals.amount_paid)
+            .saturating_add(self.totals.amount_refunded)
+    }
+
+    /// Updates amount due and payment status from paid/refunded totals.
+    fn refresh_payment_state(&mut self) {
+        self.totals.amount_due = self.expected_amount_due();
+
+        if self.totals.amount_refunded >= self.totals.grand_total {
+            self.payment_status = PaymentStatus::Refunded;
+        } else if self.totals.amount_refunded > 0 {
+            self.payment_status = PaymentStatus::PartiallyRefunded;
+        } else if self.totals.amount_paid >= self.totals.grand_total {
+            self.payment_status = PaymentStatus::Captured;
+        } else if self.totals.amount_paid > 0 {
+            self.payment_status = PaymentStatus::PartiallyPaid;
+        }
+    }
+
+    /// Applies an additional order-level discount, e.g. a negotiated B2B
+    /// discount.
+    pub fn apply_order_discount(&mut self, amount: u64) {
+        self.totals.order_discount = self.totals.order_discount.saturating_add(amount);
+        self.recalculate_totals();
+        self.add_history_event(
+            OrderEventType::DiscountApplied,
+            format!("Order discount of {} applied", amount),
+            None,
+        );
+    }
+
+    /// Cancels some units of the order's lines.
+    ///
+    /// `lines` pairs line item IDs with the quantity to cancel. Each line's
+    /// amounts shrink in proportion to the units it keeps and the totals are
+    /// recalculated; an order discount larger than what remains is reduced
+    /// to match. Every cancellation is recorded in the history, and the
+    /// order is cancelled once nothing remains on any line. Releasing
+    /// reserved stock is left to the caller, e.g.
+    /// `OrderService::cancel_order_lines`.
+    ///
+    /// # Errors
+    /// Returns `OrderNotCancellable` unless the order is cancellable, and
+    /// `ValidationError` for an unknown line or a quantity above what is
+    /// still open on it; nothing is cancelled in that case.
+    pub fn cancel_lines(
+        &mut self, lines: &[(String, u32)], reason: &str,
+    ) -> Result<(), CommerceError> {
+        let cancellations = self.check_line_cancellations(lines)?;
+        self.apply_line_cancellations(&cancellations, reason);
+        Ok(())
+    }
+
+    /// Checks that `lines` can be cancelled, returning the quantity to cancel
+    /// per line index.
+    pub(crate) fn check_line_cancellations(
+        &self, lines: &[(String, u32)],
+    ) -> Result<Vec<(usize, u32)>, CommerceError> {
+        if !self.can_cancel() {
+            return Err(CommerceError::OrderNotCancellable(self.id.0.clone()));
+        }
+
+        let mut cancellations: Vec<(usize, u32)> = Vec::with_capacity(lines.len());
+        for (line_id, quantity) in lines {
+            let index =
+                self.line_items.iter().position(|l| &l.id == line_id).ok_or_else(|| {
+                    CommerceError::ValidationError(format!("unknown line {line_id}"))
+                })?;
+            match cancellations.iter_mut().find(|(i, _)| *i == index) {
+                Some((_, total)) => *total = total.saturating_add(*quantity),
+                None => cancellations.push((index, *quantity)),
+            }
+        }
+
+        for &(index, quantity) in &cancellations {
+            let line = &self.line_items[index];
+            let open = line
+                .quantity
+                .saturating_sub(line.quantity_fulfilled)
+                .saturating_sub(line.quantity_cancelled);
+            if quantity > open {
+                return Err(CommerceError::ValidationError(format!(
+                    "cannot cancel {quantity} of line {}: only {open} open",
+                    line.id
+                )));
+            }
+        }
+        Ok(cancellations)
+    }
+
+    /// Cancels line quantities already checked by
+    /// [`Self::check_line_cancellations`].
+    pub(crate) fn apply_line_cancellations(
+        &mut self, cancellations: &[(usize, u32)], reason: &str,
+    ) {
+        let mut descriptions = Vec::with_capacity(cancellations.len());
+        for &(index, quantity) in cancellations {
+            let line = &mut self.line_items[index];
+            let kept = line.quantity.saturating_sub(line.quantity_cancelled);
+            let remaining = kept.saturating_sub(quantity);
+            let scale = |amount: u64| {
+                if kept == 0 {
+                    0
+                } else {
+                    (u128::from(amount) * u128::from(remaining) / u128::from(kept)) as u64
+                }
+            };
+            line.subtotal = scale(line.subtotal);
+            line.discount = scale(line.discount);
+            line.tax = scale(line.tax);
+            line.total = scale(line.total);
+            line.quantity_cancelled += quantity;
+            descriptions.push(format!("Cancelled {quantity} x {}: {reason}", line.sku));
+        }
+
+        let remaining: u64 =
+            self.line_items.iter().map(|li| li.subtotal.saturating_sub(li.discount)).sum();
+        self.totals.order_discount = self.totals.order_discount.min(remaining);
+        self.recalculate_totals();
+        for description in descriptions {
+            self.add_history_event(OrderEventType::LineItemCancelled, description, None);
+        }
+
+        if self.line_items.iter().all(|li| li.quantity_cancelled >= li.quantity) {
+            self.update_status(OrderStatus::Cancelled, None);
+        }
+    }
+
+    /// Cancels a line item that has not been fulfilled.
+    ///
+    /// Everything still open on the line is cancelled as by
+    /// [`Self::cancel_lines`], which zeroes its amounts. Releasing the
+    /// line's reserved stock is left to the caller, e.g.
+    /// `OrderService::cancel_line_item`.
+    ///
+    /// # Errors
+    /// Returns `OrderNotCancellable` unless the order is cancellable and
+    /// unfulfilled or partially fulfilled, and `ValidationError` for an
+    /// unknown line or one that is already fulfilled or cancelled.
+    pub fn cancel_line_item(&mut self, line_item_id: &str) -> Result<(), CommerceError> {
+        let cancellations = self.check_line_item_cancellation(line_item_id)?;
+        self.apply_line_cancellations(&cancellations, "cancelled before fulfillment");
+        Ok(())
+    }
+
+    /// Checks that a line item can be cancelled; see
+    /// [`Self::cancel_line_item`].
+    pub(crate) fn check_line_item_cancellation(
+        &self, line_item_id: &str,
+    ) -> Result<Vec<(usize, u32)>, CommerceError> {
+        if !self.can_cancel()
+            || !matches!(
+                self.fulfillment_status,
+                FulfillmentStatus::Unfulfilled | FulfillmentStatus::PartiallyFulfilled
+            )
+        {
+            return Err(CommerceError::OrderNotCancellable(self.id.0.clone()));
+        }
+
+        let line = self.line_items.iter().find(|li| li.id == line_item_id).ok_or_else(|| {
+            CommerceError::ValidationError(format!("unknown line {line_item_id}"))
+        })?;
+        if line.quantity_fulfilled > 0 {
+            return Err(CommerceError::ValidationError(format!(
+                "line {line_item_id} is already fulfilled"
+            )));
+        }
+        if line.quantity_cancelled >= line.quantity {
+            return Err(CommerceError::ValidationError(format!(
+                "line {line_item_id} is already cancelled"
+            )));
+        }
+
+        let open = line.quantity - line.quantity_cancelled;
+        self.check_line_cancellations(&[(line_item_id.to_string(), open)])
+    }
+
+    /// Recomputes totals from line items, shipping, and the order discount.
+    pub fn recalculate_totals(&mut self) {
+        let subtotal: u64 = self.line_items.iter().map(|li| li.subtotal).sum();
+        let line_discounts: u64 = self.line_items.iter().map(|li| li.discount).sum();
+        let tax_total: u64 = self.line_items.iter().map(|li| li.tax).sum();
+
+        self.totals.subtotal = subtotal;
+        self.totals.discount_total = line_discounts.saturating_add(self.totals.order_discount);
+        self.totals.tax_total = tax_total;
+        self.totals.grand_total = subtotal
+            .saturating_sub(self.totals.discount_total)
+            .saturating_add(self.totals.shipping_total)
+            .saturating_add(tax_total);
+        self.totals.amount_due = self.expected_amount_due();
+        self.touch();
+        debug_assert!(self.assert_totals_consistent().is_ok());
+    }
+
     /// Adds a shipment.
-    pub fn add_shipment(&mut self, shipment: Shipment) {
+    ///
+    /// The order's gift message is carried onto the shipment unless it
+    /// already has one. When a source location is given, shipped quantities
+    /// are recorded against it on each line item.
+    ///
+    /// # Errors
+    /// Returns `OrderOnHold` while the order is held, or `PaymentIncomplete`
+    /// until the order is fully paid. With `ship_on_deposit` set, a paid
+    /// deposit is enough.
+    pub fn add_shipment(
+        &mut self, mut shipment: Shipment, source_location: Option<&LocationId>,
+    ) -> Result<(), CommerceError> {
+        if self.status == OrderStatus::OnHold {
+            return Err(CommerceError::OrderOnHold(self.id.0.clone()));
+        }
+        // Refunds reduce what has been paid, so a partly paid order does not
+        // qualify just because its status moved to `PartiallyRefunded`
+        let net_paid = self.totals.amount_paid.saturating_sub(self.totals.amount_refunded);
+        let deposit_ok = self.ship_on_deposit && net_paid > 0;
+        if net_paid < self.totals.grand_total && !deposit_ok {
+            return Err(CommerceError::PaymentIncomplete(self.id.0.clone()));
+        }
+
+        if shipment.gift_message.is_none() {
+            shipment.gift_message = self.gift_message.clone();
+        }
+
         // Update line item fulfillment quantities
         for ship_item in &shipment.items {
             if let Some(line_item) =
@@ -177,6 +750,9 @@ impl Order {
             {
                 line_item.quantity_fulfilled =
                     line_item.quantity_fulfilled.saturating_add(ship_item.quantity);
+                if let Some(location_id) = source_location {
+                    line_item.record_fulfillment(location_id, ship_item.quantity);
+                }
             }
         }
 
@@ -199,6 +775,57 @@ impl Order {
         );
         self.shipments.push(shipment);
         self.touch();
+        Ok(())
+    }
+
+    /// Holds the order (e.g. for fraud review), blocking fulfillment until
+    /// [`Self::release_hold`].
+    ///
+    /// # Errors
+    /// Returns `InvalidStatusTransition` if the order is already held or in a
+    /// final state.
+    pub fn place_on_hold(&mut self, reason: impl Into<String>) -> Result<(), CommerceError> {
+        if self.status == OrderStatus::OnHold || self.status.is_final() {
+            return Err(CommerceError::InvalidStatusTransition {
+                from: self.status.display_name().to_string(),
+                to:   OrderStatus::OnHold.display_name().to_string(),
+            });
+        }
+
+        let reason = reason.into();
+        self.status_before_hold = Some(self.status);
+        self.update_status(OrderStatus::OnHold, None);
+        self.add_history_event(
+            OrderEventType::StatusChanged,
+            format!("Placed on hold: {}", reason),
+            None,
+        );
+        self.hold_reason = Some(reason);
+        Ok(())
+    }
+
+    /// Releases a hold, restoring the status the order had before it.
+    ///
+    /// # Errors
+    /// Returns `InvalidStatusTransition` if the order is not on hold.
+    pub fn release_hold(&mut self) -> Result<(), CommerceError> {
+        let restored = self.status_before_hold.unwrap_or(OrderStatus::Processing);
+        if self.status != OrderStatus::OnHold {
+            return Err(CommerceError::InvalidStatusTransition {
+                from: self.status.display_name().to_string(),
+                to:   restored.display_name().to_string(),
+            });
+        }
+
+        let reason = self.hold_reason.take().unwrap_or_default();
+        self.status_before_hold = None;
+        self.update_status(restored, None);
+        self.add_history_event(
+            OrderEventType::StatusChanged,
+            format!("Hold released: {}", reason),
+            None,
+        );
+        Ok(())
     }
 
     /// Adds a note to the order.
@@ -212,6 +839,32 @@ impl Order {
         self.touch();
     }
 
+    /// Adds a tag, normalized to lowercase. Duplicate tags are ignored.
+    pub fn add_tag(&mut self, tag: impl AsRef<str>) {
+        let tag = normalize_tag(tag.as_ref());
+        if !tag.is_empty() && !self.tags.contains(&tag) {
+            self.tags.push(tag);
+            self.touch();
+        }
+    }
+
+    /// Removes a tag, matching case-insensitively.
+    pub fn remove_tag(&mut self, tag: &str) {
+        let tag = normalize_tag(tag);
+        let before = self.tags.len();
+        self.tags.retain(|t| normalize_tag(t) != tag);
+        if self.tags.len() != before {
+            self.touch();
+        }
+    }
+
+    /// Whether the order carries the tag, matching case-insensitively.
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = normalize_tag(tag);
+        self.tags.iter().any(|t| normalize_tag(t) == tag)
+    }
+
     /// Whether order can be cancelled.
     #[must_use]
     pub fn can_cancel(&self) -> bool {
@@ -238,3 +891,61 @@ impl Order {
             .unwrap_or(0);
     }
 }
+
+/// Normalizes an order tag for storage and comparison.
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Splits `tax_total` across line items in proportion to their taxable
+/// amounts weighted by each line's tax rate, giving leftover units to the
+/// largest remainders, and updates each line total.
+fn allocate_tax(line_items: &mut [OrderLineItem], rates: &[f64], tax_total: u64) {
+    let mut taxable: Vec<u128> = line_items
+        .iter()
+        .zip(rates)
+        .map(|(li, rate)| {
+            // Rates in hundredths of a basis point keep the weights integral
+            let rate = (rate.max(0.0) * 10_000.0).round() as u128;
+            u128::from(li.subtotal.saturating_sub(li.discount)) * rate
+        })
+        .collect();
+    if taxable.iter().all(|&weight| weight == 0) {
+        taxable = line_items
+            .iter()
+            .map(|li| u128::from(li.subtotal.saturating_sub(li.discount)))
+            .collect();
+    }
+    let taxable_total: u128 = taxable.iter().sum();
+
+    let mut shares: Vec<(usize, u64, u128)> = taxable
+        .iter()
+        .enumerate()
+        .map(|(i, &amount)| {
+            let scaled = u128::from(tax_total) * amount;
+            let share = scaled.checked_div(taxable_total).unwrap_or(0);
+            (
+                i,
+                share as u64,
+                scaled.checked_rem(taxable_total).unwrap_or(0),
+            )
+        })
+        .collect();
+
+    let allocated: u64 = shares.iter().map(|(_, share, _)| share).sum();
+    let mut leftover = tax_total - allocated;
+    shares.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+    for (_, share, _) in shares.iter_mut() {
+        if leftover == 0 {
+            break;
+        }
+        *share += 1;
+        leftover -= 1;
+    }
+    shares.sort_by_key(|(i, _, _)| *i);
+
+    for (line, (_, tax, _)) in line_items.iter_mut().zip(shares) {
+        line.tax = tax;
+        line.total = line.subtotal.saturating_sub(line.discount) + tax;
+    }
+}
@@ -9,11 +9,19 @@ use std::{
 
 use super::super::types::{
     basic_types::{OrderCustomerId, OrderId, OrderStatus},
+    event_types::{OrderEventSink, OrderLifecycleEvent},
     main_order_types::Order,
-    order_types::OrderNote,
-    service_types::{OrderFilter, OrderService},
+    order_types::{OrderNote, PaymentTransaction, Shipment},
+    service_types::{OrderFilter, OrderNumberFormat, OrderService},
+};
+use crate::{
+    errors::CommerceError,
+    implementation::cart_system::Cart,
+    types::{
+        inventory_sync::{InventoryKey, InventoryService, LocationId},
+        product_catalog::ProductId,
+    },
 };
-use crate::{errors::CommerceError, implementation::cart_system::Cart};
 
 impl OrderService {
     /// Creates a new order service.
@@ -23,30 +31,87 @@ impl OrderService {
             orders:             Arc::new(Mutex::new(HashMap::new())),
             orders_by_customer: Arc::new(Mutex::new(HashMap::new())),
             order_counter:      Arc::new(Mutex::new(1000)),
+            number_format:      OrderNumberFormat::default(),
+            event_sink:         None,
+        }
+    }
+
+    /// Uses a custom order number format.
+    #[must_use]
+    pub fn with_number_format(mut self, number_format: OrderNumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    /// Reports lifecycle events to `sink`.
+    #[must_use]
+    pub fn with_event_sink(mut self, sink: Arc<dyn OrderEventSink>) -> Self {
+        self.event_sink = Some(sink);
+        self
+    }
+
+    /// Sends events to the sink, if one is registered.
+    ///
+    /// Call with the order store unlocked.
+    fn emit(&self, events: &[OrderLifecycleEvent]) {
+        if let Some(sink) = &self.event_sink {
+            for event in events {
+                sink.emit(event);
+            }
         }
     }
 
     /// Generates the next order number.
-    fn next_order_number(&self) -> u64 {
+    ///
+    /// Numbers come from a single counter, so they are unique per service.
+    fn next_order_number(&self) -> String {
         let mut counter = self.order_counter.lock().unwrap_or_else(
             |e: std::sync::PoisonError<std::sync::MutexGuard<'_, u64>>| e.into_inner(),
         );
         let num = *counter;
         *counter += 1;
-        num
+        self.number_format.format(num)
     }
 
     /// Creates an order from a cart.
     pub fn create_order(
         &self, cart: &Cart, customer_email: impl Into<String>,
     ) -> Result<Order, CommerceError> {
-        cart.validate_for_checkout()?;
+        let order = self.build_order(cart, customer_email)?;
+        self.store_order(order)
+    }
 
-        let mut order = Order::from_cart(cart, customer_email);
+    /// Creates an order from a cart and reserves its stock.
+    ///
+    /// Stock is reserved at `location_id` under the order ID, with bundle
+    /// lines reserving their components; see [`Cart::reserve_stock`]. The
+    /// order is only created once the whole cart is reserved.
+    pub fn checkout(
+        &self, cart: &Cart, customer_email: impl Into<String>, inventory: &InventoryService,
+        location_id: &LocationId,
+    ) -> Result<Order, CommerceError> {
+        let order = self.build_order(cart, customer_email)?;
+        cart.reserve_stock(inventory, location_id, &order.id.0)?;
+        self.store_order(order)
+    }
 
-        // Use sequential order number
-        order.order_number = format!("#{}", self.next_order_number());
+    /// Builds an order from a cart ready for checkout.
+    fn build_order(
+        &self, cart: &Cart, customer_email: impl Into<String>,
+    ) -> Result<Order, CommerceError> {
+        cart.validate_for_checkout()?;
 
+        let order = Order::from_cart(cart, customer_email);
+        order.validate_currency()?;
+        Ok(order)
+    }
+
+    /// Numbers and stores a new order and reports its creation.
+    ///
+    /// The number is only allocated here, so a checkout that fails earlier
+    /// leaves no gap in the sequence.
+    fn store_order(&self, mut order: Order) -> Result<Order, CommerceError> {
+        order.order_number = self.next_order_number();
         let order_id = order.id.clone();
         let customer_id = order.customer_id.clone();
 
@@ -55,8 +120,14 @@ impl OrderService {
             self.orders_by_customer.lock().map_err(|_| CommerceError::LockError)?;
 
         orders.insert(order_id.clone(), order.clone());
-        by_customer.entry(customer_id).or_insert_with(Vec::new).push(order_id);
-
+        by_customer.entry(customer_id).or_insert_with(Vec::new).push(order_id.clone());
+        drop(by_customer);
+        drop(orders);
+
+        self.emit(&[OrderLifecycleEvent::Created {
+            order_id,
+            order_number: order.order_number.clone(),
+        }]);
         Ok(order)
     }
 
@@ -108,7 +179,40 @@ impl OrderService {
             .get_mut(order_id)
             .ok_or_else(|| CommerceError::OrderNotFound(order_id.0.clone()))?;
 
+        let from = order.status;
         order.update_status(status, user);
+        drop(orders);
+
+        self.emit(&[OrderLifecycleEvent::StatusChanged {
+            order_id: order_id.clone(),
+            from,
+            to: status,
+        }]);
+        Ok(())
+    }
+
+    /// Records a payment transaction on an order.
+    pub fn record_payment(
+        &self, order_id: &OrderId, transaction: PaymentTransaction,
+    ) -> Result<(), CommerceError> {
+        let mut orders = self.orders.lock().map_err(|_| CommerceError::LockError)?;
+
+        let order = orders
+            .get_mut(order_id)
+            .ok_or_else(|| CommerceError::OrderNotFound(order_id.0.clone()))?;
+
+        let transaction_id = transaction.id.clone();
+        let amount = transaction.amount;
+        order.record_payment(transaction);
+        let event = OrderLifecycleEvent::PaymentRecorded {
+            order_id: order_id.clone(),
+            transaction_id,
+            amount,
+            payment_status: order.payment_status,
+        };
+        drop(orders);
+
+        self.emit(&[event]);
         Ok(())
     }
 
@@ -126,15 +230,160 @@ impl OrderService {
             return Err(CommerceError::OrderNotCancellable(order_id.0.clone()));
         }
 
+        let from = order.status;
+        let reason = reason.into();
         order.update_status(OrderStatus::Cancelled, None);
         order.add_note(OrderNote::internal(
-            format!("Order cancelled: {}", reason.into()),
+            format!("Order cancelled: {}", reason),
             "System",
         ));
+        drop(orders);
+
+        self.emit(&[
+            OrderLifecycleEvent::StatusChanged {
+                order_id: order_id.clone(),
+                from,
+                to: OrderStatus::Cancelled,
+            },
+            OrderLifecycleEvent::Cancelled { order_id: order_id.clone(), reason },
+        ]);
+        Ok(())
+    }
+
+    /// Adds a shipment to an order; see [`Order::add_shipment`].
+    pub fn add_shipment(
+        &self, order_id: &OrderId, shipment: Shipment, source_location: Option<&LocationId>,
+    ) -> Result<(), CommerceError> {
+        let mut orders = self.orders.lock().map_err(|_| CommerceError::LockError)?;
 
+        let order = orders
+            .get_mut(order_id)
+            .ok_or_else(|| CommerceError::OrderNotFound(order_id.0.clone()))?;
+
+        let shipment_id = shipment.id.clone();
+        order.add_shipment(shipment, source_location)?;
+        drop(orders);
+
+        self.emit(&[OrderLifecycleEvent::Shipped { order_id: order_id.clone(), shipment_id }]);
         Ok(())
     }
 
+    /// Cancels some units of an order's lines, releasing exactly their
+    /// reserved stock.
+    ///
+    /// See [`Order::cancel_lines`]. Stock is released from `location_id`
+    /// against the reservation made under the order ID, with bundle lines
+    /// releasing their components. The release happens before the order
+    /// changes, under a single inventory lock.
+    ///
+    /// # Errors
+    /// Returns the errors of [`Order::cancel_lines`], or `ValidationError`
+    /// for a quantity above what is still reserved for the order; nothing is
+    /// released or cancelled in that case.
+    pub fn cancel_order_lines(
+        &self, order_id: &OrderId, lines: &[(String, u32)], location_id: &LocationId,
+        inventory: &InventoryService, reason: impl Into<String>,
+    ) -> Result<(), CommerceError> {
+        self.cancel_checked_lines(order_id, location_id, inventory, reason.into(), |order| {
+            order.check_line_cancellations(lines)
+        })
+    }
+
+    /// Cancels an unfulfilled line item, releasing the stock reserved for it.
+    ///
+    /// See [`Order::cancel_line_item`]; stock is released as by
+    /// [`Self::cancel_order_lines`].
+    ///
+    /// # Errors
+    /// Returns the errors of [`Order::cancel_line_item`], or
+    /// `ValidationError` if the line's stock is no longer reserved for the
+    /// order; nothing is released or cancelled in that case.
+    pub fn cancel_line_item(
+        &self, order_id: &OrderId, line_item_id: &str, location_id: &LocationId,
+        inventory: &InventoryService,
+    ) -> Result<(), CommerceError> {
+        let reason = format!("line {line_item_id} cancelled");
+        self.cancel_checked_lines(order_id, location_id, inventory, reason, |order| {
+            order.check_line_item_cancellation(line_item_id)
+        })
+    }
+
+    /// Releases the stock of the line quantities `check` returns, then
+    /// cancels them on the order.
+    fn cancel_checked_lines(
+        &self, order_id: &OrderId, location_id: &LocationId, inventory: &InventoryService,
+        reason: String, check: impl FnOnce(&Order) -> Result<Vec<(usize, u32)>, CommerceError>,
+    ) -> Result<(), CommerceError> {
+        let mut orders = self.orders.lock().map_err(|_| CommerceError::LockError)?;
+
+        let order = orders
+            .get_mut(order_id)
+            .ok_or_else(|| CommerceError::OrderNotFound(order_id.0.clone()))?;
+
+        let cancellations = check(order)?;
+        let releases: Vec<(InventoryKey, u32)> = cancellations
+            .iter()
+            .flat_map(|&(index, quantity)| {
+                let line = &order.line_items[index];
+                InventoryKey::for_line(
+                    &line.product_id,
+                    line.variant_id.as_ref(),
+                    &line.bundle_items,
+                    location_id,
+                    quantity,
+                )
+            })
+            .collect();
+        inventory.release_for_reference_batch(&releases, &order_id.0)?;
+
+        let from = order.status;
+        order.apply_line_cancellations(&cancellations, &reason);
+
+        let mut events = Vec::new();
+        if order.status != from {
+            events.push(OrderLifecycleEvent::StatusChanged {
+                order_id: order_id.clone(),
+                from,
+                to: order.status,
+            });
+            events.push(OrderLifecycleEvent::Cancelled { order_id: order_id.clone(), reason });
+        }
+        drop(orders);
+
+        self.emit(&events);
+        Ok(())
+    }
+
+    /// Picks the location to fulfill an order's open lines from.
+    ///
+    /// Pickup orders only draw on their pickup location, and rush orders
+    /// prefer the location with the shortest delivery estimate; see
+    /// [`InventoryService::select_fulfillment_location`].
+    pub fn select_fulfillment_location(
+        &self, order_id: &OrderId, inventory: &InventoryService,
+    ) -> Result<LocationId, CommerceError> {
+        let orders = self.orders.lock().map_err(|_| CommerceError::LockError)?;
+
+        let order = orders
+            .get(order_id)
+            .ok_or_else(|| CommerceError::OrderNotFound(order_id.0.clone()))?;
+
+        let items: Vec<(ProductId, Option<ProductId>, u32)> = order
+            .line_items
+            .iter()
+            .map(|l| {
+                let open = l
+                    .quantity
+                    .saturating_sub(l.quantity_fulfilled)
+                    .saturating_sub(l.quantity_cancelled);
+                (l.product_id.clone(), l.variant_id.clone(), open)
+            })
+            .filter(|(_, _, open)| *open > 0)
+            .collect();
+
+        inventory.select_fulfillment_location(&items, order.is_rush, &order.fulfillment_type)
+    }
+
     /// Searches orders.
     pub fn search_orders(&self, filter: &OrderFilter) -> Result<Vec<Order>, CommerceError> {
         let orders = self.orders.lock().map_err(|_| CommerceError::LockError)?;
@@ -189,6 +438,10 @@ impl OrderService {
             return false;
         }
 
+        if !filter.tags.iter().all(|tag| order.has_tag(tag)) {
+            return false;
+        }
+
         true
     }
 }
@@ -12,12 +12,14 @@ pub mod types {
     //! Type definitions for order management.
 
     pub mod basic_types;
+    pub mod event_types;
     pub mod main_order_types;
     pub mod order_types;
     pub mod service_types;
 
     // Re-export commonly used types
     pub use basic_types::*;
+    pub use event_types::*;
     pub use main_order_types::*;
     pub use order_types::*;
     pub use service_types::*;
@@ -43,6 +45,9 @@ pub mod errors {
     pub use r#mod::*;
 }
 
+#[cfg(test)]
+mod tests;
+
 // Re-export main types for convenience
 pub use implementations::*;
 pub use types::*;
@@ -0,0 +1,888 @@
+//! # Order Management Tests
+//!
+//! Test suite for order management functionality.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        errors::CommerceError,
+        implementation::{
+            cart_system::{
+                AppliedDiscount, Cart, CouponCode, CustomerId, ShippingAddress, ShippingMethod,
+                TaxMode, TaxRounding,
+            },
+            order_management::types::{
+                FulfillmentStatus, LedgerAccount, Order, OrderCustomerId, OrderEventSink,
+                OrderEventType, OrderFilter, OrderLifecycleEvent, OrderNumberFormat, OrderService,
+                OrderStatus, PaymentStatus, PaymentTransaction, RefundDestination, Shipment,
+                ShipmentItem, ShipmentStatus, TransactionStatus, TransactionType,
+            },
+        },
+        types::{
+            inventory_sync::{InventoryLocation, InventoryService, LocationId},
+            product_catalog::{
+                BundleComponent, Currency, Price, Product, ProductId, ProductStatus, ProductType,
+                Sku,
+            },
+        },
+    };
+
+    /// Sink that keeps every event in memory.
+    #[derive(Debug, Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<OrderLifecycleEvent>>,
+    }
+
+    impl RecordingSink {
+        /// Events recorded so far, oldest first.
+        fn events(&self) -> Vec<OrderLifecycleEvent> {
+            self.events.lock().map(|events| events.clone()).unwrap_or_default()
+        }
+    }
+
+    impl OrderEventSink for RecordingSink {
+        fn emit(&self, event: &OrderLifecycleEvent) {
+            if let Ok(mut events) = self.events.lock() {
+                events.push(event.clone());
+            }
+        }
+    }
+
+    fn create_test_cart(price: u64) -> Cart {
+        let mut product = Product::new(ProductId::new("prod-001"), Sku::new("SKU-001"), "Widget");
+        product.status = ProductStatus::Active;
+        product.price = Price::new(price, Currency::usd(), 2);
+        product.inventory_quantity = 100;
+
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&product, 1).expect("add item");
+        cart.set_shipping_address(ShippingAddress::default());
+        cart
+    }
+
+    fn gadget_product(price: u64) -> Product {
+        let mut gadget = Product::new(ProductId::new("prod-002"), Sku::new("SKU-002"), "Gadget");
+        gadget.status = ProductStatus::Active;
+        gadget.price = Price::new(price, Currency::usd(), 2);
+        gadget.inventory_quantity = 100;
+        gadget
+    }
+
+    fn shipment(id: &str, items: Vec<ShipmentItem>) -> Shipment {
+        Shipment {
+            id: id.to_string(),
+            carrier: "Test Carrier".to_string(),
+            tracking_number: None,
+            tracking_url: None,
+            status: ShipmentStatus::Pending,
+            items,
+            shipping_address: ShippingAddress::default(),
+            gift_message: None,
+            shipped_at: None,
+            delivered_at: None,
+            created_at: 0,
+        }
+    }
+
+    fn create_test_order(price: u64) -> Order {
+        Order::from_cart(&create_test_cart(price), "customer@example.com")
+    }
+
+    fn transaction(id: &str, transaction_type: TransactionType, amount: u64) -> PaymentTransaction {
+        PaymentTransaction {
+            id: id.to_string(),
+            external_id: None,
+            transaction_type,
+            amount,
+            currency: Currency::usd(),
+            status: TransactionStatus::Success,
+            gateway: "test".to_string(),
+            payment_method: None,
+            error_message: None,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_interleaved_payments() {
+        let mut order = create_test_order(1000);
+
+        order.record_payment(transaction("tx-1", TransactionType::Capture, 600));
+        order.record_payment(transaction("tx-2", TransactionType::Refund, 100));
+        order.record_payment(transaction("tx-3", TransactionType::Capture, 400));
+        assert!(order.is_balanced());
+
+        // Simulate totals drifting from the transaction log
+        order.totals.amount_paid = 400;
+        order.totals.amount_due = 700;
+        assert!(!order.is_balanced());
+
+        order.reconcile_payments();
+
+        assert!(order.is_balanced());
+        assert_eq!(order.totals.amount_paid, 1000);
+        assert_eq!(order.totals.amount_refunded, 100);
+        assert_eq!(order.totals.amount_due, 100);
+    }
+
+    #[test]
+    fn test_order_discount_reduces_grand_total() {
+        let mut order = create_test_order(1000);
+        assert_eq!(order.totals.grand_total, 1000);
+
+        order.apply_order_discount(150);
+
+        assert_eq!(order.totals.order_discount, 150);
+        assert_eq!(order.totals.discount_total, 150);
+        assert_eq!(order.totals.grand_total, 850);
+        assert_eq!(order.totals.amount_due, 850);
+    }
+
+    #[test]
+    fn test_gift_message_carried_to_shipment() {
+        let mut order = create_test_order(1000);
+        let total = order.totals.grand_total;
+        order.record_payment(transaction("tx-1", TransactionType::Capture, total));
+        order.gift_message = Some("Happy birthday!".to_string());
+
+        order.add_shipment(shipment("ship-1", Vec::new()), None).expect("ship");
+
+        assert_eq!(
+            order.shipments[0].gift_message.as_deref(),
+            Some("Happy birthday!")
+        );
+    }
+
+    #[test]
+    fn test_order_tags_normalized_and_deduplicated() {
+        let mut order = create_test_order(1000);
+
+        order.add_tag("VIP");
+        order.add_tag(" vip ");
+        order.add_tag("Gift");
+        assert_eq!(order.tags, vec!["vip".to_string(), "gift".to_string()]);
+
+        order.remove_tag("GIFT");
+        assert_eq!(order.tags, vec!["vip".to_string()]);
+    }
+
+    #[test]
+    fn test_search_orders_by_tags() {
+        let service = OrderService::new();
+
+        let mut vip =
+            service.create_order(&create_test_cart(1000), "a@example.com").expect("order");
+        vip.add_tag("vip");
+        service.update_order(vip.clone()).expect("update");
+
+        let mut vip_gift =
+            service.create_order(&create_test_cart(2000), "b@example.com").expect("order");
+        vip_gift.add_tag("vip");
+        vip_gift.add_tag("gift");
+        service.update_order(vip_gift.clone()).expect("update");
+
+        service.create_order(&create_test_cart(3000), "c@example.com").expect("order");
+
+        let filter = OrderFilter { tags: vec!["VIP".to_string()], ..Default::default() };
+        assert_eq!(service.search_orders(&filter).expect("search").len(), 2);
+
+        let filter =
+            OrderFilter { tags: vec!["vip".to_string(), "gift".to_string()], ..Default::default() };
+        let results = service.search_orders(&filter).expect("search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, vip_gift.id);
+    }
+
+    #[test]
+    fn test_split_fulfillment_restocks_to_source_locations() {
+        let mut cart = create_test_cart(1000);
+        cart.update_item_quantity(&ProductId::new("prod-001"), 5).expect("set quantity");
+        let mut order = Order::from_cart(&cart, "customer@example.com");
+        let total = order.totals.grand_total;
+        order.record_payment(transaction("tx-1", TransactionType::Capture, total));
+        let line_id = order.line_items[0].id.clone();
+
+        let east = LocationId::new("east");
+        let west = LocationId::new("west");
+        let items = |quantity: u32| vec![ShipmentItem { line_item_id: line_id.clone(), quantity }];
+        order.add_shipment(shipment("ship-1", items(3)), Some(&east)).expect("ship");
+        order.add_shipment(shipment("ship-2", items(2)), Some(&west)).expect("ship");
+
+        let line = &mut order.line_items[0];
+        assert_eq!(line.quantity_fulfilled, 5);
+        assert_eq!(line.fulfilled_from, vec![
+            (east.clone(), 3),
+            (west.clone(), 2)
+        ]);
+
+        // Returning 4 units drains the most recent location first
+        let restock = line.take_returned(4);
+        assert_eq!(restock, vec![(west.clone(), 2), (east.clone(), 2)]);
+        assert_eq!(line.fulfilled_from, vec![(east.clone(), 1)]);
+
+        let inventory = InventoryService::new();
+        let product_id = ProductId::new("prod-001");
+        for (location_id, quantity) in &restock {
+            inventory
+                .receive_stock(&product_id, None, location_id, *quantity, "return")
+                .expect("restock");
+        }
+        let on_hand = |location: &LocationId| {
+            inventory.get_inventory(&product_id, None, location).expect("level").on_hand
+        };
+        assert_eq!(on_hand(&east), 2);
+        assert_eq!(on_hand(&west), 2);
+    }
+
+    #[test]
+    fn test_order_numbers_sequential_with_custom_format() {
+        let service = OrderService::new().with_number_format(OrderNumberFormat::new("WEB-", 6));
+
+        let numbers: Vec<String> = (0..3)
+            .map(|_| {
+                service
+                    .create_order(&create_test_cart(1000), "a@example.com")
+                    .expect("order")
+                    .order_number
+            })
+            .collect();
+
+        assert_eq!(numbers, vec!["WEB-001000", "WEB-001001", "WEB-001002"]);
+        assert!(create_test_order(1000).order_number.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_order_lines_releases_only_cancelled_line() {
+        let mut cart = create_test_cart(1000);
+        let gadget = gadget_product(2500);
+        cart.add_item(&gadget, 2).expect("add gadget");
+
+        let service = OrderService::new();
+        let order = service.create_order(&cart, "a@example.com").expect("order");
+
+        let inventory = InventoryService::new();
+        let location = LocationId::default_warehouse();
+        for line in &order.line_items {
+            inventory
+                .set_inventory(
+                    line.product_id.clone(),
+                    None,
+                    location.clone(),
+                    10,
+                    "Initial",
+                )
+                .expect("set");
+            inventory
+                .reserve_stock(
+                    &line.product_id,
+                    None,
+                    &location,
+                    line.quantity,
+                    order.id.0.clone(),
+                )
+                .expect("reserve");
+        }
+
+        let widget_line = order.line_items[0].id.clone();
+        let gadget_line = order.line_items[1].id.clone();
+        let committed = |product: &str| {
+            inventory
+                .get_inventory(&ProductId::new(product), None, &location)
+                .expect("level")
+                .committed
+        };
+
+        // Cancelling more than is on the line fails without releasing anything
+        let over = service.cancel_order_lines(
+            &order.id,
+            &[(widget_line.clone(), 1), (gadget_line.clone(), 3)],
+            &location,
+            &inventory,
+            "out of stock",
+        );
+        assert!(over.is_err());
+        assert_eq!((committed("prod-001"), committed("prod-002")), (1, 2));
+
+        service
+            .cancel_order_lines(
+                &order.id,
+                &[(gadget_line.clone(), 1)],
+                &location,
+                &inventory,
+                "changed mind",
+            )
+            .expect("cancel one gadget");
+        assert_eq!((committed("prod-001"), committed("prod-002")), (1, 1));
+        let partial = service.get_order(&order.id).expect("order");
+        assert_eq!(partial.line_items[1].subtotal, 2500);
+        assert_eq!(partial.totals.grand_total, 3500);
+
+        service
+            .cancel_order_lines(
+                &order.id,
+                &[(gadget_line, 1)],
+                &location,
+                &inventory,
+                "changed mind",
+            )
+            .expect("cancel gadget");
+        assert_eq!((committed("prod-001"), committed("prod-002")), (1, 0));
+
+        let order = service.get_order(&order.id).expect("order");
+        assert_eq!(order.line_items[1].quantity_cancelled, 2);
+        assert_eq!(order.line_items[0].quantity_cancelled, 0);
+        assert_eq!(order.totals.grand_total, 1000);
+        let cancellations = order
+            .history
+            .iter()
+            .filter(|e| e.event_type == OrderEventType::LineItemCancelled)
+            .count();
+        assert_eq!(cancellations, 2);
+        assert!(order.can_cancel());
+    }
+
+    #[test]
+    fn test_checkout_reserves_bundle_components() {
+        let inventory = InventoryService::new();
+        let location = LocationId::default_warehouse();
+        let frame = ProductId::new("frame");
+        let wheel = ProductId::new("wheel");
+        for (product_id, stock) in [(&frame, 5), (&wheel, 5)] {
+            inventory
+                .set_inventory(product_id.clone(), None, location.clone(), stock, "Initial")
+                .expect("set");
+        }
+
+        let mut bike = Product::new(ProductId::new("bike"), Sku::new("BIKE"), "Bike");
+        bike.status = ProductStatus::Active;
+        bike.product_type = ProductType::Bundle;
+        bike.price = Price::new(20000, Currency::usd(), 2);
+        bike.inventory_quantity = 100;
+        bike.bundle_items = vec![
+            BundleComponent::new(frame.clone(), 1),
+            BundleComponent::new(wheel.clone(), 2),
+        ];
+
+        let committed = |product_id: &ProductId| {
+            inventory.get_inventory(product_id, None, &location).expect("level").committed
+        };
+        let service = OrderService::new();
+
+        // Three bikes need six wheels: nothing is reserved or ordered
+        let mut cart = Cart::new(CustomerId::new("customer-1"));
+        cart.add_item(&bike, 3).expect("add bike");
+        cart.set_shipping_address(ShippingAddress::default());
+        let short = service.checkout(&cart, "a@example.com", &inventory, &location);
+        assert!(matches!(
+            short,
+            Err(CommerceError::InsufficientInventory { .. })
+        ));
+        assert_eq!((committed(&frame), committed(&wheel)), (0, 0));
+        let customer_id = OrderCustomerId::new("customer-1");
+        assert!(service.get_customer_orders(&customer_id).expect("orders").is_empty());
+
+        cart.update_item_quantity(&bike.id, 2).expect("update");
+        let order = service
+            .checkout(&cart, "a@example.com", &inventory, &location)
+            .expect("checkout");
+        // The failed checkout did not use up an order number
+        assert_eq!(order.order_number, "#1000");
+        assert_eq!((committed(&frame), committed(&wheel)), (2, 4));
+        assert_eq!(
+            inventory
+                .reserved_for_reference(&wheel, None, &location, &order.id.0)
+                .expect("ledger"),
+            4
+        );
+    }
+
+    #[test]
+    fn test_cancel_line_item_drops_totals_and_releases_stock() {
+        let mut cart = create_test_cart(1000);
+        let gadget = gadget_product(2500);
+        cart.add_item(&gadget, 2).expect("add gadget");
+
+        let service = OrderService::new();
+        let order = service.create_order(&cart, "a@example.com").expect("order");
+        assert_eq!(order.totals.grand_total, 6000);
+
+        let inventory = InventoryService::new();
+        let location = LocationId::default_warehouse();
+        let gadget_id = ProductId::new("prod-002");
+        inventory
+            .set_inventory(gadget_id.clone(), None, location.clone(), 10, "Initial")
+            .expect("set");
+        inventory
+            .reserve_stock(&gadget_id, None, &location, 2, order.id.0.clone())
+            .expect("reserve");
+
+        // The widget's stock was never reserved: nothing is cancelled
+        let widget_line = order.line_items[0].id.clone();
+        assert!(matches!(
+            service.cancel_line_item(&order.id, &widget_line, &location, &inventory),
+            Err(CommerceError::InventoryNotFound(_))
+        ));
+        let unchanged = service.get_order(&order.id).expect("order");
+        assert_eq!(unchanged.line_items[0].quantity_cancelled, 0);
+        assert_eq!(unchanged.totals.grand_total, 6000);
+
+        let gadget_line = order.line_items[1].id.clone();
+        service
+            .cancel_line_item(&order.id, &gadget_line, &location, &inventory)
+            .expect("cancel gadget");
+
+        let order = service.get_order(&order.id).expect("order");
+        assert_eq!(order.totals.grand_total, 1000);
+        assert_eq!(order.line_items[1].quantity_cancelled, 2);
+        assert!(order.can_cancel());
+        let level = inventory.get_inventory(&gadget_id, None, &location).expect("level");
+        assert_eq!(level.committed, 0);
+
+        // A line cannot be cancelled twice
+        assert!(
+            service
+                .cancel_line_item(&order.id, &gadget_line, &location, &inventory)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cancel_line_item_rejects_fulfilled_line() {
+        let mut cart = create_test_cart(1000);
+        let gadget = gadget_product(2500);
+        cart.add_item(&gadget, 1).expect("add gadget");
+        let mut order = Order::from_cart(&cart, "customer@example.com");
+
+        order.line_items[0].quantity_fulfilled = 1;
+        order.fulfillment_status = FulfillmentStatus::PartiallyFulfilled;
+        assert!(matches!(
+            order.cancel_line_item("line-1"),
+            Err(CommerceError::ValidationError(_))
+        ));
+        assert_eq!(order.totals.grand_total, 3500);
+
+        order.fulfillment_status = FulfillmentStatus::Fulfilled;
+        assert!(matches!(
+            order.cancel_line_item("line-2"),
+            Err(CommerceError::OrderNotCancellable(_))
+        ));
+    }
+
+    #[test]
+    fn test_held_order_blocks_shipment_until_released() {
+        let mut order = create_test_order(1000);
+        let total = order.totals.grand_total;
+        order.record_payment(transaction("tx-1", TransactionType::Capture, total));
+        order.update_status(OrderStatus::Processing, None);
+
+        order.place_on_hold("fraud review").expect("hold");
+        assert_eq!(order.status, OrderStatus::OnHold);
+        assert_eq!(order.hold_reason.as_deref(), Some("fraud review"));
+        assert!(order.history.iter().any(|e| e.description.contains("fraud review")));
+        assert!(order.place_on_hold("again").is_err());
+        assert!(matches!(
+            order.add_shipment(shipment("ship-1", Vec::new()), None),
+            Err(CommerceError::OrderOnHold(_))
+        ));
+        assert!(order.shipments.is_empty());
+
+        order.release_hold().expect("release");
+        assert_eq!(order.status, OrderStatus::Processing);
+        assert!(order.hold_reason.is_none());
+        assert!(order.status_before_hold.is_none());
+        assert!(order.history.iter().any(|e| e.description == "Hold released: fraud review"));
+        assert!(order.release_hold().is_err());
+        order.add_shipment(shipment("ship-1", Vec::new()), None).expect("ship");
+        assert_eq!(order.shipments.len(), 1);
+
+        // Cancelling a held order also clears the hold
+        order.place_on_hold("chargeback").expect("hold");
+        order.update_status(OrderStatus::Cancelled, None);
+        assert!(order.hold_reason.is_none());
+        assert!(order.status_before_hold.is_none());
+    }
+
+    #[test]
+    fn test_refund_to_store_credit_skips_gateway() {
+        let mut order = create_test_order(1000);
+        order.record_payment(transaction("tx-1", TransactionType::Capture, 1000));
+        order.status = OrderStatus::Processing;
+
+        order.refund(300, RefundDestination::StoreCredit).expect("refund");
+
+        assert_eq!(order.store_credits.len(), 1);
+        assert_eq!(order.store_credit_issued(), 300);
+        assert!(!order.transactions.iter().any(|t| t.transaction_type == TransactionType::Refund));
+        assert_eq!(order.totals.amount_refunded, 300);
+        assert_eq!(order.max_refund_amount(), 700);
+        assert!(order.is_balanced());
+    }
+
+    #[test]
+    fn test_refund_line_item_to_original_method() {
+        let mut order = create_test_order(1000);
+        order.record_payment(transaction("tx-1", TransactionType::Capture, 1000));
+        order.status = OrderStatus::Processing;
+        let line_id = order.line_items[0].id.clone();
+
+        order
+            .refund_line_item(&line_id, 1, RefundDestination::OriginalMethod)
+            .expect("refund");
+
+        let refund = order
+            .transactions
+            .iter()
+            .find(|t| t.transaction_type == TransactionType::Refund)
+            .expect("gateway refund recorded");
+        assert_eq!(refund.amount, 1000);
+        assert_eq!(refund.gateway, "test");
+        assert!(order.store_credits.is_empty());
+        assert_eq!(order.line_items[0].quantity_refunded, 1);
+        assert!(matches!(
+            order.refund_line_item(&line_id, 1, RefundDestination::StoreCredit),
+            Err(CommerceError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_refund_line_item_after_partial_cancellation() {
+        let mut cart = create_test_cart(1000);
+        cart.items_mut()[0].quantity = 4;
+        let mut order = Order::from_cart(&cart, "customer@example.com");
+        order.record_payment(transaction("tx-1", TransactionType::Capture, 4000));
+        order.status = OrderStatus::Processing;
+        let line_id = order.line_items[0].id.clone();
+        order.cancel_lines(&[(line_id.clone(), 2)], "out of stock").expect("cancel");
+        assert_eq!(order.line_items[0].total, 2000);
+
+        order
+            .refund_line_item(&line_id, 1, RefundDestination::StoreCredit)
+            .expect("refund");
+        assert_eq!(order.totals.amount_refunded, 1000);
+        assert!(matches!(
+            order.refund_line_item(&line_id, 2, RefundDestination::StoreCredit),
+            Err(CommerceError::ValidationError(_))
+        ));
+        order
+            .refund_line_item(&line_id, 1, RefundDestination::StoreCredit)
+            .expect("refund");
+        assert_eq!(order.totals.amount_refunded, 2000);
+    }
+
+    #[test]
+    fn test_rush_order_ships_from_fastest_location() {
+        let inventory = InventoryService::new();
+        let main = LocationId::new("warehouse-east");
+        let express = LocationId::new("express-hub");
+        inventory
+            .add_location(
+                InventoryLocation::warehouse(main.clone(), "East Warehouse")
+                    .with_delivery_estimate(5),
+            )
+            .expect("add main");
+        let mut hub =
+            InventoryLocation::warehouse(express.clone(), "Express Hub").with_delivery_estimate(1);
+        hub.fulfillment_priority = 5;
+        inventory.add_location(hub).expect("add hub");
+        for location in [&main, &express] {
+            inventory
+                .set_inventory(
+                    ProductId::new("prod-001"),
+                    None,
+                    location.clone(),
+                    10,
+                    "Initial",
+                )
+                .expect("set");
+        }
+
+        let service = OrderService::new();
+        let mut order =
+            service.create_order(&create_test_cart(1000), "a@example.com").expect("order");
+        assert_eq!(
+            service.select_fulfillment_location(&order.id, &inventory).expect("select"),
+            main
+        );
+
+        order.is_rush = true;
+        service.update_order(order.clone()).expect("update");
+        assert_eq!(
+            service.select_fulfillment_location(&order.id, &inventory).expect("select"),
+            express
+        );
+    }
+
+    #[test]
+    fn test_event_sink_sees_order_lifecycle_in_order() {
+        use std::sync::Arc;
+
+        let sink = Arc::new(RecordingSink::default());
+        let service = OrderService::new().with_event_sink(sink.clone());
+
+        let order = service.create_order(&create_test_cart(1000), "a@example.com").expect("order");
+        service
+            .record_payment(
+                &order.id,
+                transaction("tx-1", TransactionType::Capture, 1000),
+            )
+            .expect("pay");
+        service
+            .update_order_status(&order.id, OrderStatus::Processing, None)
+            .expect("process");
+        service
+            .add_shipment(&order.id, shipment("ship-1", Vec::new()), None)
+            .expect("ship");
+
+        let from = order.status;
+        assert_eq!(sink.events(), vec![
+            OrderLifecycleEvent::Created {
+                order_id:     order.id.clone(),
+                order_number: order.order_number.clone(),
+            },
+            OrderLifecycleEvent::PaymentRecorded {
+                order_id:       order.id.clone(),
+                transaction_id: "tx-1".to_string(),
+                amount:         1000,
+                payment_status: PaymentStatus::Captured,
+            },
+            OrderLifecycleEvent::StatusChanged {
+                order_id: order.id.clone(),
+                from,
+                to: OrderStatus::Processing,
+            },
+            OrderLifecycleEvent::Shipped {
+                order_id:    order.id.clone(),
+                shipment_id: "ship-1".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_deposit_then_balance() {
+        let mut order = create_test_order(1000);
+        let total = order.totals.grand_total;
+        let deposit = total * 30 / 100;
+        let shipment = shipment("ship-1", Vec::new());
+
+        // Unpaid and merely authorized orders are not shipped either
+        for status in [PaymentStatus::Pending, PaymentStatus::Authorized] {
+            order.payment_status = status;
+            assert!(matches!(
+                order.add_shipment(shipment.clone(), None),
+                Err(CommerceError::PaymentIncomplete(_))
+            ));
+        }
+        order.payment_status = PaymentStatus::Pending;
+
+        order.record_deposit(deposit).expect("deposit");
+
+        assert_eq!(order.payment_status, PaymentStatus::PartiallyPaid);
+        assert_eq!(order.status, OrderStatus::Processing);
+        assert_eq!(order.totals.amount_due, total - deposit);
+        assert!(order.is_balanced());
+        assert!(matches!(
+            order.add_shipment(shipment.clone(), None),
+            Err(CommerceError::PaymentIncomplete(_))
+        ));
+        assert!(matches!(
+            order.record_deposit(total),
+            Err(CommerceError::ValidationError(_))
+        ));
+
+        order.record_deposit(total - deposit).expect("balance");
+
+        assert_eq!(order.payment_status, PaymentStatus::Captured);
+        assert_eq!(order.totals.amount_due, 0);
+        order.add_shipment(shipment, None).expect("ship");
+    }
+
+    #[test]
+    fn test_partial_refund_does_not_unlock_shipping() {
+        let mut order = create_test_order(1000);
+        let total = order.totals.grand_total;
+        order.record_payment(transaction("tx-1", TransactionType::Capture, total / 2));
+        order.record_payment(transaction("tx-2", TransactionType::Refund, 100));
+        assert_eq!(order.payment_status, PaymentStatus::PartiallyRefunded);
+
+        assert!(matches!(
+            order.add_shipment(shipment("ship-1", Vec::new()), None),
+            Err(CommerceError::PaymentIncomplete(_))
+        ));
+        assert!(order.shipments.is_empty());
+    }
+
+    #[test]
+    fn test_create_order_rejects_mixed_currency_cart() {
+        let service = OrderService::new();
+        let mut cart = create_test_cart(1000);
+
+        // Bypass the cart's own currency check, e.g. a stale persisted cart
+        let mut foreign = cart.items()[0].clone();
+        foreign.product_id = ProductId::new("prod-002");
+        foreign.unit_price = Price::new(900, Currency::new("EUR"), 2);
+        cart.items_mut().push(foreign);
+
+        let result = service.create_order(&cart, "customer@example.com");
+        assert!(matches!(
+            result,
+            Err(CommerceError::CurrencyMismatch { ref expected, ref got })
+                if expected == "USD" && got == "EUR"
+        ));
+        assert!(service.search_orders(&OrderFilter::default()).expect("search").is_empty());
+    }
+
+    #[test]
+    fn test_order_totals_consistent_with_awkward_amounts() {
+        let cases = [
+            (
+                vec![(333, 3), (199, 7), (1, 13)],
+                7.25,
+                TaxRounding::HalfUp,
+                10,
+            ),
+            (
+                vec![(999, 1), (1, 1), (2, 1)],
+                8.875,
+                TaxRounding::Bankers,
+                15,
+            ),
+            (vec![(1, 1), (1, 1), (1, 1)], 33.0, TaxRounding::Truncate, 0),
+        ];
+
+        for (lines, tax_rate, rounding, coupon_percent) in cases {
+            let mut cart = Cart::new(CustomerId::new("customer-1"));
+            for (i, (price, quantity)) in lines.into_iter().enumerate() {
+                let mut product = Product::new(
+                    ProductId::new(format!("prod-{i}")),
+                    Sku::new(format!("SKU-{i}")),
+                    "Widget",
+                );
+                product.status = ProductStatus::Active;
+                product.price = Price::new(price, Currency::usd(), 2);
+                product.inventory_quantity = 100;
+                cart.add_item(&product, quantity).expect("add item");
+            }
+            cart.set_tax_rate(tax_rate);
+            cart.set_tax_rounding(rounding);
+            if coupon_percent > 0 {
+                cart.apply_discount(AppliedDiscount::percentage(
+                    CouponCode::new("SAVE"),
+                    coupon_percent,
+                    "Coupon",
+                ))
+                .expect("apply coupon");
+            }
+            cart.set_shipping_address(ShippingAddress::default());
+
+            let mut order = Order::from_cart(&cart, "customer@example.com");
+            let cart_totals = cart.calculate_totals();
+            assert_eq!(order.totals.tax_total, cart_totals.tax_total);
+            assert_eq!(order.totals.grand_total, cart_totals.grand_total);
+            order.assert_totals_consistent().expect("consistent after from_cart");
+
+            order.apply_order_discount(7);
+            order.assert_totals_consistent().expect("consistent after recalculation");
+        }
+    }
+
+    #[test]
+    fn test_order_lines_use_item_tax_rates() {
+        let mut cart = create_test_cart(1000);
+        let gadget = gadget_product(2000);
+        cart.add_item(&gadget, 1).expect("add gadget");
+        cart.set_tax_rate(8.25);
+        cart.items_mut()[0].tax_rate = Some(0.0);
+
+        let order = Order::from_cart(&cart, "customer@example.com");
+        assert_eq!(order.line_items[0].tax, 0);
+        assert_eq!(order.line_items[1].tax, 165);
+        assert_eq!(order.totals.tax_total, 165);
+        order.assert_totals_consistent().expect("consistent");
+    }
+
+    #[test]
+    fn test_order_from_tax_inclusive_cart_keeps_gross_total() {
+        let mut cart = create_test_cart(12000);
+        cart.set_tax_rate(20.0);
+        cart.set_tax_mode(TaxMode::Inclusive);
+
+        let mut order = Order::from_cart(&cart, "customer@example.com");
+        assert_eq!(order.totals.subtotal, 10000);
+        assert_eq!(order.totals.tax_total, 2000);
+        assert_eq!(order.totals.grand_total, 12000);
+        assert_eq!(order.line_items[0].total, 12000);
+        order.assert_totals_consistent().expect("consistent after from_cart");
+
+        order.recalculate_totals();
+        assert_eq!(order.totals.grand_total, 12000);
+    }
+
+    #[test]
+    fn test_ledger_entries_balance_against_payment() {
+        let mut cart = create_test_cart(2499);
+        let gadget = gadget_product(1333);
+        cart.add_item(&gadget, 3).expect("add gadget");
+        cart.set_tax_rate(8.25);
+        cart.set_shipping_method(ShippingMethod::new(
+            "ground",
+            "Ground",
+            Price::new(799, Currency::usd(), 2),
+        ))
+        .expect("set shipping");
+        cart.apply_discount(AppliedDiscount::percentage(
+            CouponCode::new("SAVE10"),
+            10,
+            "10% off",
+        ))
+        .expect("apply coupon");
+
+        let mut order = Order::from_cart(&cart, "customer@example.com");
+        order.apply_order_discount(150);
+
+        let balance = |order: &Order| {
+            let entries = order.to_ledger_entries();
+            let debits: u64 = entries.iter().map(|e| e.debit).sum();
+            let credits: u64 = entries.iter().map(|e| e.credit).sum();
+            let cash: u64 = entries
+                .iter()
+                .filter(|e| e.account == LedgerAccount::Cash)
+                .map(|e| e.debit)
+                .sum();
+            (debits, credits, cash, entries)
+        };
+
+        // Unpaid: the whole total is receivable
+        let (debits, credits, cash, entries) = balance(&order);
+        assert_eq!(debits, credits);
+        assert_eq!(cash, 0);
+        assert!(
+            entries.iter().any(|e| e.account == LedgerAccount::AccountsReceivable
+                && e.debit == order.totals.grand_total)
+        );
+
+        let total = order.totals.grand_total;
+        order.record_payment(transaction("tx-1", TransactionType::Capture, total));
+        order.status = OrderStatus::Processing;
+        order.refund(200, RefundDestination::StoreCredit).expect("store credit");
+        order.refund(100, RefundDestination::OriginalMethod).expect("refund");
+
+        let (debits, credits, cash, entries) = balance(&order);
+        assert_eq!(debits, credits);
+        assert_eq!(cash, order.totals.amount_paid);
+        assert!(!entries.iter().any(|e| e.account == LedgerAccount::AccountsReceivable));
+        let revenue: u64 = entries
+            .iter()
+            .filter(|e| e.account == LedgerAccount::Revenue)
+            .map(|e| e.credit)
+            .sum();
+        assert_eq!(revenue, order.totals.subtotal);
+        let refunds: u64 = entries
+            .iter()
+            .filter(|e| e.account == LedgerAccount::Refunds)
+            .map(|e| e.debit)
+            .sum();
+        assert_eq!(refunds, 300);
+    }
+}
@@ -134,6 +134,8 @@ pub enum PaymentStatus {
     Pending,
     /// Payment authorized but not captured.
     Authorized,
+    /// Deposit or part payment captured, balance outstanding.
+    PartiallyPaid,
     /// Payment captured.
     Captured,
     /// Payment partially refunded.
@@ -146,6 +148,14 @@ pub enum PaymentStatus {
     Cancelled,
 }
 
+impl PaymentStatus {
+    /// Whether the full order total has been captured.
+    #[must_use]
+    pub fn is_fully_paid(&self) -> bool {
+        matches!(self, Self::Captured | Self::PartiallyRefunded)
+    }
+}
+
 /// Fulfillment status.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FulfillmentStatus {
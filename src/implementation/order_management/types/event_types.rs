@@ -0,0 +1,61 @@
+//! Order lifecycle events.
+//!
+//! `OrderService` reports lifecycle changes to an optional
+//! [`OrderEventSink`] so integrations (email, analytics, ERP) can react.
+
+use super::basic_types::{OrderId, OrderStatus, PaymentStatus};
+
+/// A change in an order's lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderLifecycleEvent {
+    /// Order created from a cart.
+    Created {
+        /// Order ID.
+        order_id:     OrderId,
+        /// Customer-facing order number.
+        order_number: String,
+    },
+    /// Order status changed.
+    StatusChanged {
+        /// Order ID.
+        order_id: OrderId,
+        /// Previous status.
+        from:     OrderStatus,
+        /// New status.
+        to:       OrderStatus,
+    },
+    /// Payment transaction recorded.
+    PaymentRecorded {
+        /// Order ID.
+        order_id:       OrderId,
+        /// Transaction ID.
+        transaction_id: String,
+        /// Transaction amount.
+        amount:         u64,
+        /// Payment status after the transaction.
+        payment_status: PaymentStatus,
+    },
+    /// Shipment added.
+    Shipped {
+        /// Order ID.
+        order_id:    OrderId,
+        /// Shipment ID.
+        shipment_id: String,
+    },
+    /// Order cancelled.
+    Cancelled {
+        /// Order ID.
+        order_id: OrderId,
+        /// Cancellation reason.
+        reason:   String,
+    },
+}
+
+/// Receiver of order lifecycle events.
+///
+/// Events are emitted after the order store is unlocked, so a sink may call
+/// back into the `OrderService`.
+pub trait OrderEventSink: Send + Sync + std::fmt::Debug {
+    /// Handles an event.
+    fn emit(&self, event: &OrderLifecycleEvent);
+}
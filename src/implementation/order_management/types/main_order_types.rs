@@ -5,11 +5,13 @@
 
 use super::{
     basic_types::{FulfillmentStatus, OrderCustomerId, OrderId, OrderStatus, PaymentStatus},
-    order_types::{OrderHistoryEvent, OrderLineItem, OrderNote, PaymentTransaction, Shipment},
+    order_types::{
+        OrderHistoryEvent, OrderLineItem, OrderNote, PaymentTransaction, Shipment, StoreCredit,
+    },
 };
 use crate::{
     implementation::cart_system::{ShippingAddress, ShippingMethod},
-    types::product_catalog::Currency,
+    types::{inventory_sync::FulfillmentType, product_catalog::Currency},
 };
 
 /// Complete order.
@@ -17,7 +19,7 @@ use crate::{
 pub struct Order {
     /// Order ID.
     pub id:                 OrderId,
-    /// Order number (display).
+    /// Order number (display), assigned by `OrderService`.
     pub order_number:       String,
     /// Customer ID.
     pub customer_id:        OrderCustomerId,
@@ -45,6 +47,8 @@ pub struct Order {
     pub currency:           Currency,
     /// Payment transactions.
     pub transactions:       Vec<PaymentTransaction>,
+    /// Store credit issued as refunds.
+    pub store_credits:      Vec<StoreCredit>,
     /// Payment invoice ID (from payment plugin).
     pub payment_invoice_id: Option<String>,
     /// Blockchain transaction ID (for settlement).
@@ -57,6 +61,8 @@ pub struct Order {
     pub history:            Vec<OrderHistoryEvent>,
     /// Customer note at checkout.
     pub customer_note:      Option<String>,
+    /// Gift message printed on shipments and confirmations.
+    pub gift_message:       Option<String>,
     /// IP address.
     pub ip_address:         Option<String>,
     /// User agent.
@@ -65,6 +71,18 @@ pub struct Order {
     pub source:             OrderSource,
     /// Tags.
     pub tags:               Vec<String>,
+    /// Rush orders ship from the fastest location rather than the preferred
+    /// one.
+    pub is_rush:            bool,
+    /// Shipped or collected at a pickup location.
+    pub fulfillment_type:   FulfillmentType,
+    /// Whether a partially paid order may be fulfilled before the balance is
+    /// paid.
+    pub ship_on_deposit:    bool,
+    /// Why the order is on hold (e.g. fraud review).
+    pub hold_reason:        Option<String>,
+    /// Status to restore when the hold is released.
+    pub status_before_hold: Option<OrderStatus>,
     /// Creation timestamp.
     pub created_at:         u64,
     /// Last update timestamp.
@@ -76,8 +94,10 @@ pub struct Order {
 pub struct OrderTotals {
     /// Subtotal.
     pub subtotal:        u64,
-    /// Total discounts.
+    /// Total discounts (line discounts plus the order discount).
     pub discount_total:  u64,
+    /// Order-level discount not attributed to line items.
+    pub order_discount:  u64,
     /// Shipping total.
     pub shipping_total:  u64,
     /// Tax total.
@@ -86,7 +106,7 @@ pub struct OrderTotals {
     pub grand_total:     u64,
     /// Amount paid.
     pub amount_paid:     u64,
-    /// Amount refunded.
+    /// Amount refunded, to the payment method or as store credit.
     pub amount_refunded: u64,
     /// Amount due.
     pub amount_due:      u64,
@@ -99,6 +119,7 @@ impl OrderTotals {
         Self {
             subtotal:        totals.subtotal,
             discount_total:  totals.discount_total,
+            order_discount:  0,
             shipping_total:  totals.shipping_total,
             tax_total:       totals.tax_total,
             grand_total:     totals.grand_total,
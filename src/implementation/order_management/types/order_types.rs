@@ -8,7 +8,10 @@ use std::collections::HashMap;
 use super::basic_types::OrderStatus;
 use crate::{
     implementation::cart_system::{CartItem, ShippingAddress},
-    types::product_catalog::{Currency, Price, ProductId},
+    types::{
+        inventory_sync::LocationId,
+        product_catalog::{BundleComponent, Currency, Price, ProductId},
+    },
 };
 
 // ============================================================================
@@ -24,6 +27,8 @@ pub struct OrderLineItem {
     pub product_id:         ProductId,
     /// Variant ID.
     pub variant_id:         Option<ProductId>,
+    /// Components per unit (for bundles).
+    pub bundle_items:       Vec<BundleComponent>,
     /// Product name.
     pub name:               String,
     /// SKU.
@@ -34,6 +39,10 @@ pub struct OrderLineItem {
     pub quantity_fulfilled: u32,
     /// Quantity refunded.
     pub quantity_refunded:  u32,
+    /// Quantity cancelled before fulfillment.
+    pub quantity_cancelled: u32,
+    /// Fulfilled quantity per source inventory location.
+    pub fulfilled_from:     Vec<(LocationId, u32)>,
     /// Unit price.
     pub unit_price:         Price,
     /// Total before discount.
@@ -56,8 +65,12 @@ pub struct OrderLineItem {
 
 impl OrderLineItem {
     /// Creates a line item from a cart item.
+    ///
+    /// Tax is charged at the item's own rate when it has one, and at
+    /// `tax_rate` otherwise.
     #[must_use]
     pub fn from_cart_item(item: &CartItem, line_id: String, tax_rate: f64) -> Self {
+        let tax_rate = item.tax_rate.unwrap_or(tax_rate);
         let subtotal = item.subtotal();
         let discount = item.total_discount();
         let taxable_amount = subtotal.saturating_sub(discount);
@@ -68,11 +81,14 @@ impl OrderLineItem {
             id: line_id,
             product_id: item.product_id.clone(),
             variant_id: item.variant_id.clone(),
+            bundle_items: item.bundle_items.clone(),
             name: item.product_name.to_string(),
             sku: item.product_sku.to_string(),
             quantity: item.quantity,
             quantity_fulfilled: 0,
             quantity_refunded: 0,
+            quantity_cancelled: 0,
+            fulfilled_from: Vec::new(),
             unit_price: item.unit_price.clone(),
             subtotal,
             discount,
@@ -89,6 +105,35 @@ impl OrderLineItem {
         }
     }
 
+    /// Records quantity fulfilled from an inventory location.
+    pub fn record_fulfillment(&mut self, location_id: &LocationId, quantity: u32) {
+        match self.fulfilled_from.iter_mut().find(|(id, _)| id == location_id) {
+            Some((_, fulfilled)) => *fulfilled = fulfilled.saturating_add(quantity),
+            None => self.fulfilled_from.push((location_id.clone(), quantity)),
+        }
+    }
+
+    /// Takes up to `quantity` returned units off the fulfillment records,
+    /// most recent location first, and returns where each should be
+    /// restocked.
+    pub fn take_returned(&mut self, quantity: u32) -> Vec<(LocationId, u32)> {
+        let mut remaining = quantity;
+        let mut restock = Vec::new();
+        while remaining > 0 {
+            let Some((location_id, fulfilled)) = self.fulfilled_from.last_mut() else {
+                break;
+            };
+            let taken = remaining.min(*fulfilled);
+            *fulfilled -= taken;
+            remaining -= taken;
+            restock.push((location_id.clone(), taken));
+            if *fulfilled == 0 {
+                self.fulfilled_from.pop();
+            }
+        }
+        restock
+    }
+
     /// Quantity remaining to fulfill.
     #[must_use]
     pub fn quantity_remaining(&self) -> u32 {
@@ -189,6 +234,81 @@ pub enum TransactionType {
     Void,
 }
 
+/// Where refunded money goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefundDestination {
+    /// Back through the gateway to the original payment method.
+    #[default]
+    OriginalMethod,
+    /// Store credit issued to the customer.
+    StoreCredit,
+}
+
+/// Store credit issued in place of a gateway refund.
+#[derive(Debug, Clone)]
+pub struct StoreCredit {
+    /// Redemption code.
+    pub code:      String,
+    /// Credit amount.
+    pub amount:    u64,
+    /// Currency.
+    pub currency:  Currency,
+    /// Issue timestamp.
+    pub issued_at: u64,
+}
+
+// ============================================================================
+// ACCOUNTING LEDGER
+// ============================================================================
+
+/// Account a ledger entry is posted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerAccount {
+    /// Payments received.
+    Cash,
+    /// Balance still owed by the customer (credit when overpaid).
+    AccountsReceivable,
+    /// Product sales.
+    Revenue,
+    /// Discounts, as contra-revenue.
+    Discounts,
+    /// Tax collected on behalf of the tax authority.
+    TaxPayable,
+    /// Shipping charged to the customer.
+    ShippingIncome,
+    /// Refunds, as contra-revenue.
+    Refunds,
+    /// Store credit owed to the customer.
+    StoreCreditLiability,
+}
+
+/// A single debit or credit row in an order's accounting export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// Account posted to.
+    pub account: LedgerAccount,
+    /// Debit amount (zero for a credit row).
+    pub debit:   u64,
+    /// Credit amount (zero for a debit row).
+    pub credit:  u64,
+    /// What the row is for, e.g. a line item SKU.
+    pub memo:    String,
+}
+
+impl LedgerEntry {
+    /// Creates a debit row.
+    #[must_use]
+    pub fn debit(account: LedgerAccount, amount: u64, memo: impl Into<String>) -> Self {
+        Self { account, debit: amount, credit: 0, memo: memo.into() }
+    }
+
+    /// Creates a credit row.
+    #[must_use]
+    pub fn credit(account: LedgerAccount, amount: u64, memo: impl Into<String>) -> Self {
+        Self { account, debit: 0, credit: amount, memo: memo.into() }
+    }
+}
+
 // ============================================================================
 // SHIPMENT & TRACKING
 // ============================================================================
@@ -210,6 +330,8 @@ pub struct Shipment {
     pub items:            Vec<ShipmentItem>,
     /// Shipping address.
     pub shipping_address: ShippingAddress,
+    /// Gift message to include in the package.
+    pub gift_message:     Option<String>,
     /// Shipped date.
     pub shipped_at:       Option<u64>,
     /// Delivered date.
@@ -337,4 +459,8 @@ pub enum OrderEventType {
     NoteAdded,
     /// Fulfillment updated.
     FulfillmentUpdated,
+    /// Order-level discount applied.
+    DiscountApplied,
+    /// Line item cancelled before fulfillment.
+    LineItemCancelled,
 }
@@ -10,6 +10,7 @@ use std::{
 
 use super::{
     basic_types::{FulfillmentStatus, OrderCustomerId, OrderId, OrderStatus, PaymentStatus},
+    event_types::OrderEventSink,
     main_order_types::Order,
 };
 
@@ -22,6 +23,39 @@ pub struct OrderService {
     pub(crate) orders_by_customer: Arc<Mutex<HashMap<OrderCustomerId, Vec<OrderId>>>>,
     /// Order number counter.
     pub(crate) order_counter:      Arc<Mutex<u64>>,
+    /// Order number format.
+    pub(crate) number_format:      OrderNumberFormat,
+    /// Receiver of lifecycle events, if any.
+    pub(crate) event_sink:         Option<Arc<dyn OrderEventSink>>,
+}
+
+/// Format of customer-facing order numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderNumberFormat {
+    /// Text before the number (e.g. "#" or "WEB-").
+    pub prefix:  String,
+    /// Minimum number of digits, zero-padded.
+    pub padding: usize,
+}
+
+impl OrderNumberFormat {
+    /// Creates an order number format.
+    #[must_use]
+    pub fn new(prefix: impl Into<String>, padding: usize) -> Self {
+        Self { prefix: prefix.into(), padding }
+    }
+
+    /// Formats a sequence number.
+    #[must_use]
+    pub fn format(&self, number: u64) -> String {
+        format!("{}{:0width$}", self.prefix, number, width = self.padding)
+    }
+}
+
+impl Default for OrderNumberFormat {
+    fn default() -> Self {
+        Self::new("#", 0)
+    }
 }
 
 /// Order search filter.
@@ -41,4 +75,6 @@ pub struct OrderFilter {
     pub created_from:       Option<u64>,
     /// Created to timestamp.
     pub created_to:         Option<u64>,
+    /// Tags the order must all carry.
+    pub tags:               Vec<String>,
 }
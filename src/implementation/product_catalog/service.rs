@@ -3,15 +3,17 @@
 //! Service implementation for product catalog management.
 
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
     sync::{Arc, Mutex},
 };
 
 use crate::{
     errors::CommerceError,
     types::product_catalog::{
-        Category, CategoryId, PaginatedProducts, Product, ProductFilter, ProductId,
-        ProductSortOrder, Sku,
+        BulkAddResult, CatalogChange, CatalogChangeKind, Category, CategoryId, FieldMatch,
+        MatchedField, PaginatedProducts, Price, Product, ProductFilter, ProductId,
+        ProductSearchHit, ProductSortOrder, Sku, UniversalResults,
     },
 };
 
@@ -19,6 +21,18 @@ use crate::{
 // PRODUCT CATALOG SERVICE
 // ============================================================================
 
+/// Number of undrained changes kept; older ones are dropped first.
+pub const CHANGE_FEED_CAPACITY: usize = 1024;
+
+/// Undrained product changes.
+#[derive(Debug, Default)]
+struct ChangeFeed {
+    /// Changes, oldest first.
+    changes: VecDeque<CatalogChange>,
+    /// Changes dropped since the last drain.
+    dropped: u64,
+}
+
 /// Product catalog management service.
 #[derive(Debug)]
 pub struct ProductCatalog {
@@ -30,6 +44,8 @@ pub struct ProductCatalog {
     categories:        Arc<Mutex<HashMap<CategoryId, Category>>>,
     /// Category hierarchy (parent -> children).
     category_children: Arc<Mutex<HashMap<CategoryId, Vec<CategoryId>>>>,
+    /// Product changes not yet drained.
+    changes:           Arc<Mutex<ChangeFeed>>,
 }
 
 impl ProductCatalog {
@@ -41,6 +57,7 @@ impl ProductCatalog {
             products_by_sku:   Arc::new(Mutex::new(HashMap::new())),
             categories:        Arc::new(Mutex::new(HashMap::new())),
             category_children: Arc::new(Mutex::new(HashMap::new())),
+            changes:           Arc::new(Mutex::new(ChangeFeed::default())),
         }
     }
 
@@ -50,9 +67,11 @@ impl ProductCatalog {
 
     /// Adds a category to the catalog.
     ///
+    /// The slug is suffixed (`-2`, `-3`, ...) if another category uses it.
+    ///
     /// # Errors
     /// Returns error if category ID already exists.
-    pub fn add_category(&self, category: Category) -> Result<(), CommerceError> {
+    pub fn add_category(&self, mut category: Category) -> Result<(), CommerceError> {
         let mut categories = self.categories.lock().map_err(|_| CommerceError::LockError)?;
         let mut children = self.category_children.lock().map_err(|_| CommerceError::LockError)?;
 
@@ -62,6 +81,10 @@ impl ProductCatalog {
             ));
         }
 
+        category.slug = unique_slug(&category.slug, |slug| {
+            categories.values().any(|c| c.slug == slug)
+        });
+
         // Update parent's children list
         if let Some(parent_id) = &category.parent_id {
             children
@@ -109,25 +132,46 @@ impl ProductCatalog {
 
     /// Adds a product to the catalog.
     ///
+    /// The slug is suffixed (`-2`, `-3`, ...) if another product uses it.
+    ///
     /// # Errors
-    /// Returns error if product ID or SKU already exists.
+    /// Returns error if product ID or SKU already exists, or if a tier price
+    /// is in a different currency than the base price.
     pub fn add_product(&self, product: Product) -> Result<(), CommerceError> {
         let mut products = self.products.lock().map_err(|_| CommerceError::LockError)?;
         let mut by_sku = self.products_by_sku.lock().map_err(|_| CommerceError::LockError)?;
 
-        if products.contains_key(&product.id) {
-            return Err(CommerceError::ProductAlreadyExists(
-                product.id.0.to_string(),
-            ));
-        }
+        let mut slugs: HashSet<String> = products.values().map(|p| p.slug.clone()).collect();
+        self.insert_new_product(&mut products, &mut by_sku, &mut slugs, product)
+    }
 
-        if by_sku.contains_key(&product.sku) {
-            return Err(CommerceError::SkuAlreadyExists(product.sku.0.to_string()));
+    /// Adds many products under a single lock acquisition.
+    ///
+    /// Unlike calling [`Self::add_product`] in a loop, a product that fails
+    /// validation or clashes on ID or SKU is reported in `skipped` and the
+    /// rest of the batch is still added.
+    pub fn bulk_add_products(&self, batch: Vec<Product>) -> Result<BulkAddResult, CommerceError> {
+        let mut products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        let mut by_sku = self.products_by_sku.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut slugs: HashSet<String> = products.values().map(|p| p.slug.clone()).collect();
+        let mut result = BulkAddResult::default();
+        for product in batch {
+            let id = product.id.clone();
+            match self.insert_new_product(&mut products, &mut by_sku, &mut slugs, product) {
+                Ok(()) => result.added += 1,
+                Err(CommerceError::LockError) => return Err(CommerceError::LockError),
+                Err(e) => result.skipped.push((id, e)),
+            }
         }
+        Ok(result)
+    }
 
-        by_sku.insert(product.sku.clone(), product.id.clone());
-        products.insert(product.id.clone(), product);
-        Ok(())
+    /// Returns `base`, or `base` with the first free numeric suffix (`-2`,
+    /// `-3`, ...) if a product already uses it.
+    pub fn ensure_unique_slug(&self, base: &str) -> Result<String, CommerceError> {
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        Ok(slug_free_of(&products, base, None))
     }
 
     /// Gets a product by ID.
@@ -161,15 +205,22 @@ impl ProductCatalog {
 
     /// Updates a product.
     ///
+    /// As in [`Self::ensure_unique_slug`], the slug is suffixed if another
+    /// product uses it.
+    ///
     /// # Errors
-    /// Returns error if product not found.
-    pub fn update_product(&self, product: Product) -> Result<(), CommerceError> {
+    /// Returns error if product not found, or if a tier price is in a
+    /// different currency than the base price.
+    pub fn update_product(&self, mut product: Product) -> Result<(), CommerceError> {
+        product.validate_tier_prices()?;
         let mut products = self.products.lock().map_err(|_| CommerceError::LockError)?;
 
         if !products.contains_key(&product.id) {
             return Err(CommerceError::ProductNotFound(product.id.0.to_string()));
         }
+        product.slug = slug_free_of(&products, &product.slug, Some(&product.id));
 
+        self.record_change(CatalogChangeKind::Updated, &product.id)?;
         products.insert(product.id.clone(), product);
         Ok(())
     }
@@ -186,6 +237,7 @@ impl ProductCatalog {
             .remove(id)
             .ok_or_else(|| CommerceError::ProductNotFound(id.0.to_string()))?;
         by_sku.remove(&product.sku);
+        self.record_change(CatalogChangeKind::Removed, id)?;
         Ok(product)
     }
 
@@ -193,22 +245,23 @@ impl ProductCatalog {
     pub fn search_products(
         &self, filter: &ProductFilter, sort: ProductSortOrder, page: usize, page_size: usize,
     ) -> Result<PaginatedProducts, CommerceError> {
+        let filter = self.expand_category_filter(filter)?;
         let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
 
         // Filter products
-        let mut filtered: Vec<Product> =
-            products.values().filter(|p| self.matches_filter(p, filter)).cloned().collect();
+        let mut filtered: Vec<&Product> =
+            products.values().filter(|p| self.matches_filter(p, &filter)).collect();
 
         let total_count = filtered.len();
 
         // Sort products
-        self.sort_products(&mut filtered, sort);
+        self.sort_products(&mut filtered, sort, filter.search_query.as_deref());
 
-        // Paginate
+        // Paginate, cloning only the requested page
         let start = page * page_size;
         let end = (start + page_size).min(filtered.len());
         let page_products = if start < filtered.len() {
-            filtered[start..end].to_vec()
+            filtered[start..end].iter().map(|p| (*p).clone()).collect()
         } else {
             Vec::new()
         };
@@ -222,6 +275,117 @@ impl ProductCatalog {
         })
     }
 
+    /// Counts products matching a filter without cloning them.
+    pub fn count_products(&self, filter: &ProductFilter) -> Result<usize, CommerceError> {
+        let filter = self.expand_category_filter(filter)?;
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        Ok(products.values().filter(|p| self.matches_filter(p, &filter)).count())
+    }
+
+    /// Number of products directly in each category, for category facets.
+    ///
+    /// Each count equals [`Self::count_products`] for a filter on that
+    /// category. Categories without products are omitted; subcategory
+    /// products are not rolled up into their parents.
+    pub fn category_counts(&self) -> Result<HashMap<CategoryId, usize>, CommerceError> {
+        let filter = ProductFilter::new();
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        let mut counts: HashMap<CategoryId, usize> = HashMap::new();
+        for product in products.values().filter(|p| self.matches_filter(p, &filter)) {
+            let categories: HashSet<&CategoryId> = product.categories.iter().collect();
+            for category_id in categories {
+                *counts.entry(category_id.clone()).or_default() += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Searches products, returning only the IDs of all matches in sort
+    /// order.
+    ///
+    /// Pair with [`Self::get_products_batch`] to hydrate just the page being
+    /// displayed.
+    pub fn search_ids(
+        &self, filter: &ProductFilter, sort: ProductSortOrder,
+    ) -> Result<Vec<ProductId>, CommerceError> {
+        let filter = self.expand_category_filter(filter)?;
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+
+        let mut filtered: Vec<&Product> =
+            products.values().filter(|p| self.matches_filter(p, &filter)).collect();
+        self.sort_products(&mut filtered, sort, filter.search_query.as_deref());
+
+        Ok(filtered.into_iter().map(|p| p.id.clone()).collect())
+    }
+
+    /// Gets products by ID, preserving the given order. Unknown IDs are
+    /// skipped.
+    pub fn get_products_batch(&self, ids: &[ProductId]) -> Result<Vec<Product>, CommerceError> {
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        Ok(ids.iter().filter_map(|id| products.get(id).cloned()).collect())
+    }
+
+    /// Searches products with filters, reporting which fields matched the
+    /// text query.
+    pub fn search_products_with_highlights(
+        &self, filter: &ProductFilter, sort: ProductSortOrder, page: usize, page_size: usize,
+    ) -> Result<Vec<ProductSearchHit>, CommerceError> {
+        let results = self.search_products(filter, sort, page, page_size)?;
+
+        Ok(results
+            .products
+            .into_iter()
+            .map(|product| {
+                let matches = filter
+                    .search_query
+                    .as_ref()
+                    .map(|query| self.text_matches(&product, query))
+                    .unwrap_or_default();
+                ProductSearchHit { product, matches }
+            })
+            .collect())
+    }
+
+    /// Searches visible products (name, description, SKU) and active
+    /// categories (name, description) for any of the query's words.
+    ///
+    /// Each list is ranked by the number of query words matched, then by
+    /// name, and capped at `limit`.
+    pub fn universal_search(
+        &self, query: &str, limit: usize,
+    ) -> Result<UniversalResults, CommerceError> {
+        let products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+        let categories = self.categories.lock().map_err(|_| CommerceError::LockError)?;
+
+        let query = query.to_lowercase();
+        let tokens: Vec<&str> = query.split_whitespace().collect();
+        let score = |fields: &[&str]| {
+            let text = fields.join(" ").to_lowercase();
+            tokens.iter().filter(|t| text.contains(*t)).count()
+        };
+
+        let mut product_hits: Vec<(usize, &Product)> = products
+            .values()
+            .filter(|p| p.status.is_visible())
+            .map(|p| (score(&[&p.name, &p.description, &p.sku.0]), p))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        product_hits.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+        let mut category_hits: Vec<(usize, &Category)> = categories
+            .values()
+            .filter(|c| c.is_active)
+            .map(|c| (score(&[&c.name, &c.description]), c))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        category_hits.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+        Ok(UniversalResults {
+            products:   product_hits.into_iter().take(limit).map(|(_, p)| p.clone()).collect(),
+            categories: category_hits.into_iter().take(limit).map(|(_, c)| c.clone()).collect(),
+        })
+    }
+
     /// Gets products in a category.
     pub fn get_products_by_category(
         &self, category_id: &CategoryId, include_subcategories: bool,
@@ -283,10 +447,124 @@ impl ProductCatalog {
             .collect())
     }
 
+    /// Marks down every product in a category by a percentage of its
+    /// effective price, returning the number of products changed.
+    ///
+    /// # Errors
+    /// Returns error if the percentage is 100 or more.
+    pub fn apply_markdown(
+        &self, category_id: &CategoryId, percent: u8, include_subcategories: bool,
+    ) -> Result<usize, CommerceError> {
+        if percent >= 100 {
+            return Err(CommerceError::ValidationError(format!(
+                "markdown of {percent}% must be below 100%"
+            )));
+        }
+        self.update_category_sale_prices(category_id, include_subcategories, |product| {
+            let price = product.effective_price();
+            let amount = u128::from(price.amount) * u128::from(100 - percent) / 100;
+            Some(Price::new(
+                amount as u64,
+                price.currency.clone(),
+                price.decimals,
+            ))
+        })
+    }
+
+    /// Removes the sale price from every product in a category, returning the
+    /// number of products changed.
+    pub fn clear_markdown(
+        &self, category_id: &CategoryId, include_subcategories: bool,
+    ) -> Result<usize, CommerceError> {
+        self.update_category_sale_prices(category_id, include_subcategories, |_| None)
+    }
+
+    // ========================================================================
+    // CHANGE FEED
+    // ========================================================================
+
+    /// Takes every product change recorded since the last drain, oldest
+    /// first, so a search indexer can apply only deltas.
+    ///
+    /// At most [`CHANGE_FEED_CAPACITY`] changes are kept between drains.
+    ///
+    /// # Errors
+    /// Returns `ChangeFeedGap` if older changes were dropped since the last
+    /// drain. The feed is emptied all the same, so the consumer should
+    /// rebuild from the catalog and keep draining from there.
+    pub fn drain_changes(&self) -> Result<Vec<CatalogChange>, CommerceError> {
+        let mut feed = self.changes.lock().map_err(|_| CommerceError::LockError)?;
+        let changes = feed.changes.drain(..).collect();
+        match std::mem::take(&mut feed.dropped) {
+            0 => Ok(changes),
+            dropped => Err(CommerceError::ChangeFeedGap { dropped }),
+        }
+    }
+
     // ========================================================================
     // PRIVATE HELPERS
     // ========================================================================
 
+    /// Validates a product that is not in the catalog yet and inserts it,
+    /// suffixing its slug if it is in `slugs`.
+    fn insert_new_product(
+        &self, products: &mut HashMap<ProductId, Product>, by_sku: &mut HashMap<Sku, ProductId>,
+        slugs: &mut HashSet<String>, mut product: Product,
+    ) -> Result<(), CommerceError> {
+        product.validate_tier_prices()?;
+
+        if products.contains_key(&product.id) {
+            return Err(CommerceError::ProductAlreadyExists(
+                product.id.0.to_string(),
+            ));
+        }
+
+        if by_sku.contains_key(&product.sku) {
+            return Err(CommerceError::SkuAlreadyExists(product.sku.0.to_string()));
+        }
+
+        product.slug = unique_slug(&product.slug, |slug| slugs.contains(slug));
+        slugs.insert(product.slug.clone());
+        by_sku.insert(product.sku.clone(), product.id.clone());
+        self.record_change(CatalogChangeKind::Added, &product.id)?;
+        products.insert(product.id.clone(), product);
+        Ok(())
+    }
+
+    /// Appends a change to the feed, dropping the oldest when full.
+    ///
+    /// The feed is locked after any catalog map.
+    fn record_change(&self, kind: CatalogChangeKind, id: &ProductId) -> Result<(), CommerceError> {
+        let mut feed = self.changes.lock().map_err(|_| CommerceError::LockError)?;
+        if feed.changes.len() == CHANGE_FEED_CAPACITY {
+            feed.changes.pop_front();
+            feed.dropped += 1;
+        }
+        feed.changes.push_back(CatalogChange { kind, id: id.clone() });
+        Ok(())
+    }
+
+    /// Resolves `include_subcategories` into an explicit category list.
+    fn expand_category_filter<'a>(
+        &self, filter: &'a ProductFilter,
+    ) -> Result<Cow<'a, ProductFilter>, CommerceError> {
+        if !filter.include_subcategories {
+            return Ok(Cow::Borrowed(filter));
+        }
+
+        let mut expanded = filter.clone();
+        expanded.categories.clear();
+        for category_id in &filter.categories {
+            for id in self.get_descendant_categories(category_id)? {
+                if !expanded.categories.contains(&id) {
+                    expanded.categories.push(id);
+                }
+            }
+        }
+        expanded.include_subcategories = false;
+        Ok(Cow::Owned(expanded))
+    }
+
     /// Checks if product matches filter.
     fn matches_filter(&self, product: &Product, filter: &ProductFilter) -> bool {
         // Status filter
@@ -306,13 +584,19 @@ impl ProductCatalog {
             return false;
         }
 
-        // Price range filter
-        let price = product.effective_price().amount;
-        if filter.min_price.is_some_and(|min| price < min) {
+        // Price range filter, only comparable within the filter's currency
+        let price = product.effective_price();
+        let same_currency = filter.currency.as_ref().is_none_or(|c| *c == price.currency);
+        if !same_currency && filter.currency_only {
             return false;
         }
-        if filter.max_price.is_some_and(|max| price > max) {
-            return false;
+        if same_currency {
+            if filter.min_price.is_some_and(|min| price.amount < min) {
+                return false;
+            }
+            if filter.max_price.is_some_and(|max| price.amount > max) {
+                return false;
+            }
         }
 
         // Tags filter
@@ -346,11 +630,7 @@ impl ProductCatalog {
 
         // Text search
         if let Some(query) = &filter.search_query {
-            let query_lower = query.to_lowercase();
-            let matches_name = product.name.to_lowercase().contains(&query_lower);
-            let matches_desc = product.description.to_lowercase().contains(&query_lower);
-            let matches_sku = product.sku.0.to_lowercase().contains(&query_lower);
-            if !matches_name && !matches_desc && !matches_sku {
+            if self.text_matches(product, query).is_empty() {
                 return false;
             }
         }
@@ -358,8 +638,38 @@ impl ProductCatalog {
         true
     }
 
+    /// Returns the product fields containing the text query.
+    fn text_matches(&self, product: &Product, query: &str) -> Vec<FieldMatch> {
+        let query_lower = query.to_lowercase();
+        let fields = [
+            (MatchedField::Name, product.name.to_lowercase()),
+            (
+                MatchedField::Description,
+                product.description.to_lowercase(),
+            ),
+            (MatchedField::Sku, product.sku.0.to_lowercase()),
+        ];
+
+        fields
+            .into_iter()
+            .filter(|(_, text)| text.contains(&query_lower))
+            .map(|(field, text)| FieldMatch {
+                field,
+                tokens: query_lower
+                    .split_whitespace()
+                    .filter(|token| text.contains(token))
+                    .map(str::to_string)
+                    .collect(),
+            })
+            .collect()
+    }
+
     /// Sorts products by specified order.
-    fn sort_products(&self, products: &mut [Product], sort: ProductSortOrder) {
+    ///
+    /// `query` is the text search, used to rank by relevance.
+    fn sort_products(
+        &self, products: &mut [&Product], sort: ProductSortOrder, query: Option<&str>,
+    ) {
         match sort {
             ProductSortOrder::Newest => {
                 products.sort_by(|a, b| b.created_at.cmp(&a.created_at));
@@ -382,9 +692,48 @@ impl ProductCatalog {
             ProductSortOrder::Featured => {
                 products.sort_by(|a, b| b.is_featured.cmp(&a.is_featured));
             },
+            ProductSortOrder::Relevance => {
+                let mut scored: Vec<(f64, &Product)> = products
+                    .iter()
+                    .map(|p| (query.map_or(0.0, |q| relevance_score(p, q)), *p))
+                    .collect();
+                scored.sort_by(|(score_a, a), (score_b, b)| {
+                    score_b.total_cmp(score_a).then_with(|| b.created_at.cmp(&a.created_at))
+                });
+                for (slot, (_, product)) in products.iter_mut().zip(scored) {
+                    *slot = product;
+                }
+            },
         }
     }
 
+    /// Sets the sale price of each product in a category.
+    fn update_category_sale_prices(
+        &self, category_id: &CategoryId, include_subcategories: bool,
+        sale_price: impl Fn(&Product) -> Option<Price>,
+    ) -> Result<usize, CommerceError> {
+        let mut products = self.products.lock().map_err(|_| CommerceError::LockError)?;
+
+        let category_ids = if include_subcategories {
+            self.get_descendant_categories(category_id)?
+        } else {
+            vec![category_id.clone()]
+        };
+
+        let mut changed = 0;
+        for product in products
+            .values_mut()
+            .filter(|p| p.categories.iter().any(|c| category_ids.contains(c)))
+        {
+            let price = sale_price(product);
+            if product.set_sale_price(price) {
+                self.record_change(CatalogChangeKind::Updated, &product.id)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
     /// Gets all descendant category IDs.
     fn get_descendant_categories(
         &self, category_id: &CategoryId,
@@ -407,6 +756,53 @@ impl ProductCatalog {
     }
 }
 
+/// Relevance of a product to a text query: occurrences of each query term,
+/// weighted by field (name > tags > short description > description).
+fn relevance_score(product: &Product, query: &str) -> f64 {
+    let name = product.name.to_lowercase();
+    let tags = product.tags.join(" ").to_lowercase();
+    let short_description = product.short_description.to_lowercase();
+    let description = product.description.to_lowercase();
+    let fields = [
+        (8.0, name.as_str()),
+        (4.0, tags.as_str()),
+        (2.0, short_description.as_str()),
+        (1.0, description.as_str()),
+    ];
+
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .map(|term| {
+            fields
+                .iter()
+                .map(|(weight, text)| weight * text.matches(term).count() as f64)
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+/// First of `base`, `base-2`, `base-3`, ... that is not taken.
+fn unique_slug(base: &str, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{base}-{n}"))
+        .find(|slug| !taken(slug))
+        .unwrap_or_else(|| base.to_string())
+}
+
+/// [`unique_slug`] against the stored products, ignoring `exclude` so a
+/// product keeps its own slug.
+fn slug_free_of(
+    products: &HashMap<ProductId, Product>, base: &str, exclude: Option<&ProductId>,
+) -> String {
+    unique_slug(base, |slug| {
+        products.values().any(|p| p.slug == slug && Some(&p.id) != exclude)
+    })
+}
+
 impl Default for ProductCatalog {
     fn default() -> Self {
         Self::new()
@@ -5,7 +5,9 @@
 #[cfg(test)]
 mod tests {
     use crate::{
-        implementation::product_catalog::service::ProductCatalog, types::product_catalog::*,
+        errors::CommerceError,
+        implementation::product_catalog::service::{CHANGE_FEED_CAPACITY, ProductCatalog},
+        types::product_catalog::*,
     };
 
     #[test]
@@ -31,6 +33,19 @@ mod tests {
         assert_eq!(total.display_amount(), 15.0);
     }
 
+    #[test]
+    fn test_price_eq_value_ignores_decimals() {
+        let cents = Price::new(1000, Currency::usd(), 2);
+        let basis_points = Price::new(100_000, Currency::usd(), 4);
+
+        assert_ne!(cents, basis_points);
+        assert!(cents.eq_value(&basis_points));
+        assert!(basis_points.eq_value(&cents));
+        assert!(!cents.eq_value(&Price::new(100_001, Currency::usd(), 4)));
+        assert!(!cents.eq_value(&Price::new(1000, Currency::new("EUR"), 2)));
+        assert!(Price::ess(0).eq_value(&Price::new(0, Currency::ess(), 0)));
+    }
+
     #[test]
     fn test_catalog_add_product() {
         let catalog = ProductCatalog::new();
@@ -63,6 +78,74 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_bulk_add_products_reports_duplicate_skus() {
+        let catalog = ProductCatalog::new();
+        catalog
+            .add_product(Product::new(
+                ProductId::new("prod-001"),
+                Sku::new("SKU-001"),
+                "Existing",
+            ))
+            .expect("should add existing product");
+
+        let batch = vec![
+            Product::new(ProductId::new("prod-002"), Sku::new("SKU-002"), "Lamp"),
+            Product::new(
+                ProductId::new("prod-003"),
+                Sku::new("SKU-001"),
+                "Clash with catalog",
+            ),
+            Product::new(ProductId::new("prod-004"), Sku::new("SKU-004"), "Lamp"),
+            Product::new(
+                ProductId::new("prod-005"),
+                Sku::new("SKU-002"),
+                "Clash with batch",
+            ),
+            Product::new(ProductId::new("prod-006"), Sku::new("SKU-006"), "Desk"),
+        ];
+        let result = catalog.bulk_add_products(batch).expect("bulk add");
+
+        assert_eq!(result.added, 3);
+        assert_eq!(result.skipped.len(), 2);
+        assert_eq!(result.skipped[0].0, ProductId::new("prod-003"));
+        assert_eq!(result.skipped[1].0, ProductId::new("prod-005"));
+        assert!(
+            result
+                .skipped
+                .iter()
+                .all(|(_, e)| matches!(e, CommerceError::SkuAlreadyExists(_)))
+        );
+
+        assert!(catalog.get_product(&ProductId::new("prod-006")).is_ok());
+        assert!(catalog.get_product(&ProductId::new("prod-003")).is_err());
+        let first = catalog.get_product(&ProductId::new("prod-002")).expect("get");
+        let second = catalog.get_product(&ProductId::new("prod-004")).expect("get");
+        assert_ne!(first.slug, second.slug);
+    }
+
+    #[test]
+    fn test_bulk_add_larger_than_change_feed_requires_resync() {
+        let catalog = ProductCatalog::new();
+        let batch: Vec<Product> = (0..CHANGE_FEED_CAPACITY + 5)
+            .map(|i| {
+                Product::new(
+                    ProductId::new(format!("prod-{i}")),
+                    Sku::new(format!("SKU-{i}")),
+                    format!("Bulk {i}"),
+                )
+            })
+            .collect();
+        let result = catalog.bulk_add_products(batch).expect("bulk add");
+        assert_eq!(result.added, CHANGE_FEED_CAPACITY + 5);
+
+        assert!(matches!(
+            catalog.drain_changes(),
+            Err(CommerceError::ChangeFeedGap { dropped: 5 })
+        ));
+        assert!(catalog.drain_changes().expect("should drain").is_empty());
+    }
+
     #[test]
     fn test_category_hierarchy() {
         let catalog = ProductCatalog::new();
@@ -139,4 +222,610 @@ mod tests {
         assert!(!ProductStatus::OutOfStock.is_purchasable());
         assert!(!ProductStatus::Draft.is_visible());
     }
+
+    #[test]
+    fn test_search_with_highlights() {
+        let catalog = ProductCatalog::new();
+
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("PIXEL-8"),
+            "Pixel 8 Phone",
+        );
+        product.status = ProductStatus::Active;
+        product.description = "A great phone".to_string();
+        catalog.add_product(product).expect("add product");
+
+        let mut filter = ProductFilter::new();
+        filter.search_query = Some("Pixel".to_string());
+
+        let hits = catalog
+            .search_products_with_highlights(&filter, ProductSortOrder::Newest, 0, 10)
+            .expect("search should succeed");
+
+        assert_eq!(hits.len(), 1);
+        let fields: Vec<MatchedField> = hits[0].matches.iter().map(|m| m.field).collect();
+        assert_eq!(fields, vec![MatchedField::Name, MatchedField::Sku]);
+        assert!(hits[0].matches.iter().all(|m| m.tokens == vec!["pixel".to_string()]));
+    }
+
+    #[test]
+    fn test_max_purchasable_inventory_limited() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+        product.inventory_quantity = 5;
+        product.max_quantity_per_order = Some(10);
+
+        assert_eq!(product.max_purchasable(0), 5);
+        assert_eq!(product.max_purchasable(3), 2);
+        assert_eq!(product.max_purchasable(5), 0);
+    }
+
+    #[test]
+    fn test_max_purchasable_backorder_limited_by_cap() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+        product.inventory_quantity = 0;
+        product.backorders_allowed = true;
+        product.max_quantity_per_order = Some(4);
+
+        assert_eq!(product.max_purchasable(0), 4);
+
+        product.max_quantity_per_order = None;
+        assert_eq!(product.max_purchasable(0), u32::MAX);
+    }
+
+    #[test]
+    fn test_max_purchasable_near_cap() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+        product.inventory_quantity = 100;
+        product.max_quantity_per_order = Some(3);
+
+        assert_eq!(product.max_purchasable(2), 1);
+        assert_eq!(product.max_purchasable(3), 0);
+        assert_eq!(product.max_purchasable(7), 0);
+    }
+
+    #[test]
+    fn test_product_approval_lifecycle() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+        product.updated_at = 0;
+
+        product.submit_for_approval().expect("draft -> pending");
+        assert_eq!(product.status, ProductStatus::PendingApproval);
+        assert!(product.updated_at > 0);
+
+        product.approve().expect("pending -> active");
+        product.unpublish().expect("active -> inactive");
+        product.publish().expect("inactive -> active");
+        product.discontinue().expect("active -> discontinued");
+        assert_eq!(product.status, ProductStatus::Discontinued);
+    }
+
+    #[test]
+    fn test_publish_draft_without_approval_fails() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+
+        let err = product.publish().expect_err("draft cannot be published");
+        assert!(matches!(err, CommerceError::InvalidStatusTransition { .. }));
+        assert!(product.approve().is_err());
+        assert_eq!(product.status, ProductStatus::Draft);
+    }
+
+    #[test]
+    fn test_display_price_respects_map() {
+        let mut product = Product::new(
+            ProductId::new("prod-001"),
+            Sku::new("SKU-001"),
+            "Test Product",
+        );
+        product.price = Price::new(10000, Currency::usd(), 2);
+        product.map_price = Some(Price::new(9000, Currency::usd(), 2));
+
+        assert_eq!(
+            product.display_price(),
+            DisplayPrice::Amount(product.price.clone())
+        );
+
+        product.sale_price = Some(Price::new(8000, Currency::usd(), 2));
+        assert_eq!(product.display_price(), DisplayPrice::SeePriceInCart);
+        assert_eq!(product.effective_price().amount, 8000);
+    }
+
+    #[test]
+    fn test_price_filter_with_mixed_currencies() {
+        let catalog = ProductCatalog::new();
+
+        let mut usd = Product::new(ProductId::new("prod-usd"), Sku::new("SKU-USD"), "USD Item");
+        usd.status = ProductStatus::Active;
+        usd.price = Price::new(2500, Currency::usd(), 2);
+        catalog.add_product(usd).expect("add usd");
+
+        let mut usd_expensive = Product::new(
+            ProductId::new("prod-usd-2"),
+            Sku::new("SKU-USD-2"),
+            "Pricey Item",
+        );
+        usd_expensive.status = ProductStatus::Active;
+        usd_expensive.price = Price::new(90000, Currency::usd(), 2);
+        catalog.add_product(usd_expensive).expect("add expensive usd");
+
+        // 1 ESS in 18-decimal units dwarfs any USD cent amount
+        let mut ess = Product::new(ProductId::new("prod-ess"), Sku::new("SKU-ESS"), "ESS Item");
+        ess.status = ProductStatus::Active;
+        ess.price = Price::ess(1_000_000_000_000_000_000);
+        catalog.add_product(ess).expect("add ess");
+
+        let mut filter = ProductFilter::new()
+            .with_price_range(Some(1000), Some(5000))
+            .with_currency(Currency::usd());
+        let results = catalog
+            .search_products(&filter, ProductSortOrder::NameAsc, 0, 10)
+            .expect("search");
+        let ids: Vec<&str> = results.products.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["prod-ess", "prod-usd"]);
+
+        filter.currency_only = true;
+        let results = catalog
+            .search_products(&filter, ProductSortOrder::NameAsc, 0, 10)
+            .expect("search");
+        let ids: Vec<&str> = results.products.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["prod-usd"]);
+    }
+
+    #[test]
+    fn test_search_ids_and_batch_fetch() {
+        let catalog = ProductCatalog::new();
+        for (id, price) in [("prod-001", 3000), ("prod-002", 1000), ("prod-003", 2000)] {
+            let mut product = Product::new(ProductId::new(id), Sku::new(id), id);
+            product.status = ProductStatus::Active;
+            product.price = Price::new(price, Currency::usd(), 2);
+            catalog.add_product(product).expect("add product");
+        }
+
+        let filter = ProductFilter::new();
+        let ids = catalog.search_ids(&filter, ProductSortOrder::PriceAsc).expect("search ids");
+        let products = catalog
+            .search_products(&filter, ProductSortOrder::PriceAsc, 0, 10)
+            .expect("search products");
+        let expected: Vec<ProductId> = products.products.iter().map(|p| p.id.clone()).collect();
+        assert_eq!(ids, expected);
+
+        let page = catalog
+            .get_products_batch(&[ids[1].clone(), ProductId::new("missing"), ids[0].clone()])
+            .expect("batch");
+        let names: Vec<&str> = page.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["prod-003", "prod-002"]);
+    }
+
+    #[test]
+    fn test_price_for_group_falls_back_to_effective_price() {
+        let mut product = Product::new(ProductId::new("p1"), Sku::new("SKU-P1"), "Widget");
+        product.price = Price::new(1000, Currency::usd(), 2);
+        product.sale_price = Some(Price::new(900, Currency::usd(), 2));
+        product
+            .tier_prices
+            .insert("wholesale".to_string(), Price::new(600, Currency::usd(), 2));
+
+        assert_eq!(product.price_for_group(Some("wholesale")).amount, 600);
+        assert_eq!(product.price_for_group(Some("retail")).amount, 900);
+        assert_eq!(product.price_for_group(None).amount, 900);
+    }
+
+    #[test]
+    fn test_tier_prices_must_match_base_currency() {
+        let catalog = ProductCatalog::new();
+        let mut product = Product::new(ProductId::new("p1"), Sku::new("SKU-P1"), "Widget");
+        product.price = Price::new(1000, Currency::usd(), 2);
+        product.tier_prices.insert("wholesale".to_string(), Price::ess(600));
+
+        assert!(matches!(
+            catalog.add_product(product.clone()),
+            Err(CommerceError::CurrencyMismatch { .. })
+        ));
+
+        product
+            .tier_prices
+            .insert("wholesale".to_string(), Price::new(600, Currency::usd(), 2));
+        catalog.add_product(product).expect("matching tier currency");
+    }
+
+    #[test]
+    fn test_apply_and_clear_category_markdown() {
+        let catalog = ProductCatalog::new();
+        let electronics = CategoryId::new("electronics");
+        let phones = CategoryId::new("phones");
+        catalog
+            .add_category(Category::new(electronics.clone(), "Electronics"))
+            .expect("add category");
+        catalog
+            .add_category(Category::new(phones.clone(), "Phones").with_parent(electronics.clone()))
+            .expect("add subcategory");
+
+        let mut tv = Product::new(ProductId::new("tv"), Sku::new("TV"), "TV");
+        tv.price = Price::new(50_000, Currency::usd(), 2);
+        tv.categories.push(electronics.clone());
+        let mut phone = Product::new(ProductId::new("phone"), Sku::new("PHONE"), "Phone");
+        phone.price = Price::new(20_000, Currency::usd(), 2);
+        phone.categories.push(phones.clone());
+        catalog.add_product(tv).expect("add tv");
+        catalog.add_product(phone).expect("add phone");
+
+        assert!(matches!(
+            catalog.apply_markdown(&electronics, 100, true),
+            Err(CommerceError::ValidationError(_))
+        ));
+        assert_eq!(
+            catalog.apply_markdown(&electronics, 10, false).expect("markdown"),
+            1
+        );
+        assert_eq!(
+            catalog.apply_markdown(&phones, 25, false).expect("markdown"),
+            1
+        );
+
+        let tv = catalog.get_product(&ProductId::new("tv")).expect("tv");
+        assert_eq!(tv.effective_price().amount, 45_000);
+        assert_eq!(tv.sale_price_history.len(), 1);
+        let phone = catalog.get_product(&ProductId::new("phone")).expect("phone");
+        assert_eq!(phone.effective_price().amount, 15_000);
+
+        assert_eq!(
+            catalog.clear_markdown(&electronics, true).expect("clear"),
+            2
+        );
+        let tv = catalog.get_product(&ProductId::new("tv")).expect("tv");
+        assert!(!tv.is_on_sale());
+        assert_eq!(tv.sale_price_history.len(), 2);
+        assert_eq!(
+            tv.sale_price_history[1].previous.as_ref().map(|p| p.amount),
+            Some(45_000)
+        );
+        assert_eq!(
+            catalog.clear_markdown(&electronics, true).expect("clear"),
+            0
+        );
+    }
+
+    #[test]
+    fn test_duplicate_names_get_unique_slugs() {
+        let catalog = ProductCatalog::new();
+        for n in 1..=3 {
+            let product = Product::new(
+                ProductId::new(format!("shirt-{n}")),
+                Sku::new(format!("SHIRT-{n}")),
+                "Blue Shirt",
+            );
+            catalog.add_product(product).expect("add product");
+        }
+
+        let slug = |id: &str| catalog.get_product(&ProductId::new(id)).expect("product").slug;
+        assert_eq!(slug("shirt-1"), "blue-shirt");
+        assert_eq!(slug("shirt-2"), "blue-shirt-2");
+        assert_eq!(slug("shirt-3"), "blue-shirt-3");
+        assert_eq!(
+            catalog.ensure_unique_slug("blue-shirt").expect("slug"),
+            "blue-shirt-4"
+        );
+        assert_eq!(
+            catalog.ensure_unique_slug("red-shirt").expect("slug"),
+            "red-shirt"
+        );
+
+        // Updates keep a product's own slug but not another's
+        let first = catalog.get_product(&ProductId::new("shirt-1")).expect("product");
+        catalog.update_product(first).expect("update");
+        assert_eq!(slug("shirt-1"), "blue-shirt");
+        let mut third = catalog.get_product(&ProductId::new("shirt-3")).expect("product");
+        third.slug = "blue-shirt-2".to_string();
+        catalog.update_product(third).expect("update");
+        assert_eq!(slug("shirt-3"), "blue-shirt-2-2");
+
+        catalog
+            .add_category(Category::new(CategoryId::new("shirts"), "Shirts"))
+            .expect("add category");
+        catalog
+            .add_category(Category::new(CategoryId::new("shirts-sale"), "Shirts"))
+            .expect("add category");
+        let category_slug =
+            |id: &str| catalog.get_category(&CategoryId::new(id)).expect("category").slug;
+        assert_eq!(category_slug("shirts"), "shirts");
+        assert_eq!(category_slug("shirts-sale"), "shirts-2");
+    }
+
+    #[test]
+    fn test_universal_search_matches_products_and_categories() {
+        let catalog = ProductCatalog::new();
+        catalog
+            .add_category(Category::new(CategoryId::new("garden"), "Garden"))
+            .expect("add category");
+        catalog
+            .add_category(Category::new(CategoryId::new("kitchen"), "Kitchen"))
+            .expect("add category");
+
+        for (id, name, status) in [
+            ("hose", "Garden Hose", ProductStatus::Active),
+            ("gnome", "Garden Gnome", ProductStatus::Draft),
+            ("pan", "Frying Pan", ProductStatus::Active),
+        ] {
+            let mut product = Product::new(ProductId::new(id), Sku::new(id.to_uppercase()), name);
+            product.status = status;
+            catalog.add_product(product).expect("add product");
+        }
+
+        let results = catalog.universal_search("garden hose", 10).expect("search");
+        assert_eq!(results.categories.len(), 1);
+        assert_eq!(results.categories[0].name, "Garden");
+        // Draft products are not shown to shoppers
+        assert_eq!(results.products.len(), 1);
+        assert_eq!(results.products[0].name, "Garden Hose");
+
+        let results = catalog.universal_search("pan kitchen", 1).expect("search");
+        assert_eq!(results.products[0].id, ProductId::new("pan"));
+        assert_eq!(results.categories[0].id, CategoryId::new("kitchen"));
+        assert!(catalog.universal_search("   ", 10).expect("search").products.is_empty());
+    }
+
+    #[test]
+    fn test_relevance_ranks_title_match_first() {
+        let catalog = ProductCatalog::new();
+        let mut in_description =
+            Product::new(ProductId::new("case"), Sku::new("CASE"), "Leather Case");
+        in_description.description = "Fits every kettle-sized gadget".to_string();
+        in_description.created_at = 200;
+        let mut in_title = Product::new(ProductId::new("kettle"), Sku::new("KTL"), "Steel Kettle");
+        in_title.created_at = 100;
+        let mut in_tags = Product::new(ProductId::new("mug"), Sku::new("MUG"), "Travel Mug");
+        in_tags.tags.push("kettle".to_string());
+        in_tags.description = "Pairs with a kettle".to_string();
+        for product in [in_description, in_title, in_tags] {
+            catalog.add_product(product).expect("add product");
+        }
+
+        let mut filter = ProductFilter::new();
+        filter.search_query = Some("kettle".to_string());
+        let ids: Vec<String> = catalog
+            .search_products(&filter, ProductSortOrder::Relevance, 0, 10)
+            .expect("search")
+            .products
+            .into_iter()
+            .map(|p| p.id.0.to_string())
+            .collect();
+
+        assert_eq!(ids, vec!["kettle", "mug", "case"]);
+    }
+
+    #[test]
+    fn test_counts_match_search_totals() {
+        let catalog = ProductCatalog::new();
+        let kitchen = CategoryId::new("kitchen");
+        let garden = CategoryId::new("garden");
+        for (id, category, featured) in [
+            ("pan", &kitchen, true),
+            ("pot", &kitchen, false),
+            ("whisk", &kitchen, false),
+            ("hose", &garden, true),
+        ] {
+            let mut product = Product::new(ProductId::new(id), Sku::new(id), id);
+            product.categories.push(category.clone());
+            if id == "pan" {
+                // Listed twice, still one product in the facet
+                product.categories.push(category.clone());
+            }
+            product.is_featured = featured;
+            catalog.add_product(product).expect("add product");
+        }
+
+        let filters = [
+            ProductFilter::new(),
+            ProductFilter::new().with_category(kitchen.clone()),
+            ProductFilter { featured_only: true, ..ProductFilter::new() },
+        ];
+        for filter in &filters {
+            let searched = catalog
+                .search_products(filter, ProductSortOrder::Newest, 0, 1)
+                .expect("search")
+                .total_count;
+            assert_eq!(catalog.count_products(filter).expect("count"), searched);
+        }
+
+        let counts = catalog.category_counts().expect("category counts");
+        assert_eq!(counts.len(), 2);
+        for (category_id, count) in &counts {
+            let filter = ProductFilter::new().with_category(category_id.clone());
+            assert_eq!(*count, catalog.count_products(&filter).expect("count"));
+        }
+        assert_eq!(counts[&kitchen], 3);
+    }
+
+    #[test]
+    fn test_price_format_localized() {
+        let price = Price::new(123_456, Currency::usd(), 2);
+
+        assert_eq!(price.format_localized(&LocaleFormat::us("$")), "$1,234.56");
+        assert_eq!(
+            price.format_localized(&LocaleFormat::european("$")),
+            "1.234,56 $"
+        );
+
+        let small = Price::new(5, Currency::usd(), 2);
+        assert_eq!(small.format_localized(&LocaleFormat::us("$")), "$0.05");
+        let whole = Price::new(1_000_000, Currency::new("JPY"), 0);
+        assert_eq!(
+            whole.format_localized(&LocaleFormat::european("JPY")),
+            "1.000.000 JPY"
+        );
+    }
+
+    #[test]
+    fn test_search_within_category_tree() {
+        let catalog = ProductCatalog::new();
+        let electronics = CategoryId::new("electronics");
+        let phones = CategoryId::new("phones");
+        let android = CategoryId::new("android");
+        catalog
+            .add_category(Category::new(electronics.clone(), "Electronics"))
+            .expect("add category");
+        catalog
+            .add_category(Category::new(phones.clone(), "Phones").with_parent(electronics.clone()))
+            .expect("add subcategory");
+        catalog
+            .add_category(Category::new(android.clone(), "Android").with_parent(phones.clone()))
+            .expect("add subcategory");
+
+        for (id, name, category) in [
+            ("tv", "Smart TV", &electronics),
+            ("pixel", "Smart Phone", &android),
+            ("case", "Phone Case", &phones),
+        ] {
+            let mut product = Product::new(ProductId::new(id), Sku::new(id), name);
+            product.categories.push(category.clone());
+            catalog.add_product(product).expect("add product");
+        }
+
+        let search = |filter: ProductFilter| {
+            let filter = ProductFilter { search_query: Some("smart".to_string()), ..filter };
+            let mut ids: Vec<String> = catalog
+                .search_products(&filter, ProductSortOrder::NameAsc, 0, 10)
+                .expect("search")
+                .products
+                .into_iter()
+                .map(|p| p.id.0.to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            search(ProductFilter::new().with_category(electronics.clone())),
+            vec!["tv"]
+        );
+        assert_eq!(
+            search(ProductFilter::new().with_category_tree(electronics.clone())),
+            vec!["pixel", "tv"]
+        );
+        assert_eq!(
+            search(ProductFilter::new().with_category_tree(phones)),
+            vec!["pixel"]
+        );
+    }
+
+    #[test]
+    fn test_price_for_quantity_uses_volume_tiers() {
+        let mut product = Product::new(ProductId::new("bolt"), Sku::new("BOLT"), "Bolt");
+        product.price = Price::new(120, Currency::usd(), 2);
+        product.quantity_tiers = vec![
+            (100, Price::new(60, Currency::usd(), 2)),
+            (1, Price::new(100, Currency::usd(), 2)),
+            (10, Price::new(80, Currency::usd(), 2)),
+        ];
+
+        assert_eq!(product.price_for_quantity(0).amount, 120);
+        assert_eq!(product.price_for_quantity(1).amount, 100);
+        assert_eq!(product.price_for_quantity(9).amount, 100);
+        assert_eq!(product.price_for_quantity(10).amount, 80);
+        assert_eq!(product.price_for_quantity(99).amount, 80);
+        assert_eq!(product.price_for_quantity(500).amount, 60);
+
+        // A sale price cheaper than the matching tier wins
+        product.sale_price = Some(Price::new(70, Currency::usd(), 2));
+        assert_eq!(product.price_for_quantity(10).amount, 70);
+        assert_eq!(product.price_for_quantity(500).amount, 60);
+        product.sale_price = None;
+
+        product.quantity_tiers.push((1000, Price::new(50, Currency::new("EUR"), 2)));
+        assert!(matches!(
+            product.validate_tier_prices(),
+            Err(CommerceError::CurrencyMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_drain_changes_returns_deltas_once() {
+        let catalog = ProductCatalog::new();
+        let first = Product::new(ProductId::new("prod-001"), Sku::new("SKU-001"), "First");
+        let second = Product::new(ProductId::new("prod-002"), Sku::new("SKU-002"), "Second");
+        catalog.add_product(first.clone()).expect("should add first");
+        catalog.add_product(second).expect("should add second");
+
+        // A rejected mutation records nothing
+        assert!(catalog.add_product(first.clone()).is_err());
+
+        let mut renamed = first;
+        renamed.name = "Renamed".to_string();
+        catalog.update_product(renamed).expect("should update");
+        catalog.remove_product(&ProductId::new("prod-002")).expect("should remove");
+
+        let change = |kind, id| CatalogChange { kind, id: ProductId::new(id) };
+        assert_eq!(catalog.drain_changes().expect("should drain"), vec![
+            change(CatalogChangeKind::Added, "prod-001"),
+            change(CatalogChangeKind::Added, "prod-002"),
+            change(CatalogChangeKind::Updated, "prod-001"),
+            change(CatalogChangeKind::Removed, "prod-002"),
+        ]);
+        assert!(catalog.drain_changes().expect("should drain").is_empty());
+    }
+
+    #[test]
+    fn test_drain_changes_reports_gap_after_overflow() {
+        let catalog = ProductCatalog::new();
+        let mut product = Product::new(ProductId::new("prod-001"), Sku::new("SKU-001"), "First");
+        catalog.add_product(product.clone()).expect("should add");
+        for i in 0..CHANGE_FEED_CAPACITY + 2 {
+            product.name = format!("Name {i}");
+            catalog.update_product(product.clone()).expect("should update");
+        }
+
+        assert!(matches!(
+            catalog.drain_changes(),
+            Err(CommerceError::ChangeFeedGap { dropped: 3 })
+        ));
+
+        // The consumer resynced; later changes arrive normally
+        catalog.remove_product(&product.id).expect("should remove");
+        assert_eq!(catalog.drain_changes().expect("should drain"), vec![
+            CatalogChange { kind: CatalogChangeKind::Removed, id: product.id }
+        ]);
+    }
+
+    #[test]
+    fn test_is_buyable_combines_status_stock_and_availability() {
+        let mut product = Product::new(ProductId::new("prod-001"), Sku::new("SKU-001"), "Widget");
+        product.status = ProductStatus::Active;
+        product.inventory_quantity = 0;
+        assert!(!product.is_buyable(1_000), "active but out of stock");
+
+        product.backorders_allowed = true;
+        assert!(product.is_buyable(1_000), "backorderable");
+        product.backorders_allowed = false;
+
+        product.inventory_quantity = 5;
+        product.status = ProductStatus::Draft;
+        assert!(!product.is_buyable(1_000), "in stock but draft");
+
+        product.status = ProductStatus::Active;
+        assert!(product.is_buyable(1_000), "fully buyable");
+
+        product.available_from = Some(2_000);
+        assert!(!product.is_buyable(1_000), "not yet released");
+        assert!(product.is_buyable(2_000));
+    }
 }
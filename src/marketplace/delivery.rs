@@ -60,12 +60,18 @@ pub struct ContentDeliveryService {
     access_tokens: HashMap<(String, super::ListingId), AccessToken>,
     /// Content providers by content hash
     providers:     HashMap<ContentHash, Vec<String>>,
+    /// Successful downloads by listing, across all buyers
+    downloads:     HashMap<super::ListingId, u64>,
 }
 
 impl ContentDeliveryService {
     /// Create new content delivery service
     pub fn new() -> Self {
-        Self { access_tokens: HashMap::new(), providers: HashMap::new() }
+        Self {
+            access_tokens: HashMap::new(),
+            providers:     HashMap::new(),
+            downloads:     HashMap::new(),
+        }
     }
 
     /// Register content provider
@@ -116,12 +122,13 @@ impl ContentDeliveryService {
             }
         }
 
-        access.download_count += 1;
-
         // Find providers
         let providers =
             self.providers.get(&access.content_hash).ok_or(MarketplaceError::NoProviders)?;
 
+        access.download_count += 1;
+        *self.downloads.entry(access.listing_id.clone()).or_default() += 1;
+
         Ok(DownloadInfo {
             content_hash: access.content_hash.clone(),
             providers:    providers.clone(),
@@ -129,6 +136,11 @@ impl ContentDeliveryService {
         })
     }
 
+    /// Total successful downloads of a listing across all buyers
+    pub fn downloads_for_listing(&self, listing_id: &super::ListingId) -> u64 {
+        self.downloads.get(listing_id).copied().unwrap_or(0)
+    }
+
     /// Revoke access (for refunds/disputes)
     pub fn revoke_access(&mut self, buyer: &str, listing_id: &super::ListingId) {
         self.access_tokens.remove(&(buyer.to_string(), listing_id.clone()));
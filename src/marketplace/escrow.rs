@@ -59,12 +59,38 @@ pub struct EscrowAccount {
     pub release_tx_id:      Option<[u8; 32]>,
     /// Blockchain transaction ID for refund
     pub refund_tx_id:       Option<[u8; 32]>,
+    /// When the assigned arbitrator decided (if arbitration is required)
+    pub arbitrated_at:      Option<u64>,
     /// Created timestamp
     pub created_at:         u64,
     /// Last updated timestamp
     pub updated_at:         u64,
 }
 
+impl EscrowAccount {
+    /// Amount still held, neither released nor refunded
+    pub fn remaining(&self) -> u64 {
+        self.total_amount
+            .saturating_sub(self.released_amount)
+            .saturating_sub(self.refunded_amount)
+    }
+
+    /// Check that released and refunded amounts never exceed the total
+    pub fn check_invariants(&self) -> EscrowResult<()> {
+        let settled = self.released_amount.checked_add(self.refunded_amount);
+        if settled.is_none_or(|settled| settled > self.total_amount) {
+            return Err(MarketplaceError::EscrowError(format!(
+                "escrow {} settled {} + {} of {}",
+                self.id.as_str(),
+                self.released_amount,
+                self.refunded_amount,
+                self.total_amount
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// Release condition types
 #[derive(Debug, Clone)]
 pub enum ReleaseCondition {
@@ -97,6 +123,22 @@ pub enum EscrowStatus {
     Resolved,
 }
 
+/// Settlement backend for submitting escrow transactions on-chain
+pub trait EscrowSettlement: Send + Sync {
+    /// Submit a transaction, returning it as accepted by the backend
+    fn submit_transaction(
+        &self, transaction: BlockchainTransaction,
+    ) -> Result<BlockchainTransaction, String>;
+}
+
+impl EscrowSettlement for BlockchainPlugin {
+    fn submit_transaction(
+        &self, transaction: BlockchainTransaction,
+    ) -> Result<BlockchainTransaction, String> {
+        BlockchainPlugin::submit_transaction(self, transaction).map_err(|e| format!("{:?}", e))
+    }
+}
+
 /// Escrow manager service
 #[derive(Default)]
 pub struct EscrowManager {
@@ -105,7 +147,7 @@ pub struct EscrowManager {
     /// Escrows by order ID
     escrows_by_order:  HashMap<super::orders::OrderId, EscrowId>,
     /// Blockchain plugin for transaction settlement
-    blockchain_plugin: Option<BlockchainPlugin>,
+    blockchain_plugin: Option<Box<dyn EscrowSettlement>>,
 }
 
 impl EscrowManager {
@@ -120,10 +162,15 @@ impl EscrowManager {
 
     /// Create new escrow manager with blockchain plugin
     pub fn with_blockchain_plugin(blockchain_plugin: BlockchainPlugin) -> EscrowResult<Self> {
+        Self::with_settlement(Box::new(blockchain_plugin))
+    }
+
+    /// Create new escrow manager with a custom settlement backend
+    pub fn with_settlement(settlement: Box<dyn EscrowSettlement>) -> EscrowResult<Self> {
         Ok(Self {
             escrows:           HashMap::new(),
             escrows_by_order:  HashMap::new(),
-            blockchain_plugin: Some(blockchain_plugin),
+            blockchain_plugin: Some(settlement),
         })
     }
 
@@ -154,7 +201,7 @@ impl EscrowManager {
 
             let tx = blockchain_plugin.submit_transaction(deposit_tx).map_err(|e| {
                 MarketplaceError::EscrowError(format!(
-                    "Failed to submit deposit transaction: {:?}",
+                    "Failed to submit deposit transaction: {}",
                     e
                 ))
             })?;
@@ -177,6 +224,7 @@ impl EscrowManager {
             deposit_tx_id,
             release_tx_id: None,
             refund_tx_id: None,
+            arbitrated_at: None,
             created_at: now,
             updated_at: now,
         };
@@ -203,14 +251,10 @@ impl EscrowManager {
             return Err(MarketplaceError::ReleaseConditionsNotMet);
         }
 
-        // Calculate available amount
-        let available = escrow.total_amount - escrow.released_amount - escrow.refunded_amount;
-        let release_amount = amount.min(available);
-
-        escrow.released_amount += release_amount;
-        escrow.updated_at = current_timestamp();
+        let release_amount = Self::validate_amount(amount, escrow.remaining())?;
 
-        // Create blockchain transaction for release if plugin available
+        // Submit to the blockchain before touching the ledger so a failed
+        // submission leaves the escrow unchanged
         if let Some(blockchain_plugin) = &self.blockchain_plugin {
             let now = current_timestamp();
             let release_tx = BlockchainTransaction {
@@ -226,7 +270,7 @@ impl EscrowManager {
 
             let tx = blockchain_plugin.submit_transaction(release_tx).map_err(|e| {
                 MarketplaceError::EscrowError(format!(
-                    "Failed to submit release transaction: {:?}",
+                    "Failed to submit release transaction: {}",
                     e
                 ))
             })?;
@@ -234,6 +278,10 @@ impl EscrowManager {
             escrow.release_tx_id = Some(tx.id);
         }
 
+        escrow.released_amount += release_amount;
+        escrow.updated_at = current_timestamp();
+        debug_assert!(escrow.check_invariants().is_ok());
+
         // Update status
         if escrow.released_amount + escrow.refunded_amount >= escrow.total_amount {
             if escrow.released_amount > 0 && escrow.refunded_amount == 0 {
@@ -252,14 +300,10 @@ impl EscrowManager {
     pub fn refund_funds(&mut self, escrow_id: &EscrowId, amount: u64) -> EscrowResult<()> {
         let escrow = self.escrows.get_mut(escrow_id).ok_or(MarketplaceError::EscrowNotFound)?;
 
-        // Calculate available amount
-        let available = escrow.total_amount - escrow.released_amount - escrow.refunded_amount;
-        let refund_amount = amount.min(available);
-
-        escrow.refunded_amount += refund_amount;
-        escrow.updated_at = current_timestamp();
+        let refund_amount = Self::validate_amount(amount, escrow.remaining())?;
 
-        // Create blockchain transaction for refund if plugin available
+        // Submit to the blockchain before touching the ledger so a failed
+        // submission leaves the escrow unchanged
         if let Some(blockchain_plugin) = &self.blockchain_plugin {
             let now = current_timestamp();
             let refund_tx = BlockchainTransaction {
@@ -274,15 +318,16 @@ impl EscrowManager {
             };
 
             let tx = blockchain_plugin.submit_transaction(refund_tx).map_err(|e| {
-                MarketplaceError::EscrowError(format!(
-                    "Failed to submit refund transaction: {:?}",
-                    e
-                ))
+                MarketplaceError::EscrowError(format!("Failed to submit refund transaction: {}", e))
             })?;
 
             escrow.refund_tx_id = Some(tx.id);
         }
 
+        escrow.refunded_amount += refund_amount;
+        escrow.updated_at = current_timestamp();
+        debug_assert!(escrow.check_invariants().is_ok());
+
         // Update status
         if escrow.released_amount + escrow.refunded_amount >= escrow.total_amount {
             if escrow.refunded_amount > 0 && escrow.released_amount == 0 {
@@ -327,29 +372,134 @@ impl EscrowManager {
             escrow.buyer.clone()
         }; // escrow borrow ends here
 
+        self.apply_resolution(escrow_id, &buyer, resolution)?;
+
+        // Update escrow status - we know it exists since we validated it above
+        let escrow = self.escrows.get_mut(escrow_id).ok_or_else(|| {
+            MarketplaceError::EscrowError("Escrow disappeared during resolution".to_string())
+        })?;
+        escrow.status = EscrowStatus::Resolved;
+        escrow.updated_at = current_timestamp();
+
+        Ok(())
+    }
+
+    /// Assign an arbitrator whose decision gates any release of funds
+    pub fn assign_arbitrator(
+        &mut self, escrow_id: &EscrowId, arbitrator: String,
+    ) -> EscrowResult<()> {
+        let escrow = self.escrows.get_mut(escrow_id).ok_or(MarketplaceError::EscrowNotFound)?;
+
+        if matches!(
+            escrow.status,
+            EscrowStatus::Released | EscrowStatus::Refunded | EscrowStatus::Resolved
+        ) {
+            return Err(MarketplaceError::InvalidEscrowState);
+        }
+
+        if escrow
+            .release_conditions
+            .iter()
+            .any(|c| matches!(c, ReleaseCondition::Arbitration { .. }))
+        {
+            return Err(MarketplaceError::EscrowError(
+                "Arbitrator already assigned".to_string(),
+            ));
+        }
+
+        escrow.release_conditions.push(ReleaseCondition::Arbitration { arbitrator });
+        escrow.updated_at = current_timestamp();
+
+        Ok(())
+    }
+
+    /// Record the assigned arbitrator's decision and settle the escrow
+    /// accordingly
+    ///
+    /// The decision is only kept if settling succeeds, so a rejected
+    /// resolution can be replaced by another.
+    pub fn arbitrator_decide(
+        &mut self, escrow_id: &EscrowId, arbitrator: &str, resolution: DisputeResolution,
+    ) -> EscrowResult<()> {
+        let (buyer, disputed) = {
+            let escrow = self.escrows.get_mut(escrow_id).ok_or(MarketplaceError::EscrowNotFound)?;
+
+            let is_assigned = escrow.release_conditions.iter().any(|c| {
+                matches!(
+                    c,
+                    ReleaseCondition::Arbitration { arbitrator: assigned } if assigned == arbitrator
+                )
+            });
+            if !is_assigned {
+                return Err(MarketplaceError::UnauthorizedArbitrator);
+            }
+            if escrow.arbitrated_at.is_some() {
+                return Err(MarketplaceError::InvalidEscrowState);
+            }
+
+            // Opens the arbitration gate for the release below
+            escrow.arbitrated_at = Some(current_timestamp());
+            (
+                escrow.buyer.clone(),
+                escrow.status == EscrowStatus::Disputed,
+            )
+        };
+
+        let result = if disputed {
+            self.resolve_dispute(escrow_id, resolution)
+        } else {
+            self.apply_resolution(escrow_id, &buyer, resolution)
+        };
+        if result.is_err()
+            && let Some(escrow) = self.escrows.get_mut(escrow_id)
+        {
+            escrow.arbitrated_at = None;
+        }
+        result
+    }
+
+    /// Apply a resolution's release and refund amounts
+    fn apply_resolution(
+        &mut self, escrow_id: &EscrowId, buyer: &str, resolution: DisputeResolution,
+    ) -> EscrowResult<()> {
         match resolution {
             DisputeResolution::ReleaseToSeller(amount) => {
-                self.release_funds(escrow_id, amount, &buyer)?;
+                self.release_funds(escrow_id, amount, buyer)?;
             },
             DisputeResolution::RefundToBuyer(amount) => {
                 self.refund_funds(escrow_id, amount)?;
             },
             DisputeResolution::Split { seller_amount, buyer_amount } => {
-                self.release_funds(escrow_id, seller_amount, &buyer)?;
-                self.refund_funds(escrow_id, buyer_amount)?;
+                // Check the combined amount first so a split never half-applies
+                let available = self
+                    .escrows
+                    .get(escrow_id)
+                    .ok_or(MarketplaceError::EscrowNotFound)?
+                    .remaining();
+                Self::validate_amount(seller_amount.saturating_add(buyer_amount), available)?;
+                // A split may award everything to one side
+                if seller_amount > 0 {
+                    self.release_funds(escrow_id, seller_amount, buyer)?;
+                }
+                if buyer_amount > 0 {
+                    self.refund_funds(escrow_id, buyer_amount)?;
+                }
             },
         }
-
-        // Update escrow status - we know it exists since we validated it above
-        let escrow = self.escrows.get_mut(escrow_id).ok_or_else(|| {
-            MarketplaceError::EscrowError("Escrow disappeared during resolution".to_string())
-        })?;
-        escrow.status = EscrowStatus::Resolved;
-        escrow.updated_at = current_timestamp();
-
         Ok(())
     }
 
+    /// Reject a zero amount or one larger than the escrow's remaining balance
+    fn validate_amount(amount: u64, available: u64) -> EscrowResult<u64> {
+        if amount == 0 {
+            return Err(MarketplaceError::AmountRequired);
+        }
+        if amount > available {
+            return Err(MarketplaceError::AmountExceedsAvailable { requested: amount, available });
+        }
+        Ok(amount)
+    }
+
     /// Get escrow account
     pub fn get_escrow(&self, escrow_id: &EscrowId) -> Option<&EscrowAccount> {
         self.escrows.get(escrow_id)
@@ -362,6 +512,29 @@ impl EscrowManager {
             .and_then(|escrow_id| self.escrows.get(escrow_id))
     }
 
+    /// Load a previously persisted escrow account, e.g. after a restart
+    ///
+    /// The account is stored as-is; use [`Self::verify_all`] to find any
+    /// whose ledger was damaged.
+    pub fn restore_escrow(&mut self, escrow: EscrowAccount) -> EscrowResult<()> {
+        if self.escrows_by_order.contains_key(&escrow.order_id) {
+            return Err(MarketplaceError::EscrowExists);
+        }
+
+        self.escrows_by_order.insert(escrow.order_id.clone(), escrow.id.clone());
+        self.escrows.insert(escrow.id.clone(), escrow);
+        Ok(())
+    }
+
+    /// IDs of escrows whose amounts violate the ledger invariants
+    pub fn verify_all(&self) -> Vec<EscrowId> {
+        self.escrows
+            .values()
+            .filter(|escrow| escrow.check_invariants().is_err())
+            .map(|escrow| escrow.id.clone())
+            .collect()
+    }
+
     /// Check if release conditions are met
     fn check_release_conditions_static(escrow: &EscrowAccount) -> bool {
         for condition in &escrow.release_conditions {
@@ -381,8 +554,10 @@ impl EscrowManager {
                     }
                 },
                 ReleaseCondition::Arbitration { .. } => {
-                    // Would check arbitration status
-                    continue;
+                    // Blocked until the assigned arbitrator has decided
+                    if escrow.arbitrated_at.is_none() {
+                        return false;
+                    }
                 },
                 ReleaseCondition::QualityVerified => {
                     // Would check quality verification
@@ -0,0 +1,70 @@
+//! # Marketplace Favorites
+//!
+//! Buyer bookmarks of listings to revisit later.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use crate::{errors::MarketplaceError, marketplace::*};
+
+/// Tracks the listings each buyer has favorited
+#[derive(Debug, Default)]
+pub struct FavoritesService {
+    /// Favorited listings by buyer
+    favorites: RwLock<HashMap<String, HashSet<ListingId>>>,
+}
+
+impl FavoritesService {
+    /// Create an empty favorites service
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Favorite a listing, returning `false` if it was already a favorite
+    pub fn add(&self, buyer: &str, listing_id: &ListingId) -> MarketplaceResult<bool> {
+        let mut favorites = self.favorites.write().map_err(|_| MarketplaceError::LockError)?;
+        Ok(favorites.entry(buyer.to_string()).or_default().insert(listing_id.clone()))
+    }
+
+    /// Unfavorite a listing, returning `false` if it was not a favorite
+    pub fn remove(&self, buyer: &str, listing_id: &ListingId) -> MarketplaceResult<bool> {
+        let mut favorites = self.favorites.write().map_err(|_| MarketplaceError::LockError)?;
+        let Some(ids) = favorites.get_mut(buyer) else {
+            return Ok(false);
+        };
+        let removed = ids.remove(listing_id);
+        if ids.is_empty() {
+            favorites.remove(buyer);
+        }
+        Ok(removed)
+    }
+
+    /// A buyer's favorite listings, sorted by ID
+    pub fn list(&self, buyer: &str) -> MarketplaceResult<Vec<ListingId>> {
+        let favorites = self.favorites.read().map_err(|_| MarketplaceError::LockError)?;
+        let mut ids: Vec<ListingId> = favorites
+            .get(buyer)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Whether a buyer has favorited a listing
+    pub fn is_favorite(&self, buyer: &str, listing_id: &ListingId) -> MarketplaceResult<bool> {
+        let favorites = self.favorites.read().map_err(|_| MarketplaceError::LockError)?;
+        Ok(favorites.get(buyer).is_some_and(|ids| ids.contains(listing_id)))
+    }
+
+    /// Drop a listing from every buyer's favorites
+    pub fn prune_listing(&self, listing_id: &ListingId) -> MarketplaceResult<()> {
+        let mut favorites = self.favorites.write().map_err(|_| MarketplaceError::LockError)?;
+        for ids in favorites.values_mut() {
+            ids.remove(listing_id);
+        }
+        favorites.retain(|_, ids| !ids.is_empty());
+        Ok(())
+    }
+}
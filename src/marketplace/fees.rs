@@ -0,0 +1,39 @@
+//! # Marketplace Fees
+//!
+//! Platform fee calculation for marketplace sales.
+
+/// Calculates the platform cut of marketplace sales
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeCalculator {
+    /// Platform fee in basis points (1/100 of a percent)
+    pub platform_fee_bps: u32,
+}
+
+impl FeeCalculator {
+    /// Create a calculator with the given platform fee in basis points
+    pub fn new(platform_fee_bps: u32) -> Self {
+        Self { platform_fee_bps: platform_fee_bps.min(10_000) }
+    }
+
+    /// Create a calculator from a fee percentage (e.g. `2.5` for 2.5%)
+    pub fn from_percentage(fee_percentage: f64) -> Self {
+        Self::new((fee_percentage * 100.0).round().max(0.0) as u32)
+    }
+
+    /// Platform fee on a sale, rounded down
+    pub fn platform_fee(&self, amount_sats: u64) -> u64 {
+        (u128::from(amount_sats) * u128::from(self.platform_fee_bps) / 10_000) as u64
+    }
+
+    /// Seller proceeds after the platform fee
+    pub fn net_proceeds(&self, amount_sats: u64) -> u64 {
+        amount_sats - self.platform_fee(amount_sats)
+    }
+}
+
+impl Default for FeeCalculator {
+    /// Matches the default `CommerceConfig::fee_percentage` of 2.5%
+    fn default() -> Self {
+        Self::new(250)
+    }
+}
@@ -5,6 +5,8 @@
 
 pub mod delivery;
 pub mod escrow;
+pub mod favorites;
+pub mod fees;
 pub mod orders;
 pub mod reviews;
 pub mod search;
@@ -119,7 +121,7 @@ impl ListingCategory {
 }
 
 /// Pricing model for a listing
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PricingModel {
     /// One-time purchase
     OneTime { price_sats: u64 },
@@ -135,6 +137,31 @@ pub enum PricingModel {
     FixedProject { price_sats: u64, milestones: Vec<Milestone> },
 }
 
+impl PricingModel {
+    /// Exact amount a purchase must pay, or `None` for models where the
+    /// buyer chooses the amount above a floor
+    pub fn required_amount(&self) -> Option<u64> {
+        match self {
+            Self::OneTime { price_sats }
+            | Self::Subscription { price_sats, .. }
+            | Self::FixedProject { price_sats, .. } => Some(*price_sats),
+            Self::Free => Some(0),
+            Self::PayWhatYouWant { .. } | Self::Hourly { .. } => None,
+        }
+    }
+
+    /// Smallest amount a purchase may pay
+    pub fn minimum_amount(&self) -> u64 {
+        match self {
+            Self::PayWhatYouWant { minimum_sats, .. } => *minimum_sats,
+            Self::Hourly { rate_sats, minimum_hours } => {
+                rate_sats.saturating_mul(u64::from(*minimum_hours))
+            },
+            _ => self.required_amount().unwrap_or(0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubscriptionInterval {
     Weekly,
@@ -142,7 +169,7 @@ pub enum SubscriptionInterval {
     Yearly,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Milestone {
     pub name:         String,
     pub description:  String,
@@ -177,6 +204,8 @@ pub struct MarketplaceListing {
     pub repo_id:           Option<String>, // Placeholder for RepoId
     /// License type
     pub license:           LicenseType,
+    /// Maximum number of licenses that may be sold, if capped
+    pub license_cap:       Option<u64>,
     /// Creation timestamp
     pub created_at:        u64,
     /// Last updated
@@ -187,6 +216,96 @@ pub struct MarketplaceListing {
     pub stats:             ListingStats,
     /// Requirements (for services)
     pub requirements:      Option<ServiceRequirements>,
+    /// Moderation state
+    pub moderation:        ModerationStatus,
+    /// User reports filed against the listing
+    pub reports:           Vec<ListingReport>,
+    /// Edit revision, bumped by each update that changes a tracked field
+    /// (`version` is the product's own release version)
+    pub revision:          u64,
+    /// Tracked edits, oldest first
+    pub change_log:        Vec<ListingChange>,
+}
+
+impl MarketplaceListing {
+    /// Licenses left to sell under the cap, if the listing has one
+    pub fn licenses_remaining(&self) -> Option<u64> {
+        self.license_cap.map(|cap| cap.saturating_sub(self.stats.purchases))
+    }
+
+    /// Tracked fields that differ in `updated`
+    pub fn diff(&self, updated: &MarketplaceListing) -> Vec<ListingFieldChange> {
+        let mut changes = Vec::new();
+        if self.pricing != updated.pricing {
+            changes.push(ListingFieldChange::Pricing {
+                from: self.pricing.clone(),
+                to:   updated.pricing.clone(),
+            });
+        }
+        if self.status != updated.status {
+            changes.push(ListingFieldChange::Status { from: self.status, to: updated.status });
+        }
+        if self.title != updated.title {
+            changes.push(ListingFieldChange::Title {
+                from: self.title.clone(),
+                to:   updated.title.clone(),
+            });
+        }
+        if self.category != updated.category {
+            changes
+                .push(ListingFieldChange::Category { from: self.category, to: updated.category });
+        }
+        if self.description != updated.description {
+            changes.push(ListingFieldChange::Description);
+        }
+        changes
+    }
+
+    /// Pricing over time as `(timestamp, pricing)`, oldest first
+    ///
+    /// Starts from the pricing at creation and adds an entry for each logged
+    /// price change.
+    pub fn price_history(&self) -> Vec<(u64, PricingModel)> {
+        let mut changes = self.change_log.iter().flat_map(|entry| {
+            entry.changes.iter().filter_map(move |change| match change {
+                ListingFieldChange::Pricing { from, to } => Some((entry.changed_at, from, to)),
+                _ => None,
+            })
+        });
+
+        let Some((changed_at, initial, to)) = changes.next() else {
+            return vec![(self.created_at, self.pricing.clone())];
+        };
+        let mut history = vec![(self.created_at, initial.clone()), (changed_at, to.clone())];
+        history.extend(changes.map(|(changed_at, _, to)| (changed_at, to.clone())));
+        history
+    }
+}
+
+/// One logged edit of a listing
+#[derive(Debug, Clone)]
+pub struct ListingChange {
+    /// Revision the edit produced
+    pub revision:   u64,
+    /// When the edit was made
+    pub changed_at: u64,
+    /// Fields the edit changed
+    pub changes:    Vec<ListingFieldChange>,
+}
+
+/// A tracked listing field and how it changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListingFieldChange {
+    /// Price changed
+    Pricing { from: PricingModel, to: PricingModel },
+    /// Status changed
+    Status { from: ListingStatus, to: ListingStatus },
+    /// Title changed
+    Title { from: String, to: String },
+    /// Category changed
+    Category { from: ListingCategory, to: ListingCategory },
+    /// Description rewritten
+    Description,
 }
 
 #[derive(Debug, Clone)]
@@ -227,6 +346,55 @@ pub enum ListingStatus {
     Discontinued,
 }
 
+/// Moderation state of a listing
+///
+/// Only listings in the `Ok` state appear in search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModerationStatus {
+    /// No open reports
+    #[default]
+    Ok,
+    /// Reported by a user, awaiting a moderator
+    Flagged,
+    /// Picked up by a moderator
+    UnderReview,
+    /// Taken down by a moderator
+    Removed,
+}
+
+/// Remaining licenses at or below which a capped listing needs attention
+pub const LOW_LICENSE_THRESHOLD: u64 = 5;
+
+/// Days an active listing may go without a view before it needs attention
+pub const UNVIEWED_LISTING_DAYS: u64 = 14;
+
+/// Why a listing shows up on the seller's attention list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AttentionReason {
+    /// Few licenses left under the listing's cap
+    LowLicenses {
+        /// Licenses left to sell
+        remaining: u64,
+    },
+    /// Active for [`UNVIEWED_LISTING_DAYS`] without a single view
+    NoViews,
+    /// Reported and awaiting or under moderation
+    Flagged,
+    /// Paused by the seller
+    Paused,
+}
+
+/// A user report against a listing
+#[derive(Debug, Clone)]
+pub struct ListingReport {
+    /// Reporting user
+    pub reporter:   String,
+    /// Reason given
+    pub reason:     String,
+    /// When the report was filed
+    pub created_at: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct ListingStats {
     /// Number of views
@@ -354,6 +522,8 @@ pub struct SearchFilters {
     pub min_seller_reputation: Option<u32>,
     /// Listing status filter
     pub status:                Option<ListingStatus>,
+    /// Hide listings from this seller (e.g. the requesting buyer's own)
+    pub exclude_seller:        Option<String>,
 }
 
 /// Pagination parameters
@@ -384,11 +554,32 @@ pub struct SearchResults {
     pub has_more:    bool,
 }
 
+/// Point-in-time snapshot of marketplace health
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketplaceMetrics {
+    /// Listings in any status
+    pub total_listings:  usize,
+    /// Listings currently active
+    pub active_listings: usize,
+    /// Registered seller profiles
+    pub total_sellers:   usize,
+    /// Orders in any status
+    pub total_orders:    usize,
+    /// Gross merchandise value of completed orders (sats)
+    pub gmv_sats:        u64,
+    /// Average completed order value (sats)
+    pub avg_order_value: u64,
+    /// Orders currently in dispute
+    pub open_disputes:   usize,
+}
+
 /// Marketplace result type
 pub type MarketplaceResult<T> = Result<T, crate::errors::MarketplaceError>;
 
 // Re-exports
 pub use delivery::*;
+pub use favorites::*;
+pub use fees::*;
 pub use orders::*;
 pub use reviews::*;
 pub use service::*;
@@ -2,6 +2,10 @@
 //!
 //! Types and structures for marketplace orders and transactions.
 
+use crate::errors::MarketplaceError;
+
+use super::MarketplaceResult;
+
 /// Unique order identifier
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct OrderId(String);
@@ -37,6 +41,8 @@ pub struct Order {
     pub payment_hash: Option<String>,
     /// Escrow status (for services)
     pub escrow:       Option<OrderEscrow>,
+    /// Escrow account holding the payment (for services)
+    pub escrow_id:    Option<super::escrow::EscrowId>,
     /// Created timestamp
     pub created_at:   u64,
     /// Paid timestamp
@@ -45,6 +51,99 @@ pub struct Order {
     pub delivered_at: Option<u64>,
     /// Completed timestamp
     pub completed_at: Option<u64>,
+    /// Cancelled timestamp
+    pub cancelled_at: Option<u64>,
+    /// Disputed timestamp
+    pub disputed_at:  Option<u64>,
+}
+
+impl Order {
+    /// Create a pending order for a listing
+    pub fn new(
+        listing_id: super::ListingId, buyer: String, seller: String, total_sats: u64,
+    ) -> Self {
+        Self {
+            id: OrderId::new(),
+            listing_id,
+            buyer,
+            seller,
+            status: OrderStatus::Pending,
+            total_sats,
+            payment_hash: None,
+            escrow: None,
+            escrow_id: None,
+            created_at: current_timestamp(),
+            paid_at: None,
+            delivered_at: None,
+            completed_at: None,
+            cancelled_at: None,
+            disputed_at: None,
+        }
+    }
+
+    /// Mark payment received
+    pub fn mark_paid(&mut self) -> MarketplaceResult<()> {
+        self.transition(&[OrderStatus::Pending], OrderStatus::Paid)?;
+        self.paid_at = Some(current_timestamp());
+        Ok(())
+    }
+
+    /// Mark content or service delivered
+    pub fn mark_delivered(&mut self) -> MarketplaceResult<()> {
+        self.transition(
+            &[OrderStatus::Paid, OrderStatus::InProgress],
+            OrderStatus::Delivered,
+        )?;
+        self.delivered_at = Some(current_timestamp());
+        Ok(())
+    }
+
+    /// Mark order completed
+    pub fn mark_completed(&mut self) -> MarketplaceResult<()> {
+        self.transition(
+            &[OrderStatus::Delivered, OrderStatus::InProgress],
+            OrderStatus::Completed,
+        )?;
+        self.completed_at = Some(current_timestamp());
+        Ok(())
+    }
+
+    /// Cancel an order that has not been delivered yet
+    pub fn mark_cancelled(&mut self) -> MarketplaceResult<()> {
+        self.transition(
+            &[OrderStatus::Pending, OrderStatus::Paid],
+            OrderStatus::Cancelled,
+        )?;
+        self.cancelled_at = Some(current_timestamp());
+        Ok(())
+    }
+
+    /// Raise a dispute on a paid order
+    pub fn mark_disputed(&mut self) -> MarketplaceResult<()> {
+        self.transition(
+            &[
+                OrderStatus::Paid,
+                OrderStatus::InProgress,
+                OrderStatus::Delivered,
+                OrderStatus::Completed,
+            ],
+            OrderStatus::Disputed,
+        )?;
+        self.disputed_at = Some(current_timestamp());
+        Ok(())
+    }
+
+    /// Move to `to` if the current status is one of `from`
+    fn transition(&mut self, from: &[OrderStatus], to: OrderStatus) -> MarketplaceResult<()> {
+        if !from.contains(&self.status) {
+            return Err(MarketplaceError::InvalidOrderTransition {
+                from: format!("{:?}", self.status),
+                to:   format!("{:?}", to),
+            });
+        }
+        self.status = to;
+        Ok(())
+    }
 }
 
 /// Order status
@@ -124,6 +223,22 @@ pub struct OrderReview {
     pub helpful_votes: u64,
 }
 
+impl OrderReview {
+    /// Create a review of an order, rejecting ratings outside 1-5
+    pub fn new(
+        order_id: OrderId, reviewer: String, rating: u8, review_text: String,
+    ) -> MarketplaceResult<Self> {
+        Ok(Self {
+            order_id,
+            reviewer,
+            rating: super::reviews::validate_rating(rating)?,
+            review_text,
+            created_at: current_timestamp(),
+            helpful_votes: 0,
+        })
+    }
+}
+
 /// Dispute information
 #[derive(Debug, Clone)]
 pub struct OrderDispute {
@@ -207,3 +322,9 @@ pub enum DisputeDecision {
     /// Escalate to arbitration
     Arbitration,
 }
+
+/// Get current timestamp
+fn current_timestamp() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
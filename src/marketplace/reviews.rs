@@ -2,6 +2,44 @@
 //!
 //! Types and structures for reviews, ratings, and seller profiles.
 
+use crate::errors::MarketplaceError;
+
+use super::MarketplaceResult;
+
+/// Check that a star rating is within 1-5
+pub fn validate_rating(rating: u8) -> MarketplaceResult<u8> {
+    if (1..=5).contains(&rating) {
+        Ok(rating)
+    } else {
+        Err(MarketplaceError::InvalidRating)
+    }
+}
+
+/// Shorten review text to at most `max_len` characters for display
+///
+/// Longer text is cut at the last word boundary that fits and ends with an
+/// ellipsis, which counts toward `max_len`.
+pub fn review_excerpt(text: &str, max_len: usize) -> String {
+    let text = text.trim();
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+    if max_len == 0 {
+        return String::new();
+    }
+
+    // Byte offset just past the first `max_len - 1` characters
+    let limit = text.char_indices().nth(max_len - 1).map_or(text.len(), |(i, _)| i);
+    let head = &text[..limit];
+    // Keep whole words unless the first word alone is too long
+    let cut = if text[limit..].starts_with(char::is_whitespace) {
+        head
+    } else {
+        head.rfind(char::is_whitespace).map_or(head, |i| &head[..i])
+    };
+    format!("{}\u{2026}", cut.trim_end())
+}
+
 /// A review for a listing or seller
 #[derive(Debug, Clone)]
 pub struct Review {
@@ -35,6 +73,14 @@ pub struct Review {
     pub seller_response:   Option<SellerResponse>,
 }
 
+impl Review {
+    /// Check the overall and category ratings are all within 1-5
+    pub fn validate_ratings(&self) -> MarketplaceResult<()> {
+        validate_rating(self.rating)?;
+        self.category_ratings.validate()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ReviewId(String);
 
@@ -64,6 +110,33 @@ pub struct CategoryRatings {
     pub documentation: Option<u8>,
 }
 
+impl CategoryRatings {
+    /// Create ratings for quality and value, leaving the rest unrated
+    pub fn new(quality: u8, value: u8) -> MarketplaceResult<Self> {
+        Ok(Self {
+            quality:       validate_rating(quality)?,
+            value:         validate_rating(value)?,
+            communication: None,
+            timeliness:    None,
+            documentation: None,
+        })
+    }
+
+    /// Check every given rating is within 1-5
+    pub fn validate(&self) -> MarketplaceResult<()> {
+        [
+            Some(self.quality),
+            Some(self.value),
+            self.communication,
+            self.timeliness,
+            self.documentation,
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(|rating| validate_rating(rating).map(|_| ()))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SellerResponse {
     pub text:         String,
@@ -97,6 +170,17 @@ pub struct SellerProfile {
     pub specializations:        Vec<String>,
 }
 
+/// Seller-editable profile fields; `None` leaves a field unchanged
+#[derive(Debug, Clone, Default)]
+pub struct SellerProfileUpdate {
+    /// New bio
+    pub bio:             Option<String>,
+    /// New specializations
+    pub specializations: Option<Vec<String>>,
+    /// New avatar hash (`Some(None)` removes the avatar)
+    pub avatar_hash:     Option<Option<String>>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SellerReputation {
     /// Total reviews
@@ -129,6 +213,15 @@ pub enum VerificationLevel {
     Premium,
 }
 
+/// Outcome of a bulk review import
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Number of reviews imported
+    pub imported: usize,
+    /// Reviews that were skipped, with the reason
+    pub skipped:  Vec<(ReviewId, String)>,
+}
+
 /// Review summary for listings
 #[derive(Debug, Clone)]
 pub struct ReviewSummary {
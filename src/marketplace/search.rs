@@ -21,7 +21,7 @@ pub struct SearchIndex {
     category_listings: HashMap<super::ListingCategory, HashSet<super::ListingId>>,
     /// Price range index (simplified)
     price_ranges:      HashMap<String, HashSet<super::ListingId>>,
-    /// Rating index
+    /// Rating index (average rating in tenths of a star -> listing IDs)
     rating_listings:   HashMap<u8, HashSet<super::ListingId>>,
 }
 
@@ -68,6 +68,33 @@ impl SearchIndex {
         let price_bucket = self.get_price_bucket(listing);
         self.price_ranges.entry(price_bucket).or_default().insert(listing_id.clone());
 
+        // Index rating (only for reviewed listings)
+        self.index_rating(listing);
+
+        Ok(())
+    }
+
+    /// Move a listing to the rating bucket matching its current stats
+    pub fn reindex_rating(&mut self, listing: &super::MarketplaceListing) -> SearchResult<()> {
+        for ids in self.rating_listings.values_mut() {
+            ids.remove(&listing.id);
+        }
+        self.index_rating(listing);
+        Ok(())
+    }
+
+    /// Clear the index and reindex the given listings from scratch
+    pub fn rebuild(&mut self, listings: &[super::MarketplaceListing]) -> SearchResult<()> {
+        self.full_text.clear();
+        self.tags.clear();
+        self.seller_listings.clear();
+        self.category_listings.clear();
+        self.price_ranges.clear();
+        self.rating_listings.clear();
+
+        for listing in listings {
+            self.index_listing(listing)?;
+        }
         Ok(())
     }
 
@@ -97,53 +124,57 @@ impl SearchIndex {
     }
 
     /// Search listings
+    ///
+    /// An empty query with no category, price or rating filter matches every
+    /// indexed listing. The query is tokenized like listing text, so one
+    /// made only of punctuation counts as empty.
     pub fn search(
         &self, query: &str, filters: &super::SearchFilters,
     ) -> SearchResult<Vec<super::ListingId>> {
-        let mut candidates = HashSet::new();
-
-        // Full-text search
-        if !query.is_empty() {
-            let query_terms: Vec<&str> = query.split_whitespace().collect();
-            for term in query_terms {
-                if let Some(ids) = self.full_text.get(&term.to_lowercase()) {
-                    if candidates.is_empty() {
-                        candidates.extend(ids.iter().cloned());
-                    } else {
-                        candidates.retain(|id| ids.contains(id));
-                    }
-                } else if candidates.is_empty() {
-                    // No matches for this term and no previous candidates
-                    return Ok(Vec::new());
-                }
-            }
+        // `None` until a filter has narrowed the search, so a filter that
+        // matched nothing is not mistaken for no filter at all
+        let mut candidates: Option<HashSet<super::ListingId>> = None;
+
+        // Browse mode: nothing to narrow by, start from everything
+        if Self::is_browse(query, filters) {
+            candidates = Some(self.seller_listings.values().flatten().cloned().collect());
+        }
+
+        // Full-text search; a query that tokenizes to nothing (e.g. only
+        // punctuation) places no text constraint
+        for term in &self.tokenize(query) {
+            Self::narrow(&mut candidates, self.full_text.get(term));
         }
 
         // Apply category filter
         if let Some(category) = filters.category {
-            if let Some(cat_ids) = self.category_listings.get(&category) {
-                if candidates.is_empty() {
-                    candidates.extend(cat_ids.iter().cloned());
-                } else {
-                    candidates.retain(|id| cat_ids.contains(id));
-                }
-            } else if candidates.is_empty() {
-                return Ok(Vec::new());
-            }
+            Self::narrow(&mut candidates, self.category_listings.get(&category));
         }
 
         // Apply price range filter
         if let Some((min_price, max_price)) = filters.price_range {
             let price_bucket = self.get_price_bucket_from_range(min_price, max_price);
-            if let Some(price_ids) = self.price_ranges.get(&price_bucket) {
-                if candidates.is_empty() {
-                    candidates.extend(price_ids.iter().cloned());
-                } else {
-                    candidates.retain(|id| price_ids.contains(id));
-                }
-            } else if candidates.is_empty() {
-                return Ok(Vec::new());
-            }
+            Self::narrow(&mut candidates, self.price_ranges.get(&price_bucket));
+        }
+
+        // Apply minimum rating filter (at tenth-of-a-star precision)
+        if let Some(min_rating) = filters.min_rating {
+            let min_bucket = Self::rating_bucket(min_rating);
+            let rated: HashSet<_> = self
+                .rating_listings
+                .iter()
+                .filter(|(bucket, _)| **bucket >= min_bucket)
+                .flat_map(|(_, ids)| ids.iter().cloned())
+                .collect();
+            Self::narrow(&mut candidates, Some(&rated));
+        }
+        let mut candidates = candidates.unwrap_or_default();
+
+        // Hide an excluded seller's listings
+        if let Some(seller) = &filters.exclude_seller
+            && let Some(own_ids) = self.seller_listings.get(seller)
+        {
+            candidates.retain(|id| !own_ids.contains(id));
         }
 
         // Convert to sorted vec (by relevance - simplified)
@@ -153,6 +184,39 @@ impl SearchIndex {
         Ok(results)
     }
 
+    /// Keep only candidates in `ids`, or start from `ids` if no filter has
+    /// applied yet; a filter with no index entry matches nothing
+    fn narrow(
+        candidates: &mut Option<HashSet<super::ListingId>>, ids: Option<&HashSet<super::ListingId>>,
+    ) {
+        match (candidates.as_mut(), ids) {
+            (Some(narrowed), Some(ids)) => narrowed.retain(|id| ids.contains(id)),
+            (Some(narrowed), None) => narrowed.clear(),
+            (None, ids) => *candidates = Some(ids.cloned().unwrap_or_default()),
+        }
+    }
+
+    /// Whether a search has no query terms or indexed filter to narrow by
+    pub(crate) fn is_browse(query: &str, filters: &super::SearchFilters) -> bool {
+        !query.chars().any(char::is_alphanumeric)
+            && filters.category.is_none()
+            && filters.price_range.is_none()
+            && filters.min_rating.is_none()
+    }
+
+    /// Index a listing's average rating if it has reviews
+    fn index_rating(&mut self, listing: &super::MarketplaceListing) {
+        if listing.stats.review_count > 0 {
+            let bucket = Self::rating_bucket(listing.stats.avg_rating);
+            self.rating_listings.entry(bucket).or_default().insert(listing.id.clone());
+        }
+    }
+
+    /// Convert a 0-5 star rating to tenths of a star
+    fn rating_bucket(rating: f64) -> u8 {
+        (rating.clamp(0.0, 5.0) * 10.0).floor() as u8
+    }
+
     /// Index full-text terms
     fn index_full_text(&mut self, listing_id: &super::ListingId, text: &str) {
         let terms = self.tokenize(text);
@@ -2,7 +2,10 @@
 //!
 //! Core marketplace service for managing listings, orders, and transactions.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
 
 use crate::{
     errors::MarketplaceError,
@@ -14,110 +17,776 @@ use crate::{
 pub struct VcsPaymentService;
 
 /// Main marketplace service
+///
+/// All state lives behind `Arc<RwLock<...>>` so the service can be shared
+/// across request handlers. When more than one lock is held, they are
+/// acquired in field declaration order to avoid lock-order inversions.
 #[allow(dead_code)] // TODO(BACKLOG): Remove when all fields are used
 pub struct MarketplaceService {
     /// All listings (indexed by ID)
-    listings:             HashMap<ListingId, MarketplaceListing>,
+    listings:             Arc<RwLock<HashMap<ListingId, MarketplaceListing>>>,
     /// Listings by seller
-    listings_by_seller:   HashMap<String, Vec<ListingId>>,
+    listings_by_seller:   Arc<RwLock<HashMap<String, Vec<ListingId>>>>,
     /// Listings by category
-    listings_by_category: HashMap<ListingCategory, Vec<ListingId>>,
+    listings_by_category: Arc<RwLock<HashMap<ListingCategory, Vec<ListingId>>>>,
     /// Active orders
-    orders:               HashMap<orders::OrderId, orders::Order>,
+    orders:               Arc<RwLock<HashMap<orders::OrderId, orders::Order>>>,
     /// Reviews
-    reviews:              HashMap<reviews::ReviewId, reviews::Review>,
+    reviews:              Arc<RwLock<HashMap<reviews::ReviewId, reviews::Review>>>,
     /// Seller profiles
-    sellers:              HashMap<String, reviews::SellerProfile>,
+    sellers:              Arc<RwLock<HashMap<String, reviews::SellerProfile>>>,
     /// Payment service reference
     payment_service:      Arc<VcsPaymentService>,
     /// Search index
-    search_index:         search::SearchIndex,
+    search_index:         Arc<RwLock<search::SearchIndex>>,
     /// Escrow manager
-    escrow_manager:       escrow::EscrowManager,
+    escrow_manager:       Arc<RwLock<escrow::EscrowManager>>,
+    /// Platform fee calculator
+    fee_calculator:       FeeCalculator,
+    /// Buyer favorites
+    favorites:            FavoritesService,
 }
 
 impl MarketplaceService {
     /// Create a new marketplace service
     pub fn new(payment_service: Arc<VcsPaymentService>) -> MarketplaceResult<Self> {
         Ok(Self {
-            listings: HashMap::new(),
-            listings_by_seller: HashMap::new(),
-            listings_by_category: HashMap::new(),
-            orders: HashMap::new(),
-            reviews: HashMap::new(),
-            sellers: HashMap::new(),
+            listings: Arc::new(RwLock::new(HashMap::new())),
+            listings_by_seller: Arc::new(RwLock::new(HashMap::new())),
+            listings_by_category: Arc::new(RwLock::new(HashMap::new())),
+            orders: Arc::new(RwLock::new(HashMap::new())),
+            reviews: Arc::new(RwLock::new(HashMap::new())),
+            sellers: Arc::new(RwLock::new(HashMap::new())),
             payment_service,
-            search_index: SearchIndex::new()?,
-            escrow_manager: EscrowManager::new()?,
+            search_index: Arc::new(RwLock::new(SearchIndex::new()?)),
+            escrow_manager: Arc::new(RwLock::new(EscrowManager::new()?)),
+            fee_calculator: FeeCalculator::default(),
+            favorites: FavoritesService::new(),
         })
     }
 
+    /// Use a custom platform fee calculator
+    pub fn with_fee_calculator(mut self, fee_calculator: FeeCalculator) -> Self {
+        self.fee_calculator = fee_calculator;
+        self
+    }
+
     /// Create a new listing
+    ///
+    /// Zero `created_at`/`updated_at` timestamps are set to the current
+    /// time; non-zero ones (e.g. from a sync import) are kept.
     pub fn create_listing(
-        &mut self, seller: String, listing: MarketplaceListing,
+        &self, seller: String, mut listing: MarketplaceListing,
     ) -> MarketplaceResult<ListingId> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut by_seller =
+            self.listings_by_seller.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut by_category =
+            self.listings_by_category.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut sellers = self.sellers.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut search_index =
+            self.search_index.write().map_err(|_| MarketplaceError::LockError)?;
+
         // Validate seller has profile
-        if !self.sellers.contains_key(&seller) {
+        if !sellers.contains_key(&seller) {
             return Err(MarketplaceError::SellerNotFound);
         }
 
         // Validate listing
         self.validate_listing(&listing)?;
 
+        let now = current_timestamp();
+        if listing.created_at == 0 {
+            listing.created_at = now;
+        }
+        if listing.updated_at == 0 {
+            listing.updated_at = listing.created_at;
+        }
+
         let id = listing.id.clone();
 
         // Index by seller
-        self.listings_by_seller.entry(seller.clone()).or_default().push(id.clone());
+        by_seller.entry(seller.clone()).or_default().push(id.clone());
 
         // Index by category
-        self.listings_by_category.entry(listing.category).or_default().push(id.clone());
+        by_category.entry(listing.category).or_default().push(id.clone());
 
         // Add to search index
-        self.search_index.index_listing(&listing)?;
+        search_index.index_listing(&listing)?;
 
         // Store listing
-        self.listings.insert(id.clone(), listing);
+        listings.insert(id.clone(), listing);
 
         // Update seller stats
-        if let Some(seller_profile) = self.sellers.get_mut(&seller) {
+        if let Some(seller_profile) = sellers.get_mut(&seller) {
             seller_profile.active_listings += 1;
         }
 
         Ok(id)
     }
 
+    /// Replace an existing listing, stamping `updated_at`
+    ///
+    /// Moderation state, reports, revision and change log are carried over
+    /// from the stored listing; if tracked fields changed, the revision is
+    /// bumped and the edit logged.
+    pub fn update_listing(&self, mut listing: MarketplaceListing) -> MarketplaceResult<()> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut by_category =
+            self.listings_by_category.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut search_index =
+            self.search_index.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let existing = listings.get(&listing.id).ok_or(MarketplaceError::ListingNotFound)?;
+        if existing.seller != listing.seller {
+            return Err(MarketplaceError::InvalidListing);
+        }
+        self.validate_listing(&listing)?;
+
+        if existing.category != listing.category {
+            if let Some(ids) = by_category.get_mut(&existing.category) {
+                ids.retain(|id| *id != listing.id);
+            }
+            by_category.entry(listing.category).or_default().push(listing.id.clone());
+        }
+
+        let now = current_timestamp();
+        let changes = existing.diff(&listing);
+        listing.moderation = existing.moderation;
+        listing.reports = existing.reports.clone();
+        listing.revision = existing.revision;
+        listing.change_log = existing.change_log.clone();
+        if !changes.is_empty() {
+            listing.revision += 1;
+            listing.change_log.push(ListingChange {
+                revision: listing.revision,
+                changed_at: now,
+                changes,
+            });
+        }
+
+        listing.updated_at = now;
+        search_index.remove_listing(&listing.id)?;
+        search_index.index_listing(&listing)?;
+        listings.insert(listing.id.clone(), listing);
+
+        Ok(())
+    }
+
+    /// Remove a listing and everything that refers to it
+    ///
+    /// The listing is dropped from the seller and category indexes, the
+    /// search index and all buyers' favorites.
+    pub fn remove_listing(&self, id: &ListingId) -> MarketplaceResult<MarketplaceListing> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut by_seller =
+            self.listings_by_seller.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut by_category =
+            self.listings_by_category.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut search_index =
+            self.search_index.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let listing = listings.remove(id).ok_or(MarketplaceError::ListingNotFound)?;
+        if let Some(ids) = by_seller.get_mut(&listing.seller) {
+            ids.retain(|l| l != id);
+        }
+        if let Some(ids) = by_category.get_mut(&listing.category) {
+            ids.retain(|l| l != id);
+        }
+        search_index.remove_listing(id)?;
+        self.favorites.prune_listing(id)?;
+
+        Ok(listing)
+    }
+
+    /// Buyer favorites
+    pub fn favorites(&self) -> &FavoritesService {
+        &self.favorites
+    }
+
     /// Search listings
+    ///
+    /// An empty query with no other filters browses all active listings.
+    /// Listings held for moderation are never returned.
     pub fn search(
         &self, query: &str, filters: SearchFilters, pagination: Pagination,
     ) -> MarketplaceResult<SearchResults> {
-        let results = self.search_index.search(query, &filters)?;
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let search_index = self.search_index.read().map_err(|_| MarketplaceError::LockError)?;
+
+        let browsing = SearchIndex::is_browse(query, &filters);
+        let status = filters.status.or(browsing.then_some(ListingStatus::Active));
+
+        let results: Vec<&MarketplaceListing> = search_index
+            .search(query, &filters)?
+            .iter()
+            .filter_map(|id| listings.get(id))
+            .filter(|l| l.moderation == ModerationStatus::Ok)
+            .filter(|l| status.is_none_or(|s| l.status == s))
+            .collect();
 
-        let listings: Vec<_> = results
+        let page_listings: Vec<_> = results
             .iter()
-            .filter_map(|id| self.listings.get(id))
             .skip(pagination.offset)
             .take(pagination.limit)
-            .cloned()
+            .map(|&l| l.clone())
             .collect();
 
         Ok(SearchResults {
-            listings,
+            listings:    page_listings,
             total_count: results.len(),
-            page: pagination.offset / pagination.limit,
-            has_more: pagination.offset + pagination.limit < results.len(),
+            page:        pagination.offset / pagination.limit,
+            has_more:    pagination.offset + pagination.limit < results.len(),
         })
     }
 
+    /// Number of browsable listings in each category
+    ///
+    /// Counts the listings a category browse would return: active and not
+    /// held for moderation. Categories without any are left out.
+    pub fn category_counts(&self) -> MarketplaceResult<HashMap<ListingCategory, usize>> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let by_category =
+            self.listings_by_category.read().map_err(|_| MarketplaceError::LockError)?;
+
+        Ok(by_category
+            .iter()
+            .map(|(&category, ids)| {
+                let active = ids
+                    .iter()
+                    .filter_map(|id| listings.get(id))
+                    .filter(|l| {
+                        l.status == ListingStatus::Active && l.moderation == ModerationStatus::Ok
+                    })
+                    .count();
+                (category, active)
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect())
+    }
+
+    /// Rebuild the search index from the stored listings
+    ///
+    /// Recovery path for when the index has drifted from the listing store.
+    pub fn rebuild_search_index(&self) -> MarketplaceResult<()> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let mut search_index =
+            self.search_index.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let current: Vec<MarketplaceListing> = listings.values().cloned().collect();
+        search_index.rebuild(&current)
+    }
+
     /// Get listing by ID
-    pub fn get_listing(&self, id: &ListingId) -> MarketplaceResult<&MarketplaceListing> {
-        self.listings.get(id).ok_or(MarketplaceError::ListingNotFound)
+    pub fn get_listing(&self, id: &ListingId) -> MarketplaceResult<MarketplaceListing> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        listings.get(id).cloned().ok_or(MarketplaceError::ListingNotFound)
+    }
+
+    /// Find active listings related to `id` by shared tags and category
+    ///
+    /// Each shared tag scores 2 and a matching category scores 1. Listings
+    /// with no overlap are omitted. Returns up to `limit` listings, best
+    /// match first.
+    pub fn similar_listings(
+        &self, id: &ListingId, limit: usize, exclude_same_seller: bool,
+    ) -> MarketplaceResult<Vec<MarketplaceListing>> {
+        const TAG_WEIGHT: usize = 2;
+        const CATEGORY_WEIGHT: usize = 1;
+
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let source = listings.get(id).ok_or(MarketplaceError::ListingNotFound)?;
+        let source_tags: HashSet<String> = source.tags.iter().map(|t| t.to_lowercase()).collect();
+
+        let mut scored: Vec<(usize, &MarketplaceListing)> = listings
+            .values()
+            .filter(|l| l.id != *id && l.status == ListingStatus::Active)
+            .filter(|l| l.moderation == ModerationStatus::Ok)
+            .filter(|l| !exclude_same_seller || l.seller != source.seller)
+            .filter_map(|l| {
+                let shared_tags =
+                    l.tags.iter().filter(|t| source_tags.contains(&t.to_lowercase())).count();
+                let category = if l.category == source.category {
+                    CATEGORY_WEIGHT
+                } else {
+                    0
+                };
+                let score = shared_tags * TAG_WEIGHT + category;
+                (score > 0).then_some((score, l))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.created_at.cmp(&a.1.created_at)));
+
+        Ok(scored.into_iter().take(limit).map(|(_, l)| l.clone()).collect())
+    }
+
+    /// Report a listing, hiding it from search until a moderator acts
+    ///
+    /// The report is recorded whatever the current state; a listing that
+    /// was `Ok` becomes `Flagged`.
+    pub fn flag_listing(
+        &self, id: &ListingId, reporter: &str, reason: impl Into<String>,
+    ) -> MarketplaceResult<ModerationStatus> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let listing = listings.get_mut(id).ok_or(MarketplaceError::ListingNotFound)?;
+
+        listing.reports.push(ListingReport {
+            reporter:   reporter.to_string(),
+            reason:     reason.into(),
+            created_at: current_timestamp(),
+        });
+        if listing.moderation == ModerationStatus::Ok {
+            listing.moderation = ModerationStatus::Flagged;
+        }
+
+        Ok(listing.moderation)
+    }
+
+    /// Take up a flagged listing for moderator review
+    pub fn review_listing(&self, id: &ListingId) -> MarketplaceResult<()> {
+        self.set_moderation(id, ModerationStatus::UnderReview, &[
+            ModerationStatus::Flagged,
+        ])
+    }
+
+    /// Reinstate a moderated listing, making it searchable again
+    ///
+    /// Reports are kept for the record.
+    pub fn reinstate_listing(&self, id: &ListingId) -> MarketplaceResult<()> {
+        self.set_moderation(id, ModerationStatus::Ok, &[
+            ModerationStatus::Flagged,
+            ModerationStatus::UnderReview,
+            ModerationStatus::Removed,
+        ])
+    }
+
+    /// Take a listing down for moderation
+    pub fn remove_for_moderation(&self, id: &ListingId) -> MarketplaceResult<()> {
+        self.set_moderation(id, ModerationStatus::Removed, &[
+            ModerationStatus::Ok,
+            ModerationStatus::Flagged,
+            ModerationStatus::UnderReview,
+        ])
+    }
+
+    /// Move a listing to `to` if its moderation state is one of `from`
+    fn set_moderation(
+        &self, id: &ListingId, to: ModerationStatus, from: &[ModerationStatus],
+    ) -> MarketplaceResult<()> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let listing = listings.get_mut(id).ok_or(MarketplaceError::ListingNotFound)?;
+
+        if !from.contains(&listing.moderation) {
+            return Err(MarketplaceError::InvalidModerationState);
+        }
+        listing.moderation = to;
+        listing.updated_at = current_timestamp();
+
+        Ok(())
+    }
+
+    /// Register a new seller so they can create listings
+    ///
+    /// The node ID and display name must be non-blank. Reputation and
+    /// activity counters start from zero and `joined_at` is set to now.
+    pub fn register_seller(&self, mut profile: reviews::SellerProfile) -> MarketplaceResult<()> {
+        if profile.node_id.trim().is_empty() || profile.display_name.trim().is_empty() {
+            return Err(MarketplaceError::InvalidSellerProfile);
+        }
+
+        let mut sellers = self.sellers.write().map_err(|_| MarketplaceError::LockError)?;
+        if sellers.contains_key(&profile.node_id) {
+            return Err(MarketplaceError::SellerExists);
+        }
+
+        profile.marketplace_reputation = reviews::SellerReputation::default();
+        profile.active_listings = 0;
+        profile.completed_orders = 0;
+        profile.joined_at = current_timestamp();
+        sellers.insert(profile.node_id.clone(), profile);
+
+        Ok(())
+    }
+
+    /// Apply seller edits to their bio, specializations or avatar
+    pub fn update_seller_profile(
+        &self, seller_id: &str, update: reviews::SellerProfileUpdate,
+    ) -> MarketplaceResult<reviews::SellerProfile> {
+        let mut sellers = self.sellers.write().map_err(|_| MarketplaceError::LockError)?;
+        let profile = sellers.get_mut(seller_id).ok_or(MarketplaceError::SellerNotFound)?;
+
+        if let Some(bio) = update.bio {
+            profile.bio = bio;
+        }
+        if let Some(specializations) = update.specializations {
+            profile.specializations = specializations;
+        }
+        if let Some(avatar_hash) = update.avatar_hash {
+            profile.avatar_hash = avatar_hash;
+        }
+
+        Ok(profile.clone())
     }
 
     /// Get seller profile
-    pub fn get_seller_profile(
-        &self, seller_id: &str,
-    ) -> MarketplaceResult<&reviews::SellerProfile> {
-        self.sellers.get(seller_id).ok_or(MarketplaceError::SellerNotFound)
+    pub fn get_seller_profile(&self, seller_id: &str) -> MarketplaceResult<reviews::SellerProfile> {
+        let sellers = self.sellers.read().map_err(|_| MarketplaceError::LockError)?;
+        sellers.get(seller_id).cloned().ok_or(MarketplaceError::SellerNotFound)
+    }
+
+    /// Import historical orders, e.g. when migrating from another platform
+    ///
+    /// Orders whose ID is already stored are skipped. Returns the number of
+    /// orders imported.
+    pub fn import_orders(&self, orders: Vec<orders::Order>) -> MarketplaceResult<usize> {
+        let mut stored = self.orders.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let mut imported = 0;
+        for order in orders {
+            if stored.contains_key(&order.id) {
+                continue;
+            }
+            stored.insert(order.id.clone(), order);
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
+    /// Import historical reviews, e.g. when migrating from another platform
+    ///
+    /// Reviews with an out-of-range overall or category rating, or with an
+    /// unknown order or listing, are skipped. Seller reputations are
+    /// recomputed once after the batch.
+    pub fn import_reviews(
+        &self, reviews: Vec<reviews::Review>,
+    ) -> MarketplaceResult<reviews::ImportReport> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let orders = self.orders.read().map_err(|_| MarketplaceError::LockError)?;
+        let mut stored = self.reviews.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut sellers = self.sellers.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let mut report = reviews::ImportReport::default();
+        let mut affected_sellers = HashSet::new();
+
+        for review in reviews {
+            let reason = if review.validate_ratings().is_err() {
+                Some("rating out of range".to_string())
+            } else if !orders.contains_key(&review.order_id) {
+                Some("order not found".to_string())
+            } else if !listings.contains_key(&review.listing_id) {
+                Some("listing not found".to_string())
+            } else {
+                None
+            };
+
+            if let Some(reason) = reason {
+                report.skipped.push((review.id, reason));
+                continue;
+            }
+
+            affected_sellers.insert(review.seller.clone());
+            stored.insert(review.id.clone(), review);
+            report.imported += 1;
+        }
+
+        for seller_id in affected_sellers {
+            let Some(profile) = sellers.get_mut(&seller_id) else {
+                continue;
+            };
+
+            let reputation = &mut profile.marketplace_reputation;
+            reputation.rating_distribution = [0; 5];
+            let mut total: u32 = 0;
+            let seller_reviews =
+                stored.values().filter(|r| r.seller == seller_id && (1..=5).contains(&r.rating));
+            for review in seller_reviews {
+                reputation.rating_distribution[usize::from(review.rating - 1)] += 1;
+                total += u32::from(review.rating);
+            }
+            reputation.review_count = reputation.rating_distribution.iter().sum();
+            reputation.average_rating = if reputation.review_count == 0 {
+                0.0
+            } else {
+                total as f32 / reputation.review_count as f32
+            };
+        }
+
+        Ok(report)
+    }
+
+    /// Refresh a listing's rating stats after a review is added
+    ///
+    /// Stores the review if it is not already present, recomputes the
+    /// listing's average rating and review count from all of its reviews and
+    /// moves it to the matching rating bucket in the search index.
+    pub fn on_review_added(&self, review: &reviews::Review) -> MarketplaceResult<()> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut stored = self.reviews.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut search_index =
+            self.search_index.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let listing =
+            listings.get_mut(&review.listing_id).ok_or(MarketplaceError::ListingNotFound)?;
+        review.validate_ratings()?;
+        stored.entry(review.id.clone()).or_insert_with(|| review.clone());
+
+        let ratings: Vec<u8> = stored
+            .values()
+            .filter(|r| r.listing_id == listing.id && (1..=5).contains(&r.rating))
+            .map(|r| r.rating)
+            .collect();
+        listing.stats.review_count = ratings.len() as u32;
+        listing.stats.avg_rating = if ratings.is_empty() {
+            0.0
+        } else {
+            ratings.iter().map(|&r| f64::from(r)).sum::<f64>() / ratings.len() as f64
+        };
+
+        search_index.reindex_rating(listing)?;
+        Ok(())
+    }
+
+    /// The review to feature on a listing page
+    ///
+    /// Picks the verified-purchase review with the most helpful votes,
+    /// preferring the newest on a tie.
+    pub fn top_review(&self, listing_id: &ListingId) -> MarketplaceResult<Option<reviews::Review>> {
+        let reviews = self.reviews.read().map_err(|_| MarketplaceError::LockError)?;
+        Ok(reviews
+            .values()
+            .filter(|r| &r.listing_id == listing_id && r.verified_purchase)
+            .max_by_key(|r| (r.helpful_count, r.created_at))
+            .cloned())
+    }
+
+    /// Place an order for an active listing
+    ///
+    /// Fixed-price listings must be paid exactly; pay-what-you-want and
+    /// hourly listings accept any amount from their minimum up. Service
+    /// listings hold the payment in an escrow released on buyer approval;
+    /// the order records the escrow's ID. Each order takes one license from
+    /// a capped listing.
+    pub fn place_order(
+        &self, listing_id: &ListingId, buyer: String, total_sats: u64,
+    ) -> MarketplaceResult<orders::Order> {
+        let mut listings = self.listings.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut orders = self.orders.write().map_err(|_| MarketplaceError::LockError)?;
+        let mut escrow_manager =
+            self.escrow_manager.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let listing = listings.get_mut(listing_id).ok_or(MarketplaceError::ListingNotFound)?;
+        if listing.status != ListingStatus::Active {
+            return Err(MarketplaceError::ListingNotActive);
+        }
+        if listing.licenses_remaining() == Some(0) {
+            return Err(MarketplaceError::LicenseCapReached);
+        }
+        match listing.pricing.required_amount() {
+            Some(expected) if total_sats != expected => {
+                return Err(MarketplaceError::AmountMismatch { expected, got: total_sats });
+            },
+            None if total_sats < listing.pricing.minimum_amount() => {
+                return Err(MarketplaceError::BelowMinimum);
+            },
+            _ => {},
+        }
+
+        let mut order = orders::Order::new(
+            listing_id.clone(),
+            buyer,
+            listing.seller.clone(),
+            total_sats,
+        );
+        if listing.category.is_service() {
+            let escrow_id = escrow_manager.create_escrow(
+                order.id.clone(),
+                order.buyer.clone(),
+                order.seller.clone(),
+                total_sats,
+                vec![escrow::ReleaseCondition::BuyerApproval],
+            )?;
+            order.escrow = Some(orders::OrderEscrow {
+                amount_sats:        total_sats,
+                release_conditions: vec![orders::ReleaseCondition::BuyerApproval],
+                status:             orders::EscrowStatus::Held,
+            });
+            order.escrow_id = Some(escrow_id);
+        }
+
+        listing.stats.purchases += 1;
+        orders.insert(order.id.clone(), order.clone());
+        Ok(order)
+    }
+
+    /// Escrow account backing an order, if it has one
+    pub fn order_escrow(
+        &self, order_id: &orders::OrderId,
+    ) -> MarketplaceResult<Option<escrow::EscrowAccount>> {
+        let orders = self.orders.read().map_err(|_| MarketplaceError::LockError)?;
+        let escrow_manager = self.escrow_manager.read().map_err(|_| MarketplaceError::LockError)?;
+
+        let order = orders.get(order_id).ok_or(MarketplaceError::OrderNotFound)?;
+        Ok(order.escrow_id.as_ref().and_then(|id| escrow_manager.get_escrow(id)).cloned())
+    }
+
+    /// Replace a stored order, e.g. after advancing its status
+    ///
+    /// The escrow link is carried over from the stored order.
+    pub fn update_order(&self, mut order: orders::Order) -> MarketplaceResult<()> {
+        let mut orders = self.orders.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let existing = orders.get(&order.id).ok_or(MarketplaceError::OrderNotFound)?;
+        order.escrow_id = existing.escrow_id.clone();
+        orders.insert(order.id.clone(), order);
+        Ok(())
+    }
+
+    /// Refund some or all of an order's escrow to the buyer
+    pub fn refund_order(
+        &self, order_id: &orders::OrderId, amount_sats: u64,
+    ) -> MarketplaceResult<()> {
+        let orders = self.orders.read().map_err(|_| MarketplaceError::LockError)?;
+        let mut escrow_manager =
+            self.escrow_manager.write().map_err(|_| MarketplaceError::LockError)?;
+
+        let order = orders.get(order_id).ok_or(MarketplaceError::OrderNotFound)?;
+        let escrow_id = order.escrow_id.as_ref().ok_or(MarketplaceError::EscrowNotFound)?;
+        escrow_manager.refund_funds(escrow_id, amount_sats)
+    }
+
+    /// Snapshot listing, seller, and order totals
+    ///
+    /// GMV and the average order value count completed orders only.
+    pub fn metrics(&self) -> MarketplaceResult<MarketplaceMetrics> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let orders = self.orders.read().map_err(|_| MarketplaceError::LockError)?;
+        let sellers = self.sellers.read().map_err(|_| MarketplaceError::LockError)?;
+
+        let mut metrics = MarketplaceMetrics {
+            total_listings: listings.len(),
+            active_listings: listings
+                .values()
+                .filter(|l| l.status == ListingStatus::Active)
+                .count(),
+            total_sellers: sellers.len(),
+            total_orders: orders.len(),
+            ..MarketplaceMetrics::default()
+        };
+
+        let mut completed: u64 = 0;
+        for order in orders.values() {
+            match order.status {
+                orders::OrderStatus::Completed => {
+                    metrics.gmv_sats = metrics.gmv_sats.saturating_add(order.total_sats);
+                    completed += 1;
+                },
+                orders::OrderStatus::Disputed => metrics.open_disputes += 1,
+                _ => {},
+            }
+        }
+        metrics.avg_order_value = metrics.gmv_sats.checked_div(completed).unwrap_or(0);
+
+        Ok(metrics)
+    }
+
+    /// Compute seller payouts for orders completed in `[since, until)`
+    ///
+    /// Each seller receives the net proceeds of their completed orders after
+    /// the platform fee, less whatever the order's escrow refunded to the
+    /// buyer. Orders whose escrow was fully refunded or is in dispute are
+    /// excluded; imported orders without an escrow account fall back to
+    /// their recorded escrow status.
+    pub fn compute_payouts(
+        &self, since: u64, until: u64,
+    ) -> MarketplaceResult<HashMap<String, u64>> {
+        let orders = self.orders.read().map_err(|_| MarketplaceError::LockError)?;
+        let escrow_manager = self.escrow_manager.read().map_err(|_| MarketplaceError::LockError)?;
+
+        let mut payouts: HashMap<String, u64> = HashMap::new();
+        for order in orders.values() {
+            if order.status != orders::OrderStatus::Completed {
+                continue;
+            }
+            let Some(completed_at) = order.completed_at else {
+                continue;
+            };
+            if completed_at < since || completed_at >= until {
+                continue;
+            }
+
+            let mut refunded = 0;
+            if let Some(escrow) =
+                order.escrow_id.as_ref().and_then(|id| escrow_manager.get_escrow(id))
+            {
+                if matches!(
+                    escrow.status,
+                    escrow::EscrowStatus::Refunded | escrow::EscrowStatus::Disputed
+                ) {
+                    continue;
+                }
+                refunded = escrow.refunded_amount;
+            } else if let Some(escrow) = &order.escrow
+                && matches!(
+                    escrow.status,
+                    orders::EscrowStatus::Refunded | orders::EscrowStatus::Disputed
+                )
+            {
+                continue;
+            }
+
+            let net = self.fee_calculator.net_proceeds(order.total_sats.saturating_sub(refunded));
+            *payouts.entry(order.seller.clone()).or_default() += net;
+        }
+
+        Ok(payouts)
+    }
+
+    /// A seller's listings that need action, with the reason for each
+    ///
+    /// A listing is reported once per reason that applies, ordered by
+    /// listing ID. Discontinued listings and those taken down by moderation
+    /// are left out.
+    pub fn listings_needing_attention(
+        &self, seller: &str, now: u64,
+    ) -> MarketplaceResult<Vec<(ListingId, AttentionReason)>> {
+        let listings = self.listings.read().map_err(|_| MarketplaceError::LockError)?;
+        let unviewed_after = UNVIEWED_LISTING_DAYS * 24 * 60 * 60;
+
+        let mut attention = Vec::new();
+        for listing in listings.values().filter(|l| l.seller == seller) {
+            if listing.status == ListingStatus::Discontinued
+                || listing.moderation == ModerationStatus::Removed
+            {
+                continue;
+            }
+
+            let mut reasons = Vec::new();
+            if let Some(remaining) = listing.licenses_remaining()
+                && remaining <= LOW_LICENSE_THRESHOLD
+            {
+                reasons.push(AttentionReason::LowLicenses { remaining });
+            }
+            if listing.status == ListingStatus::Active
+                && listing.stats.views == 0
+                && now.saturating_sub(listing.created_at) >= unviewed_after
+            {
+                reasons.push(AttentionReason::NoViews);
+            }
+            if matches!(
+                listing.moderation,
+                ModerationStatus::Flagged | ModerationStatus::UnderReview
+            ) {
+                reasons.push(AttentionReason::Flagged);
+            }
+            if listing.status == ListingStatus::Paused {
+                reasons.push(AttentionReason::Paused);
+            }
+            attention.extend(reasons.into_iter().map(|reason| (listing.id.clone(), reason)));
+        }
+
+        attention.sort();
+        Ok(attention)
     }
 
     /// Validate listing data
@@ -132,3 +801,9 @@ impl MarketplaceService {
         Ok(())
     }
 }
+
+/// Get current timestamp
+fn current_timestamp() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
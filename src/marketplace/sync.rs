@@ -2,7 +2,8 @@
 
 use std::collections::{HashMap, HashSet};
 
-use crate::errors::MarketplaceError;
+pub use crate::types::{ConflictResolution, DEFAULT_SYNC_BATCH_SIZE};
+use crate::{errors::MarketplaceError, types::CommerceConfig};
 
 /// Catalog synchronization result type
 pub type SyncResult<T> = Result<T, MarketplaceError>;
@@ -10,13 +11,17 @@ pub type SyncResult<T> = Result<T, MarketplaceError>;
 /// P2P catalog synchronizer
 pub struct P2PCatalogSync {
     /// Local catalog state
-    local_catalog: HashMap<String, CatalogEntry>,
+    local_catalog:   HashMap<String, CatalogEntry>,
     /// Known peer catalogs
-    peer_catalogs: HashMap<String, PeerCatalog>,
+    peer_catalogs:   HashMap<String, PeerCatalog>,
     /// Synchronization state
-    sync_state:    SyncState,
+    sync_state:      SyncState,
     /// Pending sync operations
-    pending_ops:   Vec<SyncOperation>,
+    pending_ops:     Vec<SyncOperation>,
+    /// Peer entries merged per batch
+    batch_size:      usize,
+    /// Resolution applied to detected conflicts
+    conflict_policy: ConflictResolution,
 }
 
 /// Catalog entry metadata
@@ -32,6 +37,15 @@ pub struct CatalogEntry {
     pub version:       u64,
     /// Entry status
     pub status:        EntryStatus,
+    /// Serialized listing
+    pub payload:       Vec<u8>,
+}
+
+impl CatalogEntry {
+    /// Whether the payload hashes to `content_hash`
+    pub fn verify_content(&self) -> bool {
+        content_hash(&self.payload) == self.content_hash
+    }
 }
 
 /// Peer catalog information
@@ -112,32 +126,19 @@ pub struct Conflict {
     pub resolution:     ConflictResolution,
 }
 
-/// Conflict resolution strategy
-#[derive(Debug, Clone)]
-pub enum ConflictResolution {
-    /// Keep local version
-    KeepLocal,
-    /// Use remote version
-    UseRemote,
-    /// Merge versions (if possible)
-    Merge,
-    /// Manual resolution required
-    Manual,
-}
-
 impl P2PCatalogSync {
     /// Create new P2P catalog synchronizer
     pub fn new() -> SyncResult<Self> {
+        Ok(Self::default())
+    }
+
+    /// Create a synchronizer using the genesis sync batch size and conflict
+    /// policy from the commerce configuration
+    pub fn from_config(config: &CommerceConfig) -> SyncResult<Self> {
         Ok(Self {
-            local_catalog: HashMap::new(),
-            peer_catalogs: HashMap::new(),
-            sync_state:    SyncState {
-                last_full_sync: 0,
-                sync_watermark: 0,
-                active_syncs:   HashSet::new(),
-                stats:          SyncStats::default(),
-            },
-            pending_ops:   Vec::new(),
+            batch_size: config.genesis_sync_batch_size.max(1),
+            conflict_policy: config.genesis_conflict_policy,
+            ..Self::default()
         })
     }
 
@@ -176,6 +177,22 @@ impl P2PCatalogSync {
         Ok(())
     }
 
+    /// Schedule a merge of entries received from a peer, in batches of the
+    /// configured size
+    pub fn receive_from_peer(
+        &mut self, source_peer: String, entries: Vec<CatalogEntry>,
+    ) -> SyncResult<()> {
+        // Pending operations run last-in first-out, so queue the batches in
+        // reverse to merge them in the order received
+        let batches: Vec<Vec<CatalogEntry>> =
+            entries.chunks(self.batch_size).map(<[CatalogEntry]>::to_vec).collect();
+        for entries in batches.into_iter().rev() {
+            self.pending_ops
+                .push(SyncOperation::MergeCatalogs { source_peer: source_peer.clone(), entries });
+        }
+        Ok(())
+    }
+
     /// Process pending sync operations
     pub fn process_pending_ops(&mut self) -> SyncResult<Vec<SyncResult<()>>> {
         let mut results = Vec::new();
@@ -266,13 +283,22 @@ impl P2PCatalogSync {
     }
 
     /// Merge catalog from peer
+    ///
+    /// Entries whose payload does not match their content hash are dropped
+    /// and counted as sync failures.
     fn merge_catalog_from_peer(
         &mut self, _source_peer: &str, entries: Vec<CatalogEntry>,
     ) -> SyncResult<()> {
-        let entries_count = entries.len() as u64;
+        let mut entries_count = 0;
         let mut conflicts = Vec::new();
 
         for entry in entries {
+            if !entry.verify_content() {
+                self.sync_state.stats.sync_failures += 1;
+                continue;
+            }
+            entries_count += 1;
+
             if let Some(local_entry) = self.local_catalog.get(&entry.listing_id.0) {
                 // Check for conflicts
                 if local_entry.version != entry.version
@@ -282,7 +308,7 @@ impl P2PCatalogSync {
                         listing_id:     entry.listing_id.clone(),
                         local_version:  local_entry.clone(),
                         remote_version: entry,
-                        resolution:     ConflictResolution::Merge, // Default to merge
+                        resolution:     self.conflict_policy,
                     });
                 }
                 // If no conflict, update if remote is newer
@@ -324,19 +350,158 @@ impl P2PCatalogSync {
 impl Default for P2PCatalogSync {
     fn default() -> Self {
         Self {
-            local_catalog: HashMap::new(),
-            peer_catalogs: HashMap::new(),
-            sync_state:    SyncState {
+            local_catalog:   HashMap::new(),
+            peer_catalogs:   HashMap::new(),
+            sync_state:      SyncState {
                 last_full_sync: 0,
                 sync_watermark: 0,
                 active_syncs:   HashSet::new(),
                 stats:          SyncStats::default(),
             },
-            pending_ops:   Vec::new(),
+            pending_ops:     Vec::new(),
+            batch_size:      DEFAULT_SYNC_BATCH_SIZE,
+            conflict_policy: ConflictResolution::default(),
         }
     }
 }
 
+/// Hash of a listing payload, as stored in `CatalogEntry::content_hash`
+///
+/// SHA-256, hex encoded.
+pub fn content_hash(payload: &[u8]) -> String {
+    sha256(payload).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// SHA-256 round constants
+const SHA256_K: [u32; 64] = [
+    0x428a_2f98,
+    0x7137_4491,
+    0xb5c0_fbcf,
+    0xe9b5_dba5,
+    0x3956_c25b,
+    0x59f1_11f1,
+    0x923f_82a4,
+    0xab1c_5ed5,
+    0xd807_aa98,
+    0x1283_5b01,
+    0x2431_85be,
+    0x550c_7dc3,
+    0x72be_5d74,
+    0x80de_b1fe,
+    0x9bdc_06a7,
+    0xc19b_f174,
+    0xe49b_69c1,
+    0xefbe_4786,
+    0x0fc1_9dc6,
+    0x240c_a1cc,
+    0x2de9_2c6f,
+    0x4a74_84aa,
+    0x5cb0_a9dc,
+    0x76f9_88da,
+    0x983e_5152,
+    0xa831_c66d,
+    0xb003_27c8,
+    0xbf59_7fc7,
+    0xc6e0_0bf3,
+    0xd5a7_9147,
+    0x06ca_6351,
+    0x1429_2967,
+    0x27b7_0a85,
+    0x2e1b_2138,
+    0x4d2c_6dfc,
+    0x5338_0d13,
+    0x650a_7354,
+    0x766a_0abb,
+    0x81c2_c92e,
+    0x9272_2c85,
+    0xa2bf_e8a1,
+    0xa81a_664b,
+    0xc24b_8b70,
+    0xc76c_51a3,
+    0xd192_e819,
+    0xd699_0624,
+    0xf40e_3585,
+    0x106a_a070,
+    0x19a4_c116,
+    0x1e37_6c08,
+    0x2748_774c,
+    0x34b0_bcb5,
+    0x391c_0cb3,
+    0x4ed8_aa4a,
+    0x5b9c_ca4f,
+    0x682e_6ff3,
+    0x748f_82ee,
+    0x78a5_636f,
+    0x84c8_7814,
+    0x8cc7_0208,
+    0x90be_fffa,
+    0xa450_6ceb,
+    0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+/// SHA-256 digest (FIPS 180-4)
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09_e667,
+        0xbb67_ae85,
+        0x3c6e_f372,
+        0xa54f_f53a,
+        0x510e_527f,
+        0x9b05_688c,
+        0x1f83_d9ab,
+        0x5be0_cd19,
+    ];
+
+    // Pad with a one bit, zeros, and the message length in bits
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0_u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 =
+                h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0_u8; 32];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
 /// Get current timestamp
 fn current_timestamp() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
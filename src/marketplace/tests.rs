@@ -2,12 +2,121 @@
 //!
 //! End-to-end tests for the decentralized marketplace functionality.
 
-use crate::marketplace::*;
+use crate::{errors::MarketplaceError, marketplace::*};
 
 #[cfg(test)]
 mod integration_tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        thread,
+    };
+
     use super::*;
 
+    fn test_seller(node_id: &str) -> reviews::SellerProfile {
+        reviews::SellerProfile {
+            node_id:                node_id.to_string(),
+            display_name:           format!("Seller {}", node_id),
+            bio:                    String::new(),
+            avatar_hash:            None,
+            joined_at:              0,
+            vcs_reputation:         0,
+            marketplace_reputation: reviews::SellerReputation::default(),
+            active_listings:        0,
+            completed_orders:       0,
+            verification:           reviews::VerificationLevel::None,
+            specializations:        Vec::new(),
+        }
+    }
+
+    fn test_listing(seller: &str, title: &str, price_sats: u64) -> MarketplaceListing {
+        MarketplaceListing {
+            id:                ListingId::new(),
+            seller:            seller.to_string(),
+            category:          ListingCategory::Plugin,
+            title:             title.to_string(),
+            description:       format!("{} description", title),
+            short_description: String::new(),
+            pricing:           PricingModel::OneTime { price_sats },
+            tags:              Vec::new(),
+            previews:          Vec::new(),
+            version:           None,
+            repo_id:           None,
+            license:           LicenseType::OpenSource { spdx_id: "MIT".to_string() },
+            license_cap:       None,
+            created_at:        1,
+            updated_at:        1,
+            status:            ListingStatus::Active,
+            stats:             ListingStats {
+                views:        0,
+                purchases:    0,
+                avg_rating:   0.0,
+                review_count: 0,
+                revenue_sats: 0,
+            },
+            requirements:      None,
+            moderation:        ModerationStatus::Ok,
+            reports:           Vec::new(),
+            revision:          0,
+            change_log:        Vec::new(),
+        }
+    }
+
+    fn test_service() -> MarketplaceService {
+        MarketplaceService::new(Arc::new(VcsPaymentService)).expect("create service")
+    }
+
+    /// Settlement backend that accepts a fixed number of submissions and
+    /// rejects everything after that.
+    struct FlakySettlement {
+        remaining_successes: AtomicUsize,
+    }
+
+    impl escrow::EscrowSettlement for FlakySettlement {
+        fn submit_transaction(
+            &self, transaction: essentia_blockchain_plugin::Transaction,
+        ) -> Result<essentia_blockchain_plugin::Transaction, String> {
+            let remaining = self.remaining_successes.load(Ordering::SeqCst);
+            if remaining == 0 {
+                return Err("node unreachable".to_string());
+            }
+            self.remaining_successes.store(remaining - 1, Ordering::SeqCst);
+            Ok(transaction)
+        }
+    }
+
+    fn add_seller(service: &MarketplaceService, node_id: &str) {
+        service.register_seller(test_seller(node_id)).expect("register seller");
+    }
+
+    fn test_review(order: &Order, rating: u8) -> reviews::Review {
+        reviews::Review {
+            id: reviews::ReviewId::new(),
+            order_id: order.id.clone(),
+            listing_id: order.listing_id.clone(),
+            reviewer: order.buyer.clone(),
+            seller: order.seller.clone(),
+            rating,
+            category_ratings: reviews::CategoryRatings {
+                quality:       rating,
+                value:         rating,
+                communication: None,
+                timeliness:    None,
+                documentation: None,
+            },
+            text: "Imported review".to_string(),
+            pros: Vec::new(),
+            cons: Vec::new(),
+            created_at: 1,
+            verified_purchase: true,
+            helpful_count: 0,
+            seller_response: None,
+        }
+    }
+
     #[test]
     fn test_listing_id_creation() {
         let id1 = ListingId::new();
@@ -72,4 +181,1183 @@ mod integration_tests {
         let hash = delivery::ContentHash::new("test_hash".to_string());
         assert_eq!(hash.as_str(), "test_hash");
     }
+
+    #[test]
+    fn test_service_shared_across_threads() {
+        let service = Arc::new(test_service());
+        add_seller(&service, "seller-1");
+
+        let existing = test_listing("seller-1", "Rust linter plugin", 500);
+        let existing_id = service
+            .create_listing("seller-1".to_string(), existing)
+            .expect("create listing");
+
+        let reader = {
+            let service = Arc::clone(&service);
+            let existing_id = existing_id.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    let listing = service.get_listing(&existing_id).expect("get listing");
+                    assert_eq!(listing.title, "Rust linter plugin");
+                    service
+                        .search("linter", SearchFilters::default(), Pagination::default())
+                        .expect("search");
+                }
+            })
+        };
+
+        let writer = {
+            let service = Arc::clone(&service);
+            thread::spawn(move || {
+                let listing = test_listing("seller-1", "Theme pack", 1500);
+                service.create_listing("seller-1".to_string(), listing).expect("create listing")
+            })
+        };
+
+        reader.join().expect("reader thread");
+        let new_id = writer.join().expect("writer thread");
+
+        assert!(service.get_listing(&new_id).is_ok());
+        let seller = service.get_seller_profile("seller-1").expect("seller profile");
+        assert_eq!(seller.active_listings, 2);
+    }
+
+    #[test]
+    fn test_refund_without_plugin_updates_ledger() {
+        let mut manager = escrow::EscrowManager::new().expect("create manager");
+        let escrow_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                Vec::new(),
+            )
+            .expect("create escrow");
+
+        manager.refund_funds(&escrow_id, 4_000).expect("refund");
+
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.refunded_amount, 4_000);
+    }
+
+    #[test]
+    fn test_failed_refund_submission_leaves_ledger_unchanged() {
+        // The deposit succeeds, the refund submission fails
+        let settlement = FlakySettlement { remaining_successes: AtomicUsize::new(1) };
+        let mut manager =
+            escrow::EscrowManager::with_settlement(Box::new(settlement)).expect("create manager");
+        let escrow_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                Vec::new(),
+            )
+            .expect("create escrow");
+
+        let result = manager.refund_funds(&escrow_id, 4_000);
+        assert!(matches!(result, Err(MarketplaceError::EscrowError(_))));
+
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.refunded_amount, 0);
+        assert_eq!(account.status, escrow::EscrowStatus::Active);
+        assert!(account.refund_tx_id.is_none());
+    }
+
+    #[test]
+    fn test_arbitration_gates_release() {
+        let mut manager = escrow::EscrowManager::new().expect("create manager");
+        let escrow_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                vec![escrow::ReleaseCondition::BuyerApproval],
+            )
+            .expect("create escrow");
+
+        manager
+            .assign_arbitrator(&escrow_id, "arbitrator-1".to_string())
+            .expect("assign arbitrator");
+
+        // Release is blocked until the arbitrator decides
+        let result = manager.release_funds(&escrow_id, 10_000, "buyer");
+        assert!(matches!(
+            result,
+            Err(MarketplaceError::ReleaseConditionsNotMet)
+        ));
+
+        // Only the assigned arbitrator may decide
+        let result = manager.arbitrator_decide(
+            &escrow_id,
+            "impostor",
+            escrow::DisputeResolution::ReleaseToSeller(10_000),
+        );
+        assert!(matches!(
+            result,
+            Err(MarketplaceError::UnauthorizedArbitrator)
+        ));
+
+        // A decision that cannot be settled is not recorded
+        let result = manager.arbitrator_decide(
+            &escrow_id,
+            "arbitrator-1",
+            escrow::DisputeResolution::ReleaseToSeller(20_000),
+        );
+        assert!(result.is_err());
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert!(account.arbitrated_at.is_none());
+        assert!(matches!(
+            manager.release_funds(&escrow_id, 10_000, "buyer"),
+            Err(MarketplaceError::ReleaseConditionsNotMet)
+        ));
+
+        manager
+            .arbitrator_decide(
+                &escrow_id,
+                "arbitrator-1",
+                escrow::DisputeResolution::ReleaseToSeller(10_000),
+            )
+            .expect("arbitrator decision");
+
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.released_amount, 10_000);
+        assert_eq!(account.status, escrow::EscrowStatus::Released);
+        assert!(account.arbitrated_at.is_some());
+    }
+
+    #[test]
+    fn test_order_lifecycle_sets_timestamps() {
+        let mut order = Order::new(ListingId::new(), "buyer".into(), "seller".into(), 1_000);
+        assert_eq!(order.status, OrderStatus::Pending);
+
+        order.mark_paid().expect("pending -> paid");
+        assert!(order.paid_at.is_some());
+
+        order.mark_delivered().expect("paid -> delivered");
+        assert!(order.delivered_at.is_some());
+
+        order.mark_completed().expect("delivered -> completed");
+        assert_eq!(order.status, OrderStatus::Completed);
+        assert!(order.completed_at.is_some());
+    }
+
+    #[test]
+    fn test_order_rejects_illegal_transition() {
+        let mut order = Order::new(ListingId::new(), "buyer".into(), "seller".into(), 1_000);
+        order.mark_paid().expect("pending -> paid");
+
+        let err = order.mark_paid().expect_err("paid -> paid is illegal");
+        assert!(matches!(
+            err,
+            MarketplaceError::InvalidOrderTransition { .. }
+        ));
+        assert!(order.mark_completed().is_err());
+        assert_eq!(order.status, OrderStatus::Paid);
+    }
+
+    #[test]
+    fn test_import_reviews_skips_invalid_and_updates_reputation() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let listing_id = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Plugin", 1_000))
+            .expect("create listing");
+
+        let order = service.place_order(&listing_id, "buyer".into(), 1_000).expect("place order");
+
+        let report = service
+            .import_reviews(vec![
+                test_review(&order, 5),
+                test_review(&order, 3),
+                test_review(&order, 9),
+            ])
+            .expect("import reviews");
+
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.skipped.len(), 1);
+
+        let reputation =
+            service.get_seller_profile("seller-1").expect("seller").marketplace_reputation;
+        assert_eq!(reputation.review_count, 2);
+        assert_eq!(reputation.rating_distribution, [0, 0, 1, 0, 1]);
+        assert!((reputation.average_rating - 4.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_similar_listings_ranks_by_tag_overlap() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        add_seller(&service, "seller-2");
+
+        let tagged = |seller: &str, title: &str, tags: &[&str]| {
+            let mut listing = test_listing(seller, title, 1_000);
+            listing.tags = tags.iter().map(|t| t.to_string()).collect();
+            listing
+        };
+
+        let source_id = service
+            .create_listing(
+                "seller-1".into(),
+                tagged("seller-1", "Linter", &["rust", "lint", "ci"]),
+            )
+            .expect("create source");
+        let best_id = service
+            .create_listing(
+                "seller-2".into(),
+                tagged("seller-2", "Formatter", &["rust", "ci"]),
+            )
+            .expect("create best");
+        let weaker_id = service
+            .create_listing("seller-2".into(), tagged("seller-2", "Theme", &["rust"]))
+            .expect("create weaker");
+        let own_id = service
+            .create_listing(
+                "seller-1".into(),
+                tagged("seller-1", "Checker", &["rust", "lint", "ci"]),
+            )
+            .expect("create own");
+
+        let similar = service.similar_listings(&source_id, 10, true).expect("similar listings");
+        let ids: Vec<ListingId> = similar.into_iter().map(|l| l.id).collect();
+        assert_eq!(ids, vec![best_id, weaker_id]);
+
+        let similar = service.similar_listings(&source_id, 1, false).expect("similar listings");
+        assert_eq!(similar[0].id, own_id);
+    }
+
+    #[test]
+    fn test_rebuild_search_index_reflects_listing_store() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let id = service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Rust linter", 500),
+            )
+            .expect("create listing");
+
+        service.rebuild_search_index().expect("rebuild");
+        let results = service
+            .search("linter", SearchFilters::default(), Pagination::default())
+            .expect("search");
+        assert_eq!(results.total_count, 1);
+        assert_eq!(results.listings[0].id, id);
+
+        // An index that missed an edit to the stored listing
+        let mut index = search::SearchIndex::new().expect("create index");
+        let mut listing = service.get_listing(&id).expect("stored listing");
+        index.index_listing(&listing).expect("index listing");
+        listing.title = "Python formatter".to_string();
+        listing.description = "Formats code".to_string();
+
+        let search = |index: &search::SearchIndex, query: &str| {
+            index.search(query, &SearchFilters::default()).expect("search").len()
+        };
+        assert_eq!(search(&index, "formatter"), 0);
+
+        index.rebuild(std::slice::from_ref(&listing)).expect("rebuild");
+
+        assert_eq!(search(&index, "formatter"), 1);
+        assert_eq!(search(&index, "linter"), 0);
+    }
+
+    #[test]
+    fn test_compute_payouts_net_of_fees_in_window() {
+        let service = test_service().with_fee_calculator(FeeCalculator::new(500));
+
+        let completed = |seller: &str, total_sats: u64, completed_at: u64| {
+            let mut order = Order::new(ListingId::new(), "buyer".into(), seller.into(), total_sats);
+            order.status = OrderStatus::Completed;
+            order.completed_at = Some(completed_at);
+            order
+        };
+
+        let mut disputed = completed("seller-2", 7_000, 150);
+        disputed.status = OrderStatus::Disputed;
+
+        let imported = service
+            .import_orders(vec![
+                completed("seller-1", 10_000, 100),
+                completed("seller-1", 20_000, 199),
+                completed("seller-1", 40_000, 200),
+                completed("seller-2", 4_000, 150),
+                completed("seller-2", 8_000, 50),
+                disputed,
+            ])
+            .expect("import orders");
+        assert_eq!(imported, 6);
+
+        // Escrowed service orders, one refunded in full and one in part
+        add_seller(&service, "seller-1");
+        let mut consulting = test_listing("seller-1", "Consulting", 50_000);
+        consulting.category = ListingCategory::Consulting;
+        let consulting_id =
+            service.create_listing("seller-1".into(), consulting).expect("create listing");
+        let complete_escrowed = |refund_sats: u64| {
+            let mut order = service
+                .place_order(&consulting_id, "buyer".into(), 50_000)
+                .expect("place order");
+            order.mark_paid().expect("paid");
+            order.mark_delivered().expect("delivered");
+            order.mark_completed().expect("completed");
+            order.completed_at = Some(150);
+            service.update_order(order.clone()).expect("update order");
+            service.refund_order(&order.id, refund_sats).expect("refund");
+        };
+        complete_escrowed(50_000);
+        complete_escrowed(10_000);
+
+        let payouts = service.compute_payouts(100, 200).expect("payouts");
+        assert_eq!(payouts.len(), 2);
+        assert_eq!(payouts["seller-1"], 66_500); // 30_000 + 40_000 less 5%
+        assert_eq!(payouts["seller-2"], 3_800); // 4_000 less 5%
+    }
+
+    #[test]
+    fn test_create_listing_stamps_zero_timestamps() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+
+        let mut listing = test_listing("seller-1", "Fresh plugin", 500);
+        listing.created_at = 0;
+        listing.updated_at = 0;
+        let id = service.create_listing("seller-1".into(), listing).expect("create listing");
+
+        let stored = service.get_listing(&id).expect("get listing");
+        assert!(stored.created_at > 0);
+        assert_eq!(stored.updated_at, stored.created_at);
+    }
+
+    #[test]
+    fn test_create_listing_preserves_preset_timestamps() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+
+        let mut listing = test_listing("seller-1", "Imported plugin", 500);
+        listing.created_at = 1_000;
+        listing.updated_at = 2_000;
+        let id = service.create_listing("seller-1".into(), listing).expect("create listing");
+
+        let mut stored = service.get_listing(&id).expect("get listing");
+        assert_eq!((stored.created_at, stored.updated_at), (1_000, 2_000));
+
+        stored.title = "Imported plugin v2".to_string();
+        service.update_listing(stored).expect("update listing");
+        let updated = service.get_listing(&id).expect("get listing");
+        assert_eq!(updated.created_at, 1_000);
+        assert!(updated.updated_at > 2_000);
+    }
+
+    #[test]
+    fn test_on_review_added_updates_stats_and_rating_index() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let rated_id = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Rated", 1_000))
+            .expect("create rated");
+        service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Unrated", 1_000),
+            )
+            .expect("create unrated");
+
+        let order = Order::new(rated_id.clone(), "buyer".into(), "seller-1".into(), 1_000);
+        service.on_review_added(&test_review(&order, 5)).expect("first review");
+        service.on_review_added(&test_review(&order, 4)).expect("second review");
+
+        let stats = service.get_listing(&rated_id).expect("listing").stats;
+        assert_eq!(stats.review_count, 2);
+        assert!((stats.avg_rating - 4.5).abs() < f64::EPSILON);
+
+        let search = |query: &str, min_rating: f64| {
+            let filters = SearchFilters { min_rating: Some(min_rating), ..Default::default() };
+            service
+                .search(query, filters, Pagination::default())
+                .expect("search")
+                .listings
+                .into_iter()
+                .map(|l| l.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(search("", 4.0), vec![rated_id]);
+        assert!(search("", 4.6).is_empty());
+        // No listing matches both terms, so the rating filter has nothing to
+        // narrow rather than starting over
+        assert!(search("rated unrated", 4.0).is_empty());
+
+        assert!(matches!(
+            service.on_review_added(&test_review(&order, 0)),
+            Err(MarketplaceError::InvalidRating)
+        ));
+    }
+
+    #[test]
+    fn test_favorites_are_idempotent_and_pruned_on_removal() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let kept = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Kept", 1_000))
+            .expect("create kept");
+        let removed = service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Removed", 1_000),
+            )
+            .expect("create removed");
+
+        let favorites = service.favorites();
+        assert!(favorites.add("buyer", &kept).expect("add"));
+        assert!(!favorites.add("buyer", &kept).expect("add again"));
+        assert!(favorites.add("buyer", &removed).expect("add second"));
+
+        let mut expected = vec![kept.clone(), removed.clone()];
+        expected.sort();
+        assert_eq!(favorites.list("buyer").expect("list"), expected);
+        assert!(favorites.is_favorite("buyer", &kept).expect("is favorite"));
+        assert!(!favorites.is_favorite("other", &kept).expect("is favorite"));
+
+        service.remove_listing(&removed).expect("remove listing");
+
+        assert_eq!(favorites.list("buyer").expect("list"), vec![kept]);
+        assert!(!favorites.is_favorite("buyer", &removed).expect("is favorite"));
+        assert!(matches!(
+            service.get_listing(&removed),
+            Err(MarketplaceError::ListingNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_verify_all_flags_corrupted_escrow() {
+        let mut manager = escrow::EscrowManager::new().expect("create manager");
+        let valid_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                vec![escrow::ReleaseCondition::BuyerApproval],
+            )
+            .expect("create escrow");
+
+        manager.release_funds(&valid_id, 4_000, "buyer").expect("release");
+        manager.refund_funds(&valid_id, 6_000).expect("refund");
+        let valid = manager.get_escrow(&valid_id).expect("escrow exists").clone();
+        assert_eq!(valid.remaining(), 0);
+        assert!(valid.check_invariants().is_ok());
+
+        // A persisted ledger that settled more than it held
+        let mut corrupt = valid.clone();
+        corrupt.id = escrow::EscrowId::new();
+        corrupt.order_id = orders::OrderId::new();
+        corrupt.released_amount = 8_000;
+        corrupt.refunded_amount = 5_000;
+        assert_eq!(corrupt.remaining(), 0);
+        let corrupt_id = corrupt.id.clone();
+        manager.restore_escrow(corrupt).expect("restore escrow");
+
+        assert_eq!(manager.verify_all(), vec![corrupt_id]);
+        assert!(matches!(
+            manager.restore_escrow(valid),
+            Err(MarketplaceError::EscrowExists)
+        ));
+    }
+
+    #[test]
+    fn test_search_excludes_requesting_seller() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        add_seller(&service, "seller-2");
+        service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Rust linter", 500),
+            )
+            .expect("create own");
+        let other_id = service
+            .create_listing(
+                "seller-2".into(),
+                test_listing("seller-2", "Rust formatter", 500),
+            )
+            .expect("create other");
+
+        let filters =
+            SearchFilters { exclude_seller: Some("seller-1".to_string()), ..Default::default() };
+        let results = service.search("rust", filters, Pagination::default()).expect("search");
+
+        assert_eq!(results.total_count, 1);
+        assert_eq!(results.listings[0].id, other_id);
+        assert_eq!(results.listings[0].seller, "seller-2");
+    }
+
+    #[test]
+    fn test_empty_search_browses_active_listings() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let mut ids = Vec::new();
+        for title in ["Alpha", "Beta", "Gamma"] {
+            ids.push(
+                service
+                    .create_listing("seller-1".into(), test_listing("seller-1", title, 500))
+                    .expect("create listing"),
+            );
+        }
+        let mut paused = test_listing("seller-1", "Paused", 500);
+        paused.status = ListingStatus::Paused;
+        service.create_listing("seller-1".into(), paused).expect("create paused");
+        ids.sort();
+
+        let results = service
+            .search("", SearchFilters::default(), Pagination {
+                offset: 0,
+                limit:  2,
+            })
+            .expect("search");
+        assert_eq!(results.total_count, 3);
+        assert!(results.has_more);
+        let page: Vec<ListingId> = results.listings.into_iter().map(|l| l.id).collect();
+        assert_eq!(page, ids[..2].to_vec());
+    }
+
+    #[test]
+    fn test_merge_rejects_entry_with_mismatched_content_hash() {
+        let mut catalog_sync = sync::P2PCatalogSync::new().expect("create sync");
+        let entry = |title: &str, payload: &[u8]| sync::CatalogEntry {
+            listing_id:    ListingId::from_content_hash(title),
+            content_hash:  sync::content_hash(title.as_bytes()),
+            last_modified: 1,
+            version:       1,
+            status:        sync::EntryStatus::Active,
+            payload:       payload.to_vec(),
+        };
+
+        catalog_sync
+            .receive_from_peer("peer-1".to_string(), vec![
+                entry("genuine", b"genuine"),
+                entry("tampered", b"tampered with"),
+            ])
+            .expect("receive");
+        catalog_sync.process_pending_ops().expect("process");
+
+        let local = catalog_sync.get_local_catalog();
+        assert_eq!(local.len(), 1);
+        assert!(local.contains_key(&ListingId::from_content_hash("genuine").0));
+        let stats = catalog_sync.get_sync_stats();
+        assert_eq!(stats.sync_failures, 1);
+        assert_eq!(stats.listings_synced, 1);
+    }
+
+    #[test]
+    fn test_content_hash_is_sha256() {
+        assert_eq!(
+            sync::content_hash(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sync::content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Two blocks once padded
+        assert_eq!(
+            sync::content_hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_downloads_for_listing_aggregates_across_buyers() {
+        let mut delivery = delivery::ContentDeliveryService::new();
+        let listing_id = ListingId::new();
+        let hash = delivery::ContentHash::new("content".to_string());
+        delivery.register_provider(hash.clone(), "provider".to_string());
+
+        let alice = delivery
+            .grant_access("alice".into(), listing_id.clone(), hash.clone())
+            .expect("grant alice");
+        let bob = delivery
+            .grant_access("bob".into(), listing_id.clone(), hash)
+            .expect("grant bob");
+
+        for _ in 0..3 {
+            delivery.get_download(&alice.token, "alice").expect("alice download");
+        }
+        delivery.get_download(&bob.token, "bob").expect("bob download");
+        assert_eq!(delivery.downloads_for_listing(&listing_id), 4);
+
+        // Revoked tokens fail and do not count
+        delivery.revoke_access("bob", &listing_id);
+        assert!(delivery.get_download(&bob.token, "bob").is_err());
+        assert_eq!(delivery.downloads_for_listing(&listing_id), 4);
+        assert_eq!(delivery.downloads_for_listing(&ListingId::new()), 0);
+    }
+
+    #[test]
+    fn test_service_order_links_to_its_escrow() {
+        let service = test_service();
+        add_seller(&service, "seller");
+
+        let mut consulting = test_listing("seller", "Consulting", 50_000);
+        consulting.category = ListingCategory::Consulting;
+        let consulting_id = service
+            .create_listing("seller".into(), consulting)
+            .expect("create consulting listing");
+        let plugin_id = service
+            .create_listing("seller".into(), test_listing("seller", "Plugin", 10))
+            .expect("create plugin listing");
+
+        let order = service
+            .place_order(&consulting_id, "buyer".into(), 50_000)
+            .expect("place service order");
+        let escrow_id = order.escrow_id.clone().expect("service orders are escrowed");
+        let escrow =
+            service.order_escrow(&order.id).expect("lookup escrow").expect("escrow exists");
+        assert_eq!(escrow.id, escrow_id);
+        assert_eq!(escrow.order_id, order.id);
+        assert_eq!(escrow.total_amount, 50_000);
+
+        let order =
+            service.place_order(&plugin_id, "buyer".into(), 10).expect("place plugin order");
+        assert!(order.escrow_id.is_none());
+        assert!(service.order_escrow(&order.id).expect("lookup escrow").is_none());
+        assert!(matches!(
+            service.order_escrow(&orders::OrderId::new()),
+            Err(MarketplaceError::OrderNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_review_ratings_must_be_one_to_five() {
+        let order = Order::new(ListingId::new(), "buyer".into(), "seller".into(), 1_000);
+
+        assert!(reviews::CategoryRatings::new(1, 5).is_ok());
+        assert!(matches!(
+            reviews::CategoryRatings::new(0, 3),
+            Err(MarketplaceError::InvalidRating)
+        ));
+        assert!(reviews::CategoryRatings::new(3, 6).is_err());
+
+        let mut review = test_review(&order, 4);
+        assert!(review.validate_ratings().is_ok());
+        review.category_ratings.timeliness = Some(200);
+        assert!(matches!(
+            review.validate_ratings(),
+            Err(MarketplaceError::InvalidRating)
+        ));
+        assert!(test_review(&order, 0).validate_ratings().is_err());
+
+        assert!(orders::OrderReview::new(order.id.clone(), "buyer".into(), 5, "ok".into()).is_ok());
+        assert!(orders::OrderReview::new(order.id, "buyer".into(), 6, "bad".into()).is_err());
+    }
+
+    #[test]
+    fn test_import_reviews_skips_out_of_range_category_rating() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let listing_id = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Plugin", 1_000))
+            .expect("create listing");
+        let order = service.place_order(&listing_id, "buyer".into(), 1_000).expect("place order");
+
+        let mut review = test_review(&order, 5);
+        review.category_ratings.quality = 200;
+        let report = service.import_reviews(vec![review.clone()]).expect("import reviews");
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(matches!(
+            service.on_review_added(&review),
+            Err(MarketplaceError::InvalidRating)
+        ));
+    }
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        add_seller(&service, "seller-2");
+        let active = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Plugin", 1_000))
+            .expect("create listing");
+        let mut draft = test_listing("seller-2", "Theme", 500);
+        draft.status = ListingStatus::Draft;
+        service.create_listing("seller-2".into(), draft).expect("create listing");
+
+        let mut history = Vec::new();
+        for (total, disputed) in [(1_000, false), (3_000, false), (2_000, true)] {
+            let mut order = Order::new(active.clone(), "buyer".into(), "seller-1".into(), total);
+            order.mark_paid().expect("paid");
+            order.mark_delivered().expect("delivered");
+            if disputed {
+                order.mark_disputed().expect("disputed");
+            } else {
+                order.mark_completed().expect("completed");
+            }
+            history.push(order);
+        }
+        service.import_orders(history).expect("import orders");
+        service.place_order(&active, "buyer".into(), 1_000).expect("place order");
+
+        let metrics = service.metrics().expect("metrics");
+        assert_eq!(metrics.total_listings, 2);
+        assert_eq!(metrics.active_listings, 1);
+        assert_eq!(metrics.total_sellers, 2);
+        assert_eq!(metrics.total_orders, 4);
+        assert_eq!(metrics.gmv_sats, 4_000);
+        assert_eq!(metrics.avg_order_value, 2_000);
+        assert_eq!(metrics.open_disputes, 1);
+    }
+
+    #[test]
+    fn test_flagged_listing_hidden_until_reinstated() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let id = service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Rust linter", 1_000),
+            )
+            .expect("create listing");
+        let search_count = || {
+            service
+                .search("linter", SearchFilters::default(), Pagination::default())
+                .expect("search")
+                .total_count
+        };
+        assert_eq!(search_count(), 1);
+
+        let status = service.flag_listing(&id, "buyer-1", "malware").expect("flag");
+        assert_eq!(status, ModerationStatus::Flagged);
+        assert_eq!(search_count(), 0);
+        let listing = service.get_listing(&id).expect("listing");
+        assert_eq!(listing.reports.len(), 1);
+        assert_eq!(listing.reports[0].reporter, "buyer-1");
+        assert_eq!(listing.reports[0].reason, "malware");
+
+        service.review_listing(&id).expect("review");
+        assert_eq!(search_count(), 0);
+        service.reinstate_listing(&id).expect("reinstate");
+        assert_eq!(
+            service.get_listing(&id).expect("listing").moderation,
+            ModerationStatus::Ok
+        );
+        assert_eq!(search_count(), 1);
+
+        // Only flagged listings can be taken up for review
+        assert!(matches!(
+            service.review_listing(&id),
+            Err(MarketplaceError::InvalidModerationState)
+        ));
+    }
+
+    #[test]
+    fn test_removed_listing_excluded_from_search() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let id = service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Rust linter", 1_000),
+            )
+            .expect("create listing");
+
+        service.remove_for_moderation(&id).expect("remove");
+        let status = service.flag_listing(&id, "buyer-2", "still bad").expect("flag");
+
+        assert_eq!(status, ModerationStatus::Removed);
+        let results = service
+            .search("linter", SearchFilters::default(), Pagination::default())
+            .expect("search");
+        assert_eq!(results.total_count, 0);
+    }
+
+    #[test]
+    fn test_listing_edits_are_logged_with_price_history() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let id = service
+            .create_listing(
+                "seller-1".into(),
+                test_listing("seller-1", "Rust linter", 1_000),
+            )
+            .expect("create listing");
+
+        for price_sats in [800, 600] {
+            let mut listing = service.get_listing(&id).expect("listing");
+            listing.pricing = PricingModel::OneTime { price_sats };
+            service.update_listing(listing).expect("update");
+        }
+        // Untracked edits do not bump the revision
+        let mut listing = service.get_listing(&id).expect("listing");
+        listing.tags.push("lint".to_string());
+        service.update_listing(listing).expect("update");
+
+        let listing = service.get_listing(&id).expect("listing");
+        assert_eq!(listing.revision, 2);
+        assert_eq!(listing.change_log.len(), 2);
+        assert_eq!(listing.change_log[1].revision, 2);
+        assert_eq!(listing.change_log[0].changes, vec![
+            ListingFieldChange::Pricing {
+                from: PricingModel::OneTime { price_sats: 1_000 },
+                to:   PricingModel::OneTime { price_sats: 800 },
+            }
+        ]);
+
+        let prices: Vec<PricingModel> =
+            listing.price_history().into_iter().map(|(_, pricing)| pricing).collect();
+        assert_eq!(prices, vec![
+            PricingModel::OneTime { price_sats: 1_000 },
+            PricingModel::OneTime { price_sats: 800 },
+            PricingModel::OneTime { price_sats: 600 },
+        ]);
+        assert_eq!(listing.price_history()[0].0, listing.created_at);
+    }
+
+    #[test]
+    fn test_escrow_rejects_zero_and_excess_amounts() {
+        let mut manager = escrow::EscrowManager::new().expect("create manager");
+        let escrow_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                Vec::new(),
+            )
+            .expect("create escrow");
+
+        let result = manager.release_funds(&escrow_id, 0, "buyer");
+        assert!(matches!(result, Err(MarketplaceError::AmountRequired)));
+        let result = manager.refund_funds(&escrow_id, 0);
+        assert!(matches!(result, Err(MarketplaceError::AmountRequired)));
+
+        manager.refund_funds(&escrow_id, 4_000).expect("refund");
+        let result = manager.release_funds(&escrow_id, 6_001, "buyer");
+        assert!(matches!(
+            result,
+            Err(MarketplaceError::AmountExceedsAvailable { requested: 6_001, available: 6_000 })
+        ));
+
+        // Exactly the remaining balance is fine
+        manager.release_funds(&escrow_id, 6_000, "buyer").expect("release");
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.released_amount, 6_000);
+        assert_eq!(account.remaining(), 0);
+    }
+
+    #[test]
+    fn test_split_resolution_over_balance_applies_nothing() {
+        let mut manager = escrow::EscrowManager::new().expect("create manager");
+        let escrow_id = manager
+            .create_escrow(
+                orders::OrderId::new(),
+                "buyer".to_string(),
+                "seller".to_string(),
+                10_000,
+                Vec::new(),
+            )
+            .expect("create escrow");
+        manager.raise_dispute(&escrow_id).expect("dispute");
+
+        let result = manager.resolve_dispute(&escrow_id, escrow::DisputeResolution::Split {
+            seller_amount: 8_000,
+            buyer_amount:  4_000,
+        });
+        assert!(matches!(
+            result,
+            Err(MarketplaceError::AmountExceedsAvailable { .. })
+        ));
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.released_amount, 0);
+        assert_eq!(account.refunded_amount, 0);
+
+        // A one-sided split skips the zero half
+        manager
+            .resolve_dispute(&escrow_id, escrow::DisputeResolution::Split {
+                seller_amount: 0,
+                buyer_amount:  10_000,
+            })
+            .expect("resolve");
+        let account = manager.get_escrow(&escrow_id).expect("escrow exists");
+        assert_eq!(account.refunded_amount, 10_000);
+        assert_eq!(account.status, escrow::EscrowStatus::Resolved);
+    }
+
+    #[test]
+    fn test_register_seller_then_create_listing() {
+        let service = test_service();
+
+        let mut blank = test_seller("node-blank");
+        blank.display_name = "  ".to_string();
+        assert!(matches!(
+            service.register_seller(blank),
+            Err(MarketplaceError::InvalidSellerProfile)
+        ));
+
+        let mut profile = test_seller("node-1");
+        profile.active_listings = 7;
+        profile.marketplace_reputation.review_count = 12;
+        service.register_seller(profile).expect("register seller");
+        assert!(matches!(
+            service.register_seller(test_seller("node-1")),
+            Err(MarketplaceError::SellerExists)
+        ));
+
+        let registered = service.get_seller_profile("node-1").expect("seller exists");
+        assert!(registered.joined_at > 0);
+        assert_eq!(registered.active_listings, 0);
+        assert_eq!(registered.marketplace_reputation.review_count, 0);
+
+        service
+            .create_listing("node-1".to_string(), test_listing("node-1", "Linter", 500))
+            .expect("create listing");
+        let updated = service
+            .update_seller_profile("node-1", reviews::SellerProfileUpdate {
+                bio: Some("Rust tooling".to_string()),
+                specializations: Some(vec!["linting".to_string()]),
+                ..Default::default()
+            })
+            .expect("update profile");
+        assert_eq!(updated.bio, "Rust tooling");
+        assert_eq!(updated.specializations, vec!["linting".to_string()]);
+        assert_eq!(updated.active_listings, 1);
+    }
+
+    #[test]
+    fn test_top_review_prefers_helpful_verified_then_newest() {
+        let service = test_service();
+        add_seller(&service, "seller-1");
+        let listing_id = service
+            .create_listing("seller-1".into(), test_listing("seller-1", "Tool", 1_000))
+            .expect("create listing");
+        let order = Order::new(listing_id.clone(), "buyer".into(), "seller-1".into(), 1_000);
+
+        assert!(service.top_review(&listing_id).expect("top review").is_none());
+
+        let mut unverified = test_review(&order, 5);
+        unverified.verified_purchase = false;
+        unverified.helpful_count = 50;
+        let mut older = test_review(&order, 4);
+        older.helpful_count = 8;
+        older.created_at = 10;
+        let mut newer = test_review(&order, 3);
+        newer.helpful_count = 8;
+        newer.created_at = 20;
+        let mut less_helpful = test_review(&order, 5);
+        less_helpful.helpful_count = 2;
+        for review in [&unverified, &older, &newer, &less_helpful] {
+            service.on_review_added(review).expect("add review");
+        }
+
+        let top = service.top_review(&listing_id).expect("top review").expect("has review");
+        assert_eq!(top.id, newer.id);
+    }
+
+    #[test]
+    fn test_review_excerpt_cuts_at_word_boundary() {
+        let text = "Solid plugin that saved our team hours of manual release work";
+        assert_eq!(reviews::review_excerpt(text, 100), text);
+        assert_eq!(
+            reviews::review_excerpt(text, 20),
+            "Solid plugin that\u{2026}"
+        );
+        assert!(reviews::review_excerpt(text, 20).chars().count() <= 20);
+        // A single long word is cut mid-word
+        assert_eq!(
+            reviews::review_excerpt("Supercalifragilistic", 6),
+            "Super\u{2026}"
+        );
+    }
+
+    #[test]
+    fn test_place_order_checks_amount_against_pricing() {
+        let service = test_service();
+        add_seller(&service, "seller");
+
+        let fixed_id = service
+            .create_listing("seller".into(), test_listing("seller", "Fixed", 1_000))
+            .expect("create fixed");
+        assert!(matches!(
+            service.place_order(&fixed_id, "buyer".into(), 999),
+            Err(MarketplaceError::AmountMismatch { expected: 1_000, got: 999 })
+        ));
+        assert!(matches!(
+            service.place_order(&fixed_id, "buyer".into(), 1_001),
+            Err(MarketplaceError::AmountMismatch { .. })
+        ));
+        service.place_order(&fixed_id, "buyer".into(), 1_000).expect("exact amount");
+
+        let mut flexible = test_listing("seller", "Flexible", 0);
+        flexible.pricing =
+            PricingModel::PayWhatYouWant { minimum_sats: 500, suggested_sats: 2_000 };
+        assert_eq!(flexible.pricing.required_amount(), None);
+        let flexible_id = service.create_listing("seller".into(), flexible).expect("create pwyw");
+        assert!(matches!(
+            service.place_order(&flexible_id, "buyer".into(), 499),
+            Err(MarketplaceError::BelowMinimum)
+        ));
+        service.place_order(&flexible_id, "buyer".into(), 500).expect("minimum");
+        service.place_order(&flexible_id, "buyer".into(), 7_777).expect("above minimum");
+    }
+
+    #[test]
+    fn test_punctuation_only_query_applies_filters_only() {
+        let service = test_service();
+        add_seller(&service, "seller");
+        let plugin_id = service
+            .create_listing("seller".into(), test_listing("seller", "Formatter", 1_000))
+            .expect("create plugin");
+        let mut theme = test_listing("seller", "Dark Theme", 1_000);
+        theme.category = ListingCategory::Theme;
+        service.create_listing("seller".into(), theme).expect("create theme");
+
+        let filters =
+            SearchFilters { category: Some(ListingCategory::Plugin), ..Default::default() };
+        let results = service.search("!!!", filters, Pagination::default()).expect("search");
+        let ids: Vec<_> = results.listings.iter().map(|l| l.id.clone()).collect();
+        assert_eq!(ids, vec![plugin_id]);
+
+        // Without filters it browses like an empty query
+        let results = service
+            .search("?!", SearchFilters::default(), Pagination::default())
+            .expect("search");
+        assert_eq!(results.total_count, 2);
+    }
+
+    #[test]
+    fn test_sync_from_config_batches_entries_and_applies_conflict_policy() {
+        let config = crate::types::CommerceConfig {
+            genesis_sync_batch_size: 2,
+            genesis_conflict_policy: crate::types::ConflictResolution::KeepLocal,
+            ..Default::default()
+        };
+        let mut catalog_sync = sync::P2PCatalogSync::from_config(&config).expect("create sync");
+        let entry = |title: &str, version: u64| sync::CatalogEntry {
+            listing_id: ListingId::from_content_hash(title),
+            content_hash: sync::content_hash(title.as_bytes()),
+            last_modified: version,
+            version,
+            status: sync::EntryStatus::Active,
+            payload: title.as_bytes().to_vec(),
+        };
+
+        catalog_sync.add_local_entry(entry("shared", 1)).expect("add local");
+        let remote: Vec<_> = ["a", "b", "c", "d"].into_iter().map(|t| entry(t, 1)).collect();
+        catalog_sync.receive_from_peer("peer-1".to_string(), remote).expect("receive");
+        catalog_sync
+            .receive_from_peer("peer-1".to_string(), vec![entry("shared", 2)])
+            .expect("receive");
+
+        // Four entries in batches of two, plus the conflicting entry
+        let results = catalog_sync.process_pending_ops().expect("process");
+        assert_eq!(results.len(), 4);
+        let local = catalog_sync.get_local_catalog();
+        assert_eq!(local.len(), 5);
+        assert_eq!(local[&ListingId::from_content_hash("shared").0].version, 1);
+    }
+
+    #[test]
+    fn test_listings_needing_attention_reports_each_reason() {
+        let service = test_service();
+        add_seller(&service, "seller");
+        add_seller(&service, "other");
+        let now = 30 * 24 * 60 * 60;
+        let viewed = |mut listing: MarketplaceListing| {
+            listing.stats.views = 10;
+            listing
+        };
+
+        let mut capped = viewed(test_listing("seller", "Capped", 10));
+        capped.license_cap = Some(100);
+        capped.stats.purchases = 97;
+        let mut unviewed = test_listing("seller", "Unviewed", 10);
+        unviewed.created_at = now - UNVIEWED_LISTING_DAYS * 24 * 60 * 60;
+        let mut flagged = viewed(test_listing("seller", "Flagged", 10));
+        flagged.moderation = ModerationStatus::Flagged;
+        let mut paused = viewed(test_listing("seller", "Paused", 10));
+        paused.status = ListingStatus::Paused;
+
+        let mut roomy = viewed(test_listing("seller", "Roomy", 10));
+        roomy.license_cap = Some(100);
+        let mut fresh = test_listing("seller", "Fresh", 10);
+        fresh.created_at = now - 60;
+        let healthy = [roomy, fresh, test_listing("other", "Someone else's", 10)];
+        for listing in healthy {
+            let seller = listing.seller.clone();
+            service.create_listing(seller, listing).expect("create healthy listing");
+        }
+
+        let mut expected = Vec::new();
+        for (listing, reason) in [
+            (capped, AttentionReason::LowLicenses { remaining: 3 }),
+            (unviewed, AttentionReason::NoViews),
+            (flagged, AttentionReason::Flagged),
+            (paused, AttentionReason::Paused),
+        ] {
+            expected.push((
+                service.create_listing("seller".into(), listing).expect("create listing"),
+                reason,
+            ));
+        }
+        expected.sort();
+
+        assert_eq!(
+            service.listings_needing_attention("seller", now).expect("attention list"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_place_order_stops_at_license_cap() {
+        let service = test_service();
+        add_seller(&service, "seller");
+        let mut capped = test_listing("seller", "Capped", 1_000);
+        capped.license_cap = Some(2);
+        let id = service.create_listing("seller".into(), capped).expect("create listing");
+
+        service.place_order(&id, "buyer-1".into(), 1_000).expect("first license");
+        service.place_order(&id, "buyer-2".into(), 1_000).expect("second license");
+        assert!(matches!(
+            service.place_order(&id, "buyer-3".into(), 1_000),
+            Err(MarketplaceError::LicenseCapReached)
+        ));
+
+        let listing = service.get_listing(&id).expect("get listing");
+        assert_eq!(listing.stats.purchases, 2);
+        assert_eq!(listing.licenses_remaining(), Some(0));
+    }
+
+    #[test]
+    fn test_category_counts_only_include_active_listings() {
+        let service = test_service();
+        add_seller(&service, "seller");
+        let listing = |title: &str, category: ListingCategory| {
+            let mut listing = test_listing("seller", title, 10);
+            listing.category = category;
+            listing
+        };
+
+        for title in ["Plugin A", "Plugin B"] {
+            service
+                .create_listing("seller".into(), listing(title, ListingCategory::Plugin))
+                .expect("create plugin listing");
+        }
+        let mut paused = listing("Paused plugin", ListingCategory::Plugin);
+        paused.status = ListingStatus::Paused;
+        service.create_listing("seller".into(), paused).expect("create paused listing");
+        service
+            .create_listing("seller".into(), listing("Theme", ListingCategory::Theme))
+            .expect("create theme listing");
+        let removed = service
+            .create_listing("seller".into(), listing("Course", ListingCategory::Course))
+            .expect("create course listing");
+        service.remove_for_moderation(&removed).expect("remove listing");
+
+        let counts = service.category_counts().expect("category counts");
+        assert_eq!(counts.get(&ListingCategory::Plugin), Some(&2));
+        assert_eq!(counts.get(&ListingCategory::Theme), Some(&1));
+        assert_eq!(counts.get(&ListingCategory::Course), None);
+    }
 }
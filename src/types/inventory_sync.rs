@@ -2,7 +2,7 @@
 //!
 //! Type definitions for real-time inventory synchronization and management.
 
-use crate::types::product_catalog::{ProductId, Sku};
+use crate::types::product_catalog::{BundleComponent, ProductId, Sku};
 
 // ============================================================================
 // CORE TYPES
@@ -59,6 +59,12 @@ pub struct InventoryLocation {
     pub can_ship:             bool,
     /// Whether location allows in-store pickup.
     pub allows_pickup:        bool,
+    /// Latitude in degrees.
+    pub latitude:             Option<f64>,
+    /// Longitude in degrees.
+    pub longitude:            Option<f64>,
+    /// Estimated days from dispatch to delivery, if known.
+    pub estimated_days:       Option<u32>,
 }
 
 impl InventoryLocation {
@@ -78,6 +84,9 @@ impl InventoryLocation {
             fulfillment_priority: 1,
             can_ship: true,
             allows_pickup: false,
+            latitude: None,
+            longitude: None,
+            estimated_days: None,
         }
     }
 
@@ -97,6 +106,62 @@ impl InventoryLocation {
             fulfillment_priority: 10,
             can_ship: true,
             allows_pickup: true,
+            latitude: None,
+            longitude: None,
+            estimated_days: None,
+        }
+    }
+
+    /// Sets the location's coordinates.
+    #[must_use]
+    pub fn with_coordinates(mut self, latitude: f64, longitude: f64) -> Self {
+        self.latitude = Some(latitude);
+        self.longitude = Some(longitude);
+        self
+    }
+
+    /// Sets the estimated delivery time in days.
+    #[must_use]
+    pub fn with_delivery_estimate(mut self, days: u32) -> Self {
+        self.estimated_days = Some(days);
+        self
+    }
+
+    /// Great-circle distance in kilometres to the given point, if the
+    /// location is geocoded.
+    #[must_use]
+    pub fn distance_km_to(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+
+        let (lat1, lng1) = (self.latitude?.to_radians(), self.longitude?.to_radians());
+        let (lat2, lng2) = (latitude.to_radians(), longitude.to_radians());
+
+        let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * ((lng2 - lng1) / 2.0).sin().powi(2);
+        Some(2.0 * EARTH_RADIUS_KM * a.sqrt().asin())
+    }
+}
+
+/// How an order reaches the customer.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FulfillmentType {
+    /// Shipped from any location that can ship.
+    #[default]
+    Ship,
+    /// Collected in person at the given pickup location.
+    Pickup(LocationId),
+}
+
+impl FulfillmentType {
+    /// Whether stock at `location` can serve this kind of fulfillment.
+    #[must_use]
+    pub fn accepts(&self, location: &InventoryLocation) -> bool {
+        if !location.is_active {
+            return false;
+        }
+        match self {
+            Self::Ship => location.can_ship,
+            Self::Pickup(store) => location.allows_pickup && location.id == *store,
         }
     }
 }
@@ -133,6 +198,8 @@ pub struct InventoryLevel {
     pub on_hand:             i64,
     /// Incoming quantity (on order from supplier).
     pub incoming:            i64,
+    /// Quantity dispatched here by a transfer but not yet received.
+    pub in_transit:          i64,
     /// Damaged/unsellable quantity.
     pub damaged:             i64,
     /// Low stock threshold.
@@ -166,6 +233,7 @@ impl InventoryLevel {
             committed: 0,
             on_hand: 0,
             incoming: 0,
+            in_transit: 0,
             damaged: 0,
             low_stock_threshold: 10,
             reorder_point: 20,
@@ -176,6 +244,13 @@ impl InventoryLevel {
         }
     }
 
+    /// Sets the variant the level tracks.
+    #[must_use]
+    pub fn with_variant(mut self, variant_id: Option<ProductId>) -> Self {
+        self.variant_id = variant_id;
+        self
+    }
+
     /// Whether stock is low.
     #[must_use]
     pub fn is_low_stock(&self) -> bool {
@@ -188,6 +263,13 @@ impl InventoryLevel {
         self.available <= 0
     }
 
+    /// Available-to-promise quantity: sellable stock plus stock in transit
+    /// to this location.
+    #[must_use]
+    pub fn available_to_promise(&self) -> i64 {
+        self.available.saturating_add(self.in_transit)
+    }
+
     /// Whether reorder is needed.
     #[must_use]
     pub fn needs_reorder(&self) -> bool {
@@ -220,6 +302,30 @@ impl std::fmt::Display for InventoryLevel {
     }
 }
 
+/// Stock thresholds of an inventory level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StockThresholds {
+    /// Low stock threshold.
+    pub low_stock_threshold: u32,
+    /// Reorder point.
+    pub reorder_point:       u32,
+    /// Reorder quantity.
+    pub reorder_quantity:    u32,
+    /// Safety stock level.
+    pub safety_stock:        u32,
+}
+
+impl Default for StockThresholds {
+    fn default() -> Self {
+        Self {
+            low_stock_threshold: 10,
+            reorder_point:       20,
+            reorder_quantity:    50,
+            safety_stock:        5,
+        }
+    }
+}
+
 // ============================================================================
 // INVENTORY ADJUSTMENT
 // ============================================================================
@@ -306,6 +412,13 @@ impl InventoryAdjustment {
         }
     }
 
+    /// Sets the variant the adjustment applies to.
+    #[must_use]
+    pub fn with_variant(mut self, variant_id: Option<ProductId>) -> Self {
+        self.variant_id = variant_id;
+        self
+    }
+
     /// Sets reference.
     #[must_use]
     pub fn with_reference(mut self, reference: impl Into<String>) -> Self {
@@ -321,6 +434,17 @@ impl InventoryAdjustment {
     }
 }
 
+/// Stock held by outstanding reservations, from the adjustment ledger.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReservedSummary {
+    /// Total quantity still reserved.
+    pub total_reserved: i64,
+    /// Quantity reserved longer ago than the service's stale age.
+    pub stale_reserved: i64,
+    /// Quantity still reserved per reference.
+    pub by_reference:   std::collections::HashMap<String, i64>,
+}
+
 // ============================================================================
 // STOCK TRANSFER
 // ============================================================================
@@ -400,14 +524,17 @@ impl StockTransfer {
         }
     }
 
-    /// Adds an item to the transfer.
+    /// Adds a product without a variant to the transfer.
     pub fn add_item(&mut self, product_id: ProductId, quantity: u32) {
-        self.items.push(TransferItem {
-            product_id,
-            variant_id: None,
-            quantity,
-            quantity_received: 0,
-        });
+        self.add_variant_item(product_id, None, quantity);
+    }
+
+    /// Adds a product, or one of its variants, to the transfer.
+    pub fn add_variant_item(
+        &mut self, product_id: ProductId, variant_id: Option<ProductId>, quantity: u32,
+    ) {
+        self.items
+            .push(TransferItem { product_id, variant_id, quantity, quantity_received: 0 });
         self.touch();
     }
 
@@ -504,12 +631,44 @@ pub struct SyncResult {
     pub items_failed:    u32,
     /// Error messages.
     pub errors:          Vec<String>,
+    /// Per-change outcomes, in the order the changes were given.
+    pub details:         Vec<SyncChangeResult>,
+    /// Applied changes that moved on-hand stock by more than the
+    /// discrepancy threshold, as (product, prior on-hand, new on-hand).
+    pub discrepancies:   Vec<(ProductId, i64, i64)>,
     /// Sync timestamp.
     pub synced_at:       u64,
     /// Duration in milliseconds.
     pub duration_ms:     u64,
 }
 
+/// Outcome of a single synced inventory change.
+#[derive(Debug, Clone)]
+pub struct SyncChangeResult {
+    /// Product ID (or external ID).
+    pub product_id:  String,
+    /// Location ID (or external ID).
+    pub location_id: String,
+    /// What happened to the change.
+    pub outcome:     SyncChangeOutcome,
+}
+
+/// What happened to a synced inventory change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncChangeOutcome {
+    /// On-hand quantity was updated.
+    Updated {
+        /// Previous on-hand quantity.
+        from: i64,
+        /// New on-hand quantity.
+        to:   i64,
+    },
+    /// Change was not applied, e.g. because it is older than local data.
+    Skipped(String),
+    /// Change could not be applied.
+    Failed(String),
+}
+
 /// Inventory change for sync.
 #[derive(Debug, Clone)]
 pub struct InventoryChange {
@@ -552,7 +711,14 @@ pub struct InventoryService {
     pub locations:
         std::sync::Arc<std::sync::Mutex<std::collections::HashMap<LocationId, InventoryLocation>>>,
     /// Adjustment history.
-    pub adjustments: std::sync::Arc<std::sync::Mutex<Vec<InventoryAdjustment>>>,
+    pub adjustments:                std::sync::Arc<std::sync::Mutex<Vec<InventoryAdjustment>>>,
+    /// Net quantity reserved per reference and key, updated with every
+    /// adjustment appended to `adjustments`.
+    pub reserved_by_reference: std::sync::Arc<
+        std::sync::Mutex<
+            std::collections::HashMap<String, std::collections::HashMap<InventoryKey, i64>>,
+        >,
+    >,
     /// Pending transfers.
     pub transfers:
         std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, StockTransfer>>>,
@@ -560,6 +726,17 @@ pub struct InventoryService {
     pub sources: std::sync::Arc<
         std::sync::Mutex<std::collections::HashMap<String, ExternalInventorySource>>,
     >,
+    /// Seconds after which an outstanding reservation counts as stale.
+    pub stale_reservation_secs:     u64,
+    /// On-hand change from a single synced update above which the update is
+    /// reported as a discrepancy.
+    pub sync_discrepancy_threshold: u64,
+    /// Whether synced changes for unknown locations fail rather than
+    /// creating a level there.
+    pub sync_known_locations_only:  bool,
+    /// Whether synced changes older than the local level's last update are
+    /// skipped rather than applied.
+    pub sync_skip_stale_changes:    bool,
 }
 
 /// Key for inventory level lookup.
@@ -572,3 +749,46 @@ pub struct InventoryKey {
     /// Location ID.
     pub location_id: LocationId,
 }
+
+impl InventoryKey {
+    /// Creates a new key for a product, or one of its variants, at a location.
+    #[must_use]
+    pub fn new(
+        product_id: ProductId, variant_id: Option<ProductId>, location_id: LocationId,
+    ) -> Self {
+        Self { product_id, variant_id, location_id }
+    }
+
+    /// Stock `quantity` bundles draw on at a location, per component.
+    #[must_use]
+    pub fn for_components(
+        components: &[BundleComponent], location_id: &LocationId, quantity: u32,
+    ) -> Vec<(Self, u32)> {
+        components
+            .iter()
+            .map(|c| {
+                let key = Self::new(
+                    c.product_id.clone(),
+                    c.variant_id.clone(),
+                    location_id.clone(),
+                );
+                (key, c.quantity.saturating_mul(quantity))
+            })
+            .collect()
+    }
+
+    /// Stock a sale line draws on at a location: its bundle components, or
+    /// the product itself.
+    #[must_use]
+    pub fn for_line(
+        product_id: &ProductId, variant_id: Option<&ProductId>, components: &[BundleComponent],
+        location_id: &LocationId, quantity: u32,
+    ) -> Vec<(Self, u32)> {
+        if components.is_empty() {
+            let key = Self::new(product_id.clone(), variant_id.cloned(), location_id.clone());
+            vec![(key, quantity)]
+        } else {
+            Self::for_components(components, location_id, quantity)
+        }
+    }
+}
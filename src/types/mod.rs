@@ -4,7 +4,7 @@ use std::fmt::Debug;
 
 use essentia_api::implementation::commerce::BusinessEntity;
 
-use crate::errors::CommerceError;
+use crate::{errors::CommerceError, types::product_catalog::Currency};
 
 /// Genesis Directory Node for commerce operations
 #[derive(Debug, Clone)]
@@ -43,6 +43,29 @@ impl GenesisDirectory {
     pub fn query(&self, filter: impl Fn(&BusinessEntity) -> bool) -> Vec<&BusinessEntity> {
         self.entities.iter().filter(|e| filter(e)).collect()
     }
+
+    /// Query business entities with at least the given coherence score
+    pub fn query_by_min_coherence(&self, min: f64) -> Vec<&BusinessEntity> {
+        self.query(|e| e.coherence_score >= min)
+    }
+
+    /// The `n` most coherent business entities, highest score first
+    pub fn top_by_coherence(&self, n: usize) -> Vec<&BusinessEntity> {
+        let mut entities = self.query(|_| true);
+        entities.sort_by(|a, b| b.coherence_score.total_cmp(&a.coherence_score));
+        entities.truncate(n);
+        entities
+    }
+
+    /// Mean coherence score, or `None` if no entities are registered
+    #[must_use]
+    pub fn average_coherence(&self) -> Option<f64> {
+        if self.entities.is_empty() {
+            return None;
+        }
+        let total: f64 = self.entities.iter().map(|e| e.coherence_score).sum();
+        Some(total / self.entities.len() as f64)
+    }
 }
 
 impl Default for GenesisDirectory {
@@ -51,32 +74,93 @@ impl Default for GenesisDirectory {
     }
 }
 
+/// Default number of peer entries merged per batch
+pub const DEFAULT_SYNC_BATCH_SIZE: usize = 100;
+
+/// Conflict resolution strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictResolution {
+    /// Keep local version
+    KeepLocal,
+    /// Use remote version
+    UseRemote,
+    /// Merge versions (if possible)
+    #[default]
+    Merge,
+    /// Manual resolution required
+    Manual,
+}
+
+impl ConflictResolution {
+    /// Every strategy, in configuration display order
+    pub const ALL: [Self; 4] = [Self::KeepLocal, Self::UseRemote, Self::Merge, Self::Manual];
+
+    /// Configuration name of the strategy
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::KeepLocal => "keep_local",
+            Self::UseRemote => "use_remote",
+            Self::Merge => "merge",
+            Self::Manual => "manual",
+        }
+    }
+
+    /// Parse a configuration name written by [`Self::as_str`]
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|policy| policy.as_str() == name)
+    }
+}
+
 /// Commerce configuration for `FlexForge` panel
 #[derive(Debug, Clone)]
 pub struct CommerceConfig {
     /// Enable marketplace functionality
-    pub marketplace_enabled: bool,
+    pub marketplace_enabled:     bool,
     /// Enable affiliate program
-    pub affiliate_enabled:   bool,
+    pub affiliate_enabled:       bool,
     /// Default currency for transactions
-    pub currency:            String,
+    pub currency:                String,
     /// Transaction fee percentage
-    pub fee_percentage:      f64,
+    pub fee_percentage:          f64,
     /// Enable genesis synchronization
-    pub genesis_sync:        bool,
+    pub genesis_sync:            bool,
+    /// Peer catalog entries merged per genesis sync batch
+    pub genesis_sync_batch_size: usize,
+    /// Resolution for catalog conflicts found during genesis sync
+    pub genesis_conflict_policy: ConflictResolution,
     /// Enable automatic verification
-    pub auto_verify:         bool,
+    pub auto_verify:             bool,
+}
+
+impl CommerceConfig {
+    /// Parse the configured currency code
+    ///
+    /// # Errors
+    ///
+    /// Returns `CommerceError::UnsupportedCurrency` if the code has no known
+    /// decimals.
+    pub fn currency_typed(&self) -> Result<Currency, CommerceError> {
+        let currency = Currency::new(self.currency.trim().to_uppercase());
+        match currency.decimals() {
+            Some(_) => Ok(currency),
+            None => Err(CommerceError::UnsupportedCurrency(self.currency.clone())),
+        }
+    }
 }
 
 impl Default for CommerceConfig {
     fn default() -> Self {
         Self {
-            marketplace_enabled: true,
-            affiliate_enabled:   true,
-            currency:            "ESS".to_string(),
-            fee_percentage:      2.5,
-            genesis_sync:        true,
-            auto_verify:         false,
+            marketplace_enabled:     true,
+            affiliate_enabled:       true,
+            currency:                "ESS".to_string(),
+            fee_percentage:          2.5,
+            genesis_sync:            true,
+            genesis_sync_batch_size: DEFAULT_SYNC_BATCH_SIZE,
+            genesis_conflict_policy: ConflictResolution::default(),
+            auto_verify:             false,
         }
     }
 }
@@ -2,7 +2,7 @@
 //!
 //! Type definitions for the product catalog management system.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use crate::errors::CommerceError;
 
@@ -168,6 +168,18 @@ impl Currency {
     pub fn usd() -> Self {
         Self("USD".to_string())
     }
+
+    /// Number of decimals in the smallest unit, if the currency is known.
+    #[must_use]
+    pub fn decimals(&self) -> Option<u8> {
+        match self.0.as_str() {
+            "ESS" | "ETH" => Some(18),
+            "BTC" => Some(8),
+            "USDT" => Some(6),
+            "USD" => Some(2),
+            _ => None,
+        }
+    }
 }
 
 /// Price with currency.
@@ -201,6 +213,61 @@ impl Price {
         self.amount as f64 / divisor as f64
     }
 
+    /// Formats the price for display, e.g. `$1,234.56` or `1.234,56 $`.
+    ///
+    /// Uses integer math throughout so large amounts and many decimals are
+    /// shown exactly.
+    #[must_use]
+    pub fn format_localized(&self, locale: &LocaleFormat) -> String {
+        let divisor = 10_u128.pow(u32::from(self.decimals));
+        let whole = (u128::from(self.amount) / divisor).to_string();
+        let fraction = u128::from(self.amount) % divisor;
+
+        let mut number = String::with_capacity(whole.len() * 2);
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                number.push_str(&locale.thousands_sep);
+            }
+            number.push(digit);
+        }
+        if self.decimals > 0 {
+            number.push_str(&locale.decimal_sep);
+            number.push_str(&format!(
+                "{:0width$}",
+                fraction,
+                width = usize::from(self.decimals)
+            ));
+        }
+
+        if locale.symbol_before {
+            format!("{}{}", locale.symbol, number)
+        } else {
+            format!("{} {}", number, locale.symbol)
+        }
+    }
+
+    /// Whether two prices have the same currency and real value.
+    ///
+    /// Unlike `==`, this ignores differences in `decimals` alone, so
+    /// `1000` at 2 decimals equals `100000` at 4.
+    #[must_use]
+    pub fn eq_value(&self, other: &Price) -> bool {
+        if self.currency != other.currency {
+            return false;
+        }
+        let (fewer, more) = if self.decimals <= other.decimals {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        10_u128
+            .checked_pow(u32::from(more.decimals - fewer.decimals))
+            .and_then(|factor| u128::from(fewer.amount).checked_mul(factor))
+            .map_or(fewer.amount == 0 && more.amount == 0, |scaled| {
+                scaled == u128::from(more.amount)
+            })
+    }
+
     /// Adds another price (must be same currency).
     ///
     /// # Errors
@@ -226,6 +293,65 @@ impl Default for Price {
     }
 }
 
+/// How prices are written for a storefront locale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocaleFormat {
+    /// Currency symbol or code.
+    pub symbol:        String,
+    /// Separator between whole and fractional units.
+    pub decimal_sep:   String,
+    /// Separator between groups of three digits.
+    pub thousands_sep: String,
+    /// Whether the symbol precedes the amount; otherwise it follows after a
+    /// space.
+    pub symbol_before: bool,
+}
+
+impl LocaleFormat {
+    /// US style: `$1,234.56`.
+    #[must_use]
+    pub fn us(symbol: impl Into<String>) -> Self {
+        Self {
+            symbol:        symbol.into(),
+            decimal_sep:   ".".to_string(),
+            thousands_sep: ",".to_string(),
+            symbol_before: true,
+        }
+    }
+
+    /// Continental European style: `1.234,56 €`.
+    #[must_use]
+    pub fn european(symbol: impl Into<String>) -> Self {
+        Self {
+            symbol:        symbol.into(),
+            decimal_sep:   ",".to_string(),
+            thousands_sep: ".".to_string(),
+            symbol_before: false,
+        }
+    }
+}
+
+/// Price as it may be shown to customers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayPrice {
+    /// Price can be displayed.
+    Amount(Price),
+    /// Price is below the minimum advertised price and is only revealed in
+    /// the cart.
+    SeePriceInCart,
+}
+
+/// A recorded change to a product's sale price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceChange {
+    /// Sale price before the change.
+    pub previous:   Option<Price>,
+    /// Sale price after the change.
+    pub current:    Option<Price>,
+    /// Change timestamp.
+    pub changed_at: u64,
+}
+
 // ============================================================================
 // PRODUCT METADATA
 // ============================================================================
@@ -255,6 +381,17 @@ impl ProductDimensions {
     pub fn volumetric_weight(&self, divisor: f32) -> f32 {
         (self.length_cm * self.width_cm * self.height_cm) / divisor
     }
+
+    /// Whether the package exceeds standard parcel limits (longest side over
+    /// 150 cm or heavier than 30 kg).
+    #[must_use]
+    pub fn is_oversize(&self) -> bool {
+        const MAX_SIDE_CM: f32 = 150.0;
+        const MAX_WEIGHT_GRAMS: u32 = 30_000;
+
+        let longest = self.length_cm.max(self.width_cm).max(self.height_cm);
+        longest > MAX_SIDE_CM || self.weight_grams > MAX_WEIGHT_GRAMS
+    }
 }
 
 /// Product image information.
@@ -322,6 +459,32 @@ impl ProductAttribute {
     }
 }
 
+/// Component of a bundle product.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleComponent {
+    /// Component product ID.
+    pub product_id: ProductId,
+    /// Component variant ID (if applicable).
+    pub variant_id: Option<ProductId>,
+    /// Quantity per bundle.
+    pub quantity:   u32,
+}
+
+impl BundleComponent {
+    /// Creates a new bundle component.
+    #[must_use]
+    pub fn new(product_id: ProductId, quantity: u32) -> Self {
+        Self { product_id, variant_id: None, quantity }
+    }
+
+    /// Uses a specific variant of the component product.
+    #[must_use]
+    pub fn with_variant(mut self, variant_id: ProductId) -> Self {
+        self.variant_id = Some(variant_id);
+        self
+    }
+}
+
 /// Product variant for configurable products.
 #[derive(Debug, Clone)]
 pub struct ProductVariant {
@@ -422,65 +585,80 @@ impl Category {
 #[derive(Debug, Clone)]
 pub struct Product {
     /// Product ID.
-    pub id:                  ProductId,
+    pub id:                     ProductId,
     /// Product SKU.
-    pub sku:                 Sku,
+    pub sku:                    Sku,
     /// Product name.
-    pub name:                String,
+    pub name:                   String,
     /// Product description.
-    pub description:         String,
+    pub description:            String,
     /// Short description for listings.
-    pub short_description:   String,
+    pub short_description:      String,
     /// Product type.
-    pub product_type:        ProductType,
+    pub product_type:           ProductType,
     /// Product status.
-    pub status:              ProductStatus,
+    pub status:                 ProductStatus,
     /// Base price.
-    pub price:               Price,
+    pub price:                  Price,
     /// Sale/promotional price.
-    pub sale_price:          Option<Price>,
+    pub sale_price:             Option<Price>,
+    /// Sale price changes, oldest first.
+    pub sale_price_history:     Vec<PriceChange>,
     /// Cost price (for profit calculation).
-    pub cost_price:          Option<Price>,
+    pub cost_price:             Option<Price>,
+    /// Minimum advertised price (MAP).
+    pub map_price:              Option<Price>,
+    /// Price overrides by customer group (e.g. "wholesale").
+    pub tier_prices:            HashMap<String, Price>,
+    /// Volume unit prices as `(minimum quantity, price)`.
+    pub quantity_tiers:         Vec<(u32, Price)>,
     /// Category IDs.
-    pub categories:          Vec<CategoryId>,
+    pub categories:             Vec<CategoryId>,
     /// Product images.
-    pub images:              Vec<ProductImage>,
+    pub images:                 Vec<ProductImage>,
     /// Product attributes.
-    pub attributes:          Vec<ProductAttribute>,
+    pub attributes:             Vec<ProductAttribute>,
     /// Product variants.
-    pub variants:            Vec<ProductVariant>,
+    pub variants:               Vec<ProductVariant>,
+    /// Component products (for bundles).
+    pub bundle_items:           Vec<BundleComponent>,
     /// Physical dimensions.
-    pub dimensions:          Option<ProductDimensions>,
+    pub dimensions:             Option<ProductDimensions>,
     /// URL slug.
-    pub slug:                String,
+    pub slug:                   String,
     /// SEO meta title.
-    pub meta_title:          Option<String>,
+    pub meta_title:             Option<String>,
     /// SEO meta description.
-    pub meta_description:    Option<String>,
+    pub meta_description:       Option<String>,
     /// Related product IDs.
-    pub related_products:    Vec<ProductId>,
+    pub related_products:       Vec<ProductId>,
     /// Cross-sell product IDs.
-    pub cross_sell_products: Vec<ProductId>,
+    pub cross_sell_products:    Vec<ProductId>,
     /// Tags for search.
-    pub tags:                Vec<String>,
+    pub tags:                   Vec<String>,
     /// Whether product is featured.
-    pub is_featured:         bool,
+    pub is_featured:            bool,
     /// Whether product is taxable.
-    pub is_taxable:          bool,
+    pub is_taxable:             bool,
     /// Tax class identifier.
-    pub tax_class:           Option<String>,
+    pub tax_class:              Option<String>,
     /// Inventory quantity (for simple products).
-    pub inventory_quantity:  i64,
+    pub inventory_quantity:     i64,
     /// Low stock threshold.
-    pub low_stock_threshold: u32,
+    pub low_stock_threshold:    u32,
     /// Whether backorders are allowed.
-    pub backorders_allowed:  bool,
+    pub backorders_allowed:     bool,
+    /// Maximum quantity per order (unlimited if not set).
+    pub max_quantity_per_order: Option<u32>,
+    /// When the product can first be bought (pre-order / coming soon). The
+    /// product stays visible before then.
+    pub available_from:         Option<u64>,
     /// Vendor/seller ID.
-    pub vendor_id:           Option<String>,
+    pub vendor_id:              Option<String>,
     /// Creation timestamp.
-    pub created_at:          u64,
+    pub created_at:             u64,
     /// Last update timestamp.
-    pub updated_at:          u64,
+    pub updated_at:             u64,
 }
 
 impl Product {
@@ -504,11 +682,16 @@ impl Product {
             status: ProductStatus::Draft,
             price: Price::default(),
             sale_price: None,
+            sale_price_history: Vec::new(),
             cost_price: None,
+            map_price: None,
+            tier_prices: HashMap::new(),
+            quantity_tiers: Vec::new(),
             categories: Vec::new(),
             images: Vec::new(),
             attributes: Vec::new(),
             variants: Vec::new(),
+            bundle_items: Vec::new(),
             dimensions: None,
             slug,
             meta_title: None,
@@ -522,6 +705,8 @@ impl Product {
             inventory_quantity: 0,
             low_stock_threshold: 10,
             backorders_allowed: false,
+            max_quantity_per_order: None,
+            available_from: None,
             vendor_id: None,
             created_at: now,
             updated_at: now,
@@ -534,6 +719,100 @@ impl Product {
         self.sale_price.as_ref().unwrap_or(&self.price)
     }
 
+    /// Sets the sale price, recording the change in the price history.
+    ///
+    /// Returns `false` without recording anything if the price is unchanged.
+    pub fn set_sale_price(&mut self, sale_price: Option<Price>) -> bool {
+        if self.sale_price == sale_price {
+            return false;
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.sale_price_history.push(PriceChange {
+            previous:   self.sale_price.take(),
+            current:    sale_price.clone(),
+            changed_at: now,
+        });
+        self.sale_price = sale_price;
+        self.updated_at = now;
+        true
+    }
+
+    /// Whether the product can be bought at `now`: its status is purchasable
+    /// and any availability date has passed.
+    #[must_use]
+    pub fn is_available_at(&self, now: u64) -> bool {
+        self.status.is_purchasable() && self.available_from.is_none_or(|from| now >= from)
+    }
+
+    /// Whether a customer can buy the product at `now`: it is available
+    /// (status and availability date) and in stock or backorderable.
+    #[must_use]
+    pub fn is_buyable(&self, now: u64) -> bool {
+        self.is_available_at(now) && self.is_in_stock()
+    }
+
+    /// Gets the price for a customer group, falling back to the effective
+    /// price when the group has no override.
+    #[must_use]
+    pub fn price_for_group(&self, group: Option<&str>) -> &Price {
+        group
+            .and_then(|g| self.tier_prices.get(g))
+            .unwrap_or_else(|| self.effective_price())
+    }
+
+    /// Gets the unit price for buying `quantity`: the volume tier with the
+    /// highest minimum quantity not above `quantity`, unless the effective
+    /// price is cheaper or no tier applies.
+    #[must_use]
+    pub fn price_for_quantity(&self, quantity: u32) -> &Price {
+        Self::volume_price(self.effective_price(), &self.quantity_tiers, quantity)
+    }
+
+    /// Picks the cheaper of `base` and the volume tier matching `quantity`.
+    pub(crate) fn volume_price<'a>(
+        base: &'a Price, tiers: &'a [(u32, Price)], quantity: u32,
+    ) -> &'a Price {
+        tiers
+            .iter()
+            .filter(|(min_quantity, _)| *min_quantity <= quantity)
+            .max_by_key(|(min_quantity, _)| *min_quantity)
+            .map(|(_, price)| price)
+            .filter(|price| price.amount < base.amount)
+            .unwrap_or(base)
+    }
+
+    /// Checks that every group and volume tier price uses the base price
+    /// currency.
+    ///
+    /// # Errors
+    /// Returns `CurrencyMismatch` for the first tier price in another currency.
+    pub fn validate_tier_prices(&self) -> Result<(), CommerceError> {
+        let mut tiers = self.tier_prices.values().chain(self.quantity_tiers.iter().map(|(_, p)| p));
+        match tiers.find(|p| p.currency != self.price.currency) {
+            Some(mismatched) => Err(CommerceError::CurrencyMismatch {
+                expected: self.price.currency.0.to_string(),
+                got:      mismatched.currency.0.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the price to advertise, hiding it when the effective price is
+    /// below the minimum advertised price. The charged price is unaffected.
+    #[must_use]
+    pub fn display_price(&self) -> DisplayPrice {
+        let price = self.effective_price();
+        match &self.map_price {
+            Some(map) if map.currency == price.currency && price.amount < map.amount => {
+                DisplayPrice::SeePriceInCart
+            },
+            _ => DisplayPrice::Amount(price.clone()),
+        }
+    }
+
     /// Checks if product is on sale.
     #[must_use]
     pub fn is_on_sale(&self) -> bool {
@@ -546,6 +825,76 @@ impl Product {
         self.inventory_quantity > 0 || self.backorders_allowed
     }
 
+    /// Largest additional quantity a customer may add, given what is
+    /// already in their cart, the stock on hand, and the per-order cap.
+    #[must_use]
+    pub fn max_purchasable(&self, already_in_cart: u32) -> u32 {
+        let cap_remaining = self
+            .max_quantity_per_order
+            .map_or(u32::MAX, |max| max.saturating_sub(already_in_cart));
+
+        if self.backorders_allowed {
+            return cap_remaining;
+        }
+
+        let stock = u32::try_from(self.inventory_quantity.max(0)).unwrap_or(u32::MAX);
+        stock.saturating_sub(already_in_cart).min(cap_remaining)
+    }
+
+    /// Submits a draft for approval.
+    pub fn submit_for_approval(&mut self) -> Result<(), CommerceError> {
+        self.transition(&[ProductStatus::Draft], ProductStatus::PendingApproval)
+    }
+
+    /// Approves a pending product, making it active.
+    pub fn approve(&mut self) -> Result<(), CommerceError> {
+        self.transition(&[ProductStatus::PendingApproval], ProductStatus::Active)
+    }
+
+    /// Re-publishes an inactive product.
+    pub fn publish(&mut self) -> Result<(), CommerceError> {
+        self.transition(&[ProductStatus::Inactive], ProductStatus::Active)
+    }
+
+    /// Takes a live product off sale without discontinuing it.
+    pub fn unpublish(&mut self) -> Result<(), CommerceError> {
+        self.transition(
+            &[ProductStatus::Active, ProductStatus::OutOfStock],
+            ProductStatus::Inactive,
+        )
+    }
+
+    /// Discontinues a previously approved product.
+    pub fn discontinue(&mut self) -> Result<(), CommerceError> {
+        self.transition(
+            &[
+                ProductStatus::Active,
+                ProductStatus::Inactive,
+                ProductStatus::OutOfStock,
+                ProductStatus::Archived,
+            ],
+            ProductStatus::Discontinued,
+        )
+    }
+
+    /// Moves to `to` if the current status is one of `from`.
+    fn transition(
+        &mut self, from: &[ProductStatus], to: ProductStatus,
+    ) -> Result<(), CommerceError> {
+        if !from.contains(&self.status) {
+            return Err(CommerceError::InvalidStatusTransition {
+                from: format!("{:?}", self.status),
+                to:   format!("{:?}", to),
+            });
+        }
+        self.status = to;
+        self.updated_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(())
+    }
+
     /// Checks if product is low on stock.
     #[must_use]
     pub fn is_low_stock(&self) -> bool {
@@ -582,27 +931,38 @@ impl Product {
 #[derive(Debug, Clone, Default)]
 pub struct ProductFilter {
     /// Filter by category IDs.
-    pub categories:    Vec<CategoryId>,
+    pub categories:            Vec<CategoryId>,
+    /// Also match products in descendants of `categories`.
+    pub include_subcategories: bool,
     /// Filter by status.
-    pub status:        Option<ProductStatus>,
+    pub status:                Option<ProductStatus>,
     /// Filter by product type.
-    pub product_type:  Option<ProductType>,
+    pub product_type:          Option<ProductType>,
     /// Minimum price filter.
-    pub min_price:     Option<u64>,
+    pub min_price:             Option<u64>,
     /// Maximum price filter.
-    pub max_price:     Option<u64>,
+    pub max_price:             Option<u64>,
+    /// Currency the price range is expressed in.
+    ///
+    /// When set, the price range only applies to products priced in this
+    /// currency; other products pass the price filter unless
+    /// `currency_only` is set. When unset, amounts are compared
+    /// as-is regardless of currency.
+    pub currency:              Option<Currency>,
+    /// Only products priced in `currency`.
+    pub currency_only:         bool,
     /// Filter by tags.
-    pub tags:          Vec<String>,
+    pub tags:                  Vec<String>,
     /// Filter by vendor ID.
-    pub vendor_id:     Option<String>,
+    pub vendor_id:             Option<String>,
     /// Only featured products.
-    pub featured_only: bool,
+    pub featured_only:         bool,
     /// Only in-stock products.
-    pub in_stock_only: bool,
+    pub in_stock_only:         bool,
     /// Only products on sale.
-    pub on_sale_only:  bool,
+    pub on_sale_only:          bool,
     /// Text search query.
-    pub search_query:  Option<String>,
+    pub search_query:          Option<String>,
 }
 
 impl ProductFilter {
@@ -619,6 +979,14 @@ impl ProductFilter {
         self
     }
 
+    /// Filters by a category and all of its descendants.
+    #[must_use]
+    pub fn with_category_tree(mut self, category_id: CategoryId) -> Self {
+        self.categories.push(category_id);
+        self.include_subcategories = true;
+        self
+    }
+
     /// Filters by status.
     #[must_use]
     pub fn with_status(mut self, status: ProductStatus) -> Self {
@@ -634,6 +1002,13 @@ impl ProductFilter {
         self
     }
 
+    /// Interprets the price range in the given currency.
+    #[must_use]
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = Some(currency);
+        self
+    }
+
     /// Only in-stock products.
     #[must_use]
     pub fn in_stock_only(mut self) -> Self {
@@ -660,6 +1035,38 @@ pub enum ProductSortOrder {
     TopRated,
     /// Sort by featured status.
     Featured,
+    /// Sort by how well the search query matches, best first; newest first
+    /// without a query.
+    Relevance,
+}
+
+/// Product field matched by a text search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchedField {
+    /// Product name.
+    Name,
+    /// Product description.
+    Description,
+    /// Product SKU.
+    Sku,
+}
+
+/// Text match within a single product field.
+#[derive(Debug, Clone)]
+pub struct FieldMatch {
+    /// Field that matched.
+    pub field:  MatchedField,
+    /// Query tokens found in the field (lowercased).
+    pub tokens: Vec<String>,
+}
+
+/// Search result with matched-term highlights.
+#[derive(Debug, Clone)]
+pub struct ProductSearchHit {
+    /// Matching product.
+    pub product: Product,
+    /// Fields that matched the text query (empty without a query).
+    pub matches: Vec<FieldMatch>,
 }
 
 /// Paginated results.
@@ -677,6 +1084,24 @@ pub struct PaginatedProducts {
     pub has_next:    bool,
 }
 
+/// Outcome of a bulk product import.
+#[derive(Debug, Clone, Default)]
+pub struct BulkAddResult {
+    /// Number of products added.
+    pub added:   usize,
+    /// Products that were not added, with the reason.
+    pub skipped: Vec<(ProductId, CommerceError)>,
+}
+
+/// Storefront search results spanning products and categories.
+#[derive(Debug, Clone, Default)]
+pub struct UniversalResults {
+    /// Matching products, best match first.
+    pub products:   Vec<Product>,
+    /// Matching categories, best match first.
+    pub categories: Vec<Category>,
+}
+
 impl PaginatedProducts {
     /// Total number of pages.
     #[must_use]
@@ -687,3 +1112,27 @@ impl PaginatedProducts {
         self.total_count.div_ceil(self.page_size)
     }
 }
+
+// ============================================================================
+// CHANGE FEED
+// ============================================================================
+
+/// Kind of catalog mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogChangeKind {
+    /// Product added.
+    Added,
+    /// Product updated (including price changes).
+    Updated,
+    /// Product removed.
+    Removed,
+}
+
+/// Product mutation recorded in the catalog change feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogChange {
+    /// Kind of mutation.
+    pub kind: CatalogChangeKind,
+    /// Affected product.
+    pub id:   ProductId,
+}